@@ -0,0 +1,72 @@
+//! Cost of the per-message path of the SIP socket.
+//!
+//! Run with `cargo bench --bench message`. The `copy` variants reproduce the
+//! former path which cloned every frame into a `String` and every sent request
+//! for its transaction; compare them with the current ones to see the gain.
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rsip::SipMessage;
+use std::hint::black_box;
+use ucware_cli::sipsocket::codec;
+
+const INVITE: &str = "INVITE sip:alice@example.com SIP/2.0\r\n\
+    Via: SIP/2.0/WSS proxy.example.com;branch=z9hG4bK776asdhds\r\n\
+    Max-Forwards: 70\r\n\
+    From: \"Bob\" <sip:bob@example.com>;tag=1928301774\r\n\
+    To: <sip:alice@example.com>\r\n\
+    Call-ID: a84b4c76e66710@pc33.example.com\r\n\
+    CSeq: 314159 INVITE\r\n\
+    Contact: <sip:bob@pc33.example.com;transport=ws>\r\n\
+    Content-Type: application/sdp\r\n\
+    Content-Length: 142\r\n\r\n\
+    v=0\r\n\
+    o=bob 2890844526 2890844526 IN IP4 pc33.example.com\r\n\
+    s=-\r\n\
+    c=IN IP4 192.0.2.101\r\n\
+    t=0 0\r\n\
+    m=audio 49172 RTP/AVP 0\r\n\
+    a=rtpmap:0 PCMU/8000\r\n";
+
+fn decode(c: &mut Criterion) {
+    let frame = Bytes::from_static(INVITE.as_bytes());
+
+    let mut group = c.benchmark_group("decode");
+    group.bench_function("copy", |b| {
+        b.iter(|| {
+            let text = String::from_utf8(frame.to_vec()).expect("valid utf-8");
+            SipMessage::try_from(black_box(text)).expect("valid message")
+        })
+    });
+    group.bench_function("bytes", |b| {
+        b.iter(|| codec::decode(black_box(frame.clone())).expect("valid message"))
+    });
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let message = codec::decode(Bytes::from_static(INVITE.as_bytes())).expect("valid message");
+
+    let mut group = c.benchmark_group("encode");
+    group.bench_function("copy", |b| {
+        b.iter_batched(
+            || message.clone(),
+            |message| {
+                let kept = black_box(message.clone());
+                (kept, String::from(message))
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("borrow", |b| {
+        b.iter_batched(
+            || message.clone(),
+            |message| codec::encode(black_box(&message)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+//! Throughput of the transaction layer, replaying calls like `ucware-cli loadtest`.
+//!
+//! Run with `cargo bench --bench transactions`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ucware_cli::loadtest::{self, Options};
+
+const CALLS: usize = 100;
+
+fn transactions(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("runtime");
+
+    let mut group = c.benchmark_group("transactions");
+    group.throughput(Throughput::Elements(2 * CALLS as u64));
+
+    for concurrency in [1, 16] {
+        group.bench_function(format!("concurrency-{concurrency}"), |b| {
+            b.iter(|| {
+                runtime
+                    .block_on(loadtest::run(Options { calls: CALLS, concurrency }))
+                    .expect("load test")
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, transactions);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=UCWARE_GIT_COMMIT");
+
+    // Builds from a tarball can pass the commit in explicitly
+    let commit = std::env::var("UCWARE_GIT_COMMIT").ok().or_else(|| {
+        let output = Command::new("git").args(["rev-parse", "HEAD"]).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=UCWARE_GIT_COMMIT={commit}");
+    }
+
+    if let Some(version) = locked_version("rsip") {
+        println!("cargo:rustc-env=UCWARE_RSIP_VERSION={version}");
+    }
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generates the gRPC service with the bundled protoc, so none needs to be installed
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    println!("cargo:rerun-if-changed=proto/control.proto");
+
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("Bundled protoc");
+    // SAFETY: build scripts are single threaded
+    unsafe { std::env::set_var("PROTOC", protoc) };
+
+    tonic_prost_build::configure()
+        .build_client(true)
+        .compile_protos(&["proto/control.proto"], &["proto"])
+        .expect("Valid protos");
+}
+
+/// Version of a dependency as resolved in the lock file
+fn locked_version(name: &str) -> Option<String> {
+    let lock = std::fs::read_to_string("Cargo.lock").ok()?;
+    let mut lines = lock.lines();
+
+    lines.find(|line| *line == format!("name = \"{name}\""))?;
+    let version = lines.next()?.strip_prefix("version = \"")?.strip_suffix('"')?;
+    Some(version.to_string())
+}
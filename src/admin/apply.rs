@@ -0,0 +1,457 @@
+use crate::ucware::Client;
+use crate::ucware::admin::{NewQueue, NewSlot, NewUser};
+use crate::ucware::user::DeviceType;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Debug;
+use std::path::Path;
+use tracing::info;
+
+/// Declarative description of users, slots and queues on the server
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct State {
+    #[serde(default)]
+    pub users: Vec<UserState>,
+
+    #[serde(default)]
+    pub slots: Vec<SlotState>,
+
+    #[serde(default)]
+    pub queues: Vec<QueueState>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UserState {
+    pub username: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: Option<String>,
+    pub extension: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SlotState {
+    /// Username of the owner
+    pub user: String,
+    pub name: String,
+    pub device_type: DeviceType,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct QueueState {
+    pub name: String,
+    pub extension: String,
+
+    /// Usernames of the queue members
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+impl State {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_yaml::from_str(&data)
+            .with_context(|| format!("Invalid state file {}", path.display()))
+    }
+}
+
+/// A single step to bring the server to the desired state
+#[derive(Debug)]
+pub enum Change {
+    CreateUser(NewUser),
+    UpdateUser {
+        id: u64,
+        user: NewUser,
+        diff: Vec<String>,
+    },
+    DeleteUser {
+        id: u64,
+        username: String,
+    },
+
+    CreateSlot {
+        user: String,
+        name: String,
+        device_type: DeviceType,
+    },
+    UpdateSlot {
+        id: u64,
+        user: String,
+        name: String,
+        device_type: DeviceType,
+        diff: Vec<String>,
+    },
+    DeleteSlot {
+        id: u64,
+        user: String,
+        name: String,
+    },
+
+    CreateQueue {
+        name: String,
+        extension: String,
+        members: Vec<String>,
+    },
+    UpdateQueue {
+        id: u64,
+        name: String,
+        extension: String,
+        members: Vec<String>,
+        diff: Vec<String>,
+    },
+    DeleteQueue {
+        id: u64,
+        name: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateUser(user) => write!(f, "+ user {}", user.username),
+            Self::UpdateUser { user, diff, .. } => {
+                write!(f, "~ user {} ({})", user.username, diff.join(", "))
+            }
+            Self::DeleteUser { username, .. } => write!(f, "- user {username}"),
+
+            Self::CreateSlot { user, name, .. } => write!(f, "+ slot {user}/{name}"),
+            Self::UpdateSlot {
+                user, name, diff, ..
+            } => {
+                write!(f, "~ slot {user}/{name} ({})", diff.join(", "))
+            }
+            Self::DeleteSlot { user, name, .. } => write!(f, "- slot {user}/{name}"),
+
+            Self::CreateQueue { name, .. } => write!(f, "+ queue {name}"),
+            Self::UpdateQueue { name, diff, .. } => {
+                write!(f, "~ queue {name} ({})", diff.join(", "))
+            }
+            Self::DeleteQueue { name, .. } => write!(f, "- queue {name}"),
+        }
+    }
+}
+
+fn compare<T: PartialEq + Debug>(diff: &mut Vec<String>, field: &str, current: &T, desired: &T) {
+    if current != desired {
+        diff.push(format!("{field}: {current:?} -> {desired:?}"));
+    }
+}
+
+/// Changes required to reach a desired state, in the order they must be applied
+pub struct Plan {
+    changes: Vec<Change>,
+
+    /// User IDs by username as known at planning time
+    users: HashMap<String, u64>,
+}
+
+impl Plan {
+    /// Diffs the desired state against the server.
+    ///
+    /// Objects missing from the state are only deleted if `prune` is set.
+    pub async fn new(client: &Client, state: &State, prune: bool) -> Result<Self> {
+        let admin = client.admin();
+        let (users, slots, queues) = (admin.users(), admin.slots(), admin.queues());
+        let (users, slots, queues) =
+            tokio::try_join!(users.get_all(), slots.get_all(), queues.get_all())?;
+
+        let usernames = users
+            .iter()
+            .map(|user| (user.id, user.username.clone()))
+            .collect::<HashMap<_, _>>();
+
+        // Users which will exist after applying the plan
+        let mut known = state
+            .users
+            .iter()
+            .map(|user| user.username.as_str())
+            .collect::<HashSet<_>>();
+        if !prune {
+            known.extend(users.iter().map(|user| user.username.as_str()));
+        }
+
+        let references = state
+            .slots
+            .iter()
+            .map(|slot| &slot.user)
+            .chain(state.queues.iter().flat_map(|queue| &queue.members));
+        for user in references {
+            if !known.contains(user.as_str()) {
+                bail!("Unknown user referenced in state: {user}");
+            }
+        }
+
+        let mut changes = Vec::new();
+        let mut deletes = Vec::new();
+
+        for desired in &state.users {
+            let user = NewUser {
+                username: desired.username.clone(),
+                first_name: desired.first_name.clone(),
+                last_name: desired.last_name.clone(),
+                email: desired.email.clone(),
+                extension: desired.extension.clone(),
+                password: None,
+            };
+
+            match users.iter().find(|u| u.username == desired.username) {
+                None => changes.push(Change::CreateUser(user)),
+                Some(current) => {
+                    let mut diff = Vec::new();
+                    compare(
+                        &mut diff,
+                        "first-name",
+                        &current.first_name,
+                        &user.first_name,
+                    );
+                    compare(&mut diff, "last-name", &current.last_name, &user.last_name);
+                    compare(&mut diff, "email", &current.email, &user.email);
+                    compare(&mut diff, "extension", &current.extension, &user.extension);
+
+                    if !diff.is_empty() {
+                        changes.push(Change::UpdateUser {
+                            id: current.id,
+                            user,
+                            diff,
+                        });
+                    }
+                }
+            }
+        }
+
+        for desired in &state.slots {
+            let current = slots.iter().find(|s| {
+                s.name == desired.name && usernames.get(&s.user_id) == Some(&desired.user)
+            });
+
+            match current {
+                None => changes.push(Change::CreateSlot {
+                    user: desired.user.clone(),
+                    name: desired.name.clone(),
+                    device_type: desired.device_type.clone(),
+                }),
+                Some(current) => {
+                    let mut diff = Vec::new();
+                    compare(
+                        &mut diff,
+                        "device-type",
+                        &current.device_type,
+                        &desired.device_type,
+                    );
+
+                    if !diff.is_empty() {
+                        changes.push(Change::UpdateSlot {
+                            id: current.id,
+                            user: desired.user.clone(),
+                            name: desired.name.clone(),
+                            device_type: desired.device_type.clone(),
+                            diff,
+                        });
+                    }
+                }
+            }
+        }
+
+        for desired in &state.queues {
+            match queues.iter().find(|q| q.name == desired.name) {
+                None => changes.push(Change::CreateQueue {
+                    name: desired.name.clone(),
+                    extension: desired.extension.clone(),
+                    members: desired.members.clone(),
+                }),
+                Some(current) => {
+                    let current_members = current
+                        .members
+                        .iter()
+                        .map(|id| {
+                            usernames
+                                .get(id)
+                                .cloned()
+                                .unwrap_or_else(|| format!("#{id}"))
+                        })
+                        .collect::<BTreeSet<_>>();
+                    let desired_members = desired.members.iter().cloned().collect::<BTreeSet<_>>();
+
+                    let mut diff = Vec::new();
+                    compare(
+                        &mut diff,
+                        "extension",
+                        &current.extension,
+                        &desired.extension,
+                    );
+                    compare(&mut diff, "members", &current_members, &desired_members);
+
+                    if !diff.is_empty() {
+                        changes.push(Change::UpdateQueue {
+                            id: current.id,
+                            name: desired.name.clone(),
+                            extension: desired.extension.clone(),
+                            members: desired.members.clone(),
+                            diff,
+                        });
+                    }
+                }
+            }
+        }
+
+        if prune {
+            for current in &queues {
+                if !state.queues.iter().any(|q| q.name == current.name) {
+                    deletes.push(Change::DeleteQueue {
+                        id: current.id,
+                        name: current.name.clone(),
+                    });
+                }
+            }
+
+            for current in &slots {
+                let user = usernames
+                    .get(&current.user_id)
+                    .cloned()
+                    .unwrap_or_else(|| format!("#{}", current.user_id));
+
+                if !state
+                    .slots
+                    .iter()
+                    .any(|s| s.name == current.name && s.user == user)
+                {
+                    deletes.push(Change::DeleteSlot {
+                        id: current.id,
+                        user,
+                        name: current.name.clone(),
+                    });
+                }
+            }
+
+            for current in &users {
+                if !state.users.iter().any(|u| u.username == current.username) {
+                    deletes.push(Change::DeleteUser {
+                        id: current.id,
+                        username: current.username.clone(),
+                    });
+                }
+            }
+        }
+
+        // Deletes go last and in reverse dependency order
+        changes.extend(deletes);
+
+        Ok(Self {
+            changes,
+            users: usernames.into_iter().map(|(id, name)| (name, id)).collect(),
+        })
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Applies all changes in order, stopping at the first failure
+    pub async fn apply(mut self, client: &Client) -> Result<()> {
+        let admin = client.admin();
+
+        for change in self.changes {
+            info!("Applying {change}");
+
+            let user_id = |users: &HashMap<String, u64>, user: &str| {
+                users
+                    .get(user)
+                    .copied()
+                    .with_context(|| format!("Unknown user: {user}"))
+            };
+
+            let result = async {
+                match &change {
+                    Change::CreateUser(user) => {
+                        let created = admin.users().create(user).await?;
+                        self.users.insert(created.username, created.id);
+                    }
+                    Change::UpdateUser { id, user, .. } => {
+                        admin.users().update(*id, user).await?;
+                    }
+                    Change::DeleteUser { id, .. } => admin.users().delete(*id).await?,
+
+                    Change::CreateSlot {
+                        user,
+                        name,
+                        device_type,
+                    } => {
+                        let slot = NewSlot {
+                            name: name.clone(),
+                            user_id: user_id(&self.users, user)?,
+                            device_type: device_type.clone(),
+                        };
+                        admin.slots().create(&slot).await?;
+                    }
+                    Change::UpdateSlot {
+                        id,
+                        user,
+                        name,
+                        device_type,
+                        ..
+                    } => {
+                        let slot = NewSlot {
+                            name: name.clone(),
+                            user_id: user_id(&self.users, user)?,
+                            device_type: device_type.clone(),
+                        };
+                        admin.slots().update(*id, &slot).await?;
+                    }
+                    Change::DeleteSlot { id, .. } => admin.slots().delete(*id).await?,
+
+                    Change::CreateQueue {
+                        name,
+                        extension,
+                        members,
+                    } => {
+                        let queue = NewQueue {
+                            name: name.clone(),
+                            extension: extension.clone(),
+                            members: members
+                                .iter()
+                                .map(|user| user_id(&self.users, user))
+                                .collect::<Result<_>>()?,
+                        };
+                        admin.queues().create(&queue).await?;
+                    }
+                    Change::UpdateQueue {
+                        id,
+                        name,
+                        extension,
+                        members,
+                        ..
+                    } => {
+                        let queue = NewQueue {
+                            name: name.clone(),
+                            extension: extension.clone(),
+                            members: members
+                                .iter()
+                                .map(|user| user_id(&self.users, user))
+                                .collect::<Result<_>>()?,
+                        };
+                        admin.queues().update(*id, &queue).await?;
+                    }
+                    Change::DeleteQueue { id, .. } => admin.queues().delete(*id).await?,
+                }
+
+                anyhow::Ok(())
+            }
+            .await;
+
+            result.with_context(|| format!("Failed to apply {change}"))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use crate::ucware::Client;
+use crate::ucware::admin::{Group, User};
+use anyhow::{Context, Result};
+
+pub mod apply;
+pub mod users;
+
+/// Looks up a user by username or ID
+pub async fn find_user(client: &Client, user: &str) -> Result<User> {
+    client
+        .admin()
+        .users()
+        .get_all()
+        .await?
+        .into_iter()
+        .find(|u| u.username == user || u.id.to_string() == user)
+        .with_context(|| format!("No such user: {user}"))
+}
+
+/// Looks up a group by name or ID
+pub async fn find_group(client: &Client, group: &str) -> Result<Group> {
+    client
+        .admin()
+        .groups()
+        .get_all()
+        .await?
+        .into_iter()
+        .find(|g| g.name == group || g.id.to_string() == group)
+        .with_context(|| format!("No such group: {group}"))
+}
@@ -0,0 +1,197 @@
+use crate::progress;
+use crate::ucware::Client;
+use crate::ucware::admin::NewUser;
+use crate::ucware::util::{CONCURRENCY, parallel};
+use anyhow::{Context, Result, bail, ensure};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use tracing::info;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+/// A user as represented in CSV files
+#[derive(Debug, Serialize, Deserialize)]
+struct Row {
+    username: String,
+    first_name: String,
+    last_name: String,
+
+    #[serde(default)]
+    email: Option<String>,
+
+    #[serde(default)]
+    extension: Option<String>,
+
+    #[serde(default, skip_serializing)]
+    password: Option<String>,
+}
+
+impl Row {
+    fn validate(self) -> Result<NewUser> {
+        let username = self.username.trim();
+        ensure!(!username.is_empty(), "Username is empty");
+        ensure!(
+            !username.contains(char::is_whitespace),
+            "Username contains whitespace: {username}"
+        );
+
+        ensure!(!self.first_name.trim().is_empty(), "First name is empty");
+        ensure!(!self.last_name.trim().is_empty(), "Last name is empty");
+
+        let email = self.email.filter(|email| !email.trim().is_empty());
+        if let Some(email) = &email {
+            ensure!(email.contains('@'), "Invalid email address: {email}");
+        }
+
+        let extension = self
+            .extension
+            .filter(|extension| !extension.trim().is_empty());
+        if let Some(extension) = &extension {
+            ensure!(
+                extension.chars().all(|c| c.is_ascii_digit()),
+                "Extension must be numeric: {extension}"
+            );
+        }
+
+        Ok(NewUser {
+            username: username.to_string(),
+            first_name: self.first_name.trim().to_string(),
+            last_name: self.last_name.trim().to_string(),
+            email,
+            extension,
+            password: self.password.filter(|password| !password.is_empty()),
+        })
+    }
+}
+
+/// Writes all users of the server in the given format
+pub async fn export(client: &Client, format: Format, writer: impl Write) -> Result<()> {
+    let users = {
+        let _progress = progress::spinner("Fetching users");
+        client.admin().users().get_all().await?
+    };
+
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for user in users {
+                writer.serialize(Row {
+                    username: user.username,
+                    first_name: user.first_name,
+                    last_name: user.last_name,
+                    email: user.email,
+                    extension: user.extension,
+                    password: None,
+                })?;
+            }
+            writer.flush()?;
+        }
+
+        Format::Json => {
+            serde_json::to_writer_pretty(writer, &users)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates users from CSV rows.
+///
+/// All rows are validated before anything is created. Rows failing validation
+/// or creation are reported with their line number and skipped; the import
+/// fails at the end if any row was skipped.
+pub async fn import(client: &Client, reader: impl Read, dry_run: bool) -> Result<()> {
+    let users = client.admin().users();
+
+    let mut usernames = users
+        .get_all()
+        .await?
+        .into_iter()
+        .map(|user| user.username)
+        .collect::<HashSet<_>>();
+    let mut extensions = HashSet::new();
+
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader
+        .headers()
+        .context("Failed to read CSV header")?
+        .clone();
+
+    let mut rows = Vec::new();
+    let mut failed = 0;
+
+    for record in reader.records() {
+        let record = record.context("Failed to read CSV")?;
+        let line = record.position().map_or(0, csv::Position::line);
+
+        let user = record
+            .deserialize::<Row>(Some(&headers))
+            .context("Malformed row")
+            .and_then(Row::validate)
+            .and_then(|user| {
+                ensure!(
+                    usernames.insert(user.username.clone()),
+                    "User already exists: {username}",
+                    username = user.username
+                );
+                if let Some(extension) = &user.extension {
+                    ensure!(
+                        extensions.insert(extension.clone()),
+                        "Duplicate extension in file: {extension}"
+                    );
+                }
+                Ok(user)
+            });
+
+        match user {
+            Ok(user) => rows.push((line, user)),
+            Err(err) => {
+                eprintln!("line {line}: {err:#}");
+                failed += 1;
+            }
+        }
+    }
+
+    let total = rows.len() + failed;
+
+    if dry_run {
+        for (_, user) in &rows {
+            println!("Would create {username}", username = user.username);
+        }
+    } else {
+        let results = parallel(
+            rows.iter().map(|(_, user)| users.create(user)),
+            CONCURRENCY,
+        )
+        .await;
+
+        for ((line, user), result) in rows.iter().zip(results) {
+            match result {
+                Ok(created) => info!(
+                    "Created {username} ({id})",
+                    username = created.username,
+                    id = created.id
+                ),
+                Err(err) => {
+                    eprintln!(
+                        "line {line}: Failed to create {username}: {err:#}",
+                        username = user.username
+                    );
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {total} rows failed");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,294 @@
+//! Answering calls with an IVR flow
+//!
+//! Each call gets a media session of its own and walks through the flow of
+//! [`crate::ivr`]. Prompts are spoken by the speech synthesis of the media
+//! config, digits arrive as RTP events and recordings are kept as WAV files,
+//! encrypted and transcribed into the call log if configured.
+
+use crate::callstate::DialogKey;
+use crate::config::{HoldConfig, MediaConfig};
+use crate::ivr::{Action, Event, Ivr, Node, Session};
+use crate::media::codec::Codecs;
+use crate::media::hold::HoldMusic;
+use crate::media::session::MediaSession;
+use crate::media::transcribe::{Transcriber, Transcript};
+use crate::media::tts::Tts;
+use crate::media::wav;
+use crate::sipsocket::{AnsweredDialog, ServerTransaction};
+use crate::store::Store;
+use anyhow::{bail, Context, Result};
+use dashmap::DashMap;
+use rsip::StatusCode;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// Digit ending a recording before its maximum length
+const END_OF_RECORDING: char = '#';
+
+pub struct AnswerBot {
+    ivr: Ivr,
+    media: MediaConfig,
+    tts: Option<Tts>,
+    transcriber: Option<Transcriber>,
+    store: Store,
+
+    /// Directory keeping the recordings of callers
+    recordings: PathBuf,
+
+    /// Calls in progress, kept to end them once the caller hangs up
+    calls: DashMap<DialogKey, oneshot::Sender<()>>,
+}
+
+impl AnswerBot {
+    pub fn new(ivr: Ivr, media: MediaConfig, store: Store, recordings: PathBuf) -> Result<Self> {
+        let speaks = ivr.nodes.values().any(|node| matches!(node, Node::Say { .. } | Node::Menu(_)));
+        let tts = media.tts.clone().map(Tts::open_default).transpose()?;
+        if speaks && tts.is_none() {
+            bail!("The IVR speaks prompts, which requires media.tts to be set");
+        }
+        let transcriber = media.transcription.clone().map(Transcriber::new);
+
+        Ok(Self {
+            ivr,
+            media,
+            tts,
+            transcriber,
+            store,
+            recordings,
+            calls: DashMap::new(),
+        })
+    }
+
+    /// Directory recordings are kept in unless given
+    pub fn default_recordings() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("ucware").join("recordings"))
+    }
+
+    /// Answers a call and walks the caller through the flow in a task of its own
+    pub fn answer(self: &Arc<Self>, key: DialogKey, tx: ServerTransaction) {
+        let (hangup_tx, hangup) = oneshot::channel();
+        self.calls.insert(key.clone(), hangup_tx);
+
+        let bot = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = bot.handle(tx, hangup).await {
+                warn!("Failed to answer call: {err:#}");
+            }
+            bot.calls.remove(&key);
+        });
+    }
+
+    /// Ends the media of a call the caller hung up on
+    pub fn hung_up(&self, key: &DialogKey) -> bool {
+        let Some((_, hangup)) = self.calls.remove(key) else {
+            return false;
+        };
+
+        let _ = hangup.send(());
+        true
+    }
+
+    async fn handle(&self, mut tx: ServerTransaction, hangup: oneshot::Receiver<()>) -> Result<()> {
+        let offer = String::from_utf8_lossy(&tx.request.body).into_owned();
+        let (mut media, sdp) = match MediaSession::answer(&offer, &Codecs::default(), &self.media).await {
+            Ok(answered) => answered,
+            Err(err) => {
+                tx.respond(StatusCode::NotAcceptableHere).send([]).await;
+                return Err(err);
+            }
+        };
+
+        let dialog = tx.answer(sdp).await?;
+        info!("Answered call {call_id}", call_id = dialog.call_id());
+
+        let result = tokio::select! {
+            result = self.run(&mut media, &dialog) => Some(result),
+            _ = hangup => None,
+        };
+
+        let stats = media.stop().await?;
+        info!("Media of call {call_id}: {stats}", call_id = dialog.call_id());
+
+        // Hung up here unless the caller did
+        if let Some(result) = result {
+            let bye = dialog.bye().await;
+            result?;
+            bye?;
+        }
+        Ok(())
+    }
+
+    /// Walks the caller through the flow until it transfers or hangs up
+    async fn run(&self, media: &mut MediaSession, dialog: &AnsweredDialog) -> Result<()> {
+        let mut digits = media.digits();
+        let (mut session, mut action) = Session::start(&self.ivr);
+
+        loop {
+            debug!("[{node}] {action}", node = session.node());
+
+            // Only menus take digits pressed during their prompt
+            let menu = matches!(self.ivr.nodes.get(session.node()), Some(Node::Menu(_)));
+
+            let event = match action {
+                Action::Say(text) => {
+                    let tts = self.tts.as_ref().context("No speech synthesis configured")?;
+                    let pcm = tts.speak(&text, media.sample_rate()).await?;
+                    prompt(media, pcm, &mut digits, menu).await?
+                }
+
+                Action::Play(path) => {
+                    let sample_rate = media.sample_rate();
+                    let pcm = tokio::task::spawn_blocking(move || wav::read(&path, sample_rate)).await??;
+                    prompt(media, pcm, &mut digits, menu).await?
+                }
+
+                Action::Collect { timeout } => match tokio::time::timeout(timeout, digits.recv()).await {
+                    Ok(Some(digit)) => Event::Digit(digit),
+                    Ok(None) => bail!("Media stopped"),
+                    Err(_) => Event::Timeout,
+                },
+
+                Action::Record { max } => {
+                    let transcript = self
+                        .transcriber
+                        .as_ref()
+                        .map(|transcriber| transcriber.stream(dialog.call_id(), media.sample_rate()));
+                    let (wav, transcript) = record(media, &mut digits, max, transcript).await?;
+
+                    let name = format!(
+                        "{call_id}-{node}",
+                        call_id = sanitize(dialog.call_id()),
+                        node = sanitize(session.node()),
+                    );
+                    let path = self.store.save_recording(&self.recordings, &name, dialog.call_id(), &wav)?;
+                    info!("Recorded caller to {path}", path = path.display());
+
+                    if let Some(transcript) = transcript
+                        && let Err(err) = transcript.finish(&self.store).await
+                    {
+                        warn!("Failed to transcribe recording: {err:#}");
+                    }
+                    Event::Done
+                }
+
+                Action::Transfer(target) => {
+                    // Bare numbers are dialled through the same server
+                    let uri = if target.contains(':') {
+                        target
+                    } else {
+                        format!("sip:{target}@{host}", host = dialog.callee().host_with_port)
+                    };
+                    return transfer(media, dialog, &uri, &self.media.hold).await;
+                }
+
+                Action::Hangup => return Ok(()),
+            };
+
+            action = session.next(event);
+        }
+    }
+}
+
+/// Plays a prompt, cut short by a digit if it is one of a menu
+async fn prompt(
+    media: &MediaSession,
+    pcm: Vec<i16>,
+    digits: &mut mpsc::UnboundedReceiver<char>,
+    menu: bool,
+) -> Result<Event> {
+    let played = media.play(pcm);
+    tokio::pin!(played);
+
+    loop {
+        tokio::select! {
+            played = &mut played => {
+                played?;
+                return Ok(Event::Done);
+            }
+
+            Some(digit) = digits.recv() => {
+                if menu {
+                    media.clear();
+                    return Ok(Event::Digit(digit));
+                }
+                debug!("Ignoring digit {digit} outside of menu");
+            }
+        }
+    }
+}
+
+/// Records the caller as WAV until the maximum length or the end digit,
+/// passing the audio on to the transcript as it arrives.
+///
+/// Returns the transcript along with the audio unless transcribing failed.
+async fn record<'a>(
+    media: &MediaSession,
+    digits: &mut mpsc::UnboundedReceiver<char>,
+    max: Duration,
+    mut transcript: Option<Transcript<'a>>,
+) -> Result<(Vec<u8>, Option<Transcript<'a>>)> {
+    let mut frames = media.listen();
+    let mut pcm = Vec::new();
+
+    let deadline = tokio::time::sleep(max);
+    tokio::pin!(deadline);
+
+    loop {
+        tokio::select! {
+            frame = frames.recv() => match frame {
+                Some(frame) => {
+                    if let Some(pending) = &mut transcript
+                        && let Err(err) = pending.push(&frame).await
+                    {
+                        warn!("Failed to transcribe recording: {err:#}");
+                        transcript = None;
+                    }
+                    pcm.extend(frame);
+                }
+                None => break,
+            },
+
+            Some(digit) = digits.recv() => {
+                if digit == END_OF_RECORDING {
+                    break;
+                }
+            }
+
+            _ = &mut deadline => break,
+        }
+    }
+
+    let wav = wav::encode(&pcm, media.sample_rate())?;
+    Ok((wav, transcript))
+}
+
+/// Puts the caller on hold with the hold music and asks it to call the target instead, see RFC 5589
+pub async fn transfer(
+    media: &mut MediaSession,
+    dialog: &AnsweredDialog,
+    target: &str,
+    hold: &HoldConfig,
+) -> Result<()> {
+    let (config, sample_rate) = (hold.clone(), media.sample_rate());
+    let music = tokio::task::spawn_blocking(move || HoldMusic::load(&config, sample_rate)).await?;
+    let music = music.unwrap_or_else(|err| {
+        warn!("Failed to load hold music: {err:#}");
+        HoldMusic::Silence
+    });
+    if let Err(err) = dialog.reinvite(media.hold(music)).await {
+        warn!("Failed to hold call: {err:#}");
+    }
+
+    info!("Transferring call {call_id} to {target}", call_id = dialog.call_id());
+    dialog.refer(target).await
+}
+
+/// A name safe to use in a file name
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect()
+}
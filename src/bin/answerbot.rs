@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use rsip::prelude::*;
+use rsip::{Method, StatusCode};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tracing::{debug, error, info};
+use ucware_cli::answerbot::AnswerBot;
+use ucware_cli::callstate::DialogKey;
+use ucware_cli::cmd;
+use ucware_cli::ivr::Ivr;
+use ucware_cli::sipsocket::{Connection, RetryAfter};
+use ucware_cli::store::Store;
+
+/// Registration interval assumed if the registrar did not tell
+const REGISTRATION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Delay before registering again after a failure the server gave no delay for
+const REGISTRATION_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Args, Debug)]
+struct AnswerBotArgs {
+    /// Path of the IVR flow to walk callers through
+    #[arg(long)]
+    ivr: PathBuf,
+
+    /// Directory to keep recordings of callers in
+    #[arg(long)]
+    recordings: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let (client, config, args) = cmd::init::<AnswerBotArgs>().await?;
+
+    let ivr = Ivr::load(&args.ivr)?;
+    let recordings = args
+        .recordings
+        .or_else(AnswerBot::default_recordings)
+        .context("No directory for recordings")?;
+    let store = Store::open_configured(&config.get().call_log)?;
+    let bot = Arc::new(AnswerBot::new(ivr, config.get().media.clone(), store, recordings)?);
+
+    let (mut socket, mut requests) = client.socket().await?;
+    info!("Answering calls");
+
+    let mut registration = registration_refresh(&socket, Ok(()));
+
+    loop {
+        let mut tx = select! {
+            tx = requests.recv() => match tx {
+                Some(tx) => tx,
+                None => bail!("Client closed connection"),
+            },
+
+            _ = tokio::time::sleep_until(registration) => {
+                debug!("Refreshing registration");
+                let result = client.reregister(&mut socket).await;
+                if let Err(err) = &result {
+                    error!("Failed to refresh registration: {err:#}");
+                }
+                registration = registration_refresh(&socket, result);
+                continue;
+            }
+        };
+
+        debug!("Received {method}", method = tx.request.method);
+
+        match tx.request.method {
+            Method::Options | Method::Cancel => {
+                tx.respond(StatusCode::Accepted).send([]).await;
+            }
+
+            Method::Invite => {
+                let key = match DialogKey::from_request(&tx.request) {
+                    Ok(key) => key,
+                    Err(err) => {
+                        tx.reject_malformed(err).await;
+                        continue;
+                    }
+                };
+
+                // Changes to answered calls, e.g. holding them, are not supported
+                let in_dialog = tx.request.to_header().and_then(|to| to.tag()).is_ok_and(|tag| tag.is_some());
+                if in_dialog {
+                    tx.respond(StatusCode::NotAcceptableHere).send([]).await;
+                    continue;
+                }
+
+                bot.answer(key, tx);
+            }
+
+            Method::Bye => {
+                if let Ok(key) = DialogKey::from_request(&tx.request) {
+                    bot.hung_up(&key);
+                }
+                tx.respond(StatusCode::OK).send([]).await;
+            }
+
+            _ => {}
+        }
+    }
+}
+
+/// Time to refresh the registration at, well before it expires or after a delay on failures
+fn registration_refresh(socket: &Connection, result: Result<()>) -> tokio::time::Instant {
+    let delay = match result {
+        Ok(()) => socket
+            .timers()
+            .refresh_after(socket.registered_for().unwrap_or(REGISTRATION_INTERVAL)),
+        Err(err) => match RetryAfter::of(&err) {
+            Some(retry_after) => retry_after.jittered(),
+            None => RetryAfter(REGISTRATION_RETRY).jittered(),
+        },
+    };
+
+    tokio::time::Instant::now() + delay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnswerBotArgs;
+
+    #[test]
+    fn command_line() {
+        ucware_cli::cmd::debug_assert::<AnswerBotArgs>();
+    }
+}
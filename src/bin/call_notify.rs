@@ -1,57 +1,607 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use clap::Args;
 use dashmap::DashMap;
-use notify_rust::{Hint, Notification, Timeout};
-use rsip::headers::ToTypedHeader;
-use rsip::message::HeadersExt;
+use notify_rust::{Notification, NotificationHandle, Timeout};
+use rsip::headers::{Contact, UntypedHeader};
 use rsip::{Method, StatusCode};
-use ucware_cli::cmd;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
+use ucware_cli::callstate::policy::{self, Screening};
+use ucware_cli::callstate::script::{self, Script};
+use ucware_cli::callstate::{Call, DialogKey, Event, Incoming, Origin, DEFAULT_ACCOUNT};
+use ucware_cli::daemon::{Daemon, DaemonArgs, Supervisor};
+use ucware_cli::sipsocket::headers::{self, Redirection};
+use ucware_cli::sipsocket::message_summary::{self, MessageSummary};
+use ucware_cli::sipsocket::{Connection, ConnectionEvent, RetryAfter, ServerTransaction};
+use ucware_cli::ucware::Client;
+use ucware_cli::store::Store;
+use ucware_cli::filter::Filters;
+use ucware_cli::config::{AccountConfig, FocusAssist};
+use ucware_cli::notification::{self, push, Presentation};
+use ucware_cli::cmd::Connector;
+use ucware_cli::{busy, cmd, ctl, focus, forward, http};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let (client, _args) = cmd::init::<()>().await?;
+/// Lifetime of the voicemail subscription
+const VOICEMAIL_SUBSCRIPTION: Duration = Duration::from_secs(3600);
 
-    let (_socket, mut requests) = client.socket().await?;
+/// Interval to renew the voicemail subscription in
+const VOICEMAIL_SUBSCRIPTION_REFRESH: Duration = Duration::from_secs(3000);
+
+/// Registration interval assumed if the registrar did not tell
+const REGISTRATION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Delay before registering again after a failure the server gave no delay for
+const REGISTRATION_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Args, Debug)]
+struct NotifyArgs {
+    #[command(flatten)]
+    daemon: DaemonArgs,
+
+    /// Path of the control socket
+    #[arg(long)]
+    ctl_socket: Option<PathBuf>,
+
+    /// Print one JSON line per call state transition to stdout
+    #[arg(long)]
+    stdout_json: bool,
+
+    /// Serve the state over HTTP on this address, e.g. `127.0.0.1:8421`
+    #[arg(long)]
+    http_listen: Option<SocketAddr>,
+
+    /// Serve the gRPC control API on this address, e.g. `127.0.0.1:8422`
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    grpc_listen: Option<SocketAddr>,
+
+    /// Show a tray icon
+    #[cfg(feature = "tray")]
+    #[arg(long)]
+    tray: bool,
+}
+
+fn main() -> Result<()> {
+    let cmd = cmd::parse::<NotifyArgs>();
+
+    // Must happen before the runtime spawns any threads
+    let _pidfile = cmd.args().daemon.setup("call-notify")?;
+
+    #[cfg(windows)]
+    if cmd.args().daemon.service {
+        return ucware_cli::service::run("ucware-call-notify", move |shutdown| {
+            tokio::runtime::Runtime::new()?.block_on(async move {
+                tokio::select! {
+                    result = run(cmd) => result,
+                    _ = shutdown => Ok(()),
+                }
+            })
+        });
+    }
+
+    tokio::runtime::Runtime::new()?.block_on(run(cmd))
+}
+
+async fn run(cmd: cmd::Cmd<NotifyArgs>) -> Result<()> {
+    let (connector, config, args) = cmd.setup().await?;
+    let client = connector.clone().connect().await?;
+
+    // Subscribe before connecting to see the initial registration
+    let connection_events = client.connection_events();
+
+    let (socket, requests) = client.socket().await?;
+
+    let store = Store::open_configured(&config.get().call_log)?;
+
+    let hooks = match Script::default_path() {
+        Some(path) => Some(script::watch(path).await?),
+        None => None,
+    };
+    let daemon = Arc::new(Daemon::new(client, config, store));
+    daemon.install_panic_hook();
+
+    // Requests of all accounts, labelled with the account they arrived on
+    let (merged, mut requests_rx) = mpsc::channel(1);
+    let mut accounts = JoinSet::new();
+    let mut supervisors = HashMap::new();
+
+    accounts.spawn({
+        let origin = origin(DEFAULT_ACCOUNT, &daemon.client).await;
+        let served = serve_account(origin, daemon.client.clone(), socket, requests, connection_events, merged.clone(), daemon.clone());
+        async move { (Account::Default, served.await) }
+    });
+
+    // Further accounts connect on their own, one failing must not keep the others from serving
+    for account in daemon.config.get().accounts.clone() {
+        daemon.calls.set_registered(&account.name, false);
+        accounts.spawn(run_account(Account::Configured(account), Duration::ZERO, connector.clone(), daemon.clone(), merged.clone()));
+    }
+
+    if args.stdout_json {
+        tokio::spawn(print_events(daemon.calls.events()));
+    }
+
+    tokio::spawn({
+        let daemon = daemon.clone();
+        async move { daemon.maintain().await }
+    });
+
+    tokio::spawn({
+        let daemon = daemon.clone();
+        async move { daemon.send_emails().await }
+    });
+
+    tokio::spawn({
+        let daemon = daemon.clone();
+        async move { daemon.track_missed().await }
+    });
+
+    tokio::spawn({
+        let daemon = daemon.clone();
+        async move { daemon.post_webhooks().await }
+    });
+
+    tokio::spawn({
+        let daemon = daemon.clone();
+        async move {
+            match daemon.sync_missed().await {
+                Ok(missed) if !missed.is_empty() && daemon.config.get().notification.desktop => {
+                    let body = missed
+                        .iter()
+                        .map(|call| call.caller.display())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    let notification = Notification::new()
+                        .summary(&format!("{count} calls missed while offline", count = missed.len()))
+                        .body(&body)
+                        .icon("phone")
+                        .show_async()
+                        .await;
+                    if let Err(err) = notification {
+                        warn!("Failed to show missed calls: {err:#}");
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => warn!("Failed to sync missed calls: {err:#}"),
+            }
+
+            daemon.track_checkpoint().await;
+        }
+    });
+
+    if let Some(path) = args.ctl_socket.or_else(ctl::default_path) {
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            if let Err(err) = ctl::serve(path, daemon).await {
+                error!("Control socket failed: {err:#}");
+            }
+        });
+    }
+
+    if let Some(addr) = args.http_listen {
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            if let Err(err) = http::serve(addr, daemon).await {
+                error!("HTTP server failed: {err:#}");
+            }
+        });
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc_listen {
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            if let Err(err) = ucware_cli::grpc::serve(addr, daemon).await {
+                error!("gRPC server failed: {err:#}");
+            }
+        });
+    }
+
+    #[cfg(feature = "tray")]
+    if args.tray {
+        let daemon = daemon.clone();
+        tokio::spawn(async move {
+            if let Err(err) = ucware_cli::tray::run(daemon).await {
+                error!("Tray icon failed: {err:#}");
+            }
+        });
+    }
+
+    if let Err(err) = notification::close_stale(&daemon.store).await {
+        warn!("Failed to close stale notifications: {err:#}");
+    }
 
     let notifications = DashMap::new();
+    let mut events = daemon.calls.events();
+    let mut filters = Filters::default();
 
     loop {
-        let Some(mut tx) = requests.recv().await else {
-            bail!("Client closed connection");
+        let (origin, mut tx) = select! {
+            Some(request) = requests_rx.recv() => request,
+
+            Some(result) = accounts.join_next() => {
+                // Accounts serve until their connection fails
+                // Panics never get here as the panic hook exits the process
+                let (account, err) = match result {
+                    Ok((_, Ok(()))) => return Ok(()),
+                    Ok((account, Err(err))) => (account, err),
+                    Err(err) => return Err(err.into()),
+                };
+
+                let name = account.name().to_string();
+                daemon.calls.set_registered(&name, false);
+                drop_ringing(&daemon, &name);
+
+                let supervisor = supervisors
+                    .entry(name.clone())
+                    .or_insert_with(|| Supervisor::new(&daemon.config.get().supervisor));
+                match supervisor.restart() {
+                    Some(delay) => {
+                        error!("Account {name} failed, connecting again in {delay:.1?}: {err:#}");
+                        accounts.spawn(run_account(account, delay, connector.clone(), daemon.clone(), merged.clone()));
+                    }
+                    None if matches!(account, Account::Default) => {
+                        return Err(err.context("Restarted too often, giving up"));
+                    }
+                    None => error!("Account {name} failed too often, giving up on it: {err:#}"),
+                }
+                continue;
+            }
+
+            event = events.recv() => {
+                // Calls declined through the daemon
+                if let Ok(Event::Screened { call }) = event
+                    && let Some((_, notification)) = notifications.remove(&call.key)
+                {
+                    close_notification(&daemon.store, notification);
+                }
+                continue;
+            }
         };
 
+        debug!("Received {method} on account {account}", method = tx.request.method, account = origin.account);
+
         match tx.request.method {
             Method::Options => {
                 tx.respond(StatusCode::Accepted).send([]).await;
             }
 
             Method::Invite => {
-                let from = tx.request.from_header().expect("valid from header");
-                let cseq = tx.request.cseq_header().expect("valid cseq header");
+                let config = daemon.config.get();
+
+                let incoming = daemon.calls.incoming(&tx.request, &config, &origin);
+                let incoming = match incoming {
+                    Ok(incoming) => incoming,
+                    Err(err) => {
+                        tx.reject_malformed(err).await;
+                        continue;
+                    }
+                };
+
+                let (call, waiting) = match incoming {
+                    Incoming::New(call) => (call, false),
+
+                    Incoming::Waiting { call, active } => {
+                        info!("Call {:?} waiting while {} call(s) in progress", call.key, active.len());
+                        (call, true)
+                    }
+
+                    Incoming::Replaces { call, replaced } => {
+                        info!("Call {:?} replaces {:?}", call.key, replaced.key);
+                        daemon.invites.remove(&replaced.key);
+                        if let Some((_, notification)) = notifications.remove(&replaced.key) {
+                            close_notification(&daemon.store, notification);
+                        }
+                        (call, false)
+                    }
+
+                    Incoming::Join { call, joined } => {
+                        info!("Call {:?} joins {:?}", call.key, joined.key);
+                        (call, false)
+                    }
+
+                    Incoming::Busy(call) => {
+                        info!("Rejecting call from {caller} as busy", caller = call.caller.display());
+                        tx.respond(StatusCode::BusyHere).send([]).await;
+                        continue;
+                    }
+
+                    Incoming::UnknownDialog(dialog) => {
+                        warn!("INVITE references unknown dialog: {dialog}");
+                        tx.respond(StatusCode::CallTransactionDoesNotExist).send([]).await;
+                        continue;
+                    }
+                };
 
-                let from = from.typed().expect("valid from header");
-                let cseq = cseq.typed().expect("valid cseq header");
+                let blocklist = daemon.store.blocklist().unwrap_or_else(|err| {
+                    warn!("Failed to load blocklist: {err:#}");
+                    Vec::new()
+                });
+
+                let dnd = daemon.calls.dnd(&config);
+                let now = Local::now().naive_local();
+                let hooked = || hooks.as_ref()?.on_incoming(&call).map(|action| action.screening(waiting));
+                let screening = policy::blocked(&call, &config, &blocklist)
+                    .or_else(hooked)
+                    .unwrap_or_else(|| policy::screen(&call, &config, &blocklist, now, dnd, waiting));
+                let (urgent, waiting) = match screening {
+                    Screening::Notify { urgent, waiting } => (urgent, waiting),
+
+                    Screening::Silent => {
+                        tx.respond(StatusCode::Trying).send([]).await;
+                        tx.respond(StatusCode::Ringing).send([]).await;
+                        continue;
+                    }
+
+                    Screening::Reject(status) => {
+                        info!("Rejecting call from {caller}", caller = call.caller.display());
+                        daemon.calls.screened(&call.key);
+                        tx.respond(status).send([]).await;
+                        continue;
+                    }
+
+                    Screening::Blocked { rule, status } => {
+                        info!("Blocking call from {caller} by rule {rule}", caller = call.caller.display());
+                        if let Err(err) = daemon.store.log_blocked(&call, &rule) {
+                            warn!("Failed to log blocked call: {err:#}");
+                        }
+                        daemon.calls.screened(&call.key);
+                        tx.respond(status).send([]).await;
+                        continue;
+                    }
+
+                    Screening::OutOfOffice(closed) if closed.answers() => {
+                        info!("Announcing office closed to {caller}", caller = call.caller.display());
+                        daemon.calls.screened(&call.key);
+                        daemon.announce_closed(call, tx, closed);
+                        continue;
+                    }
+
+                    Screening::OutOfOffice(closed) => {
+                        info!("Redirecting call from {caller} while closed", caller = call.caller.display());
+                        daemon.calls.screened(&call.key);
+                        let Some(uri) = closed.redirect else {
+                            tx.respond(StatusCode::TemporarilyUnavailable).send([]).await;
+                            continue;
+                        };
+                        tx.respond(StatusCode::MovedTemporarily)
+                            .header(Contact::new(format!("<{uri}>")))
+                            .send([])
+                            .await;
+                        continue;
+                    }
+
+                    Screening::Forward(target) => {
+                        info!("Forwarding call from {caller} to {target}", caller = call.caller.display());
+                        daemon.calls.screened(&call.key);
+                        // Bare numbers are dialled through the same server
+                        let uri = if target.contains(':') {
+                            target
+                        } else {
+                            format!("sip:{target}@{host}", host = tx.request.uri.host_with_port)
+                        };
+                        tx.respond(StatusCode::MovedTemporarily)
+                            .header(Contact::new(format!("<{uri}>")))
+                            .send([])
+                            .await;
+                        continue;
+                    }
+
+                    Screening::Voicemail(uri) => {
+                        info!("Sending call from {caller} to voicemail", caller = call.caller.display());
+                        daemon.calls.screened(&call.key);
+                        tx.respond(StatusCode::MovedTemporarily)
+                            .header(Contact::new(format!("<{uri}>")))
+                            .send([])
+                            .await;
+                        continue;
+                    }
+                };
 
                 tx.respond(StatusCode::Trying).send([]).await;
                 tx.respond(StatusCode::Ringing).send([]).await;
+                daemon.invites.insert(call.key.clone(), tx);
+
+                // Filters may drop notifications or change how the caller is shown
+                filters.update(&config.event_filters);
+                let call = match filters.apply(Event::Incoming { call: call.clone(), waiting }) {
+                    Some(Event::Incoming { call: filtered, .. }) => Call { key: call.key, ..filtered },
+                    Some(_) => call,
+                    None => {
+                        info!("Not notifying about call from {caller} as filtered", caller = call.caller.display());
+                        continue;
+                    }
+                };
+
+                let name = call.caller.name.as_deref();
+                let number = call.caller.number.as_deref();
+
+                let forwarded = call
+                    .forwarded
+                    .as_ref()
+                    .map(Redirection::description)
+                    .unwrap_or_default();
+
+                let vars = [
+                    ("name", name.unwrap_or("Unknown")),
+                    ("number", number.unwrap_or("Unknown")),
+                    ("uri", call.caller.uri.as_str()),
+                    ("forwarded", forwarded.as_str()),
+                    ("queue", call.ucware.queue().unwrap_or_default()),
+                    ("account", call.origin.account.as_str()),
+                    ("slot", call.origin.slot.as_deref().unwrap_or_default()),
+                ];
+
+                let message = push::Message {
+                    title: config.notification.summary.render(&vars),
+                    body: config.notification.body.render(&vars),
+                    urgent,
+                };
+
+                for service in config.notification.push.clone() {
+                    let message = message.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = push::send(&service, &message).await {
+                            warn!("Failed to push call: {err:#}");
+                        }
+                    });
+                }
+
+                // Someone on a call is at the desk anyway
+                if !waiting && config.forward.active(now) {
+                    let forward = config.forward.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = forward::forward(&forward, &message).await {
+                            warn!("Failed to forward call: {err:#}");
+                        }
+                    });
+                }
+
+                if !config.notification.desktop {
+                    continue;
+                }
+
+                if let Some(reason) = busy::other_softphone(&config.busy).await {
+                    info!("Not notifying about call from {caller} as {reason}", caller = call.caller.display());
+                    continue;
+                }
 
-                let notification = Notification::new()
-                    .summary("Incoming Call")
-                    .body(&format!("{}", from.display_name.as_ref().map(String::as_str).unwrap_or("Unknown")))
-                    .icon("phone")
-                    .hint(Hint::Resident(true))
-                    .timeout(Timeout::Never)
-                    .show_async().await?;
-                notifications.insert(cseq.seq, notification);
+                let focus_assist = config.notification.focus_assist;
+                let quiet = !urgent
+                    && focus_assist != FocusAssist::Ignore
+                    && focus::do_not_disturb().await;
+                if quiet && focus_assist == FocusAssist::Suppress {
+                    info!("Not notifying about call from {caller} while not to be disturbed", caller = call.caller.display());
+                    continue;
+                }
+
+                let presentation = if waiting {
+                    Presentation::Waiting
+                } else if quiet {
+                    Presentation::Quiet
+                } else {
+                    Presentation::Ringing
+                };
+
+                let notification = notification::incoming(&config.notification, &vars, presentation, urgent);
+                // The call keeps ringing, just without a notification
+                let notification = match notification.show_async().await {
+                    Ok(notification) => notification,
+                    Err(err) => {
+                        warn!("Failed to show incoming call: {err:#}");
+                        continue;
+                    }
+                };
+                if let Err(err) = daemon.store.add_notification(notification.id(), &call.key.call_id) {
+                    warn!("Failed to remember notification: {err:#}");
+                }
+                notifications.insert(call.key, notification);
             }
 
             Method::Cancel => {
-                let cseq = tx.request.cseq_header().expect("valid cseq header");
-                let cseq = cseq.typed().expect("valid cseq header");
+                let call = match daemon.calls.cancelled(&tx.request) {
+                    Ok(call) => call,
+                    Err(err) => {
+                        tx.reject_malformed(err).await;
+                        continue;
+                    }
+                };
 
                 tx.respond(StatusCode::Accepted).send([]).await;
 
-                if let Some((_, notification)) = notifications.remove(&cseq.seq) {
-                    notification.close();
+                let Some(call) = call else {
+                    continue;
+                };
+
+                daemon.invites.remove(&call.key);
+
+                if let Some((_, notification)) = notifications.remove(&call.key) {
+                    close_notification(&daemon.store, notification);
+
+                    if let Some(reason) = call.reason {
+                        info!("Call cancelled: {reason}", reason = reason.description());
+                        let notification = Notification::new()
+                            .summary("Call cancelled")
+                            .body(&reason.description())
+                            .icon("phone")
+                            .timeout(Timeout::Milliseconds(5000))
+                            .show_async()
+                            .await;
+                        if let Err(err) = notification {
+                            warn!("Failed to show cancelled call: {err:#}");
+                        }
+                    }
+                }
+            }
+
+            Method::Notify => {
+                if headers::event(&tx.request.headers) != Some(message_summary::EVENT) {
+                    tx.respond(StatusCode::BadEvent).send([]).await;
+                    continue;
+                }
+
+                tx.respond(StatusCode::OK).send([]).await;
+
+                let body = String::from_utf8_lossy(&tx.request.body);
+                let Some(summary) = MessageSummary::parse(&body) else {
+                    warn!("Invalid message summary: {body}");
+                    continue;
+                };
+
+                let previous = daemon.calls.set_voicemail(&origin.account, summary);
+                if summary.new > previous.new && daemon.config.get().notification.desktop {
+                    let notification = Notification::new()
+                        .summary("Voicemail")
+                        .body(&match summary.new {
+                            1 => "1 new voicemail".to_string(),
+                            new => format!("{new} new voicemails"),
+                        })
+                        .icon("mail-message-new")
+                        .show_async()
+                        .await;
+                    if let Err(err) = notification {
+                        warn!("Failed to show voicemail: {err:#}");
+                    }
+                }
+            }
+
+            Method::Bye => {
+                if let Ok(key) = DialogKey::from_request(&tx.request)
+                    && daemon.hung_up(&key)
+                {
+                    tx.respond(StatusCode::OK).send([]).await;
+                    continue;
+                }
+
+                let call = match daemon.calls.ended(&tx.request) {
+                    Ok(call) => call,
+                    Err(err) => {
+                        tx.reject_malformed(err).await;
+                        continue;
+                    }
+                };
+
+                tx.respond(StatusCode::OK).send([]).await;
+
+                if let Some(call) = call {
+                    daemon.invites.remove(&call.key);
+
+                    if let Some(reason) = &call.reason {
+                        info!("Call ended: {reason}", reason = reason.description());
+                    }
+
+                    if let Err(err) = daemon.wrap_up().await {
+                        warn!("Failed to start wrap-up: {err:#}");
+                    }
                 }
             }
 
@@ -59,3 +609,215 @@ async fn main() -> Result<()> {
         }
     }
 }
+
+/// An account served by the notifier
+enum Account {
+    /// The account given on the command line
+    Default,
+    Configured(AccountConfig),
+}
+
+impl Account {
+    fn name(&self) -> &str {
+        match self {
+            Account::Default => DEFAULT_ACCOUNT,
+            Account::Configured(account) => &account.name,
+        }
+    }
+}
+
+/// Connects an account after the delay and serves it until its connection fails
+async fn run_account(
+    account: Account,
+    delay: Duration,
+    connector: Connector,
+    daemon: Arc<Daemon>,
+    merged: mpsc::Sender<(Origin, ServerTransaction)>,
+) -> (Account, Result<()>) {
+    tokio::time::sleep(delay).await;
+
+    let result = connect_account(&account, connector, daemon, merged).await;
+    (account, result)
+}
+
+async fn connect_account(
+    account: &Account,
+    connector: Connector,
+    daemon: Arc<Daemon>,
+    merged: mpsc::Sender<(Origin, ServerTransaction)>,
+) -> Result<()> {
+    let name = account.name();
+    let client = match account {
+        Account::Default => daemon.client.clone(),
+        Account::Configured(config) => connector.account(config)?.connect().await?,
+    };
+
+    // Subscribe before connecting to see the initial registration
+    let events = client.connection_events();
+
+    let (socket, requests) = client
+        .socket()
+        .await
+        .with_context(|| format!("Failed to connect account {name}"))?;
+    info!("Connected account {name}");
+
+    if let Account::Configured(_) = account {
+        daemon.accounts.insert(name.to_string(), client.clone());
+    }
+
+    let origin = origin(name, &client).await;
+    serve_account(origin, client, socket, requests, events, merged, daemon).await
+}
+
+/// Forgets the ringing calls of an account whose socket failed, they can neither be answered nor declined anymore
+fn drop_ringing(daemon: &Daemon, account: &str) {
+    for call in daemon.calls.calls() {
+        if call.origin.account == account && daemon.invites.remove(&call.key).is_some() {
+            daemon.calls.screened(&call.key);
+        }
+    }
+}
+
+/// The origin of calls arriving on the socket of the given account's client
+async fn origin(account: &str, client: &Client) -> Origin {
+    let slot = match client.webrtc_slot().await {
+        Ok(slot) => Some(slot.name),
+        Err(err) => {
+            warn!("Failed to look up slot of account {account}: {err:#}");
+            None
+        }
+    };
+
+    Origin {
+        account: account.to_string(),
+        slot,
+    }
+}
+
+/// Keeps the socket of an account registered and subscribed to voicemail,
+/// handing its requests to the notifier labelled with their origin and
+/// mirroring its registration state into the call state
+async fn serve_account(
+    origin: Origin,
+    client: Client,
+    mut socket: Connection,
+    mut requests: mpsc::Receiver<ServerTransaction>,
+    mut events: broadcast::Receiver<ConnectionEvent>,
+    merged: mpsc::Sender<(Origin, ServerTransaction)>,
+    daemon: Arc<Daemon>,
+) -> Result<()> {
+    let account = origin.account.clone();
+
+    // Refreshed well before it expires, the first tick subscribes right away
+    let mut voicemail = tokio::time::interval(VOICEMAIL_SUBSCRIPTION_REFRESH);
+
+    let mut registration = registration_refresh(&socket, Ok(()));
+
+    loop {
+        select! {
+            tx = requests.recv() => match tx {
+                Some(tx) => {
+                    if merged.send((origin.clone(), tx)).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    // Old sockets would otherwise stay open until the account connects again
+                    socket.close().await;
+                    bail!("Client of account {account} closed connection");
+                }
+            },
+
+            Ok(event) = events.recv() => match event {
+                ConnectionEvent::Registered { .. } => daemon.calls.set_registered(&account, true),
+                ConnectionEvent::RegistrationLost { .. } | ConnectionEvent::Closed { .. } => {
+                    daemon.calls.set_registered(&account, false)
+                }
+                ConnectionEvent::Connected { .. } | ConnectionEvent::Redirected { .. } => {}
+            },
+
+            _ = daemon.reregister.notified() => {
+                info!("Registering account {account} again");
+                let result = client.reregister(&mut socket).await;
+                if let Err(err) = &result {
+                    error!("Failed to register account {account}: {err:#}");
+                }
+                registration = registration_refresh(&socket, result);
+            }
+
+            _ = tokio::time::sleep_until(registration) => {
+                debug!("Refreshing registration of account {account}");
+                let result = client.reregister(&mut socket).await;
+                if let Err(err) = &result {
+                    error!("Failed to refresh registration of account {account}: {err:#}");
+                }
+                registration = registration_refresh(&socket, result);
+            }
+
+            _ = voicemail.tick() => {
+                let subscription = socket.subscribe(
+                    message_summary::EVENT,
+                    message_summary::CONTENT_TYPE,
+                    VOICEMAIL_SUBSCRIPTION.as_secs() as u32,
+                );
+                if let Err(err) = subscription.await {
+                    warn!("Failed to subscribe account {account} to voicemail: {err:#}");
+                }
+            }
+        }
+    }
+}
+
+/// Closes the notification of a call which is no longer ringing
+fn close_notification(store: &Store, notification: NotificationHandle) {
+    if let Err(err) = store.remove_notification(notification.id()) {
+        warn!("Failed to forget notification: {err:#}");
+    }
+    notification.close();
+}
+
+/// Time to refresh the registration at.
+///
+/// Well before the granted interval runs out after registering, when the
+/// server asks for it after failures, or soon otherwise.
+fn registration_refresh(socket: &Connection, result: Result<()>) -> tokio::time::Instant {
+    let delay = match result {
+        Ok(()) => socket
+            .timers()
+            .refresh_after(socket.registered_for().unwrap_or(REGISTRATION_INTERVAL)),
+        Err(err) => match RetryAfter::of(&err) {
+            Some(retry_after) => retry_after.jittered(),
+            None => RetryAfter(REGISTRATION_RETRY).jittered(),
+        },
+    };
+
+    tokio::time::Instant::now() + delay
+}
+
+/// Writes call state transitions as JSON lines to stdout
+async fn print_events(mut events: broadcast::Receiver<Event>) -> Result<()> {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Skipped {skipped} events");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{event}", event = serde_json::to_string(&event)?)?;
+        stdout.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NotifyArgs;
+
+    #[test]
+    fn command_line() {
+        ucware_cli::cmd::debug_assert::<NotifyArgs>();
+    }
+}
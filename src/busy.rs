@@ -0,0 +1,71 @@
+//! Detection of other local softphones handling calls already
+
+use crate::config::BusyConfig;
+use tokio::process::Command;
+use tracing::debug;
+
+/// Media role of audio streams carrying calls
+const PHONE_ROLE: &str = "media.role = \"phone\"";
+
+/// Why notifications are left to another softphone, `None` if nothing else handles calls
+pub async fn other_softphone(config: &BusyConfig) -> Option<String> {
+    if let Some(process) = running_process(&config.processes).await {
+        return Some(format!("{process} is running"));
+    }
+
+    if config.phone_streams && phone_stream().await {
+        return Some("an audio stream with the phone role is playing".to_string());
+    }
+
+    None
+}
+
+/// Name of the first of the given processes which is running
+#[cfg(target_os = "linux")]
+async fn running_process(names: &[String]) -> Option<String> {
+    if names.is_empty() {
+        return None;
+    }
+
+    let mut entries = tokio::fs::read_dir("/proc").await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(comm) = tokio::fs::read_to_string(entry.path().join("comm")).await else {
+            continue;
+        };
+
+        let comm = comm.trim();
+        if let Some(name) = names.iter().find(|name| *name == comm) {
+            return Some(name.clone());
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn running_process(names: &[String]) -> Option<String> {
+    if !names.is_empty() {
+        debug!("Process detection is only available on Linux");
+    }
+    None
+}
+
+/// Whether the sound server plays a stream with the phone role which is not corked
+async fn phone_stream() -> bool {
+    let output = match Command::new("pactl").args(["list", "sink-inputs"]).output().await {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            debug!("pactl failed: {status}", status = output.status);
+            return false;
+        }
+        Err(err) => {
+            debug!("Failed to run pactl: {err}");
+            return false;
+        }
+    };
+
+    // Streams are separated by blank lines
+    String::from_utf8_lossy(&output).split("\n\n").any(|stream| {
+        stream.contains(PHONE_ROLE) && !stream.lines().any(|line| line.trim() == "Corked: yes")
+    })
+}
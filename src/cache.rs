@@ -0,0 +1,71 @@
+use crate::store::{CacheEntry, Store};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+/// Cache for read-mostly API responses.
+///
+/// Entries are kept in the store along with their key, lookups never fail
+/// but treat broken entries as missing.
+pub struct Cache {
+    store: Store,
+}
+
+impl Cache {
+    pub fn new(store: Store) -> Self {
+        Self { store }
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(Store::open_default()?))
+    }
+
+    /// Returns the cached value if it is younger than `ttl`
+    pub fn get<T: DeserializeOwned>(&self, key: &str, ttl: Duration) -> Option<T> {
+        let entry = self.store.cache_entry(key).ok()??;
+
+        let age = entry.stored.elapsed().unwrap_or(Duration::MAX);
+        if age > ttl {
+            debug!("Cache entry expired: {key}");
+            return None;
+        }
+
+        serde_json::from_value(entry.value).ok()
+    }
+
+    pub fn put<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        self.store.put_cache_entry(&CacheEntry {
+            key: key.to_string(),
+            stored: SystemTime::now(),
+            value: serde_json::to_value(value)?,
+        })
+    }
+
+    /// Returns all values with keys ending in the given suffix, regardless of age.
+    ///
+    /// Used where stale data is better than a server round-trip, e.g. for
+    /// shell completion.
+    pub fn find<T: DeserializeOwned>(&self, suffix: &str) -> Vec<T> {
+        let Ok(entries) = self.store.cache_entries() else {
+            return Vec::new();
+        };
+
+        entries
+            .into_iter()
+            .filter(|entry| entry.key.ends_with(suffix))
+            .filter_map(|entry| serde_json::from_value(entry.value).ok())
+            .collect()
+    }
+
+    /// Drops all entries with keys starting with the given prefix
+    pub fn invalidate(&self, prefix: &str) -> Result<()> {
+        debug!("Invalidating cache entries: {prefix}*");
+        self.store.invalidate_cache(prefix)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.invalidate("")
+    }
+}
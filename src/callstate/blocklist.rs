@@ -0,0 +1,88 @@
+use super::Call;
+use crate::config::Pattern;
+use anyhow::{Context, Result};
+use ipnet::IpNet;
+use regex::Regex;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A rule of the call blocklist
+#[derive(Debug, Clone)]
+pub enum BlockRule {
+    /// Callers withholding their identity
+    Anonymous,
+
+    /// Calls passing a network, e.g. that of an IP based trunk
+    Network(IpNet),
+
+    /// Regular expression matched against the caller's number and name
+    Regex(Regex),
+
+    /// Glob pattern matched against the caller's number and name
+    Caller(Pattern),
+}
+
+impl BlockRule {
+    pub fn matches(&self, call: &Call) -> bool {
+        let caller = &call.caller;
+        match self {
+            Self::Anonymous => caller.anonymous,
+            Self::Network(network) => call.sources.iter().any(|addr| network.contains(addr)),
+            Self::Regex(regex) => [&caller.name, &caller.number]
+                .into_iter()
+                .flatten()
+                .any(|value| regex.is_match(value)),
+            Self::Caller(pattern) => {
+                pattern.matches_caller(caller.name.as_deref(), caller.number.as_deref())
+            }
+        }
+    }
+}
+
+/// Parses `anonymous`, an address or network in CIDR notation, a regular
+/// expression enclosed in slashes or a glob pattern
+impl FromStr for BlockRule {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim();
+
+        if value.eq_ignore_ascii_case("anonymous") {
+            return Ok(Self::Anonymous);
+        }
+
+        if let Ok(network) = value.parse() {
+            return Ok(Self::Network(network));
+        }
+
+        if let Ok(addr) = value.parse::<IpAddr>() {
+            return Ok(Self::Network(addr.into()));
+        }
+
+        if let Some(regex) = value.strip_prefix('/').and_then(|value| value.strip_suffix('/')) {
+            let regex = Regex::new(regex).with_context(|| format!("Invalid regex: {regex}"))?;
+            return Ok(Self::Regex(regex));
+        }
+
+        Ok(Self::Caller(Pattern::try_from(value.to_string())?))
+    }
+}
+
+impl fmt::Display for BlockRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Anonymous => f.write_str("anonymous"),
+            Self::Network(network) => network.fmt(f),
+            Self::Regex(regex) => write!(f, "/{regex}/"),
+            Self::Caller(pattern) => pattern.fmt(f),
+        }
+    }
+}
+
+/// Rules are equal if they have the same textual form
+impl PartialEq for BlockRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
@@ -0,0 +1,570 @@
+use crate::config::{CallerIdConfig, Config, IdentitySource};
+use crate::media::jitter::RtpStats;
+use crate::sipsocket::headers::{self, DialogRef, NameAddr, Reason, Redirection, UcwareHeaders};
+use crate::sipsocket::message_summary::MessageSummary;
+use anyhow::{Context, Result};
+use rsip::headers::{ToTypedHeader, UntypedHeader};
+use rsip::message::HeadersExt;
+use rsip::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::sync::{broadcast, watch};
+
+pub mod blocklist;
+pub mod policy;
+pub mod script;
+
+/// Identifies a call by its dialog as seen from the caller side
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct DialogKey {
+    pub call_id: String,
+    pub from_tag: Option<String>,
+}
+
+impl DialogKey {
+    pub fn from_request(request: &Request) -> Result<Self> {
+        let call_id = request
+            .call_id_header()
+            .context("Missing Call-ID header")?
+            .value()
+            .to_string();
+
+        let from_tag = request
+            .from_header()
+            .context("Missing From header")?
+            .typed()
+            .context("Invalid From header")?
+            .tag()
+            .map(ToString::to_string);
+
+        Ok(Self { call_id, from_tag })
+    }
+
+    /// Whether a Replaces or Join header refers to this dialog.
+    ///
+    /// Calls are tracked while ringing, so there is no local tag to compare.
+    pub fn matches(&self, dialog: &DialogRef) -> bool {
+        self.call_id == dialog.call_id && self.from_tag == dialog.from_tag
+    }
+
+    /// Reference to this dialog for a Replaces or Join header sent to the remote party
+    pub fn to_ref(&self, local_tag: Option<String>) -> DialogRef {
+        DialogRef {
+            call_id: self.call_id.clone(),
+            to_tag: self.from_tag.clone(),
+            from_tag: local_tag,
+            early_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Caller {
+    pub name: Option<String>,
+    pub number: Option<String>,
+    pub uri: String,
+
+    /// The caller withholds their identity
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+impl Caller {
+    /// Determines the caller from the first preferred identity header present
+    pub fn from_request(request: &Request, config: &CallerIdConfig) -> Result<Self> {
+        let headers = &request.headers;
+
+        let identity = config
+            .prefer
+            .iter()
+            .take_while(|&&source| source != IdentitySource::From)
+            .find_map(|source| match source {
+                IdentitySource::PAssertedIdentity => NameAddr::p_asserted_identity(headers),
+                IdentitySource::RemotePartyId => NameAddr::remote_party_id(headers),
+                IdentitySource::From => None,
+            });
+
+        let identity = match identity {
+            Some(identity) => identity,
+            None => {
+                let from = request
+                    .from_header()
+                    .context("Missing From header")?
+                    .typed()
+                    .context("Invalid From header")?;
+
+                NameAddr {
+                    display_name: from.display_name,
+                    uri: from.uri.to_string(),
+                }
+            }
+        };
+
+        Ok(Self {
+            number: identity.user(),
+            anonymous: identity.is_anonymous() || headers::withheld(headers),
+            name: identity.display_name,
+            uri: identity.uri,
+        })
+    }
+
+    /// Name or number for display
+    pub fn display(&self) -> &str {
+        if self.anonymous {
+            return "Anonymous";
+        }
+
+        self.name.as_deref().or(self.number.as_deref()).unwrap_or("Unknown")
+    }
+}
+
+/// Label of the account given on the command line
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// The account and slot a call arrived on, telling sources apart when several are monitored
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Origin {
+    pub account: String,
+
+    /// Name of the slot whose socket received the call
+    pub slot: Option<String>,
+}
+
+impl Default for Origin {
+    fn default() -> Self {
+        Self {
+            account: DEFAULT_ACCOUNT.to_string(),
+            slot: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Call {
+    pub key: DialogKey,
+    pub caller: Caller,
+    pub since: SystemTime,
+
+    #[serde(default)]
+    pub origin: Origin,
+
+    /// Where the call was forwarded from, if it was
+    #[serde(default)]
+    pub forwarded: Option<Redirection>,
+
+    /// Why the call ended, as told by the Reason header of the CANCEL or BYE
+    #[serde(default)]
+    pub reason: Option<Reason>,
+
+    /// Proprietary headers of the server, e.g. the distributing queue
+    #[serde(default, skip_serializing_if = "UcwareHeaders::is_empty")]
+    pub ucware: UcwareHeaders,
+
+    /// Addresses the INVITE passed on its way, as recorded in the Via headers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<IpAddr>,
+
+    /// Quality of the audio received, for calls answered here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<RtpStats>,
+}
+
+/// Outcome of an incoming INVITE
+#[derive(Debug, Clone)]
+pub enum Incoming {
+    /// A new call
+    New(Call),
+
+    /// A new call while other calls are in progress
+    Waiting { call: Call, active: Vec<Call> },
+
+    /// A new call exceeding the limit of concurrent calls, which has not been tracked
+    Busy(Call),
+
+    /// A call taking over a tracked one, e.g. by call pickup
+    Replaces { call: Call, replaced: Call },
+
+    /// A call joining a tracked one, e.g. by barge-in
+    Join { call: Call, joined: Call },
+
+    /// The INVITE references a dialog which is not tracked
+    UnknownDialog(DialogRef),
+}
+
+/// A call state transition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    Registered { registered: bool },
+
+    /// A call started ringing
+    Incoming { call: Call, waiting: bool },
+
+    /// A ringing call was taken over by another one
+    Replaced { call: Call },
+
+    /// A call was turned down by screening or for being over the limit of concurrent calls
+    Screened { call: Call },
+
+    /// A call was cancelled before it was answered here
+    Cancelled { call: Call, missed: bool },
+
+    /// A call was ended by the remote party
+    Ended { call: Call },
+
+    /// The voicemail box changed
+    Voicemail { summary: MessageSummary },
+}
+
+/// Number of events buffered for slow subscribers
+const EVENTS: usize = 64;
+
+/// Number of past events kept for state dumps
+const RECENT_EVENTS: usize = 100;
+
+/// An event as kept for state dumps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentEvent {
+    pub time: SystemTime,
+    pub event: Event,
+}
+
+/// Queue agent state of the user
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum AgentState {
+    #[default]
+    Available,
+    Paused {
+        reason: Option<String>,
+    },
+    WrapUp {
+        until: SystemTime,
+    },
+}
+
+/// Number of missed calls kept for display
+const RECENT_MISSED: usize = 20;
+
+#[derive(Debug, Default)]
+struct Inner {
+    registered: BTreeMap<String, bool>,
+    dnd: Option<bool>,
+    calls: HashMap<DialogKey, Call>,
+    missed: u64,
+    recent_missed: VecDeque<Call>,
+    agent: AgentState,
+    voicemail: BTreeMap<String, MessageSummary>,
+    recent_events: VecDeque<RecentEvent>,
+}
+
+impl Inner {
+    /// Registered once all known accounts are
+    fn registered(&self) -> bool {
+        !self.registered.is_empty() && self.registered.values().all(|registered| *registered)
+    }
+}
+
+/// Tracks the state of calls on a registered slot
+#[derive(Debug)]
+pub struct CallState {
+    inner: Mutex<Inner>,
+    changes: watch::Sender<()>,
+    events: broadcast::Sender<Event>,
+}
+
+impl Default for CallState {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::default(),
+            changes: watch::Sender::default(),
+            events: broadcast::Sender::new(EVENTS),
+        }
+    }
+}
+
+impl CallState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notifies whenever the state changes
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    /// Receives every call state transition
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: Event) {
+        {
+            let mut inner = self.inner.lock().expect("not poisoned");
+            if inner.recent_events.len() == RECENT_EVENTS {
+                inner.recent_events.pop_front();
+            }
+            inner.recent_events.push_back(RecentEvent {
+                time: SystemTime::now(),
+                event: event.clone(),
+            });
+        }
+
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut Inner) -> R) -> R {
+        let result = f(&mut self.inner.lock().expect("not poisoned"));
+        self.changes.send_replace(());
+        result
+    }
+
+    /// Records whether an account is registered, emitting whether all accounts are
+    pub fn set_registered(&self, account: &str, registered: bool) {
+        let registered = self.update(|inner| {
+            inner.registered.insert(account.to_string(), registered);
+            inner.registered()
+        });
+        self.emit(Event::Registered { registered });
+    }
+
+    /// Whether all accounts are registered
+    pub fn registered(&self) -> bool {
+        self.inner.lock().expect("not poisoned").registered()
+    }
+
+    /// Registration state by account
+    pub fn registrations(&self) -> BTreeMap<String, bool> {
+        self.inner.lock().expect("not poisoned").registered.clone()
+    }
+
+    /// The last events, oldest first
+    pub fn recent_events(&self) -> Vec<RecentEvent> {
+        self.inner.lock().expect("not poisoned").recent_events.iter().cloned().collect()
+    }
+
+    /// Overrides the do-not-disturb policy from the config
+    pub fn set_dnd(&self, dnd: bool) {
+        self.update(|inner| inner.dnd = Some(dnd));
+    }
+
+    pub fn dnd(&self, config: &Config) -> bool {
+        self.inner
+            .lock()
+            .expect("not poisoned")
+            .dnd
+            .unwrap_or(config.policy.dnd)
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.inner
+            .lock()
+            .expect("not poisoned")
+            .calls
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    pub fn missed(&self) -> u64 {
+        self.inner.lock().expect("not poisoned").missed
+    }
+
+    /// The most recent missed calls, latest first
+    pub fn recent_missed(&self) -> Vec<Call> {
+        self.inner
+            .lock()
+            .expect("not poisoned")
+            .recent_missed
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear_missed(&self) {
+        self.update(|inner| {
+            inner.missed = 0;
+            inner.recent_missed.clear();
+        });
+    }
+
+    /// Updates the voicemail box of an account, returning its previous state and emitting all boxes added up
+    pub fn set_voicemail(&self, account: &str, summary: MessageSummary) -> MessageSummary {
+        let (previous, total) = self.update(|inner| {
+            let previous = inner.voicemail.insert(account.to_string(), summary);
+            (previous.unwrap_or_default(), inner.voicemail.values().copied().sum())
+        });
+        self.emit(Event::Voicemail { summary: total });
+        previous
+    }
+
+    /// The voicemail boxes of all accounts added up
+    pub fn voicemail(&self) -> MessageSummary {
+        self.inner.lock().expect("not poisoned").voicemail.values().copied().sum()
+    }
+
+    pub fn set_agent(&self, agent: AgentState) {
+        self.update(|inner| inner.agent = agent);
+    }
+
+    pub fn agent(&self) -> AgentState {
+        self.inner.lock().expect("not poisoned").agent.clone()
+    }
+
+    /// Records a new call from an incoming INVITE
+    pub fn incoming(&self, request: &Request, config: &Config, origin: &Origin) -> Result<Incoming> {
+        let call = Call {
+            key: DialogKey::from_request(request)?,
+            caller: Caller::from_request(request, &config.caller_id)?,
+            since: SystemTime::now(),
+            origin: origin.clone(),
+            forwarded: Redirection::from_headers(&request.headers),
+            reason: None,
+            ucware: UcwareHeaders::from_headers(&request.headers),
+            sources: headers::via_addresses(&request.headers),
+            media: None,
+        };
+
+        let replaces = DialogRef::replaces(&request.headers);
+        let join = DialogRef::join(&request.headers);
+
+        let incoming = self.update(|inner| {
+            let find = |inner: &Inner, dialog: &DialogRef| {
+                inner
+                    .calls
+                    .keys()
+                    .find(|key| key.matches(dialog))
+                    .cloned()
+            };
+
+            let incoming = if let Some(dialog) = replaces {
+                match find(inner, &dialog) {
+                    Some(key) => Incoming::Replaces {
+                        call: call.clone(),
+                        replaced: inner.calls.remove(&key).expect("found"),
+                    },
+                    None => return Incoming::UnknownDialog(dialog),
+                }
+            } else if let Some(dialog) = join {
+                match find(inner, &dialog) {
+                    Some(key) => Incoming::Join {
+                        call: call.clone(),
+                        joined: inner.calls[&key].clone(),
+                    },
+                    None => return Incoming::UnknownDialog(dialog),
+                }
+            } else {
+                let active = inner
+                    .calls
+                    .values()
+                    .filter(|active| active.key != call.key)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                if config.policy.max_calls.is_some_and(|max| active.len() >= max) {
+                    return Incoming::Busy(call);
+                }
+
+                if active.is_empty() {
+                    Incoming::New(call.clone())
+                } else {
+                    Incoming::Waiting { call: call.clone(), active }
+                }
+            };
+
+            inner.calls.insert(call.key.clone(), call);
+            incoming
+        });
+
+        match &incoming {
+            Incoming::New(call) | Incoming::Join { call, .. } => {
+                self.emit(Event::Incoming { call: call.clone(), waiting: false });
+            }
+            Incoming::Waiting { call, .. } => {
+                self.emit(Event::Incoming { call: call.clone(), waiting: true });
+            }
+            Incoming::Replaces { call, replaced } => {
+                self.emit(Event::Replaced { call: replaced.clone() });
+                self.emit(Event::Incoming { call: call.clone(), waiting: false });
+            }
+            Incoming::Busy(call) => {
+                self.emit(Event::Screened { call: call.clone() });
+            }
+            Incoming::UnknownDialog(_) => {}
+        }
+
+        Ok(incoming)
+    }
+
+    /// Removes a call turned down by screening without counting it as missed
+    pub fn screened(&self, key: &DialogKey) {
+        if let Some(call) = self.update(|inner| inner.calls.remove(key)) {
+            self.emit(Event::Screened { call });
+        }
+    }
+
+    /// Reports the end of a call answered here after screening had already stopped tracking it
+    pub fn answered_ended(&self, call: Call) {
+        self.emit(Event::Ended { call });
+    }
+
+    /// Removes a call cancelled before it was answered here.
+    ///
+    /// Counts as missed unless it was answered on another device.
+    pub fn cancelled(&self, request: &Request) -> Result<Option<Call>> {
+        let key = DialogKey::from_request(request)?;
+        let reason = Reason::from_headers(&request.headers).into_iter().next();
+
+        let call = self.update(|inner| {
+            let mut call = inner.calls.remove(&key)?;
+            call.reason = reason;
+
+            let missed = !call.reason.as_ref().is_some_and(Reason::answered_elsewhere);
+            if missed {
+                inner.missed += 1;
+                inner.recent_missed.push_front(call.clone());
+                inner.recent_missed.truncate(RECENT_MISSED);
+            }
+
+            Some((call, missed))
+        });
+
+        let Some((call, missed)) = call else {
+            return Ok(None);
+        };
+
+        self.emit(Event::Cancelled { call: call.clone(), missed });
+        Ok(Some(call))
+    }
+
+    /// Records a call missed while nobody was watching, e.g. before the daemon started
+    pub fn add_missed(&self, call: Call) {
+        self.update(|inner| {
+            inner.missed += 1;
+            inner.recent_missed.push_front(call);
+            inner.recent_missed.truncate(RECENT_MISSED);
+        });
+    }
+
+    /// Removes a call ended by the remote party
+    pub fn ended(&self, request: &Request) -> Result<Option<Call>> {
+        let key = DialogKey::from_request(request)?;
+        let reason = Reason::from_headers(&request.headers).into_iter().next();
+
+        let call = self.update(|inner| {
+            let mut call = inner.calls.remove(&key)?;
+            call.reason = reason;
+            Some(call)
+        });
+
+        if let Some(call) = &call {
+            self.emit(Event::Ended { call: call.clone() });
+        }
+
+        Ok(call)
+    }
+}
@@ -0,0 +1,98 @@
+use super::blocklist::BlockRule;
+use super::Call;
+use crate::config::{CallWaiting, Closed, Config, ScreeningAction};
+use chrono::NaiveDateTime;
+use rsip::StatusCode;
+
+/// What to do with an incoming call
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screening {
+    /// Raise a notification, with critical urgency if urgent.
+    ///
+    /// Waiting calls arrive while another call is in progress.
+    Notify { urgent: bool, waiting: bool },
+
+    /// Let it ring without notification
+    Silent,
+
+    /// Reject the call with the given status
+    Reject(StatusCode),
+
+    /// Reject the call as it matches a rule of the blocklist
+    Blocked { rule: BlockRule, status: StatusCode },
+
+    /// Redirect the call to the given voicemail URI
+    Voicemail(String),
+
+    /// Redirect the call or answer it with an announcement as the office is closed
+    OutOfOffice(Closed),
+
+    /// Redirect the call to a number or SIP URI as a script decided
+    Forward(String),
+}
+
+/// Rejects the call if it matches a rule of the blocklist, which no script hook may override
+pub fn blocked(call: &Call, config: &Config, blocklist: &[BlockRule]) -> Option<Screening> {
+    let rule = blocklist.iter().find(|rule| rule.matches(call))?;
+    let status = config.screening.block_status.map_or(StatusCode::Decline, StatusCode::from);
+    Some(Screening::Blocked {
+        rule: rule.clone(),
+        status,
+    })
+}
+
+/// Decides how to handle an incoming call.
+///
+/// The blocklist is checked first, then out-of-office hours at the given
+/// local time, then screening rules, then anonymous callers, then the call
+/// waiting policy. Rejecting and redirecting takes precedence over
+/// do-not-disturb and notification filters.
+pub fn screen(
+    call: &Call,
+    config: &Config,
+    blocklist: &[BlockRule],
+    now: NaiveDateTime,
+    dnd: bool,
+    waiting: bool,
+) -> Screening {
+    if let Some(blocked) = blocked(call, config, blocklist) {
+        return blocked;
+    }
+
+    if let Some(closed) = config.out_of_office.closed(now) {
+        return Screening::OutOfOffice(closed);
+    }
+
+    let caller = &call.caller;
+    let name = caller.name.as_deref();
+    let number = caller.number.as_deref();
+
+    let screening = &config.screening;
+    let action = screening
+        .rules
+        .iter()
+        .find(|rule| rule.matches(name, number))
+        .map(|rule| rule.action)
+        .or(caller.anonymous.then_some(screening.anonymous))
+        .unwrap_or_default();
+
+    let silent = dnd || config.filters.iter().any(|filter| filter.matches(name, number));
+
+    match action {
+        ScreeningAction::Reject if caller.anonymous => {
+            Screening::Reject(StatusCode::AnonymityDisallowed)
+        }
+        ScreeningAction::Reject => Screening::Reject(StatusCode::Decline),
+        ScreeningAction::Voicemail => match &screening.voicemail {
+            Some(uri) => Screening::Voicemail(uri.clone()),
+            None => Screening::Silent,
+        },
+        ScreeningAction::Silent => Screening::Silent,
+        _ if waiting && config.policy.call_waiting == CallWaiting::Reject => {
+            Screening::Reject(StatusCode::BusyHere)
+        }
+        _ if silent => Screening::Silent,
+        ScreeningAction::Notify => Screening::Notify { urgent: false, waiting },
+        ScreeningAction::Urgent => Screening::Notify { urgent: true, waiting },
+    }
+}
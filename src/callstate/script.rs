@@ -0,0 +1,168 @@
+use super::policy::Screening;
+use super::Call;
+use crate::config::watch_file;
+use anyhow::{Context, Result};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use rsip::StatusCode;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// Operations a hook may take before it is stopped, so a runaway script does not stall calls
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Decision of the `on_incoming` hook of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptAction {
+    /// Let it ring with a notification, regardless of the static policy
+    Ring,
+
+    Reject(StatusCode),
+
+    /// Redirect the call to a number or SIP URI
+    Forward(String),
+}
+
+impl ScriptAction {
+    pub fn screening(self, waiting: bool) -> Screening {
+        match self {
+            Self::Ring => Screening::Notify { urgent: false, waiting },
+            Self::Reject(status) => Screening::Reject(status),
+            Self::Forward(target) => Screening::Forward(target),
+        }
+    }
+}
+
+/// Rejection with a final error status, anything else is an error of the script
+fn reject(code: i64) -> Result<ScriptAction, Box<EvalAltResult>> {
+    match u16::try_from(code) {
+        Ok(code @ 400..=699) => Ok(ScriptAction::Reject(StatusCode::from(code))),
+        _ => Err(format!("Cannot reject with status {code}, expected 400 to 699").into()),
+    }
+}
+
+/// Call handling hooks written in rhai.
+///
+/// A script defines `on_incoming(incoming)` taking the call as a map, as
+/// `call` is reserved in rhai. It returns `ring()`, `reject(code)`,
+/// `forward(target)` or nothing to leave the call to the static policy.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("ucware").join("hooks.rhai"))
+    }
+
+    pub fn compile(source: &str) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine
+            .register_type_with_name::<ScriptAction>("Action")
+            .register_fn("ring", || ScriptAction::Ring)
+            .register_fn("reject", reject)
+            .register_fn("forward", |target: &str| ScriptAction::Forward(target.to_string()));
+
+        let ast = engine.compile(source)?;
+        Ok(Self { engine, ast })
+    }
+
+    pub async fn load(path: &Path) -> Result<Self> {
+        let source = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::compile(&source).with_context(|| format!("Invalid script {}", path.display()))
+    }
+
+    /// Decision of the `on_incoming` hook, none if the script has no such hook or leaves the call alone
+    pub fn on_incoming(&self, call: &Call) -> Result<Option<ScriptAction>> {
+        if !self.ast.iter_functions().any(|function| function.name == "on_incoming") {
+            return Ok(None);
+        }
+
+        let call = rhai::serde::to_dynamic(call)?;
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "on_incoming", (call,))?;
+        if result.is_unit() {
+            return Ok(None);
+        }
+
+        let action = result
+            .try_cast::<ScriptAction>()
+            .context("on_incoming returned neither an action nor nothing")?;
+        Ok(Some(action))
+    }
+}
+
+/// The current script, if any, reloaded when its file changes
+#[derive(Clone)]
+pub struct ScriptHandle {
+    script: watch::Sender<Option<Arc<Script>>>,
+}
+
+impl ScriptHandle {
+    pub fn get(&self) -> Option<Arc<Script>> {
+        self.script.borrow().clone()
+    }
+
+    /// Decision of the `on_incoming` hook, failures are reported and leave the call to the static policy
+    pub fn on_incoming(&self, call: &Call) -> Option<ScriptAction> {
+        let script = self.get()?;
+        match script.on_incoming(call) {
+            Ok(action) => action,
+            Err(err) => {
+                warn!("Script hook on_incoming failed: {err:#}");
+                None
+            }
+        }
+    }
+
+    fn set(&self, script: Option<Script>) {
+        self.script.send_replace(script.map(Arc::new));
+    }
+}
+
+/// Loads the script if it exists and keeps watching the file for changes.
+///
+/// An invalid script is reported on change and the previous one stays active.
+/// A removed script is dropped.
+pub async fn watch(path: PathBuf) -> Result<ScriptHandle> {
+    let handle = ScriptHandle {
+        script: watch::Sender::new(None),
+    };
+
+    if tokio::fs::try_exists(&path).await? {
+        handle.set(Some(Script::load(&path).await?));
+        info!("Loaded script from {path}", path = path.display());
+    }
+
+    let Some(mut changes) = watch_file(&path)? else {
+        debug!("Script directory does not exist - not watching for changes");
+        return Ok(handle);
+    };
+
+    let reloader = handle.clone();
+    tokio::spawn(async move {
+        while changes.recv().await.is_some() {
+            if !tokio::fs::try_exists(&path).await.unwrap_or(true) {
+                info!("Script {path} removed", path = path.display());
+                reloader.set(None);
+                continue;
+            }
+
+            match Script::load(&path).await {
+                Ok(script) => {
+                    info!("Reloaded script from {path}", path = path.display());
+                    reloader.set(Some(script));
+                }
+                Err(err) => warn!("Ignoring invalid script: {err:#}"),
+            }
+        }
+    });
+
+    Ok(handle)
+}
@@ -1,6 +1,22 @@
-use crate::ucware::{Client, TokenStore};
-use anyhow::{anyhow, Result};
-use clap::{Args, Parser};
+use crate::cache::Cache;
+use crate::config::{
+    self, AccountConfig, Config, ConfigHandle, DnsConfig, RpcConfig, SipConfig, SipTimersConfig,
+};
+use crate::progress;
+use crate::resolver::Resolver;
+use crate::sipsocket::redirect;
+use crate::sipsocket::{AddressFamily, Limits, SipTimers};
+use crate::store::Store;
+use crate::ucware::{token, Client, RequestPolicy, SocketOptions, TokenStore};
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, CommandFactory, Parser, ValueEnum};
+use clap_complete::CompleteEnv;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
+use tracing_subscriber::util::SubscriberInitExt;
 use url::Url;
 
 #[derive(Parser, Debug)]
@@ -12,12 +28,45 @@ where
     #[command(flatten)]
     verbosity: clap_verbosity_flag::Verbosity,
 
+    /// Base URL of the UCware server
     #[arg(short, long)]
-    url: Url,
+    url: Option<Url>,
 
     #[arg(short, long)]
     token: Option<String>,
 
+    /// Path to the config file
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Always query the server instead of using cached responses
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Output format of commands printing data
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    output: Output,
+
+    /// Try IPv4 addresses of the SIP server first
+    #[arg(long, global = true, conflicts_with = "prefer_ipv6")]
+    prefer_ipv4: bool,
+
+    /// Try IPv6 addresses of the SIP server first
+    #[arg(long, global = true)]
+    prefer_ipv6: bool,
+
+    /// Register a new SIP contact instead of reusing the one of the last run
+    #[arg(long, global = true)]
+    fresh_contact: bool,
+
+    /// Give up on API requests taking longer, like `5s` - overrides the config
+    #[arg(long, global = true)]
+    timeout: Option<humantime::Duration>,
+
+    /// Retries of reading API requests after transient failures - overrides the config
+    #[arg(long, global = true)]
+    retries: Option<u32>,
+
     #[clap(flatten)]
     inner: A,
 }
@@ -33,22 +82,253 @@ where
 //     }
 // }
 
-pub async fn init<A: Args>() -> Result<(Client, A)> {
-    let args = CmdArgs::<A>::parse();
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Human readable
+    Text,
+
+    /// Machine readable, disables progress indicators
+    Json,
+}
+
+/// Parsed command line arguments of a command which has not been initialized yet
+pub struct Cmd<A: Args> {
+    args: CmdArgs<A>,
+}
+
+/// Parses the command line.
+///
+/// If invoked by the shell for completion (`COMPLETE=<shell>` in the
+/// environment), prints the candidates and exits instead.
+pub fn parse<A: Args>() -> Cmd<A> {
+    CompleteEnv::with_factory(CmdArgs::<A>::command).complete();
+
+    Cmd {
+        args: CmdArgs::<A>::parse(),
+    }
+}
+
+/// Checks the command line of a command, panicking on invalid definitions.
+///
+/// Besides the checks of clap, this rejects arguments of subcommands
+/// shadowing a global one: clap does not propagate the global argument then
+/// and panics once it is accessed with the other type.
+pub fn debug_assert<A: Args>() {
+    let command = CmdArgs::<A>::command();
+    command.clone().debug_assert();
+
+    let globals = command
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .map(|arg| arg.get_id().clone())
+        .collect::<Vec<_>>();
+
+    fn check(command: &clap::Command, globals: &[clap::Id], path: &str) {
+        for subcommand in command.get_subcommands() {
+            let path = format!("{path} {name}", name = subcommand.get_name());
+            if let Some(arg) = subcommand.get_arguments().find(|arg| globals.contains(arg.get_id())) {
+                panic!("Argument `{id}` of `{path}` shadows the global one", id = arg.get_id());
+            }
+            check(subcommand, globals, &path);
+        }
+    }
+    check(&command, &globals, command.get_name());
+}
+
+pub async fn init<A: Args>() -> Result<(Client, ConfigHandle, A)> {
+    parse::<A>().init().await
+}
+
+impl<A: Args> Cmd<A> {
+    pub fn args(&self) -> &A {
+        &self.args.inner
+    }
+
+    pub fn output(&self) -> Output {
+        self.args.output
+    }
+
+    /// Initializes logging and config while deferring the connection to the server
+    pub async fn setup(self) -> Result<(Connector, ConfigHandle, A)> {
+        let args = self.args;
+        let config = setup(&args.verbosity, args.config).await?;
+
+        progress::init(args.output == Output::Text && std::io::stdout().is_terminal());
 
-    tracing_subscriber::fmt()
-        .with_max_level(args.verbosity)
+        let connector = Connector {
+            url: args.url,
+            token: args.token,
+            account: None,
+            no_cache: args.no_cache,
+            fresh_contact: args.fresh_contact,
+            sip: config.get().sip.clone(),
+            prefer: match (args.prefer_ipv4, args.prefer_ipv6) {
+                (true, _) => Some(AddressFamily::Ipv4),
+                (_, true) => Some(AddressFamily::Ipv6),
+                _ => None,
+            },
+            dns: config.get().dns.clone(),
+            rpc: RpcConfig {
+                timeout: args
+                    .timeout
+                    .map(|timeout| config::Age(timeout.into()))
+                    .or(config.get().rpc.timeout),
+                retries: args.retries.or(config.get().rpc.retries),
+                backoff: config.get().rpc.backoff,
+                token_leeway: config.get().rpc.token_leeway,
+            },
+        };
+
+        Ok((connector, config, args.inner))
+    }
+
+    pub async fn init(self) -> Result<(Client, ConfigHandle, A)> {
+        let (connector, config, args) = self.setup().await?;
+        let client = connector.connect().await?;
+        Ok((client, config, args))
+    }
+}
+
+/// Connection parameters for commands which may not need the server at all
+#[derive(Clone)]
+pub struct Connector {
+    url: Option<Url>,
+    token: Option<String>,
+
+    /// Name of the further account connected to, the main one if unset
+    account: Option<String>,
+
+    no_cache: bool,
+    fresh_contact: bool,
+    sip: SipConfig,
+    prefer: Option<AddressFamily>,
+    dns: DnsConfig,
+    rpc: RpcConfig,
+}
+
+impl Connector {
+    /// Connects to the given further account instead, sharing all other options
+    pub fn account(&self, account: &AccountConfig) -> Result<Self> {
+        let url = account
+            .url
+            .parse()
+            .with_context(|| format!("Invalid URL of account {name}", name = account.name))?;
+
+        Ok(Self {
+            url: Some(url),
+            token: account.token.clone(),
+            account: Some(account.name.clone()),
+            ..self.clone()
+        })
+    }
+
+    pub async fn connect(self) -> Result<Client> {
+        let url = self.url.ok_or_else(|| anyhow!("No server URL specified"))?;
+
+        let store = Store::open_default()?;
+
+        let token = match (self.account, self.token) {
+            (Some(account), initial) => TokenStore::open_account(store.clone(), &account, initial)?
+                .ok_or_else(|| anyhow!("No token specified for account {account}")),
+            (None, None) => TokenStore::open(store.clone())
+                .await?
+                .ok_or_else(|| anyhow!("No token specified and no store available")),
+            (None, Some(token)) => TokenStore::with_token(store.clone(), token),
+        }?
+        .leeway(self.rpc.token_leeway.map_or(token::DEFAULT_LEEWAY, |leeway| leeway.0));
+
+        let _progress = progress::spinner(format!("Connecting to {url}"));
+
+        let cache = (!self.no_cache).then(|| Cache::new(store.clone()));
+
+        let defaults = Limits::default();
+        let socket = SocketOptions {
+            resolver: Resolver::new(&self.dns)?,
+            fallback_hosts: self.sip.fallback_hosts,
+            prefer: self.prefer,
+            compact_headers: self.sip.compact_headers,
+            fresh_contact: self.fresh_contact,
+            max_redirects: self.sip.max_redirects.unwrap_or(redirect::DEFAULT_MAX_REDIRECTS),
+            timers: sip_timers(&self.sip.timers),
+            limits: Limits {
+                max_message_size: self.sip.max_message_size.unwrap_or(defaults.max_message_size),
+                max_requests_per_second: self
+                    .sip
+                    .max_requests_per_second
+                    .unwrap_or(defaults.max_requests_per_second),
+            },
+        };
+
+        let defaults = RequestPolicy::default();
+        let policy = RequestPolicy {
+            timeout: self.rpc.timeout.map_or(defaults.timeout, |timeout| timeout.0),
+            retries: self.rpc.retries.unwrap_or(defaults.retries),
+            backoff: self.rpc.backoff.map_or(defaults.backoff, |backoff| backoff.0),
+        };
+
+        let client = Client::builder(url, token, socket)
+            .cache(cache)
+            .request_policy(policy)
+            .store(store)
+            .build()?;
+        client.refresh_token().await?;
+
+        Ok(client)
+    }
+}
+
+/// Timers as configured, deriving unset timeouts from T1 like the RFC does
+fn sip_timers(config: &SipTimersConfig) -> SipTimers {
+    let defaults = match config.t1 {
+        Some(t1) => SipTimers::with_t1(t1.0),
+        None => SipTimers::default(),
+    };
+
+    SipTimers {
+        t1: defaults.t1,
+        timer_b: config.timer_b.map_or(defaults.timer_b, |timer_b| timer_b.0),
+        timer_f: config.timer_f.map_or(defaults.timer_f, |timer_f| timer_f.0),
+        refresh_margin: config.refresh_margin.map_or(defaults.refresh_margin, |margin| margin.0),
+    }
+}
+
+async fn setup(
+    verbosity: &clap_verbosity_flag::Verbosity,
+    config: Option<PathBuf>,
+) -> Result<ConfigHandle> {
+    let (filter, log_level) = reload::Layer::new(LevelFilter::from(*verbosity));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            // Keep stdout clean for machine readable output
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal()),
+        )
         .init();
 
-    let token = match args.token {
-        None => TokenStore::open(".token")
-            .await?
-            .ok_or_else(|| anyhow!("No token specified and no store available")),
-        Some(token) => TokenStore::with_token(".token", token).await,
-    }?;
+    let config_path = config
+        .or_else(Config::default_path)
+        .ok_or_else(|| anyhow!("No config specified and no default location available"))?;
+    let config = config::watch(config_path).await?;
+
+    // Explicit verbosity flags take precedence over the config
+    if !verbosity.is_present() {
+        let default = LevelFilter::from(*verbosity);
+        let level = move |config: &Config| config.log_level.map_or(default, |level| level.0);
+
+        let mut config = config.subscribe();
+        log_level.reload(level(&config.borrow_and_update()))?;
 
-    let client = Client::new(args.url, token)?;
-    client.refresh_token().await?;
+        tokio::spawn(async move {
+            while config.changed().await.is_ok() {
+                let level = level(&config.borrow_and_update());
+                if log_level.reload(level).is_err() {
+                    return;
+                }
+            }
+        });
+    }
 
-    Ok((client, args.inner))
+    Ok(config)
 }
@@ -0,0 +1,47 @@
+use crate::cache::Cache;
+use crate::store::Store;
+use clap_complete::CompletionCandidate;
+use serde::Deserialize;
+
+/// Names of the entries of a cached list response
+fn cached(suffix: &str) -> Vec<CompletionCandidate> {
+    #[derive(Deserialize)]
+    struct Named {
+        name: String,
+    }
+
+    let Ok(cache) = Cache::open_default() else {
+        return Vec::new();
+    };
+
+    cache
+        .find::<Vec<Named>>(suffix)
+        .into_iter()
+        .flatten()
+        .map(|entry| CompletionCandidate::new(entry.name))
+        .collect()
+}
+
+/// Slot names from the last cached slot list
+pub fn slots() -> Vec<CompletionCandidate> {
+    cached("user/slot/getAll")
+}
+
+/// Queue names from the last cached queue list
+pub fn queues() -> Vec<CompletionCandidate> {
+    cached("user/queue/getAll")
+}
+
+/// Names of the locally stored favorites
+pub fn favorites() -> Vec<CompletionCandidate> {
+    let Ok(store) = Store::open_default() else {
+        return Vec::new();
+    };
+
+    store
+        .favorites()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|favorite| CompletionCandidate::new(favorite.name).help(Some(favorite.number.into())))
+        .collect()
+}
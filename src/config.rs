@@ -0,0 +1,1220 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Log level overriding the default verbosity
+    pub log_level: Option<LogLevel>,
+
+    pub notification: NotificationConfig,
+
+    /// Callers matching any of these filters do not raise notifications
+    pub filters: Vec<Filter>,
+
+    pub policy: Policy,
+
+    pub agent: AgentConfig,
+
+    pub caller_id: CallerIdConfig,
+
+    pub screening: ScreeningConfig,
+
+    pub out_of_office: OutOfOfficeConfig,
+
+    pub sip: SipConfig,
+
+    pub dns: DnsConfig,
+
+    pub rpc: RpcConfig,
+
+    pub supervisor: SupervisorConfig,
+
+    pub call_log: CallLogConfig,
+
+    pub retention: RetentionConfig,
+
+    pub forward: ForwardConfig,
+
+    /// Emails missed calls and new voicemails if set
+    pub email: Option<EmailConfig>,
+
+    /// Room used by `ucware matrix-bridge`
+    pub matrix: Option<MatrixConfig>,
+
+    /// Slack compatible webhooks to post calls to
+    pub webhooks: Vec<WebhookConfig>,
+
+    /// WASM modules filtering and transforming events before notifications and webhooks
+    pub event_filters: Vec<EventFilterConfig>,
+
+    pub hotkeys: HotkeysConfig,
+
+    pub busy: BusyConfig,
+
+    pub audio: AudioConfig,
+
+    pub media: MediaConfig,
+
+    /// Further accounts monitored by `ucware-call-notify` besides the one given on the command line
+    pub accounts: Vec<AccountConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LogLevel(pub LevelFilter);
+
+impl TryFrom<String> for LogLevel {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let level = value
+            .parse()
+            .with_context(|| format!("Invalid log level: {value}"))?;
+        Ok(Self(level))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct NotificationConfig {
+    pub summary: Template,
+    pub body: Template,
+
+    /// How to notify while the desktop is in do-not-disturb mode
+    pub focus_assist: FocusAssist,
+
+    pub urgency: UrgencyConfig,
+
+    /// Whether ringing calls stay on screen until answered
+    pub persistence: Persistence,
+
+    /// Category hint passed to the notification server
+    pub category: String,
+
+    /// Name of a sound from the freedesktop sound theme, e.g. `phone-incoming-call`
+    pub sound: Option<String>,
+
+    /// Shows notifications on the desktop, disable on headless machines
+    pub desktop: bool,
+
+    /// Services to push incoming calls to in addition
+    pub push: Vec<PushConfig>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            summary: Template("Incoming Call".to_string()),
+            body: Template("{name}\n{forwarded}".to_string()),
+            focus_assist: FocusAssist::default(),
+            urgency: UrgencyConfig::default(),
+            persistence: Persistence::default(),
+            category: "call.incoming".to_string(),
+            sound: None,
+            desktop: true,
+            push: Vec::new(),
+        }
+    }
+}
+
+/// Urgency of ringing call notifications
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UrgencyConfig {
+    pub normal: Urgency,
+
+    /// Callers marked as urgent by the screening rules
+    pub urgent: Urgency,
+}
+
+impl Default for UrgencyConfig {
+    fn default() -> Self {
+        Self {
+            normal: Urgency::Normal,
+            urgent: Urgency::Critical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+/// Lifetime of a notification on screen and in the notification history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Persistence {
+    /// Stay on screen until the call ends
+    #[default]
+    Resident,
+
+    /// Disappear after the timeout of the notification server
+    Timeout,
+
+    /// Disappear after the timeout without being kept in the history
+    Transient,
+}
+
+/// Handling of call notifications while the desktop asks not to be disturbed.
+///
+/// Urgent callers always raise a critical notification. Missed calls are
+/// counted either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FocusAssist {
+    /// Notify as usual
+    Ignore,
+
+    /// Show a low urgency notification which does not stay on screen
+    #[default]
+    Downgrade,
+
+    /// Do not show a notification
+    Suppress,
+}
+
+/// A string with `{variable}` placeholders
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template(String);
+
+impl Template {
+    pub const VARIABLES: &'static [&'static str] =
+        &["name", "number", "uri", "forwarded", "queue", "account", "slot"];
+
+    /// Replaces all placeholders, trimming whitespace left by empty values.
+    ///
+    /// Substitutes in a single pass, so placeholders within values are kept as they are.
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        let mut result = String::with_capacity(self.0.len());
+
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+
+            let placeholder = rest[start..].find('}').and_then(|end| {
+                let name = &rest[start + 1..start + end];
+                let (_, value) = vars.iter().find(|(var, _)| *var == name)?;
+                Some((end, *value))
+            });
+            match placeholder {
+                Some((end, value)) => {
+                    result.push_str(value);
+                    rest = &rest[start + end + 1..];
+                }
+                None => {
+                    result.push('{');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        result.push_str(rest);
+
+        result.trim().to_string()
+    }
+
+    /// Checks that the template only uses the given variables
+    fn parse(value: String, variables: &[&str]) -> Result<Self> {
+        let mut rest = value.as_str();
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                bail!("Unterminated placeholder in template: {value}");
+            };
+
+            let name = &rest[start + 1..start + end];
+            if !variables.contains(&name) {
+                bail!("Unknown placeholder '{{{name}}}' in template: {value}");
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Self::parse(value, Self::VARIABLES)
+    }
+}
+
+/// A template for voicemail messages, with counts instead of caller details
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct VoicemailTemplate(Template);
+
+impl VoicemailTemplate {
+    pub const VARIABLES: &'static [&'static str] = &["new", "old"];
+
+    pub fn render(&self, vars: &[(&str, &str)]) -> String {
+        self.0.render(vars)
+    }
+}
+
+impl TryFrom<String> for VoicemailTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Ok(Self(Template::parse(value, Self::VARIABLES)?))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Filter {
+    /// Glob pattern matched against the caller's number and name
+    pub caller: Pattern,
+}
+
+impl Filter {
+    pub fn matches(&self, name: Option<&str>, number: Option<&str>) -> bool {
+        self.caller.matches_caller(name, number)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(glob::Pattern);
+
+impl Pattern {
+    /// Whether the caller's name or number matches
+    pub fn matches_caller(&self, name: Option<&str>, number: Option<&str>) -> bool {
+        [name, number]
+            .into_iter()
+            .flatten()
+            .any(|value| self.0.matches(value))
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let pattern = glob::Pattern::new(&value)
+            .with_context(|| format!("Invalid pattern: {value}"))?;
+        Ok(Self(pattern))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Policy {
+    /// Do not disturb - suppress all notifications
+    pub dnd: bool,
+
+    /// How to handle a second call while another one is in progress
+    pub call_waiting: CallWaiting,
+
+    /// Calls beyond this number of concurrent calls are rejected as busy - 1 gives busy-on-busy
+    pub max_calls: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CallWaiting {
+    /// Notify about the waiting call
+    #[default]
+    Allow,
+
+    /// Reject the waiting call as busy
+    Reject,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AgentConfig {
+    /// Seconds to stay paused after an answered call - zero disables wrap-up
+    pub wrap_up: u64,
+}
+
+/// Header the caller identity is taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdentitySource {
+    PAssertedIdentity,
+    RemotePartyId,
+    From,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CallerIdConfig {
+    /// Identity headers in order of preference, falling back to From if none is present
+    pub prefer: Vec<IdentitySource>,
+}
+
+impl Default for CallerIdConfig {
+    fn default() -> Self {
+        Self {
+            prefer: vec![
+                IdentitySource::PAssertedIdentity,
+                IdentitySource::RemotePartyId,
+                IdentitySource::From,
+            ],
+        }
+    }
+}
+
+/// What to do with a screened call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreeningAction {
+    /// Notify as usual
+    #[default]
+    Notify,
+
+    /// Notify with critical urgency
+    Urgent,
+
+    /// Let it ring without notification
+    Silent,
+
+    /// Reject the call
+    Reject,
+
+    /// Redirect the call to the voicemail box
+    Voicemail,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ScreeningRule {
+    /// Glob pattern matched against the caller's number and name
+    pub caller: Pattern,
+
+    pub action: ScreeningAction,
+}
+
+impl ScreeningRule {
+    pub fn matches(&self, name: Option<&str>, number: Option<&str>) -> bool {
+        self.caller.matches_caller(name, number)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ScreeningConfig {
+    /// Action for callers withholding their identity
+    pub anonymous: ScreeningAction,
+
+    /// URI calls are redirected to by the voicemail action
+    pub voicemail: Option<String>,
+
+    /// Actions for specific callers - the first matching rule applies
+    pub rules: Vec<ScreeningRule>,
+
+    /// Status calls matching the blocklist are rejected with - defaults to 603 Decline
+    pub block_status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SipConfig {
+    /// Hosts tried in order if the slot's SIP host fails, as `host` or `host:port`
+    pub fallback_hosts: Vec<String>,
+
+    /// Send compact header forms and omit redundant headers, for constrained links
+    pub compact_headers: bool,
+
+    /// Size in bytes above which received requests are rejected - 64 KiB if unset
+    pub max_message_size: Option<usize>,
+
+    /// Requests per call and second above which requests are rejected - 10 if unset
+    pub max_requests_per_second: Option<u32>,
+
+    /// Redirects of own requests followed before giving up - 3 if unset, 0 disables them
+    pub max_redirects: Option<u32>,
+
+    /// Transaction timers, for slow SBCs needing more than the RFC defaults
+    pub timers: SipTimersConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SipTimersConfig {
+    /// Estimate of the round trip time - 500ms if unset
+    pub t1: Option<Age>,
+
+    /// Time to wait for the final response to an INVITE - 64 times T1 if unset
+    pub timer_b: Option<Age>,
+
+    /// Time to wait for the final response to other requests - 64 times T1 if unset
+    pub timer_f: Option<Age>,
+
+    /// Time before a registration expires to refresh it at - 5m if unset
+    pub refresh_margin: Option<Age>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DnsConfig {
+    /// Name servers to query instead of those of the system
+    pub servers: Vec<NameServer>,
+
+    /// Number of cached records, hickory's default if unset
+    pub cache_size: Option<usize>,
+}
+
+/// How API requests are sent
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RpcConfig {
+    /// Time after which a request is abandoned - 30s if unset
+    pub timeout: Option<Age>,
+
+    /// Retries of reading requests after connection failures or gateway errors - 2 if unset
+    pub retries: Option<u32>,
+
+    /// Time before the token expires to refresh it at, covering skewed clocks - 60s if unset
+    pub token_leeway: Option<Age>,
+
+    /// Delay before the first retry, doubled for each further one - 500ms if unset
+    pub backoff: Option<Age>,
+}
+
+/// How `ucware-call-notify` reconnects its accounts after a connection failed, keeping the call state
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SupervisorConfig {
+    /// Restarts within the window before giving up, 0 to exit on the first failure - 5 if unset
+    pub max_restarts: Option<u32>,
+
+    /// Time restarts are counted in - 10m if unset
+    pub window: Option<Age>,
+
+    /// Delay before the first restart, doubled for each further one within the window - 1s if unset
+    pub backoff: Option<Age>,
+}
+
+/// Address of a name server as `ip` or `ip:port`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NameServer(pub SocketAddr);
+
+impl TryFrom<String> for NameServer {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let addr = match value.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, 53),
+            Err(_) => value
+                .parse()
+                .with_context(|| format!("Invalid name server: {value}"))?,
+        };
+        Ok(Self(addr))
+    }
+}
+
+/// Time of day in `HH:MM` notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(pub NaiveTime);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let time = NaiveTime::parse_from_str(&value, "%H:%M")
+            .with_context(|| format!("Invalid time of day: {value}"))?;
+        Ok(Self(time))
+    }
+}
+
+/// A recurring period of time during the week
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TimeProfile {
+    /// Days the period starts on - every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+
+    /// Start of the period - the start of the day if unset
+    pub from: Option<TimeOfDay>,
+
+    /// End of the period, which may be on the next day - the end of the day if unset
+    pub until: Option<TimeOfDay>,
+
+    /// Overrides the redirect target while this profile is active
+    pub redirect: Option<String>,
+}
+
+impl TimeProfile {
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        let starts_on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+
+        let from = self.from.map_or(NaiveTime::MIN, |from| from.0);
+        let now = time.time();
+
+        match self.until.map(|until| until.0) {
+            None => starts_on(time.weekday()) && now >= from,
+            Some(until) if from <= until => {
+                starts_on(time.weekday()) && now >= from && now < until
+            }
+
+            // Spans midnight
+            Some(until) => {
+                (starts_on(time.weekday()) && now >= from)
+                    || (starts_on(time.weekday().pred()) && now < until)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OutOfOfficeConfig {
+    /// URI calls are redirected to while closed, e.g. an announcement on the PBX
+    pub redirect: Option<String>,
+
+    /// WAV file played to callers while closed before they are redirected
+    pub announcement: Option<PathBuf>,
+
+    /// Text spoken to callers while closed by the speech synthesis of the media, after the announcement file
+    pub announcement_text: Option<String>,
+
+    /// Times the office is closed
+    pub closed: Vec<TimeProfile>,
+}
+
+/// What happens to calls while the office is closed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closed {
+    /// Where calls are redirected to - hung up on after the announcement if unset
+    pub redirect: Option<String>,
+
+    /// Announcement calls are answered with
+    pub announcement: Option<PathBuf>,
+
+    /// Text spoken after the announcement
+    pub announcement_text: Option<String>,
+}
+
+impl Closed {
+    /// Whether calls are answered to play an announcement rather than just redirected
+    pub fn answers(&self) -> bool {
+        self.announcement.is_some() || self.announcement_text.is_some()
+    }
+}
+
+impl OutOfOfficeConfig {
+    /// How to handle calls if the office is closed at the given local time
+    pub fn closed(&self, time: NaiveDateTime) -> Option<Closed> {
+        let profile = self.closed.iter().find(|profile| profile.contains(time))?;
+        Some(Closed {
+            redirect: profile.redirect.clone().or_else(|| self.redirect.clone()),
+            announcement: self.announcement.clone(),
+            announcement_text: self.announcement_text.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CallLogConfig {
+    /// Encrypts callers, transcripts and recordings in the local call log, takes effect on restart
+    pub encryption: Option<Encryption>,
+}
+
+/// How long local data is kept, forever if unset
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RetentionConfig {
+    /// Age of call log entries
+    pub call_log: Option<Age>,
+
+    /// Age of cached API responses, regardless of how long they are served
+    pub cache: Option<Age>,
+
+    /// Age of recordings of callers taken by the answer bot
+    pub recordings: Option<Age>,
+}
+
+/// A duration like `90d` or `12h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Age(pub Duration);
+
+impl TryFrom<String> for Age {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let age = humantime::parse_duration(&value)
+            .with_context(|| format!("Invalid age: {value}"))?;
+        Ok(Self(age))
+    }
+}
+
+/// Forwarding of incoming calls to a phone, e.g. while away from the desk
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ForwardConfig {
+    /// Times calls are forwarded at - always if empty, redirects are ignored
+    pub active: Vec<TimeProfile>,
+
+    /// Sends a ping to a device paired with KDE Connect
+    pub kde_connect: Option<KdeConnectConfig>,
+
+    /// Publishes to an ntfy topic, used if KDE Connect is not configured or fails
+    pub ntfy: Option<NtfyConfig>,
+}
+
+impl ForwardConfig {
+    /// Whether calls are forwarded at the given local time
+    pub fn active(&self, time: NaiveDateTime) -> bool {
+        (self.kde_connect.is_some() || self.ntfy.is_some())
+            && (self.active.is_empty() || self.active.iter().any(|profile| profile.contains(time)))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct KdeConnectConfig {
+    /// ID of the device - all reachable devices if unset
+    pub device: Option<String>,
+}
+
+/// A push notification service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "service", rename_all = "kebab-case")]
+pub enum PushConfig {
+    Ntfy(NtfyConfig),
+    Gotify(GotifyConfig),
+    Pushover(PushoverConfig),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct NtfyConfig {
+    #[serde(default = "NtfyConfig::default_server")]
+    pub server: String,
+
+    pub topic: String,
+
+    /// Access token for protected topics
+    pub token: Option<String>,
+}
+
+impl NtfyConfig {
+    fn default_server() -> String {
+        "https://ntfy.sh".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GotifyConfig {
+    pub server: String,
+
+    /// Token of the application to post as
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PushoverConfig {
+    /// API token of the application
+    pub token: String,
+
+    /// Key of the user or group to notify
+    pub user: String,
+
+    /// Notifies only this device of the user
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EmailConfig {
+    /// Host name of the SMTP server
+    pub server: String,
+
+    /// Port of the server - the default of the security mode if unset
+    pub port: Option<u16>,
+
+    #[serde(default)]
+    pub security: SmtpSecurity,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    pub from: String,
+    pub to: Vec<String>,
+
+    /// Sends an email for each missed call
+    #[serde(default = "default_true")]
+    pub missed: bool,
+
+    /// Sends an email when a new voicemail arrived
+    #[serde(default = "default_true")]
+    pub voicemail: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmtpSecurity {
+    /// Upgrades the connection with STARTTLS, failing if the server does not support it
+    #[default]
+    Starttls,
+
+    /// Connects with TLS right away
+    Tls,
+
+    /// Sends everything in plain text, only for servers on localhost
+    None,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MatrixConfig {
+    /// Base URL of the homeserver, e.g. `https://matrix.example.com`
+    pub homeserver: String,
+
+    /// Access token of the bot account, which must have joined the room
+    pub token: String,
+
+    /// ID of the room to post to, e.g. `!abc:example.com`
+    pub room: String,
+
+    /// Users allowed to send commands, e.g. `@alice:example.com`
+    #[serde(default)]
+    pub users: Vec<String>,
+}
+
+/// Another UCware account, e.g. of a customer's PBX
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AccountConfig {
+    /// Label of the account in logs and events
+    pub name: String,
+
+    /// Base URL of the UCware server
+    pub url: String,
+
+    /// Token of the first run, later runs use the refreshed one kept in the store
+    pub token: Option<String>,
+}
+
+/// An incoming webhook of Slack or a compatible service like Mattermost.
+///
+/// Messages about the same call are grouped in a thread if the endpoint
+/// returns the timestamp of the posted message, e.g. Slack's
+/// `chat.postMessage` used with a bot token. An empty template skips the event.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WebhookConfig {
+    pub url: String,
+
+    /// Sent as bearer token
+    pub token: Option<String>,
+
+    /// Overrides the channel of the webhook, required by `chat.postMessage`
+    pub channel: Option<String>,
+
+    #[serde(default = "WebhookConfig::default_incoming")]
+    pub incoming: Template,
+
+    #[serde(default = "WebhookConfig::default_missed")]
+    pub missed: Template,
+
+    #[serde(default = "WebhookConfig::default_voicemail")]
+    pub voicemail: VoicemailTemplate,
+}
+
+impl WebhookConfig {
+    fn default_incoming() -> Template {
+        Template(":telephone_receiver: Incoming call from *{name}* {number} {forwarded}".to_string())
+    }
+
+    fn default_missed() -> Template {
+        Template(":no_entry: Missed call from *{name}* {number}".to_string())
+    }
+
+    fn default_voicemail() -> VoicemailTemplate {
+        VoicemailTemplate(Template(":envelope: *{new}* new voicemails".to_string()))
+    }
+}
+
+/// A WASM module filtering and transforming events, see `ucware_cli::filter`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EventFilterConfig {
+    pub module: PathBuf,
+
+    /// Instructions the module may run per event - 10 million if unset
+    pub fuel: Option<u64>,
+}
+
+/// Shortcuts suggested to the desktop by `ucware hotkeys`, e.g. `CTRL+ALT+D`.
+///
+/// The desktop decides about the actual triggers and usually asks the user.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HotkeysConfig {
+    pub decline: Option<String>,
+    pub dnd: Option<String>,
+    pub redial: Option<String>,
+    pub dial_clipboard: Option<String>,
+}
+
+/// Leaves notifying to another softphone on the same machine, e.g. the UCware desktop app.
+///
+/// Calls are still counted and pushed to phones, only the desktop notification is skipped.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BusyConfig {
+    /// Names of softphone processes ringing for the same calls
+    pub processes: Vec<String>,
+
+    /// Whether an audio stream with the phone role means another softphone is in a call
+    pub phone_streams: bool,
+}
+
+/// Local devices for call audio, as listed by `ucware audio devices`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct AudioConfig {
+    /// Name of the microphone - the system default if unset
+    pub input: Option<String>,
+
+    /// Name of the speaker or headset - the system default if unset
+    pub output: Option<String>,
+
+    /// Volume of the microphone in percent - 100 if unset
+    pub input_volume: Option<u16>,
+
+    /// Volume of the remote party in percent - 100 if unset
+    pub output_volume: Option<u16>,
+}
+
+/// Media of calls answered by this client
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MediaConfig {
+    pub silence_suppression: SilenceSuppressionConfig,
+
+    pub hold: HoldConfig,
+
+    /// Speech synthesis for announcements if set
+    pub tts: Option<TtsConfig>,
+
+    /// Transcribes call audio into the call log if set
+    pub transcription: Option<TranscriptionConfig>,
+}
+
+/// Media sent to held calls
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HoldConfig {
+    /// WAV file played in a loop - silence if unset
+    pub music: Option<PathBuf>,
+}
+
+/// Sends comfort noise instead of silent frames, saving bandwidth for bots mostly listening
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SilenceSuppressionConfig {
+    /// Takes effect on calls whose offer includes comfort noise
+    pub enabled: bool,
+
+    /// Level below which frames count as silence, in dBFS - -50 if unset
+    pub threshold: Option<f32>,
+
+    /// Time frames are still sent after the level dropped below the threshold - 200ms if unset
+    pub hangover: Option<Age>,
+
+    /// Interval of comfort noise updates during silence - 5s if unset
+    pub refresh: Option<Age>,
+}
+
+/// A speech synthesis engine producing WAV audio
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "engine", rename_all = "kebab-case")]
+pub enum TtsConfig {
+    EspeakNg(EspeakNgConfig),
+    Piper(PiperConfig),
+
+    /// An endpoint answering a JSON object with the `text` with WAV audio
+    Http(HttpTtsConfig),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EspeakNgConfig {
+    /// Voice like `de` or `en-us` - espeak-ng's default if unset
+    pub voice: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PiperConfig {
+    /// Path to the voice model
+    pub model: PathBuf,
+
+    /// Path to the piper executable - `piper` from the search path if unset
+    pub binary: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HttpTtsConfig {
+    pub url: String,
+
+    /// Bearer token sent with requests
+    pub token: Option<String>,
+}
+
+/// A speech recognition engine
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "engine", rename_all = "kebab-case")]
+pub enum TranscriptionConfig {
+    /// The command line tool of whisper.cpp
+    Whisper(WhisperConfig),
+
+    /// An endpoint taking WAV audio and answering a JSON object with the `text`
+    Http(HttpTranscriptionConfig),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WhisperConfig {
+    /// Path to the model
+    pub model: PathBuf,
+
+    /// Path to the executable - `whisper-cli` from the search path if unset
+    pub binary: Option<PathBuf>,
+
+    /// Spoken language like `de` - detected if unset
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HttpTranscriptionConfig {
+    pub url: String,
+
+    /// Bearer token sent with requests
+    pub token: Option<String>,
+}
+
+/// Source of the key encrypting personal data stored locally
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encryption {
+    /// A random key kept in the system keyring
+    Keyring,
+
+    /// A key derived from the passphrase in `UCWARE_CALL_LOG_PASSPHRASE`
+    Passphrase,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("ucware").join("config.toml"))
+    }
+
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        if !tokio::fs::try_exists(path).await? {
+            debug!("No config file found at {path}, using defaults", path = path.display());
+            return Ok(Self::default());
+        }
+
+        let data = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read config: {path}", path = path.display()))?;
+
+        let config: Self = toml::from_str(&data)
+            .with_context(|| format!("Invalid config: {path}", path = path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("Invalid config: {path}", path = path.display()))?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let screening = &self.screening;
+        let voicemail = std::iter::once(screening.anonymous)
+            .chain(screening.rules.iter().map(|rule| rule.action))
+            .any(|action| action == ScreeningAction::Voicemail);
+        if voicemail && screening.voicemail.is_none() {
+            bail!("Screening action 'voicemail' requires screening.voicemail to be set");
+        }
+
+        if let Some(status) = screening.block_status
+            && !(400..700).contains(&status)
+        {
+            bail!("screening.block-status must be a final error status, got {status}");
+        }
+
+        let out_of_office = &self.out_of_office;
+        if out_of_office.redirect.is_none()
+            && out_of_office.announcement.is_none()
+            && out_of_office.announcement_text.is_none()
+            && out_of_office.closed.iter().any(|profile| profile.redirect.is_none())
+        {
+            bail!("out-of-office.redirect or an announcement must be set for profiles without their own redirect");
+        }
+
+        if out_of_office.announcement_text.is_some() && self.media.tts.is_none() {
+            bail!("out-of-office.announcement-text requires media.tts to be set");
+        }
+
+        let mut names = std::collections::HashSet::new();
+        for account in &self.accounts {
+            if !names.insert(&account.name) {
+                bail!("Account name used more than once: {name}", name = account.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A live view on the config, updated whenever the config file changes
+#[derive(Clone)]
+pub struct ConfigHandle {
+    path: PathBuf,
+    config: watch::Sender<Arc<Config>>,
+}
+
+impl ConfigHandle {
+    /// The currently active config
+    pub fn get(&self) -> Arc<Config> {
+        self.config.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.config.subscribe()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Re-reads the config file and publishes it if it is valid
+    pub async fn reload(&self) -> Result<()> {
+        let config = Config::load(&self.path).await?;
+
+        info!("Reloaded config from {path}", path = self.path.display());
+        self.config.send_replace(Arc::new(config));
+
+        Ok(())
+    }
+}
+
+/// Loads the config and keeps watching the file for changes.
+///
+/// Changes are validated before they are published. An invalid config is
+/// reported and the previous config stays active.
+pub async fn watch(path: PathBuf) -> Result<ConfigHandle> {
+    let config = Config::load(&path).await?;
+
+    let handle = ConfigHandle {
+        path: path.clone(),
+        config: watch::Sender::new(Arc::new(config)),
+    };
+
+    let Some(mut changes) = watch_file(&path)? else {
+        debug!("Config directory does not exist - not watching for changes");
+        return Ok(handle);
+    };
+
+    let reloader = handle.clone();
+    tokio::spawn(async move {
+        while changes.recv().await.is_some() {
+            // Editors replacing the file remove it first - keep the last config until it is back
+            if !tokio::fs::try_exists(&reloader.path).await.unwrap_or(false) {
+                debug!("Config file is gone - keeping the active config");
+                continue;
+            }
+
+            if let Err(err) = reloader.reload().await {
+                warn!("Ignoring invalid config: {err:#}");
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Signals changes of the file, none if its directory does not exist
+pub(crate) fn watch_file(path: &Path) -> Result<Option<mpsc::Receiver<()>>> {
+    // Editors tend to replace files instead of writing them, so watch the
+    // whole directory and filter for the file
+    let Some(dir) = path.parent().filter(|dir| dir.is_dir()) else {
+        return Ok(None);
+    };
+
+    let (events_tx, mut events_rx) = mpsc::channel(1);
+
+    let mut watcher = RecommendedWatcher::new(
+        move |event: notify::Result<notify::Event>| {
+            let _ = events_tx.blocking_send(event);
+        },
+        notify::Config::default(),
+    )?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    let (changes_tx, changes_rx) = mpsc::channel(1);
+    let name = path.file_name().map(ToOwned::to_owned);
+    tokio::spawn(async move {
+        // Keep the watcher alive as long as the task is running
+        let _watcher = watcher;
+
+        while let Some(event) = events_rx.recv().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    warn!("Failed to watch file: {err}");
+                    continue;
+                }
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+                || !event.paths.iter().any(|p| p.file_name() == name.as_deref())
+            {
+                continue;
+            }
+
+            // Let the writer finish and collapse bursts of events into a single change
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            while events_rx.try_recv().is_ok() {}
+
+            if changes_tx.send(()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(Some(changes_rx))
+}
@@ -0,0 +1,260 @@
+use crate::callstate::{AgentState, Call, DialogKey, RecentEvent};
+use crate::sipsocket::message_summary::MessageSummary;
+use crate::sipsocket::{MetricsSnapshot, TracedMessage};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::select;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+#[cfg(unix)]
+mod unix;
+
+#[cfg(unix)]
+pub use unix::{follow, request, serve};
+
+#[cfg(windows)]
+mod windows;
+
+#[cfg(windows)]
+pub use windows::{follow, request, serve};
+
+/// A command sent to a running daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    Dnd { enabled: bool },
+    Dial { number: String },
+    Redial,
+    Callback,
+    Decline,
+    Reload,
+    AgentPause { reason: Option<String> },
+    AgentResume,
+    AgentWrapUp,
+    Reregister,
+    DumpState,
+
+    /// The last SIP messages, followed by new ones as they pass if `follow` is set
+    Trace {
+        #[serde(default)]
+        follow: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Status(Status),
+    State(Box<StateDump>),
+    Trace { messages: Vec<TracedMessage> },
+    Error { message: String },
+}
+
+impl From<Result<()>> for Response {
+    fn from(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::Ok,
+            Err(err) => Self::Error {
+                message: format!("{err:#}"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub registered: bool,
+    pub dnd: bool,
+    pub missed: u64,
+    pub calls: Vec<Call>,
+    pub agent: AgentState,
+
+    /// New voice messages
+    #[serde(default)]
+    pub voicemail: u32,
+
+    /// Messages received on the SIP socket and rejected by its limits
+    #[serde(default)]
+    pub socket: MetricsSnapshot,
+
+    /// Socket metrics of further accounts by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, MetricsSnapshot>,
+}
+
+/// Snapshot of the daemon state to attach to bug reports, free of secrets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDump {
+    pub taken_at: SystemTime,
+    pub version: String,
+
+    /// Hash of the active config, telling whether it changed without revealing it
+    pub config_hash: String,
+
+    /// Registration of the account given on the command line and further ones by name
+    pub registrations: BTreeMap<String, Registration>,
+
+    pub dnd: bool,
+    pub agent: AgentState,
+    pub missed: u64,
+    pub voicemail: MessageSummary,
+
+    /// Calls currently tracked
+    pub dialogs: Vec<Call>,
+
+    /// INVITEs not answered finally yet
+    pub transactions: Vec<PendingTransaction>,
+
+    /// The last call state transitions, oldest first
+    pub events: Vec<RecentEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Registration {
+    /// API base URL without credentials or query
+    pub server: String,
+
+    /// Whether the SIP socket is registered, if tracked for the account
+    pub registered: Option<bool>,
+
+    pub socket: MetricsSnapshot,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub dialog: DialogKey,
+    pub method: String,
+    pub cseq: Option<u32>,
+}
+
+/// Handles control requests on the daemon side
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, request: Request) -> impl Future<Output = Response> + Send;
+
+    /// Receives SIP messages as they pass - `None` if not traced
+    fn follow_trace(&self) -> Option<broadcast::Receiver<TracedMessage>> {
+        None
+    }
+}
+
+#[cfg(unix)]
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::runtime_dir()?.join("ucware").join("ctl.sock"))
+}
+
+#[cfg(windows)]
+pub fn default_path() -> Option<PathBuf> {
+    Some(PathBuf::from(r"\\.\pipe\ucware-ctl"))
+}
+
+/// Handles a single control connection on the daemon side.
+///
+/// The protocol is line based: each line is a JSON encoded [Request] which is
+/// answered by a single line containing the JSON encoded [Response]. A
+/// followed trace is answered by a line per message until the client hangs up.
+async fn handle(stream: impl AsyncRead + AsyncWrite, handler: Arc<impl Handler>) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                let response = Response::Error {
+                    message: format!("Invalid request: {err}"),
+                };
+                write_response(&mut writer, &response).await?;
+                continue;
+            }
+        };
+
+        debug!("Control request: {request:?}");
+
+        // Subscribe before taking the recent messages, so none falls in between
+        let live = match request {
+            Request::Trace { follow: true } => handler.follow_trace(),
+            _ => None,
+        };
+
+        let response = handler.handle(request).await;
+        write_response(&mut writer, &response).await?;
+
+        let Some(mut live) = live else {
+            continue;
+        };
+
+        loop {
+            select! {
+                message = live.recv() => match message {
+                    Ok(message) => {
+                        let response = Response::Trace { messages: vec![message] };
+                        write_response(&mut writer, &response).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Trace follower lagged behind, skipped {skipped} messages");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+
+                line = lines.next_line() => if line?.is_none() {
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_response(writer: &mut (impl AsyncWrite + Unpin), response: &Response) -> Result<()> {
+    let mut response = serde_json::to_vec(response)?;
+    response.push(b'\n');
+    writer.write_all(&response).await?;
+    Ok(())
+}
+
+/// Sends a single request over an established control connection
+async fn exchange(stream: impl AsyncRead + AsyncWrite, request: &Request) -> Result<Response> {
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    let mut request = serde_json::to_vec(request)?;
+    request.push(b'\n');
+    writer.write_all(&request).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .context("Daemon closed connection without response")?;
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Sends a single request and passes on every response until the daemon hangs up
+async fn stream(
+    stream: impl AsyncRead + AsyncWrite,
+    request: &Request,
+    mut on_response: impl FnMut(Response) -> Result<()>,
+) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    let mut request = serde_json::to_vec(request)?;
+    request.push(b'\n');
+    writer.write_all(&request).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        on_response(serde_json::from_str(&line)?)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::ctl::{Handler, Request, Response};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+
+/// Serves control requests on a Unix domain socket
+pub async fn serve(path: impl AsRef<Path>, handler: Arc<impl Handler>) -> Result<()> {
+    let path = path.as_ref();
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    // A left-over socket from a previous instance - the PID file ensures there
+    // is no other instance running
+    if tokio::fs::try_exists(path).await? {
+        tokio::fs::remove_file(path).await?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket: {path}", path = path.display()))?;
+
+    info!("Listening for control requests on {path}", path = path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(err) = super::handle(stream, handler).await {
+                warn!("Control connection failed: {err:#}");
+            }
+        });
+    }
+}
+
+/// Sends a single request to the daemon listening on the given socket
+pub async fn request(path: impl AsRef<Path>, request: &Request) -> Result<Response> {
+    super::exchange(connect(path.as_ref()).await?, request).await
+}
+
+/// Sends a single request and passes on every response until the daemon hangs up
+pub async fn follow(
+    path: impl AsRef<Path>,
+    request: &Request,
+    on_response: impl FnMut(Response) -> Result<()>,
+) -> Result<()> {
+    super::stream(connect(path.as_ref()).await?, request, on_response).await
+}
+
+async fn connect(path: &Path) -> Result<UnixStream> {
+    UnixStream::connect(path).await.with_context(|| {
+        format!("Failed to connect to daemon - is it running? ({path})", path = path.display())
+    })
+}
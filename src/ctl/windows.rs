@@ -0,0 +1,67 @@
+use crate::ctl::{Handler, Request, Response};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient, ServerOptions};
+use tracing::{info, warn};
+
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// Serves control requests on a named pipe
+pub async fn serve(path: impl AsRef<Path>, handler: Arc<impl Handler>) -> Result<()> {
+    let path = path.as_ref();
+
+    // Creating the first instance fails if another daemon already owns the pipe
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(path)
+        .with_context(|| format!("Failed to create control pipe: {path}", path = path.display()))?;
+
+    info!("Listening for control requests on {path}", path = path.display());
+
+    loop {
+        server.connect().await?;
+
+        // Create the next instance before handing off the connected one so
+        // clients never see the pipe missing
+        let stream = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(err) = super::handle(stream, handler).await {
+                warn!("Control connection failed: {err:#}");
+            }
+        });
+    }
+}
+
+/// Sends a single request to the daemon listening on the given pipe
+pub async fn request(path: impl AsRef<Path>, request: &Request) -> Result<Response> {
+    super::exchange(connect(path.as_ref()).await?, request).await
+}
+
+/// Sends a single request and passes on every response until the daemon hangs up
+pub async fn follow(
+    path: impl AsRef<Path>,
+    request: &Request,
+    on_response: impl FnMut(Response) -> Result<()>,
+) -> Result<()> {
+    super::stream(connect(path.as_ref()).await?, request, on_response).await
+}
+
+async fn connect(path: &Path) -> Result<NamedPipeClient> {
+    loop {
+        match ClientOptions::new().open(path) {
+            Ok(stream) => return Ok(stream),
+            Err(err) if err.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to connect to daemon - is it running? ({path})", path = path.display())
+                });
+            }
+        }
+    }
+}
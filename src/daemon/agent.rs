@@ -0,0 +1,73 @@
+use crate::callstate::AgentState;
+use crate::daemon::Daemon;
+use anyhow::Result;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Pause reason reported to the server during wrap-up
+const WRAP_UP_REASON: &str = "wrap-up";
+
+impl Daemon {
+    /// Pauses the agent in all queues
+    pub async fn pause(&self, reason: Option<String>) -> Result<()> {
+        info!("Pausing agent: {reason}", reason = reason.as_deref().unwrap_or("no reason"));
+
+        self.client.user().queues().pause(reason.as_deref()).await?;
+        self.calls.set_agent(AgentState::Paused { reason });
+
+        Ok(())
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        info!("Resuming agent");
+
+        self.client.user().queues().resume().await?;
+        self.calls.set_agent(AgentState::Available);
+
+        Ok(())
+    }
+
+    /// Pauses the agent for the configured wrap-up time.
+    ///
+    /// The agent is resumed automatically unless the state was changed in
+    /// the meantime.
+    pub async fn wrap_up(&self) -> Result<()> {
+        let duration = Duration::from_secs(self.config.get().agent.wrap_up);
+        if duration.is_zero() {
+            return Ok(());
+        }
+
+        // Do not override a manual pause
+        if matches!(self.calls.agent(), AgentState::Paused { .. }) {
+            return Ok(());
+        }
+
+        info!("Starting wrap-up for {secs}s", secs = duration.as_secs());
+
+        self.client.user().queues().pause(Some(WRAP_UP_REASON)).await?;
+
+        let until = SystemTime::now() + duration;
+        self.calls.set_agent(AgentState::WrapUp { until });
+
+        let client = self.client.clone();
+        let calls = self.calls.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+
+            if calls.agent() != (AgentState::WrapUp { until }) {
+                return;
+            }
+
+            info!("Wrap-up finished");
+
+            if let Err(err) = client.user().queues().resume().await {
+                warn!("Failed to resume after wrap-up: {err:#}");
+                return;
+            }
+
+            calls.set_agent(AgentState::Available);
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use crate::answerbot::transfer;
+use crate::callstate::{Call, DialogKey};
+use crate::daemon::Daemon;
+use crate::config::{Closed, HoldConfig};
+use crate::media::codec::Codecs;
+use crate::media::session::MediaSession;
+use crate::media::tts::Tts;
+use crate::media::wav;
+use crate::sipsocket::{AnsweredDialog, ServerTransaction};
+use anyhow::{Context, Result};
+use rsip::StatusCode;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+impl Daemon {
+    /// Answers a call while the office is closed and plays the announcement,
+    /// then transfers the caller to the redirect or hangs up.
+    ///
+    /// Calls are rejected as unavailable if they cannot be answered.
+    pub fn announce_closed(self: &Arc<Self>, call: Call, tx: ServerTransaction, closed: Closed) {
+        let (hangup_tx, hangup) = oneshot::channel();
+        self.answered.insert(call.key.clone(), hangup_tx);
+
+        let daemon = self.clone();
+        tokio::spawn(async move {
+            let key = call.key.clone();
+            if let Err(err) = daemon.announce(call, tx, closed, hangup).await {
+                warn!("Failed to announce office closed: {err:#}");
+            }
+            daemon.answered.remove(&key);
+        });
+    }
+
+    /// Ends the media of an answered call the caller hung up on
+    pub fn hung_up(&self, key: &DialogKey) -> bool {
+        let Some((_, hangup)) = self.answered.remove(key) else {
+            return false;
+        };
+
+        info!("Caller hung up on announcement");
+        let _ = hangup.send(());
+        true
+    }
+
+    async fn announce(
+        &self,
+        mut call: Call,
+        mut tx: ServerTransaction,
+        closed: Closed,
+        hangup: oneshot::Receiver<()>,
+    ) -> Result<()> {
+        let offer = String::from_utf8_lossy(&tx.request.body).into_owned();
+        let config = self.config.get();
+        let prepared = async {
+            let (media, sdp) = MediaSession::answer(&offer, &Codecs::default(), &config.media).await?;
+
+            let sample_rate = media.sample_rate();
+            let mut pcm = match closed.announcement {
+                Some(path) => tokio::task::spawn_blocking(move || wav::read(&path, sample_rate))
+                    .await?
+                    .context("Failed to read announcement")?,
+                None => Vec::new(),
+            };
+
+            if let Some(text) = &closed.announcement_text {
+                let tts = Tts::open_default(config.media.tts.clone().context("No speech synthesis configured")?)?;
+                pcm.extend(tts.speak(text, sample_rate).await.context("Failed to synthesize announcement")?);
+            }
+
+            anyhow::Ok((media, sdp, pcm))
+        };
+
+        let (mut media, sdp, pcm) = match prepared.await {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                tx.respond(StatusCode::TemporarilyUnavailable).send([]).await;
+                return Err(err);
+            }
+        };
+
+        let dialog = tx.answer(sdp).await?;
+
+        let played = tokio::select! {
+            played = play_closed(&mut media, &dialog, pcm, closed.redirect, &config.media.hold) => Some(played),
+            _ = hangup => None,
+        };
+
+        let stats = media.stop().await?;
+        info!("Media of call {call_id}: {stats}", call_id = dialog.call_id());
+        call.media = Some(stats);
+        self.calls.answered_ended(call);
+
+        // Hung up here unless the caller did
+        if let Some(played) = played {
+            let bye = dialog.bye().await;
+            played?;
+            bye?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays the announcement, then transfers the caller to the redirect if any
+async fn play_closed(
+    media: &mut MediaSession,
+    dialog: &AnsweredDialog,
+    pcm: Vec<i16>,
+    redirect: Option<String>,
+    hold: &HoldConfig,
+) -> Result<()> {
+    media.play(pcm).await?;
+
+    // The caller waits on hold for the transfer, see RFC 5589, section 6
+    if let Some(uri) = redirect
+        && let Err(err) = transfer(media, dialog, &uri, hold).await
+    {
+        warn!("{err:#}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use crate::ctl::StateDump;
+use crate::daemon::Daemon;
+use crate::notification;
+use crate::ucware::Client;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+/// Exit code after a panic, `EX_SOFTWARE` of sysexits(3)
+pub const EXIT_CODE: i32 = 70;
+
+/// Time given to clean up before exiting anyway, e.g. if the server does not answer
+const CLEANUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Set by the first panic, so further ones do not clean up again
+static CRASHED: AtomicBool = AtomicBool::new(false);
+
+/// Written after a panic to attach to bug reports, free of secrets like the state dump
+#[derive(Debug, Serialize)]
+pub struct CrashReport {
+    pub time: SystemTime,
+    pub version: String,
+    pub thread: Option<String>,
+    pub message: String,
+    pub location: Option<String>,
+    pub backtrace: String,
+
+    /// State right after the panic - unset if it could not be taken
+    pub state: Option<StateDump>,
+
+    /// Outcome of each cleanup step
+    pub cleanup: Vec<String>,
+}
+
+impl CrashReport {
+    fn new(info: &PanicHookInfo) -> Self {
+        Self {
+            time: SystemTime::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            thread: std::thread::current().name().map(str::to_string),
+            message: info.payload_as_str().unwrap_or("Box<dyn Any>").to_string(),
+            location: info.location().map(ToString::to_string),
+            backtrace: Backtrace::force_capture().to_string(),
+            state: None,
+            cleanup: Vec::new(),
+        }
+    }
+
+    /// Directory crash reports are written to
+    pub fn default_dir() -> Option<PathBuf> {
+        Some(dirs::state_dir().or_else(dirs::data_local_dir)?.join("ucware").join("crashes"))
+    }
+
+    /// Writes the report to a new file in the given directory and returns its path
+    pub fn write(&self, dir: impl Into<PathBuf>) -> Result<PathBuf> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let secs = self.time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = dir.join(format!("crash-{secs}-{pid}.json", pid = std::process::id()));
+
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {path}", path = path.display()))?;
+
+        Ok(path)
+    }
+}
+
+impl Daemon {
+    /// Cleans up and exits on the first panic of any thread.
+    ///
+    /// Tasks would otherwise die one by one while the registration stays
+    /// behind at the server and notifications of ringing calls stay on screen.
+    pub fn install_panic_hook(self: &Arc<Self>) {
+        let daemon = Arc::downgrade(self);
+
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+
+            // Panics while cleaning up just unwind the cleanup
+            if CRASHED.swap(true, Ordering::SeqCst) {
+                return;
+            }
+
+            let mut report = CrashReport::new(info);
+            crash(daemon.clone(), &mut report);
+
+            match CrashReport::default_dir()
+                .context("No location for crash reports available")
+                .and_then(|dir| report.write(dir))
+            {
+                Ok(path) => error!("Crashed, wrote report to {path}", path = path.display()),
+                Err(err) => error!("Crashed, failed to write report: {err:#}"),
+            }
+
+            std::process::exit(EXIT_CODE);
+        }));
+    }
+
+    /// Stores what is kept across runs, closes notifications and unregisters all accounts
+    async fn clean_up(&self) -> Vec<String> {
+        let mut steps = Vec::new();
+
+        let mut step = |name: &str, result: Result<()>| {
+            let outcome = match result {
+                Ok(()) => format!("{name}: done"),
+                Err(err) => format!("{name}: {err:#}"),
+            };
+            info!("Cleaning up after crash - {outcome}");
+            steps.push(outcome);
+        };
+
+        step("store missed call checkpoint", self.store_checkpoint());
+        step("close notifications", notification::close_stale(&self.store).await);
+
+        step("unregister", unregister(&self.client).await);
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|account| (account.key().clone(), account.value().clone()))
+            .collect::<Vec<_>>();
+        for (name, client) in accounts {
+            step(&format!("unregister account {name}"), unregister(&client).await);
+        }
+
+        steps
+    }
+}
+
+/// Cleans up on a fresh thread and runtime, as the panicking one may hold locks or be a runtime worker
+fn crash(daemon: Weak<Daemon>, report: &mut CrashReport) {
+    let (tx, rx) = mpsc::channel();
+
+    let spawned = std::thread::Builder::new().name("crash".to_string()).spawn(move || {
+        let Some(daemon) = daemon.upgrade() else {
+            return;
+        };
+
+        let _ = tx.send(Cleanup::State(daemon.dump_state()));
+
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                let _ = tx.send(Cleanup::Steps(vec![format!("start runtime: {err}")]));
+                return;
+            }
+        };
+
+        let steps = runtime.block_on(async {
+            tokio::time::timeout(CLEANUP_TIMEOUT, daemon.clean_up())
+                .await
+                .unwrap_or_else(|_| vec!["timed out".to_string()])
+        });
+        let _ = tx.send(Cleanup::Steps(steps));
+    });
+
+    if let Err(err) = spawned {
+        report.cleanup.push(format!("start cleanup: {err}"));
+        return;
+    }
+
+    // Waits a little longer than the cleanup itself, which may be stuck on a lock held by the panicking thread
+    let deadline = std::time::Instant::now() + CLEANUP_TIMEOUT + Duration::from_secs(1);
+    while let Ok(cleanup) = rx.recv_timeout(deadline.saturating_duration_since(std::time::Instant::now())) {
+        match cleanup {
+            Cleanup::State(state) => report.state = Some(state),
+            Cleanup::Steps(steps) => {
+                report.cleanup = steps;
+                return;
+            }
+        }
+    }
+
+    report.cleanup.push("did not finish".to_string());
+}
+
+enum Cleanup {
+    State(StateDump),
+    Steps(Vec<String>),
+}
+
+async fn unregister(client: &Client) -> Result<()> {
+    let (mut connection, slot) = client.registrar().await?;
+    connection.unregister(&slot.sip_username, &slot.sip_password, false).await
+}
@@ -0,0 +1,69 @@
+use crate::callstate::DEFAULT_ACCOUNT;
+use crate::ctl::{PendingTransaction, Registration, StateDump};
+use crate::daemon::Daemon;
+use crate::ucware::Client;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::SystemTime;
+use url::Url;
+
+impl Daemon {
+    /// Snapshot of the state for bug reports, leaving out tokens and the config itself
+    pub fn dump_state(&self) -> StateDump {
+        let config = self.config.get();
+
+        let mut hasher = DefaultHasher::new();
+        format!("{config:?}").hash(&mut hasher);
+
+        let mut registrations = self
+            .accounts
+            .iter()
+            .map(|account| (account.key().clone(), registration(account.value(), None)))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        registrations.insert(
+            DEFAULT_ACCOUNT.to_string(),
+            registration(&self.client, Some(self.calls.registered())),
+        );
+
+        let transactions = self
+            .invites
+            .iter()
+            .map(|invite| PendingTransaction {
+                dialog: invite.key().clone(),
+                method: invite.request.method.to_string(),
+                cseq: invite.seq().ok(),
+            })
+            .collect();
+
+        StateDump {
+            taken_at: SystemTime::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config_hash: format!("{hash:016x}", hash = hasher.finish()),
+            registrations,
+            dnd: self.calls.dnd(&config),
+            agent: self.calls.agent(),
+            missed: self.calls.missed(),
+            voicemail: self.calls.voicemail(),
+            dialogs: self.calls.calls(),
+            transactions,
+            events: self.calls.recent_events(),
+        }
+    }
+}
+
+fn registration(client: &Client, registered: Option<bool>) -> Registration {
+    Registration {
+        server: redacted(client.url()),
+        registered,
+        socket: client.socket_metrics(),
+    }
+}
+
+/// The URL without credentials or query, which may carry tokens
+fn redacted(url: &Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.set_fragment(None);
+    url.to_string()
+}
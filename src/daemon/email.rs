@@ -0,0 +1,81 @@
+use crate::callstate::{Call, Event};
+use crate::daemon::Daemon;
+use crate::notification::email;
+use chrono::{DateTime, Local};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+impl Daemon {
+    /// Emails missed calls and new voicemails, picking up changes of the config
+    pub async fn send_emails(&self) {
+        let mut events = self.calls.events();
+        let mut voicemails = self.calls.voicemail().new;
+
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Skipped {skipped} events");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            let Some(config) = self.config.get().email.clone() else {
+                continue;
+            };
+
+            let (subject, body) = match event {
+                Event::Cancelled { call, missed: true } if config.missed => (
+                    format!("Missed call from {caller}", caller = call.caller.display()),
+                    details(&call),
+                ),
+
+                Event::Voicemail { summary } => {
+                    let previous = std::mem::replace(&mut voicemails, summary.new);
+                    if !config.voicemail || summary.new <= previous {
+                        continue;
+                    }
+
+                    (
+                        "New voicemail".to_string(),
+                        format!("You have {new} new and {old} old voicemails.", new = summary.new, old = summary.old),
+                    )
+                }
+
+                _ => continue,
+            };
+
+            if let Err(err) = email::send(&config, &subject, &body).await {
+                warn!("{err:#}");
+            }
+        }
+    }
+}
+
+/// Description of the caller for the body of an email
+fn details(call: &Call) -> String {
+    let caller = &call.caller;
+
+    let mut details = vec![
+        format!("Name: {name}", name = caller.name.as_deref().unwrap_or("Unknown")),
+        format!("Number: {number}", number = caller.number.as_deref().unwrap_or("Unknown")),
+        format!("Address: {uri}", uri = caller.uri),
+        format!("Time: {time}", time = DateTime::<Local>::from(call.since).format("%Y-%m-%d %H:%M:%S")),
+    ];
+
+    if let Some(forwarded) = &call.forwarded {
+        details.push(format!("Forwarded: {forwarded}", forwarded = forwarded.description()));
+    }
+
+    if let Some(queue) = call.ucware.queue() {
+        details.push(format!("Queue: {queue}"));
+    }
+
+    details.push(format!("Account: {account}", account = call.origin.account));
+    if let Some(slot) = &call.origin.slot {
+        details.push(format!("Slot: {slot}"));
+    }
+
+    details.join("\n")
+}
@@ -0,0 +1,22 @@
+use crate::daemon::Daemon;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// Interval to enforce the retention config in while running
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+impl Daemon {
+    /// Removes local data older than allowed, picking up changes of the config
+    pub async fn maintain(&self) {
+        let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let config = self.config.get();
+            match self.store.enforce_retention(&config.retention, SystemTime::now()) {
+                Ok(purged) => debug!("Maintenance purged {purged}"),
+                Err(err) => warn!("Maintenance failed: {err:#}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::callstate::{Call, Caller, DialogKey, Event, Origin};
+use crate::daemon::Daemon;
+use crate::store::LastNumber;
+use crate::ucware::user::JournalEntry;
+use anyhow::Result;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Checkpoint of the last time the daemon was known to be running
+const CHECKPOINT: &str = "missed-calls";
+
+/// Interval to advance the checkpoint in while running
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+impl Daemon {
+    /// Records calls missed since the daemon last ran according to the server call history.
+    ///
+    /// There is nothing to catch up on for the very first run.
+    pub async fn sync_missed(&self) -> Result<Vec<Call>> {
+        let now = SystemTime::now();
+
+        let Some(since) = self.store.checkpoint(CHECKPOINT)? else {
+            self.store.set_checkpoint(CHECKPOINT, now)?;
+            return Ok(Vec::new());
+        };
+
+        let since = since.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let entries = self.client.user().journal().get_since(since).await?;
+
+        let missed = entries
+            .iter()
+            .filter(|entry| entry.is_missed())
+            .map(missed_call)
+            .collect::<Vec<_>>();
+
+        for call in &missed {
+            info!("Missed call while offline: {caller}", caller = call.caller.display());
+            self.calls.add_missed(call.clone());
+        }
+
+        if let Some(call) = missed.iter().filter(|call| !call.caller.anonymous).max_by_key(|call| call.since) {
+            self.remember_missed(call);
+        }
+
+        self.store.set_checkpoint(CHECKPOINT, now)?;
+
+        Ok(missed)
+    }
+
+    /// Remembers the number of each call missed while running for callbacks
+    pub async fn track_missed(&self) {
+        let mut events = self.calls.events();
+        loop {
+            match events.recv().await {
+                Ok(Event::Cancelled { call, missed: true }) => self.remember_missed(&call),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => warn!("Skipped {skipped} events"),
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    fn remember_missed(&self, call: &Call) {
+        let Some(number) = call.caller.number.as_deref().filter(|_| !call.caller.anonymous) else {
+            return;
+        };
+
+        if let Err(err) = self.store.set_last_number(LastNumber::Missed, number) {
+            warn!("Failed to remember missed number: {err:#}");
+        }
+    }
+
+    /// Advances the checkpoint while running so calls notified already are not synced again
+    pub async fn track_checkpoint(&self) {
+        let mut interval = tokio::time::interval(CHECKPOINT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.store_checkpoint() {
+                warn!("Failed to store checkpoint: {err:#}");
+            }
+        }
+    }
+
+    /// Marks calls until now as notified already
+    pub(super) fn store_checkpoint(&self) -> Result<()> {
+        self.store.set_checkpoint(CHECKPOINT, SystemTime::now())
+    }
+}
+
+fn missed_call(entry: &JournalEntry) -> Call {
+    Call {
+        key: DialogKey {
+            call_id: entry.id.clone(),
+            from_tag: None,
+        },
+        caller: Caller {
+            name: entry.name.clone(),
+            number: entry.number.clone(),
+            uri: entry
+                .number
+                .as_ref()
+                .map(|number| format!("tel:{number}"))
+                .unwrap_or_default(),
+            anonymous: entry.number.is_none(),
+        },
+        since: UNIX_EPOCH + Duration::from_secs(entry.started.max(0) as u64),
+        origin: Origin::default(),
+        forwarded: None,
+        reason: None,
+        ucware: Default::default(),
+        sources: Vec::new(),
+        media: None,
+    }
+}
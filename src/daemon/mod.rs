@@ -0,0 +1,119 @@
+use crate::callstate::{CallState, DialogKey};
+use crate::config::ConfigHandle;
+use crate::ctl;
+use crate::sipsocket::{ServerTransaction, TracedMessage};
+use crate::store::Store;
+use crate::ucware::Client;
+use dashmap::DashMap;
+use std::sync::Arc;
+use tokio::sync::{broadcast, oneshot, Notify};
+use tracing::info;
+
+mod agent;
+mod announcement;
+mod crash;
+mod dump;
+mod email;
+mod webhooks;
+mod maintenance;
+mod missed;
+mod process;
+mod ringing;
+mod supervisor;
+
+pub use crash::{CrashReport, EXIT_CODE};
+pub use process::{DaemonArgs, PidFile};
+pub use supervisor::Supervisor;
+
+/// The state shared by all parts of a running notifier
+pub struct Daemon {
+    pub client: Client,
+    pub config: ConfigHandle,
+    pub calls: Arc<CallState>,
+    pub store: Store,
+
+    /// Signals the SIP sockets of all accounts to register again, e.g. after credential rotation
+    pub reregister: Notify,
+
+    /// INVITE transactions of ringing calls, kept to decline them on request
+    pub invites: DashMap<DialogKey, ServerTransaction>,
+
+    /// Calls answered with an announcement, kept to end them once the caller hangs up
+    pub answered: DashMap<DialogKey, oneshot::Sender<()>>,
+
+    /// Clients of further accounts by name, see [`crate::config::AccountConfig`]
+    pub accounts: DashMap<String, Client>,
+}
+
+impl Daemon {
+    pub fn new(client: Client, config: ConfigHandle, store: Store) -> Self {
+        Self {
+            client,
+            config,
+            calls: Arc::new(CallState::new()),
+            store,
+            reregister: Notify::new(),
+            invites: DashMap::new(),
+            answered: DashMap::new(),
+            accounts: DashMap::new(),
+        }
+    }
+}
+
+impl ctl::Handler for Daemon {
+    async fn handle(&self, request: ctl::Request) -> ctl::Response {
+        match request {
+            ctl::Request::Status => ctl::Response::Status(ctl::Status {
+                registered: self.calls.registered(),
+                dnd: self.calls.dnd(&self.config.get()),
+                missed: self.calls.missed(),
+                calls: self.calls.calls(),
+                agent: self.calls.agent(),
+                voicemail: self.calls.voicemail().new,
+                socket: self.client.socket_metrics(),
+                accounts: self
+                    .accounts
+                    .iter()
+                    .map(|account| (account.key().clone(), account.socket_metrics()))
+                    .collect(),
+            }),
+
+            ctl::Request::Dnd { enabled } => {
+                info!("Switching DND {state}", state = if enabled { "on" } else { "off" });
+                self.calls.set_dnd(enabled);
+                ctl::Response::Ok
+            }
+
+            ctl::Request::Dial { number } => self.dial(&number).await.into(),
+
+            ctl::Request::Redial => self.redial().await.into(),
+
+            ctl::Request::Callback => self.callback().await.into(),
+
+            ctl::Request::Decline => self.decline().await.into(),
+
+            ctl::Request::Reload => self.config.reload().await.into(),
+
+            ctl::Request::AgentPause { reason } => self.pause(reason).await.into(),
+
+            ctl::Request::AgentResume => self.resume().await.into(),
+
+            ctl::Request::AgentWrapUp => self.wrap_up().await.into(),
+
+            ctl::Request::Reregister => {
+                self.reregister.notify_waiters();
+                ctl::Response::Ok
+            }
+
+            ctl::Request::DumpState => ctl::Response::State(Box::new(self.dump_state())),
+
+            ctl::Request::Trace { .. } => ctl::Response::Trace {
+                messages: self.client.sip_trace().recent(),
+            },
+        }
+    }
+
+    fn follow_trace(&self) -> Option<broadcast::Receiver<TracedMessage>> {
+        Some(self.client.sip_trace().follow())
+    }
+}
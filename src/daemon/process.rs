@@ -0,0 +1,133 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    /// Detach from the terminal and run in the background
+    #[cfg(unix)]
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Run under the Windows service manager
+    #[cfg(windows)]
+    #[arg(long)]
+    pub service: bool,
+
+    /// PID file used to ensure only a single instance is running
+    #[arg(long)]
+    pub pidfile: Option<PathBuf>,
+
+    /// Log file receiving all output when running as daemon
+    #[arg(long)]
+    pub logfile: Option<PathBuf>,
+}
+
+impl DaemonArgs {
+    /// Acquires the PID file and detaches if requested.
+    ///
+    /// This forks the process and must therefore be called before any threads
+    /// (including the async runtime) are started.
+    pub fn setup(&self, name: &str) -> Result<Option<PidFile>> {
+        let pidfile = self
+            .pidfile
+            .clone()
+            .or_else(|| Some(dirs::runtime_dir()?.join("ucware").join(format!("{name}.pid"))))
+            .map(PidFile::acquire)
+            .transpose()?;
+
+        #[cfg(unix)]
+        if self.daemon {
+            let logfile = self
+                .logfile
+                .clone()
+                .or_else(|| Some(dirs::state_dir()?.join("ucware").join(format!("{name}.log"))))
+                .context("No log file specified and no default location available")?;
+            detach(&logfile)?;
+        }
+
+        let pidfile = pidfile.map(PidFile::write).transpose()?;
+
+        Ok(pidfile)
+    }
+}
+
+/// An exclusively locked file containing the PID of the running process
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    pub fn acquire(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open PID file: {path}", path = path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut pid = String::new();
+                file.read_to_string(&mut pid)?;
+                bail!("Another instance is already running (PID {pid})", pid = pid.trim());
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to lock PID file: {path}", path = path.display())
+                });
+            }
+        }
+
+        Ok(Self { path, file })
+    }
+
+    /// Writes the PID of the current process - must be called after forking
+    fn write(mut self) -> Result<Self> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{pid}", pid = std::process::id())?;
+        self.file.flush()?;
+
+        Ok(self)
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn detach(logfile: &Path) -> Result<()> {
+    if let Some(dir) = logfile.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(logfile)
+        .with_context(|| format!("Failed to open log file: {logfile}", logfile = logfile.display()))?;
+
+    daemonize::Daemonize::new()
+        .working_directory(std::env::current_dir()?)
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .start()
+        .context("Failed to daemonize")?;
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use crate::daemon::Daemon;
+use crate::store::LastNumber;
+use anyhow::{bail, Context, Result};
+use rsip::StatusCode;
+use tracing::{info, warn};
+
+impl Daemon {
+    /// Declines all ringing calls on this slot
+    pub async fn decline(&self) -> Result<()> {
+        let keys = self.invites.iter().map(|entry| entry.key().clone()).collect::<Vec<_>>();
+        if keys.is_empty() {
+            bail!("No ringing call");
+        }
+
+        for key in keys {
+            let Some((key, mut tx)) = self.invites.remove(&key) else {
+                continue;
+            };
+
+            info!("Declining call {key:?}");
+            tx.respond(StatusCode::Decline).send([]).await;
+            self.calls.screened(&key);
+        }
+
+        Ok(())
+    }
+
+    pub async fn dial(&self, number: &str) -> Result<()> {
+        info!("Dialing {number}");
+        self.client.user().calls().dial(number).await?;
+
+        if let Err(err) = self.store.set_last_number(LastNumber::Dialed, number) {
+            warn!("Failed to remember dialed number: {err:#}");
+        }
+        Ok(())
+    }
+
+    /// Dials the number last dialed again
+    pub async fn redial(&self) -> Result<()> {
+        let number = self.store.last_number(LastNumber::Dialed)?.context("Nothing dialed yet")?;
+        self.dial(&number).await
+    }
+
+    /// Calls back the last missed caller
+    pub async fn callback(&self) -> Result<()> {
+        let number = self.store.last_number(LastNumber::Missed)?.context("No missed call to return")?;
+        self.dial(&number).await
+    }
+}
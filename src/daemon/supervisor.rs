@@ -0,0 +1,61 @@
+use crate::config::SupervisorConfig;
+use crate::sipsocket::RetryAfter;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const MAX_RESTARTS: u32 = 5;
+const WINDOW: Duration = Duration::from_secs(600);
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound of the delay, however often restarted
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Decides whether and when failed parts of a daemon are restarted.
+///
+/// Allows a limited number of restarts within a sliding window, each waiting
+/// longer than the one before, so a persistent failure ends the process instead
+/// of hammering the server.
+#[derive(Debug)]
+pub struct Supervisor {
+    max_restarts: u32,
+    window: Duration,
+    backoff: Duration,
+
+    /// Times of the restarts within the window, oldest first
+    restarts: VecDeque<Instant>,
+}
+
+impl Supervisor {
+    pub fn new(config: &SupervisorConfig) -> Self {
+        Self {
+            max_restarts: config.max_restarts.unwrap_or(MAX_RESTARTS),
+            window: config.window.map_or(WINDOW, |window| window.0),
+            backoff: config.backoff.map_or(BACKOFF, |backoff| backoff.0),
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Delay before restarting after a failure now - `None` if the budget is used up
+    pub fn restart(&mut self) -> Option<Duration> {
+        self.restart_at(Instant::now())
+    }
+
+    /// Delay before restarting after a failure at the given time - `None` if the budget is used up
+    pub fn restart_at(&mut self, now: Instant) -> Option<Duration> {
+        while self.restarts.front().is_some_and(|restart| now.duration_since(*restart) >= self.window) {
+            self.restarts.pop_front();
+        }
+
+        if self.restarts.len() >= self.max_restarts as usize {
+            return None;
+        }
+
+        let delay = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(self.restarts.len() as u32))
+            .min(MAX_BACKOFF);
+        self.restarts.push_back(now);
+
+        Some(RetryAfter(delay).jittered())
+    }
+}
@@ -0,0 +1,92 @@
+use crate::callstate::{Call, Event};
+use crate::config::Template;
+use crate::daemon::Daemon;
+use crate::filter::Filters;
+use crate::notification::webhook;
+use crate::sipsocket::headers::Redirection;
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+impl Daemon {
+    /// Posts calls and new voicemails to the configured webhooks, picking up changes of the config
+    pub async fn post_webhooks(&self) {
+        let mut events = self.calls.events();
+        let mut voicemails = self.calls.voicemail().new;
+        let mut filters = Filters::default();
+
+        // Message to reply to per webhook URL and Call-ID
+        let mut threads = HashMap::<(String, String), String>::new();
+
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Skipped {skipped} events");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            let config = self.config.get();
+
+            // Filters may drop events or change them for all webhooks
+            filters.update(&config.event_filters);
+            if let Some(filtered) = filters.apply(event.clone()) {
+                for webhook in &config.webhooks {
+                    let (text, call_id) = match &filtered {
+                        Event::Incoming { call, .. } => (render(&webhook.incoming, call), &call.key.call_id),
+                        Event::Cancelled { call, missed: true } => (render(&webhook.missed, call), &call.key.call_id),
+                        Event::Voicemail { summary } if summary.new > voicemails => {
+                            let new = summary.new.to_string();
+                            let old = summary.old.to_string();
+                            (webhook.voicemail.render(&[("new", &new), ("old", &old)]), &String::new())
+                        }
+                        _ => continue,
+                    };
+
+                    if text.is_empty() {
+                        continue;
+                    }
+
+                    let key = (webhook.url.clone(), call_id.clone());
+                    let thread = threads.get(&key).map(String::as_str);
+                    match webhook::post(webhook, &text, thread).await {
+                        Ok(Some(ts)) if thread.is_none() && !call_id.is_empty() => {
+                            threads.insert(key, ts);
+                        }
+                        Ok(_) => {}
+                        Err(err) => warn!("{err:#}"),
+                    }
+                }
+            }
+
+            match event {
+                Event::Voicemail { summary } => voicemails = summary.new,
+                Event::Cancelled { call, .. }
+                | Event::Ended { call }
+                | Event::Replaced { call }
+                | Event::Screened { call } => threads.retain(|(_, call_id), _| *call_id != call.key.call_id),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn render(template: &Template, call: &Call) -> String {
+    let forwarded = call
+        .forwarded
+        .as_ref()
+        .map(Redirection::description)
+        .unwrap_or_default();
+
+    template.render(&[
+        ("name", call.caller.name.as_deref().unwrap_or("Unknown")),
+        ("number", call.caller.number.as_deref().unwrap_or_default()),
+        ("uri", call.caller.uri.as_str()),
+        ("forwarded", forwarded.as_str()),
+        ("queue", call.ucware.queue().unwrap_or_default()),
+        ("account", call.origin.account.as_str()),
+        ("slot", call.origin.slot.as_deref().unwrap_or_default()),
+    ])
+}
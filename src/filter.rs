@@ -0,0 +1,175 @@
+//! Event filters implemented by WASM modules, run before events reach notifications and webhooks.
+//!
+//! # Interface
+//!
+//! A module gets no imports and must export:
+//!
+//! - `memory`: its linear memory
+//! - `alloc(len: i32) -> i32`: reserves `len` bytes for the event passed in
+//! - `filter(ptr: i32, len: i32) -> i64`: processes the JSON of the event, as
+//!   printed by `ucware-call-notify --stdout-json`, and returns
+//!   - `0` to pass the event on unchanged
+//!   - `-1` to drop it
+//!   - `ptr << 32 | len` of the JSON of an event replacing it
+//!
+//! Each event runs in a fresh instance with limited memory and fuel, so
+//! modules cannot keep state or stall the daemon. A failing module passes the
+//! event on unchanged.
+
+use crate::callstate::Event;
+use crate::config::EventFilterConfig;
+use tracing::warn;
+
+/// Instructions a module may run per event unless configured otherwise
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Linear memory a module may use
+#[cfg(feature = "wasm")]
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+/// The configured event filters, applied in order
+#[derive(Default)]
+pub struct Filters {
+    configs: Vec<EventFilterConfig>,
+
+    #[cfg(feature = "wasm")]
+    filters: Vec<wasm::EventFilter>,
+}
+
+impl Filters {
+    /// Loads the modules, skipping those failing to load
+    pub fn load(configs: &[EventFilterConfig]) -> Self {
+        #[cfg(feature = "wasm")]
+        let filters = configs
+            .iter()
+            .filter_map(|config| match wasm::EventFilter::load(config) {
+                Ok(filter) => Some(filter),
+                Err(err) => {
+                    warn!("Ignoring event filter: {err:#}");
+                    None
+                }
+            })
+            .collect();
+
+        #[cfg(not(feature = "wasm"))]
+        if !configs.is_empty() {
+            warn!("Built without wasm support, ignoring event filters");
+        }
+
+        Self {
+            configs: configs.to_vec(),
+
+            #[cfg(feature = "wasm")]
+            filters,
+        }
+    }
+
+    /// Reloads the modules if the configured ones changed
+    pub fn update(&mut self, configs: &[EventFilterConfig]) {
+        if self.configs != configs {
+            *self = Self::load(configs);
+        }
+    }
+
+    /// The event as passed on by all filters, none if one dropped it
+    pub fn apply(&self, event: Event) -> Option<Event> {
+        #[cfg(feature = "wasm")]
+        let event = self.filters.iter().try_fold(event, |event, filter| match filter.apply(&event) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("Event filter failed: {err:#}");
+                Some(event)
+            }
+        })?;
+
+        Some(event)
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod wasm {
+    use super::{DEFAULT_FUEL, MAX_MEMORY};
+    use crate::callstate::Event;
+    use crate::config::EventFilterConfig;
+    use anyhow::{bail, Context, Result};
+    use std::path::PathBuf;
+    use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    /// Result of `filter` passing the event on unchanged
+    const KEEP: i64 = 0;
+
+    /// Result of `filter` dropping the event
+    const DROP: i64 = -1;
+
+    pub struct EventFilter {
+        path: PathBuf,
+        engine: Engine,
+        module: Module,
+        fuel: u64,
+    }
+
+    impl EventFilter {
+        pub fn load(config: &EventFilterConfig) -> Result<Self> {
+            let path = &config.module;
+            let engine = Engine::new(Config::new().consume_fuel(true))?;
+            let module = Module::from_file(&engine, path)
+                .with_context(|| format!("Failed to load event filter {path}", path = path.display()))?;
+
+            if let Some(import) = module.imports().next() {
+                bail!(
+                    "Event filter {path} imports {module}::{name}, but filters get no host functions",
+                    path = path.display(),
+                    module = import.module(),
+                    name = import.name()
+                );
+            }
+
+            Ok(Self {
+                path: path.clone(),
+                engine,
+                module,
+                fuel: config.fuel.unwrap_or(DEFAULT_FUEL),
+            })
+        }
+
+        /// The event as passed on by the module, none if it dropped it
+        pub fn apply(&self, event: &Event) -> Result<Option<Event>> {
+            self.run(event)
+                .with_context(|| format!("Event filter {path}", path = self.path.display()))
+        }
+
+        fn run(&self, event: &Event) -> Result<Option<Event>> {
+            let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+            let mut store = Store::new(&self.engine, limits);
+            store.limiter(|limits: &mut StoreLimits| limits);
+            store.set_fuel(self.fuel)?;
+
+            let instance = Instance::new(&mut store, &self.module, &[])?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .context("Missing export memory")?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let filter = instance.get_typed_func::<(i32, i32), i64>(&mut store, "filter")?;
+
+            let input = serde_json::to_vec(event)?;
+            let len = i32::try_from(input.len())?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as usize, &input)?;
+
+            match filter.call(&mut store, (ptr, len))? {
+                KEEP => Ok(Some(event.clone())),
+                DROP => Ok(None),
+                result => {
+                    let ptr = (result >> 32) as u32 as usize;
+                    let len = result as u32 as usize;
+                    let output = memory
+                        .data(&store)
+                        .get(ptr..ptr + len)
+                        .context("Returned event is out of bounds")?;
+                    let event = serde_json::from_slice(output).context("Invalid returned event")?;
+                    Ok(Some(event))
+                }
+            }
+        }
+    }
+}
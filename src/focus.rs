@@ -0,0 +1,78 @@
+//! Do-not-disturb state of the desktop, e.g. while presenting
+
+/// Whether the desktop asks not to be disturbed, `false` if that cannot be told
+pub async fn do_not_disturb() -> bool {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    match dbus::do_not_disturb().await {
+        Ok(state) => return state,
+        Err(err) => tracing::debug!("Failed to query do-not-disturb state: {err:#}"),
+    }
+
+    false
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod dbus {
+    use anyhow::Result;
+    use zbus::zvariant::{OwnedValue, Value};
+    use zbus::Connection;
+
+    const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+    const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+    /// Properties of notification servers telling whether notifications are held back
+    const INHIBITED: &[(&str, &str)] = &[
+        // KDE Plasma
+        ("org.freedesktop.Notifications", "Inhibited"),
+        // dunst
+        ("org.dunstproject.cmd0", "paused"),
+    ];
+
+    pub async fn do_not_disturb() -> Result<bool> {
+        let conn = Connection::session().await?;
+
+        for (interface, property) in INHIBITED {
+            if let Ok(value) = get_property(&conn, interface, property).await {
+                return Ok(bool::try_from(value)?);
+            }
+        }
+
+        // GNOME does not tell the notification server, but exposes its setting
+        let value = read_setting(&conn, "org.gnome.desktop.notifications", "show-banners").await?;
+        Ok(!bool::try_from(value)?)
+    }
+
+    async fn get_property(conn: &Connection, interface: &str, property: &str) -> Result<OwnedValue> {
+        let reply = conn
+            .call_method(
+                Some(NOTIFICATIONS),
+                NOTIFICATIONS_PATH,
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &(interface, property),
+            )
+            .await?;
+
+        Ok(reply.body().deserialize()?)
+    }
+
+    /// Reads a setting through the desktop portal
+    async fn read_setting(conn: &Connection, namespace: &str, key: &str) -> Result<OwnedValue> {
+        let reply = conn
+            .call_method(
+                Some("org.freedesktop.portal.Desktop"),
+                "/org/freedesktop/portal/desktop",
+                Some("org.freedesktop.portal.Settings"),
+                "Read",
+                &(namespace, key),
+            )
+            .await?;
+
+        // Older portals wrap the value in another variant
+        let value: OwnedValue = reply.body().deserialize()?;
+        match &*value {
+            Value::Value(inner) => Ok(inner.try_to_owned()?),
+            _ => Ok(value),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::config::KdeConnectConfig;
+use crate::notification::push::Message;
+use anyhow::{bail, Result};
+use tracing::debug;
+use zbus::Connection;
+
+const SERVICE: &str = "org.kde.kdeconnect";
+const DAEMON_PATH: &str = "/modules/kdeconnect";
+
+/// Shows the message on the device by sending a ping with it
+pub async fn send(config: &KdeConnectConfig, message: &Message) -> Result<()> {
+    let conn = Connection::session().await?;
+
+    let devices = match &config.device {
+        Some(device) => vec![device.clone()],
+        None => reachable_devices(&conn).await?,
+    };
+    if devices.is_empty() {
+        bail!("No paired device reachable");
+    }
+
+    let text = format!("{title}: {body}", title = message.title, body = message.body);
+    for device in devices {
+        debug!("Forwarding call to KDE Connect device {device}");
+        conn.call_method(
+            Some(SERVICE),
+            format!("{DAEMON_PATH}/devices/{device}/ping").as_str(),
+            Some("org.kde.kdeconnect.device.ping"),
+            "sendPing",
+            &(text.as_str(),),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn reachable_devices(conn: &Connection) -> Result<Vec<String>> {
+    let reply = conn
+        .call_method(
+            Some(SERVICE),
+            DAEMON_PATH,
+            Some("org.kde.kdeconnect.daemon"),
+            "devices",
+            // Only reachable, only paired
+            &(true, true),
+        )
+        .await?;
+
+    Ok(reply.body().deserialize()?)
+}
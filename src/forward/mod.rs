@@ -0,0 +1,36 @@
+//! Forwarding of incoming calls to a phone
+
+use crate::config::ForwardConfig;
+use crate::notification::push::{self, Message};
+use anyhow::{bail, Result};
+use tracing::warn;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod kde_connect;
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod kde_connect {
+    use crate::config::KdeConnectConfig;
+    use anyhow::{bail, Result};
+
+    pub async fn send(_: &KdeConnectConfig, _: &crate::notification::push::Message) -> Result<()> {
+        bail!("KDE Connect is not available on this platform");
+    }
+}
+
+/// Sends the message to the configured targets, falling back to ntfy if KDE Connect fails
+pub async fn forward(config: &ForwardConfig, message: &Message) -> Result<()> {
+    if let Some(kde_connect) = &config.kde_connect {
+        match kde_connect::send(kde_connect, message).await {
+            Ok(()) => return Ok(()),
+            Err(err) if config.ntfy.is_some() => warn!("Failed to forward via KDE Connect: {err:#}"),
+            Err(err) => return Err(err),
+        }
+    }
+
+    if let Some(ntfy) = &config.ntfy {
+        return push::ntfy::send(ntfy, message).await;
+    }
+
+    bail!("No forwarding target available");
+}
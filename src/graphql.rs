@@ -0,0 +1,282 @@
+//! GraphQL API of a running daemon for web dashboards, served next to the HTTP view
+//!
+//! Queries are posted as JSON to `/graphql`. Subscriptions are streamed as
+//! server-sent events from `/graphql/events?query=...`, so dashboards can use
+//! a plain `EventSource`. Both require the token of the HTTP view.
+
+use crate::callstate::{self, Event};
+use crate::daemon::Daemon;
+use crate::sipsocket::headers::Redirection;
+use crate::ucware::user::Direction;
+use async_graphql::{EmptyMutation, Json, Object, Schema, SimpleObject, Subscription};
+use axum::extract::{Query as QueryString, State};
+use axum::response::sse::{self, Sse};
+use axum::routing::{get, post};
+use axum::Router;
+use futures::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// Range of the call history returned unless asked otherwise
+const DEFAULT_HISTORY: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub type DaemonSchema = Schema<Query, EmptyMutation, Subscription>;
+
+pub fn schema(daemon: Arc<Daemon>) -> DaemonSchema {
+    Schema::build(Query, EmptyMutation, Subscription).data(daemon).finish()
+}
+
+/// Routes of the API, to be nested at `/graphql`
+pub fn router(daemon: Arc<Daemon>) -> Router {
+    Router::new()
+        .route("/", post(query))
+        .route("/events", get(subscribe))
+        .with_state(schema(daemon))
+}
+
+async fn query(
+    State(schema): State<DaemonSchema>,
+    axum::Json(request): axum::Json<async_graphql::Request>,
+) -> axum::Json<async_graphql::Response> {
+    axum::Json(schema.execute(request).await)
+}
+
+#[derive(Debug, Deserialize)]
+struct Subscribe {
+    query: String,
+
+    /// Variables as JSON object
+    variables: Option<String>,
+}
+
+async fn subscribe(
+    State(schema): State<DaemonSchema>,
+    QueryString(subscribe): QueryString<Subscribe>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let mut request = async_graphql::Request::new(subscribe.query);
+    if let Some(variables) = subscribe.variables {
+        match serde_json::from_str(&variables) {
+            Ok(variables) => request = request.variables(async_graphql::Variables::from_json(variables)),
+            Err(err) => warn!("Ignoring invalid GraphQL variables: {err}"),
+        }
+    }
+
+    let events = schema.execute_stream(request).filter_map(|response| async move {
+        match sse::Event::default().json_data(response) {
+            Ok(event) => Some(Ok(event)),
+            Err(err) => {
+                warn!("Failed to encode GraphQL response: {err}");
+                None
+            }
+        }
+    });
+
+    Sse::new(events).keep_alive(sse::KeepAlive::default())
+}
+
+fn daemon<'a>(ctx: &async_graphql::Context<'a>) -> &'a Arc<Daemon> {
+    ctx.data_unchecked::<Arc<Daemon>>()
+}
+
+fn unix_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() as i64)
+}
+
+#[derive(SimpleObject)]
+pub struct Call {
+    call_id: String,
+    caller_name: Option<String>,
+    caller_number: Option<String>,
+    caller_uri: String,
+    anonymous: bool,
+    account: String,
+    slot: Option<String>,
+
+    /// Where the call was forwarded from, if it was
+    forwarded: Option<String>,
+
+    /// Unix timestamp the call started ringing at
+    since: i64,
+}
+
+impl From<&callstate::Call> for Call {
+    fn from(call: &callstate::Call) -> Self {
+        Self {
+            call_id: call.key.call_id.clone(),
+            caller_name: call.caller.name.clone(),
+            caller_number: call.caller.number.clone(),
+            caller_uri: call.caller.uri.clone(),
+            anonymous: call.caller.anonymous,
+            account: call.origin.account.clone(),
+            slot: call.origin.slot.clone(),
+            forwarded: call.forwarded.as_ref().map(Redirection::description),
+            since: unix_time(call.since),
+        }
+    }
+}
+
+/// A call of the history kept by the server
+#[derive(SimpleObject)]
+pub struct HistoryEntry {
+    id: String,
+    inbound: bool,
+    number: Option<String>,
+    name: Option<String>,
+
+    /// Unix timestamp of the start of the call
+    started: i64,
+    answered: bool,
+}
+
+#[derive(SimpleObject)]
+pub struct Voicemail {
+    new: u32,
+    old: u32,
+}
+
+#[derive(SimpleObject)]
+pub struct QueueStats {
+    id: u64,
+    name: String,
+
+    /// Callers currently waiting
+    waiting: usize,
+
+    answered: u64,
+    abandoned: u64,
+
+    /// Percentage of calls answered within the service level time
+    service_level: f64,
+}
+
+#[derive(SimpleObject)]
+pub struct CallEvent {
+    /// Like `incoming` or `cancelled`, as printed by `--stdout-json`
+    kind: String,
+
+    /// The call the event is about, if any
+    call: Option<Call>,
+
+    /// The complete event
+    details: Json<serde_json::Value>,
+}
+
+impl From<&Event> for CallEvent {
+    fn from(event: &Event) -> Self {
+        let details = serde_json::to_value(event).unwrap_or_default();
+
+        let call = match event {
+            Event::Incoming { call, .. }
+            | Event::Replaced { call }
+            | Event::Screened { call }
+            | Event::Cancelled { call, .. }
+            | Event::Ended { call } => Some(call.into()),
+            Event::Registered { .. } | Event::Voicemail { .. } => None,
+        };
+
+        Self {
+            kind: details["event"].as_str().unwrap_or_default().to_string(),
+            call,
+            details: Json(details),
+        }
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn registered(&self, ctx: &async_graphql::Context<'_>) -> bool {
+        daemon(ctx).calls.registered()
+    }
+
+    async fn dnd(&self, ctx: &async_graphql::Context<'_>) -> bool {
+        let daemon = daemon(ctx);
+        daemon.calls.dnd(&daemon.config.get())
+    }
+
+    /// Calls currently ringing
+    async fn calls(&self, ctx: &async_graphql::Context<'_>) -> Vec<Call> {
+        daemon(ctx).calls.calls().iter().map(Call::from).collect()
+    }
+
+    /// Recently missed calls, most recent first
+    async fn missed(&self, ctx: &async_graphql::Context<'_>) -> Vec<Call> {
+        daemon(ctx).calls.recent_missed().iter().map(Call::from).collect()
+    }
+
+    async fn voicemail(&self, ctx: &async_graphql::Context<'_>) -> Voicemail {
+        let summary = daemon(ctx).calls.voicemail();
+        Voicemail {
+            new: summary.new,
+            old: summary.old,
+        }
+    }
+
+    /// Calls started after the given unix timestamp, the last day if unset
+    async fn history(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+        since: Option<i64>,
+    ) -> async_graphql::Result<Vec<HistoryEntry>> {
+        let since = since.unwrap_or_else(|| unix_time(SystemTime::now() - DEFAULT_HISTORY));
+        let entries = daemon(ctx).client.user().journal().get_since(since).await?;
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| HistoryEntry {
+                id: entry.id,
+                inbound: entry.direction == Direction::Inbound,
+                number: entry.number,
+                name: entry.name,
+                started: entry.started,
+                answered: entry.answered,
+            })
+            .collect())
+    }
+
+    /// Statistics of the queues of the user
+    async fn queues(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Vec<QueueStats>> {
+        let queues = daemon(ctx).client.user().queues();
+
+        let mut stats = Vec::new();
+        for queue in queues.get_all().await? {
+            let (callers, statistics) =
+                tokio::try_join!(queues.get_callers(queue.id), queues.get_statistics(queue.id))?;
+            stats.push(QueueStats {
+                id: queue.id,
+                name: queue.name,
+                waiting: callers.len(),
+                answered: statistics.answered,
+                abandoned: statistics.abandoned,
+                service_level: statistics.service_level,
+            });
+        }
+
+        Ok(stats)
+    }
+}
+
+pub struct Subscription;
+
+#[Subscription]
+impl Subscription {
+    /// Call state transitions as they happen
+    async fn events(&self, ctx: &async_graphql::Context<'_>) -> impl Stream<Item = CallEvent> + use<> {
+        stream::unfold(daemon(ctx).calls.events(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((CallEvent::from(&event), events)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("GraphQL subscriber skipped {skipped} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,154 @@
+//! gRPC control API of a running daemon, for integrators without D-Bus or the control socket
+//!
+//! All calls must carry the token written to [`token_path`] on startup as
+//! `authorization: Bearer <token>` metadata. See `proto/control.proto`.
+
+use crate::callstate::{self, Event};
+use crate::ctl::{self, Handler};
+use crate::daemon::Daemon;
+use anyhow::{Context, Result};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+/// Generated messages, services and clients
+#[allow(unused_qualifications)]
+pub mod proto {
+    tonic::include_proto!("ucware.control.v1");
+}
+
+use proto::control_server::{Control, ControlServer};
+
+pub fn token_path() -> Option<PathBuf> {
+    let dir = dirs::runtime_dir().or_else(dirs::cache_dir)?;
+    Some(dir.join("ucware").join("grpc-token"))
+}
+
+/// Serves the API on the given address with a new random token
+pub async fn serve(addr: SocketAddr, daemon: Arc<Daemon>) -> Result<()> {
+    let path = token_path().context("No location for the gRPC token available")?;
+    let token = crate::http::write_token(&path).await?;
+    let expected = format!("Bearer {token}");
+
+    let service = ControlServer::with_interceptor(Service { daemon }, move |request: Request<()>| {
+        let authorized = request
+            .metadata()
+            .get("authorization")
+            .is_some_and(|value| value == expected.as_str());
+        if !authorized {
+            return Err(Status::unauthenticated("Missing or invalid token"));
+        }
+        Ok(request)
+    });
+
+    info!("Serving gRPC on {addr}, token in {path}", path = path.display());
+
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve(addr)
+        .await
+        .with_context(|| format!("Failed to serve gRPC on {addr}"))
+}
+
+struct Service {
+    daemon: Arc<Daemon>,
+}
+
+impl Service {
+    async fn request(&self, request: ctl::Request) -> Result<ctl::Response, Status> {
+        match self.daemon.handle(request).await {
+            ctl::Response::Error { message } => Err(Status::unavailable(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Control for Service {
+    async fn dial(&self, request: Request<proto::DialRequest>) -> Result<Response<proto::DialReply>, Status> {
+        let number = request.into_inner().number;
+        self.request(ctl::Request::Dial { number }).await?;
+        Ok(Response::new(proto::DialReply {}))
+    }
+
+    async fn hangup(&self, _: Request<proto::HangupRequest>) -> Result<Response<proto::HangupReply>, Status> {
+        self.request(ctl::Request::Decline).await?;
+        Ok(Response::new(proto::HangupReply {}))
+    }
+
+    async fn status(&self, _: Request<proto::StatusRequest>) -> Result<Response<proto::StatusReply>, Status> {
+        let ctl::Response::Status(status) = self.request(ctl::Request::Status).await? else {
+            return Err(Status::internal("Unexpected response"));
+        };
+
+        Ok(Response::new(proto::StatusReply {
+            registered: status.registered,
+            dnd: status.dnd,
+            missed: status.missed,
+            voicemail: status.voicemail,
+            calls: status.calls.iter().map(proto::Call::from).collect(),
+        }))
+    }
+
+    type StreamEventsStream = BoxStream<'static, Result<proto::Event, Status>>;
+
+    async fn stream_events(
+        &self,
+        _: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let events = self.daemon.calls.events();
+        let events = stream::unfold(events, |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((proto::Event::try_from(&event), events)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("gRPC client skipped {skipped} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        Ok(Response::new(events.boxed()))
+    }
+}
+
+impl From<&callstate::Call> for proto::Call {
+    fn from(call: &callstate::Call) -> Self {
+        Self {
+            call_id: call.key.call_id.clone(),
+            caller_name: call.caller.name.clone(),
+            caller_number: call.caller.number.clone(),
+            caller_uri: call.caller.uri.clone(),
+            anonymous: call.caller.anonymous,
+            account: call.origin.account.clone(),
+        }
+    }
+}
+
+impl TryFrom<&Event> for proto::Event {
+    type Error = Status;
+
+    fn try_from(event: &Event) -> Result<Self, Status> {
+        let json = serde_json::to_value(event).map_err(|err| Status::internal(err.to_string()))?;
+
+        let call = match event {
+            Event::Incoming { call, .. }
+            | Event::Replaced { call }
+            | Event::Screened { call }
+            | Event::Cancelled { call, .. }
+            | Event::Ended { call } => Some(call.into()),
+            Event::Registered { .. } | Event::Voicemail { .. } => None,
+        };
+
+        Ok(Self {
+            kind: json["event"].as_str().unwrap_or_default().to_string(),
+            call,
+            json: json.to_string(),
+        })
+    }
+}
@@ -0,0 +1,231 @@
+//! Global shortcuts acting on a running daemon
+//!
+//! Shortcuts are registered with the GlobalShortcuts interface of the desktop
+//! portal, so they work regardless of the focused window, on Wayland too.
+
+use crate::config::HotkeysConfig;
+use crate::ctl;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Decline,
+    Dnd,
+    Redial,
+    DialClipboard,
+}
+
+impl Action {
+    const ALL: [Self; 4] = [Self::Decline, Self::Dnd, Self::Redial, Self::DialClipboard];
+
+    fn id(self) -> &'static str {
+        match self {
+            Self::Decline => "decline",
+            Self::Dnd => "dnd",
+            Self::Redial => "redial",
+            Self::DialClipboard => "dial-clipboard",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Decline => "Decline ringing call",
+            Self::Dnd => "Toggle do-not-disturb",
+            Self::Redial => "Redial last number",
+            Self::DialClipboard => "Dial number from clipboard",
+        }
+    }
+
+    fn trigger(self, config: &HotkeysConfig) -> Option<&str> {
+        match self {
+            Self::Decline => config.decline.as_deref(),
+            Self::Dnd => config.dnd.as_deref(),
+            Self::Redial => config.redial.as_deref(),
+            Self::DialClipboard => config.dial_clipboard.as_deref(),
+        }
+    }
+}
+
+/// Binds the shortcuts and runs their actions until the portal session ends
+pub async fn run(socket: &Path, config: &HotkeysConfig) -> Result<()> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return portal::run(socket, config).await;
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = (socket, config);
+        bail!("Global shortcuts are only available through the desktop portal");
+    }
+}
+
+async fn activated(socket: &Path, action: Action) {
+    info!("Shortcut activated: {action}", action = action.id());
+    if let Err(err) = perform(socket, action).await {
+        warn!("Failed to {action}: {err:#}", action = action.description().to_lowercase());
+    }
+}
+
+async fn perform(socket: &Path, action: Action) -> Result<()> {
+    let request = match action {
+        Action::Decline => ctl::Request::Decline,
+        Action::Redial => ctl::Request::Redial,
+        Action::Dnd => match ctl::request(socket, &ctl::Request::Status).await? {
+            ctl::Response::Status(status) => ctl::Request::Dnd { enabled: !status.dnd },
+            response => bail!("Unexpected response from daemon: {response:?}"),
+        },
+        Action::DialClipboard => ctl::Request::Dial {
+            number: clipboard_number().await?,
+        },
+    };
+
+    match ctl::request(socket, &request).await? {
+        ctl::Response::Error { message } => bail!(message),
+        _ => Ok(()),
+    }
+}
+
+/// Phone number in the clipboard, stripped of formatting
+async fn clipboard_number() -> Result<String> {
+    const TOOLS: &[(&str, &[&str])] = &[
+        ("wl-paste", &["--no-newline"]),
+        ("xclip", &["-selection", "clipboard", "-o"]),
+        ("xsel", &["--clipboard", "--output"]),
+    ];
+
+    let mut text = None;
+    for (tool, args) in TOOLS {
+        match Command::new(tool).args(*args).output().await {
+            Ok(output) if output.status.success() => {
+                text = Some(String::from_utf8_lossy(&output.stdout).into_owned());
+                break;
+            }
+            _ => continue,
+        }
+    }
+    let text = text.context("Failed to read clipboard - is wl-paste, xclip or xsel installed?")?;
+
+    let number = text
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '+' | '*' | '#'))
+        .collect::<String>();
+    if number.is_empty() {
+        bail!("No phone number in clipboard: {text}");
+    }
+
+    Ok(number)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod portal {
+    use super::{activated, Action};
+    use crate::config::HotkeysConfig;
+    use anyhow::{bail, Context, Result};
+    use futures::StreamExt;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use tracing::{debug, info};
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+    use zbus::{Connection, Proxy};
+
+    const PORTAL: &str = "org.freedesktop.portal.Desktop";
+    const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+    const GLOBAL_SHORTCUTS: &str = "org.freedesktop.portal.GlobalShortcuts";
+
+    pub async fn run(socket: &Path, config: &HotkeysConfig) -> Result<()> {
+        let conn = Connection::session().await?;
+        let shortcuts = Proxy::new(&conn, PORTAL, PORTAL_PATH, GLOBAL_SHORTCUTS).await?;
+
+        // Subscribe first to not miss activations right after binding
+        let mut activations = shortcuts.receive_signal("Activated").await?;
+
+        let results = request(&conn, "ucware_session", async |token| {
+            let options = HashMap::from([
+                ("handle_token", Value::from(token)),
+                ("session_handle_token", Value::from("ucware")),
+            ]);
+            Ok(shortcuts.call("CreateSession", &(options,)).await?)
+        })
+        .await
+        .context("Failed to create shortcut session")?;
+
+        let session = results
+            .get("session_handle")
+            .context("Portal did not return a session")?
+            .try_clone()?;
+        let session = match session.downcast_ref::<&str>() {
+            Ok(path) => OwnedObjectPath::try_from(path)?,
+            Err(_) => OwnedObjectPath::try_from(session)?,
+        };
+
+        let bindings = Action::ALL
+            .iter()
+            .map(|action| {
+                let mut options = HashMap::from([("description", Value::from(action.description()))]);
+                if let Some(trigger) = action.trigger(config) {
+                    options.insert("preferred_trigger", Value::from(trigger));
+                }
+                (action.id(), options)
+            })
+            .collect::<Vec<_>>();
+
+        request(&conn, "ucware_bind", async |token| {
+            let options = HashMap::from([("handle_token", Value::from(token))]);
+            Ok(shortcuts
+                .call("BindShortcuts", &(&session, bindings, "", options))
+                .await?)
+        })
+        .await
+        .context("Failed to bind shortcuts")?;
+
+        info!("Global shortcuts bound, waiting for activations");
+
+        while let Some(signal) = activations.next().await {
+            let (signal_session, id, _timestamp, _options): (OwnedObjectPath, String, u64, HashMap<String, OwnedValue>) =
+                signal.body().deserialize()?;
+            if signal_session != session {
+                continue;
+            }
+
+            match Action::ALL.into_iter().find(|action| action.id() == id) {
+                Some(action) => activated(socket, action).await,
+                None => debug!("Unknown shortcut activated: {id}"),
+            }
+        }
+
+        bail!("Portal closed the shortcut session");
+    }
+
+    /// Performs a portal request and waits for its results.
+    ///
+    /// The portal answers asynchronously on a request object derived from the
+    /// unique bus name and the handle token passed to `call`.
+    async fn request(
+        conn: &Connection,
+        token: &str,
+        call: impl AsyncFnOnce(&str) -> Result<OwnedObjectPath>,
+    ) -> Result<HashMap<String, OwnedValue>> {
+        let sender = conn
+            .unique_name()
+            .context("Not connected to the session bus")?
+            .trim_start_matches(':')
+            .replace('.', "_");
+        let path = format!("{PORTAL_PATH}/request/{sender}/{token}");
+
+        let request = Proxy::new(conn, PORTAL, path, "org.freedesktop.portal.Request").await?;
+        let mut responses = request.receive_signal("Response").await?;
+
+        call(token).await?;
+
+        let response = responses.next().await.context("Portal did not respond")?;
+        let (code, results): (u32, HashMap<String, OwnedValue>) = response.body().deserialize()?;
+        if code != 0 {
+            bail!("Request denied by the portal");
+        }
+
+        Ok(results)
+    }
+}
@@ -0,0 +1,153 @@
+//! Read-only HTTP view on a running daemon for dashboards and button boxes
+//!
+//! All requests must carry the token written to [`token_path`] on startup as
+//! `Authorization: Bearer <token>`.
+
+use crate::ctl::{self, Handler};
+use crate::daemon::Daemon;
+use anyhow::{Context, Result};
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::info;
+
+pub fn token_path() -> Option<PathBuf> {
+    let dir = dirs::runtime_dir().or_else(dirs::cache_dir)?;
+    Some(dir.join("ucware").join("http-token"))
+}
+
+#[derive(Clone)]
+struct AppState {
+    daemon: Arc<Daemon>,
+    token: Arc<str>,
+}
+
+/// Failure of a request, reported as JSON
+struct Error(StatusCode, String);
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Dial {
+    number: String,
+}
+
+/// Serves the API on the given address with a new random token
+pub async fn serve(addr: SocketAddr, daemon: Arc<Daemon>) -> Result<()> {
+    let path = token_path().context("No location for the HTTP token available")?;
+    let token = write_token(&path).await?;
+
+    let state = AppState {
+        daemon,
+        token: token.into(),
+    };
+
+    let app = Router::new()
+        .route("/status", get(status))
+        .route("/calls", get(calls))
+        .route("/missed", get(missed))
+        .route("/slots", get(slots))
+        .route("/dial", post(dial));
+
+    #[cfg(feature = "graphql")]
+    let app = app.nest_service("/graphql", crate::graphql::router(state.daemon.clone()));
+
+    let app = app
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP listener: {addr}"))?;
+
+    info!("Serving HTTP on {addr}, token in {path}", path = path.display());
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Creates a random token readable only by the user
+pub(crate) async fn write_token(path: &Path) -> Result<String> {
+    let token = rand::random::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to write token: {path}", path = path.display()))?;
+    tokio::io::AsyncWriteExt::write_all(&mut file, token.as_bytes()).await?;
+
+    Ok(token)
+}
+
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token == &*state.token);
+
+    if !authorized {
+        return Error(StatusCode::UNAUTHORIZED, "Missing or invalid token".to_string()).into_response();
+    }
+
+    next.run(request).await
+}
+
+async fn status(State(state): State<AppState>) -> Result<Response, Error> {
+    match state.daemon.handle(ctl::Request::Status).await {
+        ctl::Response::Status(status) => Ok(Json(status).into_response()),
+        response => Err(Error(StatusCode::INTERNAL_SERVER_ERROR, format!("Unexpected response: {response:?}"))),
+    }
+}
+
+async fn calls(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.daemon.calls.calls())
+}
+
+async fn missed(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.daemon.calls.recent_missed())
+}
+
+async fn slots(State(state): State<AppState>) -> Result<Response, Error> {
+    let slots = state.daemon.client.user().slots().get_all().await?;
+    Ok(Json(slots).into_response())
+}
+
+async fn dial(State(state): State<AppState>, Json(dial): Json<Dial>) -> Result<Response, Error> {
+    match state.daemon.handle(ctl::Request::Dial { number: dial.number }).await {
+        ctl::Response::Error { message } => Err(Error(StatusCode::BAD_GATEWAY, message)),
+        _ => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
@@ -0,0 +1,235 @@
+//! Declarative IVR flows like "press 1 for support"
+//!
+//! A flow is a YAML file of named nodes, each with a `type`, starting at
+//! `start`. A [`Session`] walks through it, telling the caller's media what
+//! to do next and taking the digits pressed in return.
+
+use crate::config::Age;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Time to wait for a digit unless the menu sets it
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest recording unless the node sets it
+const DEFAULT_RECORDING: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Ivr {
+    /// Name of the first node
+    pub start: String,
+
+    pub nodes: BTreeMap<String, Node>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Node {
+    /// Speaks the text by speech synthesis
+    Say { text: String, next: Option<String> },
+
+    /// Plays a WAV file
+    Play { file: PathBuf, next: Option<String> },
+
+    /// Speaks the prompt and branches by the digit pressed
+    Menu(Menu),
+
+    /// Records the caller, e.g. for a voicemail
+    Record { max: Option<Age>, next: Option<String> },
+
+    /// Hands the call to another number
+    Transfer { target: String },
+
+    Hangup,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Menu {
+    pub prompt: String,
+
+    /// Nodes by digit
+    pub options: BTreeMap<char, String>,
+
+    /// Time to wait for a digit after the prompt - 5s if unset
+    pub timeout: Option<Age>,
+
+    /// Times the prompt is repeated after an invalid or missing digit
+    #[serde(default)]
+    pub retries: u32,
+
+    /// Node to go to once all retries failed - hangs up if unset
+    pub fallback: Option<String>,
+}
+
+impl Ivr {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let ivr: Self = serde_yaml::from_str(&data)
+            .with_context(|| format!("Invalid IVR file {}", path.display()))?;
+        ivr.validate()?;
+        Ok(ivr)
+    }
+
+    /// Ensures all nodes referred to exist
+    pub fn validate(&self) -> Result<()> {
+        let check = |from: &str, to: &str| {
+            if !self.nodes.contains_key(to) {
+                bail!("Node {from} refers to unknown node {to}");
+            }
+            Ok(())
+        };
+
+        check("start", &self.start)?;
+        for (name, node) in &self.nodes {
+            match node {
+                Node::Say { next, .. } | Node::Play { next, .. } | Node::Record { next, .. } => {
+                    next.iter().try_for_each(|next| check(name, next))?;
+                }
+                Node::Menu(menu) => {
+                    menu.options.values().try_for_each(|next| check(name, next))?;
+                    menu.fallback.iter().try_for_each(|next| check(name, next))?;
+                }
+                Node::Transfer { .. } | Node::Hangup => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the media of the call should do next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Say(String),
+    Play(PathBuf),
+
+    /// Wait for a digit, reporting a timeout if none is pressed in time
+    Collect { timeout: Duration },
+
+    Record { max: Duration },
+    Transfer(String),
+    Hangup,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Say(text) => write!(f, "Say \"{text}\""),
+            Self::Play(file) => write!(f, "Play {}", file.display()),
+            Self::Collect { timeout } => write!(f, "Wait {} for a digit", humantime::format_duration(*timeout)),
+            Self::Record { max } => write!(f, "Record up to {}", humantime::format_duration(*max)),
+            Self::Transfer(target) => write!(f, "Transfer to {target}"),
+            Self::Hangup => write!(f, "Hang up"),
+        }
+    }
+}
+
+/// What happened since the last action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The action finished, e.g. the prompt was spoken
+    Done,
+
+    /// The caller pressed a digit, possibly during a prompt
+    Digit(char),
+
+    /// No digit was pressed in time
+    Timeout,
+}
+
+/// Progress of a call through a flow
+pub struct Session<'a> {
+    ivr: &'a Ivr,
+    node: &'a str,
+
+    /// Failed attempts at the current menu
+    attempts: u32,
+}
+
+impl<'a> Session<'a> {
+    /// Starts at the first node of the flow, returning the first action
+    pub fn start(ivr: &'a Ivr) -> (Self, Action) {
+        let mut session = Self {
+            ivr,
+            node: &ivr.start,
+            attempts: 0,
+        };
+        let action = session.enter(&ivr.start);
+        (session, action)
+    }
+
+    /// Name of the current node
+    pub fn node(&self) -> &str {
+        self.node
+    }
+
+    pub fn next(&mut self, event: Event) -> Action {
+        let Some(node) = self.ivr.nodes.get(self.node) else {
+            return Action::Hangup;
+        };
+
+        match (node, event) {
+            (Node::Menu(menu), Event::Digit(digit)) => match menu.options.get(&digit) {
+                Some(next) => self.enter(next),
+                None => self.retry(menu),
+            },
+            (Node::Menu(menu), Event::Done) => Action::Collect {
+                timeout: menu.timeout.map_or(DEFAULT_TIMEOUT, |timeout| timeout.0),
+            },
+            (Node::Menu(menu), Event::Timeout) => self.retry(menu),
+
+            (Node::Say { next, .. } | Node::Play { next, .. } | Node::Record { next, .. }, Event::Done) => {
+                match next {
+                    Some(next) => self.enter(next),
+                    None => Action::Hangup,
+                }
+            }
+
+            // Digits pressed outside of menus are ignored
+            (Node::Say { .. } | Node::Play { .. } | Node::Record { .. }, _) => self.action(node),
+
+            (Node::Transfer { .. } | Node::Hangup, _) => Action::Hangup,
+        }
+    }
+
+    fn enter(&mut self, name: &'a str) -> Action {
+        self.node = name;
+        self.attempts = 0;
+        match self.ivr.nodes.get(name) {
+            Some(node) => self.action(node),
+            None => Action::Hangup,
+        }
+    }
+
+    /// Repeats the prompt after an invalid or missing digit, or gives up
+    fn retry(&mut self, menu: &'a Menu) -> Action {
+        self.attempts += 1;
+        if self.attempts > menu.retries {
+            return match &menu.fallback {
+                Some(fallback) => self.enter(fallback),
+                None => Action::Hangup,
+            };
+        }
+        Action::Say(menu.prompt.clone())
+    }
+
+    fn action(&self, node: &Node) -> Action {
+        match node {
+            Node::Say { text, .. } => Action::Say(text.clone()),
+            Node::Play { file, .. } => Action::Play(file.clone()),
+            Node::Menu(menu) => Action::Say(menu.prompt.clone()),
+            Node::Record { max, .. } => Action::Record {
+                max: max.map_or(DEFAULT_RECORDING, |max| max.0),
+            },
+            Node::Transfer { target } => Action::Transfer(target.clone()),
+            Node::Hangup => Action::Hangup,
+        }
+    }
+}
@@ -1,3 +1,42 @@
 pub mod ucware;
 pub mod sipsocket;
-pub mod cmd;
\ No newline at end of file
+pub mod cmd;
+pub mod config;
+pub mod callstate;
+pub mod ctl;
+pub mod statusbar;
+pub mod wallboard;
+pub mod daemon;
+pub mod store;
+pub mod cache;
+pub mod admin;
+pub mod progress;
+pub mod completion;
+pub mod selftest;
+pub mod loadtest;
+pub mod version;
+pub mod resolver;
+pub mod focus;
+pub mod notification;
+pub mod forward;
+pub mod matrix;
+pub mod http;
+pub mod hotkeys;
+pub mod busy;
+pub mod media;
+pub mod ivr;
+pub mod answerbot;
+pub mod plugin;
+pub mod filter;
+
+#[cfg(windows)]
+pub mod service;
+
+#[cfg(feature = "tray")]
+pub mod tray;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+#[cfg(feature = "graphql")]
+pub mod graphql;
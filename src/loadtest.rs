@@ -0,0 +1,226 @@
+use crate::callstate::{CallState, Origin};
+use crate::config::Config;
+use crate::sipsocket::{codec, Connection, ServerTransaction};
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use rsip::headers::{ToTypedHeader, UntypedHeader};
+use rsip::message::HeadersExt;
+use rsip::{Method, SipMessage, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use url::Url;
+
+/// Time to wait for the socket to answer a request before giving up
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Number of INVITE/CANCEL pairs to replay
+    pub calls: usize,
+
+    /// Number of calls ringing at the same time
+    pub concurrency: usize,
+}
+
+/// Latency percentiles of a request until its response, in microseconds
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Latency {
+    pub min: u128,
+    pub p50: u128,
+    pub p95: u128,
+    pub p99: u128,
+    pub max: u128,
+}
+
+impl Latency {
+    fn of(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        samples.sort_unstable();
+        let at = |p: usize| samples[(samples.len() - 1) * p / 100].as_micros();
+
+        Self {
+            min: at(0),
+            p50: at(50),
+            p95: at(95),
+            p99: at(99),
+            max: at(100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub calls: usize,
+    pub transactions: usize,
+    pub duration_ms: u128,
+
+    /// Completed transactions per second
+    pub throughput: f64,
+
+    pub invite: Latency,
+    pub cancel: Latency,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{calls} calls, {transactions} transactions in {duration}ms ({throughput:.0}/s)",
+            calls = self.calls,
+            transactions = self.transactions,
+            duration = self.duration_ms,
+            throughput = self.throughput,
+        )?;
+
+        for (method, latency) in [("INVITE", &self.invite), ("CANCEL", &self.cancel)] {
+            writeln!(
+                f,
+                "{method}: min {min}µs, p50 {p50}µs, p95 {p95}µs, p99 {p99}µs, max {max}µs",
+                min = latency.min,
+                p50 = latency.p50,
+                p95 = latency.p95,
+                p99 = latency.p99,
+                max = latency.max,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replays synthetic INVITE/CANCEL pairs from an in-process fake server
+/// through a SIP socket and the call state.
+///
+/// The INVITE is answered with 180 and the CANCEL with 200 as the daemon
+/// does, the time until each response arrives at the server is recorded.
+pub async fn run(options: Options) -> Result<Report> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let port = listener.local_addr()?.port();
+
+    let server = tokio::spawn(serve(listener, options));
+
+    let url = Url::parse(&format!("ws://localhost:{port}"))?;
+    let (_connection, requests) = Connection::builder(url, "loadtest").connect().await?;
+    tokio::spawn(answer(requests));
+
+    server.await?
+}
+
+/// Answers requests of the fake server like the daemon would
+async fn answer(mut requests: mpsc::Receiver<ServerTransaction>) {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    while let Some(mut tx) = requests.recv().await {
+        let status = match tx.request.method {
+            Method::Invite => match calls.incoming(&tx.request, &config, &Origin::default()) {
+                Ok(_) => StatusCode::Ringing,
+                Err(_) => StatusCode::BadRequest,
+            },
+            Method::Cancel => match calls.cancelled(&tx.request) {
+                Ok(Some(_)) => StatusCode::OK,
+                Ok(None) => StatusCode::CallTransactionDoesNotExist,
+                Err(_) => StatusCode::BadRequest,
+            },
+            _ => StatusCode::NotImplemented,
+        };
+
+        tx.respond(status).send([]).await;
+    }
+}
+
+async fn serve(listener: TcpListener, options: Options) -> Result<Report> {
+    let (stream, _) = listener.accept().await?;
+    let mut socket = tokio_tungstenite::accept_hdr_async(stream, handshake).await?;
+
+    // Each call has a single transaction in flight, first the INVITE then the CANCEL
+    let mut pending = HashMap::<String, (Method, Instant)>::new();
+    let mut invite = Vec::with_capacity(options.calls);
+    let mut cancel = Vec::with_capacity(options.calls);
+
+    let start = Instant::now();
+    let mut started = 0;
+
+    while cancel.len() < options.calls {
+        while started < options.calls && pending.len() < options.concurrency.max(1) {
+            let call_id = format!("loadtest-{started}");
+            socket.send(Message::text(request(Method::Invite, &call_id))).await?;
+            pending.insert(call_id, (Method::Invite, Instant::now()));
+            started += 1;
+        }
+
+        let frame = tokio::time::timeout(TIMEOUT, socket.next())
+            .await
+            .context("No response from socket")?
+            .context("Socket closed")??;
+        let Message::Text(frame) = frame else {
+            continue;
+        };
+
+        let SipMessage::Response(response) = codec::decode(Bytes::from(frame))? else {
+            bail!("Unexpected request from socket");
+        };
+
+        let call_id = response.call_id_header()?.value().to_string();
+        let method = response.cseq_header()?.typed()?.method;
+        let sent = match pending.remove(&call_id) {
+            Some((expected, sent)) if expected == method => sent,
+            _ => bail!("Unexpected response to {method} {call_id}"),
+        };
+
+        match (method, response.status_code) {
+            (Method::Invite, StatusCode::Ringing) => {
+                invite.push(sent.elapsed());
+
+                socket.send(Message::text(request(Method::Cancel, &call_id))).await?;
+                pending.insert(call_id, (Method::Cancel, Instant::now()));
+            }
+            (Method::Cancel, StatusCode::OK) => cancel.push(sent.elapsed()),
+            (method, status) => bail!("Unexpected response to {method} {call_id}: {status}"),
+        }
+    }
+
+    let duration = start.elapsed();
+    let transactions = invite.len() + cancel.len();
+
+    Ok(Report {
+        calls: options.calls,
+        transactions,
+        duration_ms: duration.as_millis(),
+        throughput: transactions as f64 / duration.as_secs_f64(),
+        invite: Latency::of(invite),
+        cancel: Latency::of(cancel),
+    })
+}
+
+/// Accepts the `sip` sub-protocol requested by the socket
+#[allow(clippy::result_large_err)] // Signature required by tungstenite
+fn handshake(_: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    response
+        .headers_mut()
+        .insert("Sec-WebSocket-Protocol", "sip".parse().expect("valid header value"));
+    Ok(response)
+}
+
+/// A request of a call from a distinct caller, the CANCEL matching its INVITE
+fn request(method: Method, call_id: &str) -> String {
+    format!(
+        "{method} sip:loadtest@localhost SIP/2.0\r\n\
+         Via: SIP/2.0/WSS 127.0.0.1;branch=z9hG4bK{call_id}\r\n\
+         From: <sip:{call_id}@localhost>;tag={call_id}\r\n\
+         To: <sip:loadtest@localhost>\r\n\
+         Call-ID: {call_id}\r\n\
+         CSeq: 1 {method}\r\n\
+         Content-Length: 0\r\n\r\n"
+    )
+}
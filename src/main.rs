@@ -1,14 +1,739 @@
-use anyhow::{bail, Result};
-use rsip::message::HeadersExt;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use clap::{Args, Subcommand, ValueEnum};
+use clap_complete::ArgValueCandidates;
 use rsip::{Method, StatusCode};
-use rsip::headers::ToTypedHeader;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 use tracing::{debug, info};
-use ucware_cli::cmd;
+use ucware_cli::admin::{self, apply, users};
+use ucware_cli::cache::Cache;
+use ucware_cli::callstate::blocklist::BlockRule;
+use ucware_cli::callstate::{AgentState, Caller};
+use ucware_cli::config::{Config, ConfigHandle};
+use ucware_cli::cmd::{self, Connector, Output};
+use ucware_cli::ivr::{Action, Event, Ivr, Session};
+use ucware_cli::{completion, ctl, hotkeys, loadtest, matrix, plugin, selftest, statusbar, wallboard};
+use ucware_cli::sipsocket::headers::Reason;
+use ucware_cli::sipsocket::{TraceDirection, TracedMessage};
+use ucware_cli::store::{Favorite, LastNumber, Store};
+use ucware_cli::ucware::Client;
+use ucware_cli::ucware::util::{CONCURRENCY, parallel};
+use ucware_cli::ucware::system::HealthState;
+use ucware_cli::version::BuildInfo;
+
+#[cfg(feature = "audio")]
+use ucware_cli::media::audio::{self, Direction};
+#[cfg(feature = "audio")]
+use ucware_cli::media::level::Volume;
+
+#[derive(Args, Debug)]
+struct MainArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Control a running notifier daemon
+    Ctl(CtlArgs),
+
+    /// Continuously print the daemon state for status bars
+    Statusbar(StatusbarArgs),
+
+    /// Post calls of the running daemon to a Matrix room and accept commands from it
+    MatrixBridge(MatrixBridgeArgs),
+
+    /// Bind global shortcuts acting on the running daemon
+    Hotkeys {
+        /// Path to the control socket of the daemon
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// Manage favorite numbers
+    Favorites {
+        #[command(subcommand)]
+        command: FavoritesCommand,
+    },
+
+    /// Manage blocked callers
+    Block {
+        #[command(subcommand)]
+        command: BlockCommand,
+    },
+
+    /// Maintain the local call log
+    Log {
+        #[command(subcommand)]
+        command: LogCommand,
+    },
+
+    /// Remove local data older than the retention config allows
+    ///
+    /// The daemon does the same every hour.
+    Maintain,
+
+    /// Dial the last dialed number again
+    Redial {
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Call back the last missed caller
+    Callback {
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Queue agent state via the running daemon
+    Agent {
+        /// Path to the control socket of the daemon
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        #[command(subcommand)]
+        command: AgentCommand,
+    },
+
+    /// Send and receive faxes
+    Fax {
+        #[command(subcommand)]
+        command: FaxCommand,
+    },
+
+    /// Send and list text messages
+    Sms {
+        #[command(subcommand)]
+        command: SmsCommand,
+    },
+
+    /// Call queues
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+
+    /// Phone slots of the user
+    Slots {
+        #[command(subcommand)]
+        command: SlotsCommand,
+    },
+
+    /// Shared phonebook
+    Phonebook {
+        #[command(subcommand)]
+        command: PhonebookCommand,
+    },
+
+    /// Manage cached server responses
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+
+    /// Information about the PBX itself
+    Server {
+        #[command(subcommand)]
+        command: ServerCommand,
+    },
+
+    /// Administrative tasks, requires admin permissions
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+
+    /// Register the WebRTC slot once, e.g. with `--expires 0` to remove a stale binding
+    ///
+    /// The contact stored by the last run is used, so this removes the binding
+    /// left behind by a crashed daemon. Contacts stored by versions before the
+    /// whole contact was kept are unknown, remove those with `--all`.
+    Register {
+        /// Seconds the binding is valid for, zero removes it
+        #[arg(long)]
+        expires: u32,
+
+        /// Remove all bindings of the slot, not only the one of this client
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Contacts registered for the WebRTC slot
+    Bindings {
+        #[command(subcommand)]
+        command: BindingsCommand,
+    },
+
+    /// Check token refresh, slot lookup, SIP socket and registration
+    ///
+    /// Prints a TAP report, or JSON with `--output json`, and exits with a
+    /// failure if any check failed.
+    Selftest,
+
+    /// Replay synthetic calls through an in-process fake server
+    ///
+    /// Measures throughput and latency of the transaction layer to catch
+    /// performance regressions.
+    #[command(hide = true)]
+    Loadtest {
+        /// Number of INVITE/CANCEL pairs
+        #[arg(long, default_value_t = 1000)]
+        calls: usize,
+
+        /// Number of calls ringing at the same time
+        #[arg(long, default_value_t = 16)]
+        concurrency: usize,
+    },
+
+    /// Local audio devices
+    Audio {
+        #[command(subcommand)]
+        command: AudioCommand,
+    },
+
+    /// IVR flows for answer bots
+    Ivr {
+        #[command(subcommand)]
+        command: IvrCommand,
+    },
+
+    /// List plugins found on the PATH
+    Plugins,
+
+    /// Show version, enabled features and build details
+    Version,
+
+    /// Run the plugin `ucware-<name>` found on the PATH
+    #[command(external_subcommand)]
+    Plugin(Vec<String>),
+}
+
+#[derive(Subcommand, Debug)]
+enum IvrCommand {
+    /// Check a flow for syntax errors and unknown nodes
+    Check { path: PathBuf },
+
+    /// Walk through a flow on the terminal, typing the digits a caller would press
+    Simulate { path: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+enum AudioCommand {
+    /// List input and output devices, marking the system defaults and configured ones
+    Devices,
+
+    /// Play the microphone back on the speaker to check local audio
+    ///
+    /// Uses the configured devices and volumes and shows the level of the
+    /// microphone until interrupted.
+    Loopback {
+        /// Time between recording and playing back
+        #[arg(long, default_value = "500ms")]
+        delay: humantime::Duration,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SlotsCommand {
+    /// List all slots
+    List,
+
+    /// Generate new SIP credentials for a slot
+    ///
+    /// A running notifier daemon is told to register again with the new
+    /// credentials.
+    RotateSipPassword {
+        /// Name or ID of the slot
+        #[arg(add = ArgValueCandidates::new(completion::slots))]
+        slot: String,
+
+        /// Path to the control socket of the daemon
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BindingsCommand {
+    /// List the bindings known to the registrar
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum PhonebookCommand {
+    /// List all contacts
+    List,
+
+    /// Search contacts by name or number
+    Search { query: String },
+
+    /// Add a contact
+    Add {
+        name: String,
+
+        #[arg(required = true)]
+        numbers: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Remove all cached responses
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+enum ServerCommand {
+    /// Show version, license usage and service health
+    ///
+    /// Exits with a failure if a service is not healthy or a license is
+    /// exhausted.
+    Info,
+}
+
+#[derive(Subcommand, Debug)]
+enum AdminCommand {
+    /// Provisioned phones
+    Devices {
+        #[command(subcommand)]
+        command: DevicesCommand,
+    },
+
+    /// User accounts
+    Users {
+        #[command(subcommand)]
+        command: UsersCommand,
+    },
+
+    /// Permission groups
+    Groups {
+        #[command(subcommand)]
+        command: GroupsCommand,
+    },
+
+    /// License assignment
+    Licenses {
+        #[command(subcommand)]
+        command: LicensesCommand,
+    },
+
+    /// Bring users, slots and queues in line with a state file
+    Apply {
+        /// YAML file describing the desired state
+        file: PathBuf,
+
+        /// Only print the change plan
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Delete objects missing from the state file
+        #[arg(long)]
+        prune: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum GroupsCommand {
+    /// List all groups with their permissions
+    List,
+
+    /// List the members of a group
+    Members { group: String },
+
+    /// Add a user to a group
+    AddMember { group: String, user: String },
+
+    /// Remove a user from a group
+    RemoveMember { group: String, user: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum LicensesCommand {
+    /// List all licenses and their usage
+    List,
+
+    /// Assign a license to a user
+    Assign { license: String, user: String },
+
+    /// Take a license away from a user
+    Unassign { license: String, user: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum UsersCommand {
+    /// Export all users
+    Export {
+        #[arg(short, long, value_enum, default_value = "csv")]
+        format: users::Format,
+
+        /// Output file, defaults to stdout
+        #[arg(short = 'o', long)]
+        file: Option<PathBuf>,
+    },
+
+    /// Create users from a CSV file
+    ///
+    /// Expects the columns username, first_name, last_name and optionally
+    /// email, extension and password.
+    Import {
+        file: PathBuf,
+
+        /// Only validate the file without creating users
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DevicesCommand {
+    /// List all provisioned devices
+    List,
+
+    /// Reboot a device
+    Reboot { mac: String },
+
+    /// Push a fresh provisioning config to a device
+    Reprovision { mac: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum AgentCommand {
+    /// Pause in all queues
+    Pause {
+        /// Reason for the pause
+        #[arg(short, long)]
+        reason: Option<String>,
+    },
+
+    /// Resume from a pause
+    Resume,
+
+    /// Start the wrap-up timer
+    WrapUp,
+}
+
+#[derive(Subcommand, Debug)]
+enum FaxCommand {
+    /// Send a PDF document and wait for it to be delivered
+    Send { file: PathBuf, number: String },
+
+    /// List received faxes
+    List,
+
+    /// Download a received fax as PDF
+    Download {
+        id: u64,
+
+        /// Output file, defaults to fax-<id>.pdf
+        #[arg(short = 'o', long)]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SmsCommand {
+    /// Send a message and wait for its delivery
+    Send {
+        number: String,
+        text: String,
+
+        /// ID of the gateway to use instead of the default one
+        #[arg(short, long)]
+        gateway: Option<u64>,
+
+        /// Return as soon as the message is accepted
+        #[arg(long)]
+        no_wait: bool,
+    },
+
+    /// List sent and received messages
+    List,
+
+    /// List available gateways
+    Gateways,
+}
+
+#[derive(Subcommand, Debug)]
+enum QueueCommand {
+    /// List all queues
+    List,
+
+    /// Join a queue as agent
+    Login {
+        /// Name or ID of the queue
+        #[arg(add = ArgValueCandidates::new(completion::queues))]
+        queue: String,
+    },
+
+    /// Leave a queue
+    Logout {
+        /// Name or ID of the queue
+        #[arg(add = ArgValueCandidates::new(completion::queues))]
+        queue: String,
+    },
+
+    /// Show a live view of a queue
+    Wallboard {
+        /// Name or ID of the queue
+        #[arg(add = ArgValueCandidates::new(completion::queues))]
+        queue: String,
+
+        /// Seconds between updates
+        #[arg(short, long, default_value_t = 2)]
+        interval: u64,
+    },
+}
+
+#[derive(Args, Debug)]
+struct StatusbarArgs {
+    /// Path to the control socket of the daemon
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "json")]
+    format: statusbar::Format,
+
+    /// Seconds between updates
+    #[arg(short, long, default_value_t = 1)]
+    interval: u64,
+}
+
+#[derive(Args, Debug)]
+struct MatrixBridgeArgs {
+    /// Path to the control socket of the daemon
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Seconds between polls of the daemon
+    #[arg(short, long, default_value_t = 1)]
+    interval: u64,
+}
+
+#[derive(Args, Debug)]
+struct CtlArgs {
+    /// Path to the control socket of the daemon
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Show registration and call state
+    Status,
+
+    /// Switch do-not-disturb on or off
+    Dnd { state: Toggle },
+
+    /// Dial a number
+    Dial { number: String },
+
+    /// Dial the last dialed number again
+    Redial,
+
+    /// Call back the last missed caller
+    Callback,
+
+    /// Decline the ringing call
+    Decline,
+
+    /// Reload the config file
+    Reload,
+
+    /// Write a JSON snapshot of the daemon state for bug reports, free of secrets
+    DumpState {
+        /// File to write to instead of stdout
+        path: Option<PathBuf>,
+    },
+
+    /// Show the last SIP messages sent and received by the daemon
+    Trace {
+        /// Keep printing messages as they pass
+        #[arg(short, long)]
+        follow: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum FavoritesCommand {
+    /// Add or replace a favorite
+    Add { name: String, number: String },
+
+    /// Remove a favorite
+    Remove {
+        #[arg(add = ArgValueCandidates::new(completion::favorites))]
+        name: String,
+    },
+
+    /// List all favorites
+    List,
+
+    /// Dial a favorite
+    Call {
+        #[arg(add = ArgValueCandidates::new(completion::favorites))]
+        name: String,
+    },
+
+    /// Push favorites to the speed dial keys on the server
+    Sync,
+}
+
+#[derive(Subcommand, Debug)]
+enum BlockCommand {
+    /// Block calls matching a rule.
+    ///
+    /// A rule is either `anonymous`, an IP address or network in CIDR
+    /// notation matched against the Via headers, or a glob pattern or
+    /// `/regex/` matched against the caller's number and name.
+    Add { rule: BlockRule },
+
+    /// Remove a rule
+    Remove { rule: BlockRule },
+
+    /// List all rules
+    List,
+
+    /// List calls rejected by the blocklist
+    Log,
+}
+
+#[derive(Subcommand, Debug)]
+enum LogCommand {
+    /// Delete entries and recordings older than the given age, e.g. `90d`
+    Purge {
+        #[arg(long)]
+        older_than: humantime::Duration,
+    },
+
+    /// Print the transcript of a call's audio
+    Transcript {
+        /// Call-ID of the call
+        call_id: String,
+    },
+
+    /// List the files of a call's recordings
+    Recordings {
+        /// Call-ID of the call
+        call_id: String,
+
+        /// Write the recordings as plain WAV files into this directory, decrypting them if needed
+        #[arg(long)]
+        decrypt_to: Option<PathBuf>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Toggle {
+    On,
+    Off,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let (client, _args) = cmd::init::<()>().await?;
+    let cmd = cmd::parse::<MainArgs>();
+    let output = cmd.output();
+    let (connector, config, args) = cmd.setup().await?;
+
+    match args.command {
+        None => monitor(connector.connect().await?, &config.get()).await,
+        Some(Command::Ctl(args)) => ctl(args).await,
+        Some(Command::Statusbar(args)) => {
+            let socket = ctl_socket(args.socket)?;
+            statusbar::run(socket, args.format, Duration::from_secs(args.interval)).await
+        }
+        Some(Command::Hotkeys { socket }) => {
+            hotkeys::run(&ctl_socket(socket)?, &config.get().hotkeys).await
+        }
+        Some(Command::MatrixBridge(args)) => {
+            let socket = ctl_socket(args.socket)?;
+            let matrix = config.get().matrix.clone().context("No matrix section in config")?;
+            matrix::run(socket, matrix, Duration::from_secs(args.interval)).await
+        }
+        Some(Command::Favorites { command }) => favorites(command, connector).await,
+        Some(Command::Block { command }) => block(command, &config.get(), output),
+        Some(Command::Log { command }) => log(command, &config.get()),
+        Some(Command::Redial { yes }) => dial_last(LastNumber::Dialed, yes, connector).await,
+        Some(Command::Callback { yes }) => dial_last(LastNumber::Missed, yes, connector).await,
+        Some(Command::Maintain) => {
+            let store = Store::open_default()?;
+            let purged = store.enforce_retention(&config.get().retention, SystemTime::now())?;
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&purged)?),
+                Output::Text => println!("Purged {purged}"),
+            }
+            Ok(())
+        }
+        Some(Command::Agent { socket, command }) => {
+            let request = match command {
+                AgentCommand::Pause { reason } => ctl::Request::AgentPause { reason },
+                AgentCommand::Resume => ctl::Request::AgentResume,
+                AgentCommand::WrapUp => ctl::Request::AgentWrapUp,
+            };
+            ctl_request(ctl_socket(socket)?, request).await
+        }
+        Some(Command::Fax { command }) => fax(command, connector.connect().await?).await,
+        Some(Command::Sms { command }) => sms(command, connector.connect().await?).await,
+        Some(Command::Queue { command }) => queue(command, connector.connect().await?).await,
+        Some(Command::Slots { command }) => slots(command, connector.connect().await?).await,
+        Some(Command::Phonebook { command }) => {
+            phonebook(command, connector.connect().await?).await
+        }
+        Some(Command::Cache {
+            command: CacheCommand::Clear,
+        }) => Cache::open_default()?.clear(),
+        Some(Command::Server { command }) => {
+            server(command, connector.connect().await?, output).await
+        }
+        Some(Command::Admin { command }) => admin(command, connector.connect().await?).await,
+        Some(Command::Register { expires, all }) => {
+            register(connector.connect().await?, expires, all).await
+        }
+        Some(Command::Bindings { command }) => {
+            bindings(command, connector.connect().await?, output).await
+        }
+        Some(Command::Selftest) => {
+            let report = selftest::run(&connector.connect().await?).await;
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                Output::Text => print!("{report}"),
+            }
 
+            if !report.passed() {
+                bail!("Self test failed");
+            }
+            Ok(())
+        }
+        Some(Command::Loadtest { calls, concurrency }) => {
+            let report = loadtest::run(loadtest::Options { calls, concurrency }).await?;
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                Output::Text => print!("{report}"),
+            }
+            Ok(())
+        }
+        Some(Command::Audio { command }) => audio(command, &config.get(), output).await,
+        Some(Command::Ivr { command }) => ivr(command),
+        Some(Command::Plugins) => plugins(output).await,
+        Some(Command::Plugin(args)) => run_plugin(args, connector, &config, output).await,
+        Some(Command::Version) => {
+            let info = BuildInfo::get();
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&info)?),
+                Output::Text => println!("{info}"),
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn monitor(client: Client, config: &Config) -> Result<()> {
     let (_socket, mut requests) = client.socket().await?;
 
     loop {
@@ -24,20 +749,38 @@ async fn main() -> Result<()> {
             }
 
             Method::Invite => {
-                let from = tx.request.from_header().expect("valid from header");
-                let seq = tx.request.cseq_header().expect("cseq").seq().expect("cseq");
+                let invite = tx.seq().and_then(|seq| {
+                    Ok((seq, Caller::from_request(&tx.request, &config.caller_id)?))
+                });
+                let (seq, caller) = match invite {
+                    Ok(invite) => invite,
+                    Err(err) => {
+                        tx.reject_malformed(err).await;
+                        continue;
+                    }
+                };
 
-                let from = from.typed().expect("valid from header");
+                let ucware = tx.ucware_headers();
 
-                info!("Invite: {seq}: {from:?}");
+                info!("Invite: {seq}: {caller:?} {ucware:?}", ucware = ucware.values);
 
                 tx.respond(StatusCode::Trying).send([]).await;
                 tx.respond(StatusCode::Ringing).send([]).await;
             }
 
             Method::Cancel => {
-                let seq = tx.request.cseq_header().expect("cseq").seq().expect("cseq");
-                info!("Cancel: {seq}");
+                let seq = match tx.seq() {
+                    Ok(seq) => seq,
+                    Err(err) => {
+                        tx.reject_malformed(err).await;
+                        continue;
+                    }
+                };
+                let reasons = Reason::from_headers(&tx.request.headers)
+                    .iter()
+                    .map(Reason::description)
+                    .collect::<Vec<_>>();
+                info!("Cancel: {seq}: {reasons:?}");
 
                 tx.respond(StatusCode::Accepted).send([]).await;
             }
@@ -46,3 +789,927 @@ async fn main() -> Result<()> {
         }
     }
 }
+
+fn ctl_socket(socket: Option<PathBuf>) -> Result<PathBuf> {
+    socket
+        .or_else(ctl::default_path)
+        .ok_or_else(|| anyhow!("No control socket specified and no default location available"))
+}
+
+async fn ctl(args: CtlArgs) -> Result<()> {
+    let socket = ctl_socket(args.socket)?;
+
+    let request = match args.command {
+        CtlCommand::Status => ctl::Request::Status,
+        CtlCommand::Dnd { state } => ctl::Request::Dnd {
+            enabled: matches!(state, Toggle::On),
+        },
+        CtlCommand::Dial { number } => ctl::Request::Dial { number },
+        CtlCommand::Redial => ctl::Request::Redial,
+        CtlCommand::Callback => ctl::Request::Callback,
+        CtlCommand::Decline => ctl::Request::Decline,
+        CtlCommand::Reload => ctl::Request::Reload,
+        CtlCommand::DumpState { path } => return dump_state(socket, path).await,
+        CtlCommand::Trace { follow } => return sip_trace(socket, follow).await,
+    };
+
+    ctl_request(socket, request).await
+}
+
+async fn ctl_request(socket: PathBuf, request: ctl::Request) -> Result<()> {
+    match ctl::request(socket, &request).await? {
+        ctl::Response::Ok => {}
+
+        ctl::Response::Status(status) => {
+            println!("Registered: {}", if status.registered { "yes" } else { "no" });
+            println!("DND:        {}", if status.dnd { "on" } else { "off" });
+            println!("Missed:     {}", status.missed);
+            println!(
+                "Agent:      {}",
+                match status.agent {
+                    AgentState::Available => "available".to_string(),
+                    AgentState::Paused { reason } =>
+                        format!("paused ({})", reason.as_deref().unwrap_or("no reason")),
+                    AgentState::WrapUp { .. } => "wrap-up".to_string(),
+                }
+            );
+
+            for call in status.calls {
+                println!(
+                    "Ringing:    {name} <{number}>",
+                    name = call.caller.name.as_deref().unwrap_or("Unknown"),
+                    number = call.caller.number.as_deref().unwrap_or("unknown"),
+                );
+            }
+        }
+
+        ctl::Response::State(state) => {
+            println!("{}", serde_json::to_string_pretty(&state)?);
+        }
+
+        ctl::Response::Trace { messages } => print_trace(&messages),
+
+        ctl::Response::Error { message } => {
+            bail!("Daemon failed: {message}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn dump_state(socket: PathBuf, path: Option<PathBuf>) -> Result<()> {
+    let state = match ctl::request(socket, &ctl::Request::DumpState).await? {
+        ctl::Response::State(state) => state,
+        ctl::Response::Error { message } => bail!("Daemon failed: {message}"),
+        response => bail!("Unexpected response: {response:?}"),
+    };
+
+    let json = serde_json::to_string_pretty(&state)?;
+    match path {
+        Some(path) => {
+            std::fs::write(&path, json).with_context(|| format!("Failed to write {path}", path = path.display()))?;
+            info!("Wrote state to {path}", path = path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+async fn sip_trace(socket: PathBuf, follow: bool) -> Result<()> {
+    ctl::follow(socket, &ctl::Request::Trace { follow }, |response| match response {
+        ctl::Response::Trace { messages } => {
+            print_trace(&messages);
+            Ok(())
+        }
+        ctl::Response::Error { message } => bail!("Daemon failed: {message}"),
+        response => bail!("Unexpected response: {response:?}"),
+    })
+    .await
+}
+
+fn print_trace(messages: &[TracedMessage]) {
+    for message in messages {
+        let direction = match message.direction {
+            TraceDirection::Sent => "sent",
+            TraceDirection::Received => "received",
+        };
+
+        println!(
+            "--- {time} {direction}",
+            time = DateTime::<Local>::from(message.time).format("%H:%M:%S%.3f"),
+        );
+        println!("{}", message.message.trim_end());
+    }
+}
+
+/// Dials a remembered number, asking first if interactive
+async fn dial_last(kind: LastNumber, yes: bool, connector: Connector) -> Result<()> {
+    let store = Store::open_default()?;
+    let number = store.last_number(kind)?.with_context(|| match kind {
+        LastNumber::Dialed => "Nothing dialed yet",
+        LastNumber::Missed => "No missed call to return",
+    })?;
+
+    if !yes && std::io::stdin().is_terminal() {
+        print!("Call {number}? [y/N] ");
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Ok(());
+        }
+    }
+
+    let client = connector.connect().await?;
+    client.user().calls().dial(&number).await?;
+    store.set_last_number(LastNumber::Dialed, &number)?;
+
+    info!("Dialing {number}");
+    Ok(())
+}
+
+async fn favorites(command: FavoritesCommand, connector: Connector) -> Result<()> {
+    let store = Store::open_default()?;
+
+    match command {
+        FavoritesCommand::Add { name, number } => {
+            store.add_favorite(&Favorite { name, number })?;
+        }
+
+        FavoritesCommand::Remove { name } => {
+            if !store.remove_favorite(&name)? {
+                bail!("No such favorite: {name}");
+            }
+        }
+
+        FavoritesCommand::List => {
+            for favorite in store.favorites()? {
+                println!("{name}\t{number}", name = favorite.name, number = favorite.number);
+            }
+        }
+
+        FavoritesCommand::Call { name } => {
+            let favorite = store
+                .favorite(&name)?
+                .ok_or_else(|| anyhow!("No such favorite: {name}"))?;
+
+            let client = connector.connect().await?;
+            client.user().calls().dial(&favorite.number).await?;
+            store.set_last_number(LastNumber::Dialed, &favorite.number)?;
+        }
+
+        FavoritesCommand::Sync => {
+            let client = connector.connect().await?;
+            let speed_dials = client.user().speed_dials();
+            let favorites = store.favorites()?;
+
+            let results = parallel(
+                (1..).zip(&favorites).map(|(key, favorite)| {
+                    info!("Setting speed dial {key} to {name}", name = favorite.name);
+                    speed_dials.set(key, &favorite.name, &favorite.number)
+                }),
+                CONCURRENCY,
+            )
+            .await;
+
+            results.into_iter().collect::<Result<Vec<_>>>()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn block(command: BlockCommand, config: &Config, output: Output) -> Result<()> {
+    let store = Store::open_configured(&config.call_log)?;
+
+    match command {
+        BlockCommand::Add { rule } => {
+            if !store.add_block(&rule)? {
+                bail!("Already blocked: {rule}");
+            }
+        }
+
+        BlockCommand::Remove { rule } => {
+            if !store.remove_block(&rule)? {
+                bail!("No such rule: {rule}");
+            }
+        }
+
+        BlockCommand::List => {
+            for rule in store.blocklist()? {
+                println!("{rule}");
+            }
+        }
+
+        BlockCommand::Log => {
+            let calls = store.blocked_calls()?;
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&calls)?),
+                Output::Text => {
+                    for call in calls {
+                        println!(
+                            "{time}\t{rule}\t{number}\t{name}",
+                            time = call.time,
+                            rule = call.rule,
+                            number = call.number.as_deref().unwrap_or(&call.uri),
+                            name = call.name.as_deref().unwrap_or_default(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn log(command: LogCommand, config: &Config) -> Result<()> {
+    let store = Store::open_configured(&config.call_log)?;
+
+    match command {
+        LogCommand::Purge { older_than } => {
+            let before = SystemTime::now()
+                .checked_sub(*older_than)
+                .context("Age exceeds the epoch")?;
+            let purged = store.purge_blocked_calls(before)? + store.purge_transcripts(before)?;
+            let recordings = store.purge_recordings(before)?;
+            println!("Purged {purged} entries and {recordings} recordings");
+        }
+        LogCommand::Transcript { call_id } => {
+            let transcript = store
+                .transcript(&call_id)?
+                .with_context(|| format!("No transcript for call {call_id}"))?;
+            println!("{transcript}");
+        }
+        LogCommand::Recordings { call_id, decrypt_to } => {
+            for path in store.recordings(&call_id)? {
+                let Some(dir) = &decrypt_to else {
+                    println!("{path}", path = path.display());
+                    continue;
+                };
+
+                let name = path.file_name().context("Recording without file name")?;
+                let target = dir.join(name).with_extension("").with_extension("wav");
+                std::fs::write(&target, store.load_recording(&path)?)
+                    .with_context(|| format!("Failed to write {target}", target = target.display()))?;
+                println!("{target}", target = target.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn queue(command: QueueCommand, client: Client) -> Result<()> {
+    match command {
+        QueueCommand::List => {
+            for queue in client.user().queues().get_all().await? {
+                println!("{id}\t{name}", id = queue.id, name = queue.name);
+            }
+        }
+
+        QueueCommand::Login { queue } => {
+            let queues = client.user().queues();
+            let queue = queues.find(&queue).await?;
+            queues.login(queue.id).await?;
+        }
+
+        QueueCommand::Logout { queue } => {
+            let queues = client.user().queues();
+            let queue = queues.find(&queue).await?;
+            queues.logout(queue.id).await?;
+        }
+
+        QueueCommand::Wallboard { queue, interval } => {
+            wallboard::run(&client, &queue, Duration::from_secs(interval)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn fax(command: FaxCommand, client: Client) -> Result<()> {
+    let faxes = client.user().faxes();
+
+    match command {
+        FaxCommand::Send { file, number } => {
+            let document = tokio::fs::read(&file)
+                .await
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let filename = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "document.pdf".to_string());
+
+            let mut job = faxes.send(&number, &filename, &document).await?;
+            let mut last = None;
+
+            loop {
+                let progress = (job.state, job.pages_sent);
+                if last != Some(progress) {
+                    match job.pages_total {
+                        Some(total) => eprintln!(
+                            "{state}: page {sent}/{total}",
+                            state = job.state,
+                            sent = job.pages_sent
+                        ),
+                        None => eprintln!("{state}", state = job.state),
+                    }
+                    last = Some(progress);
+                }
+
+                if job.state.is_final() {
+                    break;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                job = faxes.get_job(job.id).await?;
+            }
+
+            if job.state.is_failed() {
+                bail!(
+                    "Fax to {number} failed: {error}",
+                    error = job.error.as_deref().unwrap_or("unknown error")
+                );
+            }
+        }
+
+        FaxCommand::List => {
+            for fax in faxes.get_received().await? {
+                println!(
+                    "{id}\t{received}\t{number}\t{pages} pages",
+                    id = fax.id,
+                    received = fax.received,
+                    number = fax.number,
+                    pages = fax.pages,
+                );
+            }
+        }
+
+        FaxCommand::Download { id, file } => {
+            let file = file.unwrap_or_else(|| PathBuf::from(format!("fax-{id}.pdf")));
+            let document = faxes.download(id).await?;
+            tokio::fs::write(&file, document)
+                .await
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn sms(command: SmsCommand, client: Client) -> Result<()> {
+    let sms = client.user().sms();
+
+    match command {
+        SmsCommand::Send {
+            number,
+            text,
+            gateway,
+            no_wait,
+        } => {
+            if sms.get_gateways().await?.is_empty() {
+                bail!("No SMS gateways configured on the server");
+            }
+
+            let mut message = sms.send(&number, &text, gateway).await?;
+            eprintln!("{state}", state = message.state);
+
+            while !no_wait && !message.state.is_final() {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+
+                let state = message.state;
+                message = sms.get(message.id).await?;
+                if message.state != state {
+                    eprintln!("{state}", state = message.state);
+                }
+            }
+
+            if message.state.is_failed() {
+                bail!(
+                    "SMS to {number} failed: {error}",
+                    error = message.error.as_deref().unwrap_or("unknown error")
+                );
+            }
+        }
+
+        SmsCommand::List => {
+            for message in sms.get_all().await? {
+                println!(
+                    "{timestamp}\t{direction}\t{number}\t{state}\t{text}",
+                    timestamp = message.timestamp,
+                    direction = if message.incoming { "<" } else { ">" },
+                    number = message.number,
+                    state = message.state,
+                    text = message.text,
+                );
+            }
+        }
+
+        SmsCommand::Gateways => {
+            for gateway in sms.get_gateways().await? {
+                println!("{id}\t{name}", id = gateway.id, name = gateway.name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn admin(command: AdminCommand, client: Client) -> Result<()> {
+    match command {
+        AdminCommand::Devices { command } => {
+            let devices = client.admin().devices();
+
+            match command {
+                DevicesCommand::List => {
+                    for device in devices.get_all().await? {
+                        println!(
+                            "{mac}\t{model}\t{firmware}\t{ip}\t{registered}",
+                            mac = device.mac,
+                            model = device.model,
+                            firmware = device.firmware.as_deref().unwrap_or("-"),
+                            ip = device.ip.as_deref().unwrap_or("-"),
+                            registered = if device.registered { "registered" } else { "offline" },
+                        );
+                    }
+                }
+
+                DevicesCommand::Reboot { mac } => devices.reboot(&mac).await?,
+                DevicesCommand::Reprovision { mac } => devices.reprovision(&mac).await?,
+            }
+        }
+
+        AdminCommand::Users { command } => match command {
+            UsersCommand::Export { format, file } => match file {
+                Some(path) => {
+                    let file = std::fs::File::create(&path)
+                        .with_context(|| format!("Failed to create {}", path.display()))?;
+                    users::export(&client, format, file).await?;
+                }
+                None => users::export(&client, format, std::io::stdout().lock()).await?,
+            },
+
+            UsersCommand::Import { file, dry_run } => {
+                let reader = std::fs::File::open(&file)
+                    .with_context(|| format!("Failed to open {}", file.display()))?;
+                users::import(&client, reader, dry_run).await?;
+            }
+        },
+
+        AdminCommand::Groups { command } => {
+            let groups = client.admin().groups();
+
+            match command {
+                GroupsCommand::List => {
+                    for group in groups.get_all().await? {
+                        println!(
+                            "{id}\t{name}\t{permissions}",
+                            id = group.id,
+                            name = group.name,
+                            permissions = group.permissions.join(","),
+                        );
+                    }
+                }
+
+                GroupsCommand::Members { group } => {
+                    let group = admin::find_group(&client, &group).await?;
+                    let members = groups.get_members(group.id).await?;
+
+                    for user in client.admin().users().get_all().await? {
+                        if members.contains(&user.id) {
+                            println!("{id}\t{username}", id = user.id, username = user.username);
+                        }
+                    }
+                }
+
+                GroupsCommand::AddMember { group, user } => {
+                    let group = admin::find_group(&client, &group).await?;
+                    let user = admin::find_user(&client, &user).await?;
+                    groups.add_member(group.id, user.id).await?;
+                }
+
+                GroupsCommand::RemoveMember { group, user } => {
+                    let group = admin::find_group(&client, &group).await?;
+                    let user = admin::find_user(&client, &user).await?;
+                    groups.remove_member(group.id, user.id).await?;
+                }
+            }
+        }
+
+        AdminCommand::Licenses { command } => {
+            let licenses = client.admin().licenses();
+
+            match command {
+                LicensesCommand::List => {
+                    for license in licenses.get_all().await? {
+                        println!(
+                            "{name}\t{used}/{total}",
+                            name = license.name,
+                            used = license.used,
+                            total = license.total,
+                        );
+                    }
+                }
+
+                LicensesCommand::Assign { license, user } => {
+                    let user = admin::find_user(&client, &user).await?;
+                    licenses.assign(&license, user.id).await?;
+                }
+
+                LicensesCommand::Unassign { license, user } => {
+                    let user = admin::find_user(&client, &user).await?;
+                    licenses.unassign(&license, user.id).await?;
+                }
+            }
+        }
+
+        AdminCommand::Apply {
+            file,
+            dry_run,
+            prune,
+        } => {
+            let state = apply::State::load(&file)?;
+            let plan = apply::Plan::new(&client, &state, prune).await?;
+
+            if plan.is_empty() {
+                println!("No changes");
+                return Ok(());
+            }
+
+            for change in plan.changes() {
+                println!("{change}");
+            }
+
+            if !dry_run {
+                plan.apply(&client).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn server(command: ServerCommand, client: Client, output: Output) -> Result<()> {
+    match command {
+        ServerCommand::Info => {
+            let system = client.system().info();
+            let (version, licenses, health) = tokio::try_join!(
+                system.get_version(),
+                system.get_licenses(),
+                system.get_health(),
+            )?;
+
+            if output == Output::Json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "version": version,
+                        "licenses": licenses,
+                        "health": health,
+                    }))?
+                );
+            } else {
+                match &version.build {
+                    Some(build) => println!("Version: {} ({build})", version.version),
+                    None => println!("Version: {}", version.version),
+                }
+
+                println!();
+                for license in &licenses {
+                    println!(
+                        "{name:<24} {used}/{total}",
+                        name = license.name,
+                        used = license.used,
+                        total = license.total,
+                    );
+                }
+
+                println!();
+                for service in &health {
+                    println!(
+                        "{name:<24} {state:?}{message}",
+                        name = service.service,
+                        state = service.state,
+                        message = service
+                            .message
+                            .as_deref()
+                            .map(|message| format!(": {message}"))
+                            .unwrap_or_default(),
+                    );
+                }
+            }
+
+            let unhealthy = health
+                .iter()
+                .filter(|service| service.state != HealthState::Ok)
+                .count();
+            let exhausted = licenses.iter().filter(|license| license.is_exhausted()).count();
+
+            if unhealthy > 0 || exhausted > 0 {
+                bail!("{unhealthy} services unhealthy, {exhausted} licenses exhausted");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn slots(command: SlotsCommand, client: Client) -> Result<()> {
+    let slots = client.user().slots();
+
+    match command {
+        SlotsCommand::List => {
+            for slot in slots.get_all().await? {
+                println!(
+                    "{id}\t{name}\t{device_type}\t{registered}",
+                    id = slot.id,
+                    name = slot.name,
+                    device_type = slot.device_type,
+                    registered = match slot.registered {
+                        Some(true) => "registered",
+                        Some(false) => "offline",
+                        None => "-",
+                    },
+                );
+            }
+        }
+
+        SlotsCommand::RotateSipPassword { slot, socket } => {
+            let slot = slots.find(&slot).await?;
+            slots.rotate_sip_password(slot.id).await?;
+            info!("Rotated SIP password of {name}", name = slot.name);
+
+            // The daemon only uses the WebRTC slot but re-registering is harmless
+            let socket = ctl_socket(socket)?;
+            match ctl::request(&socket, &ctl::Request::Reregister).await {
+                Ok(ctl::Response::Error { message }) => {
+                    bail!("Failed to notify daemon: {message}")
+                }
+                Ok(_) => info!("Told daemon to register again"),
+                Err(err) => debug!("No daemon to notify: {err:#}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn register(client: Client, expires: u32, all: bool) -> Result<()> {
+    if all && expires != 0 {
+        bail!("Only all bindings can be removed, use --expires 0");
+    }
+
+    let (mut connection, slot) = client.registrar().await?;
+
+    if expires == 0 {
+        connection
+            .unregister(&slot.sip_username, &slot.sip_password, all)
+            .await?;
+        info!(
+            "Removed {bindings} of slot {name}",
+            bindings = if all { "all bindings" } else { "binding" },
+            name = slot.name
+        );
+    } else {
+        connection
+            .register_for(&slot.sip_username, &slot.sip_password, expires)
+            .await?;
+        info!("Registered slot {name} for {expires}s", name = slot.name);
+    }
+
+    Ok(())
+}
+
+async fn bindings(command: BindingsCommand, client: Client, output: Output) -> Result<()> {
+    match command {
+        BindingsCommand::List => {
+            let (mut connection, slot) = client.registrar().await?;
+            let bindings = connection
+                .bindings(&slot.sip_username, &slot.sip_password)
+                .await?;
+
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&bindings)?),
+                Output::Text => {
+                    let own = connection.contact_id();
+                    for binding in bindings {
+                        println!(
+                            "{contact}\t{expires}\t{instance}{own}",
+                            contact = binding.contact,
+                            expires = binding.expires.map_or("-".to_string(), |expires| format!("{expires}s")),
+                            instance = binding.instance.as_deref().unwrap_or("-"),
+                            own = if binding.instance.as_deref() == Some(own.instance.as_str()) {
+                                "\t(this client)"
+                            } else {
+                                ""
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn phonebook(command: PhonebookCommand, client: Client) -> Result<()> {
+    let phonebook = client.user().phonebook();
+
+    let contacts = match command {
+        PhonebookCommand::List => phonebook.get_all().await?,
+        PhonebookCommand::Search { query } => phonebook.search(&query).await?,
+        PhonebookCommand::Add { name, numbers } => vec![phonebook.add(&name, &numbers).await?],
+    };
+
+    for contact in contacts {
+        println!(
+            "{name}\t{numbers}",
+            name = contact.name,
+            numbers = contact.numbers.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+fn ivr(command: IvrCommand) -> Result<()> {
+    match command {
+        IvrCommand::Check { path } => {
+            let ivr = Ivr::load(&path)?;
+            println!("{nodes} nodes, starting at {start}", nodes = ivr.nodes.len(), start = ivr.start);
+        }
+        IvrCommand::Simulate { path } => {
+            let ivr = Ivr::load(&path)?;
+            let (mut session, mut action) = Session::start(&ivr);
+            let mut lines = std::io::stdin().lines();
+
+            loop {
+                println!("[{node}] {action}", node = session.node());
+                let event = match action {
+                    Action::Transfer(_) | Action::Hangup => break,
+                    Action::Collect { .. } => {
+                        print!("Digit, nothing for a timeout: ");
+                        std::io::stdout().flush()?;
+                        let line = lines.next().transpose()?.unwrap_or_default();
+                        match line.trim().chars().next() {
+                            Some(digit) => Event::Digit(digit),
+                            None => Event::Timeout,
+                        }
+                    }
+                    _ => Event::Done,
+                };
+                action = session.next(event);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn plugins(output: Output) -> Result<()> {
+    let mut plugins = Vec::new();
+    for plugin in plugin::installed() {
+        let handshake = plugin.handshake().await;
+        plugins.push((plugin, handshake));
+    }
+
+    match output {
+        Output::Json => {
+            let plugins = plugins
+                .into_iter()
+                .map(|(plugin, handshake)| {
+                    serde_json::json!({
+                        "name": plugin.name,
+                        "path": plugin.path,
+                        "handshake": handshake.ok(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&plugins)?);
+        }
+        Output::Text => {
+            for (plugin, handshake) in plugins {
+                match handshake {
+                    Ok(handshake) => println!(
+                        "{name}\t{description}",
+                        name = plugin.name,
+                        description = handshake.description.unwrap_or_default()
+                    ),
+                    Err(err) => println!("{name}\t(unusable: {err:#})", name = plugin.name),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_plugin(args: Vec<String>, connector: Connector, config: &ConfigHandle, output: Output) -> Result<()> {
+    let (name, args) = args.split_first().context("Missing plugin name")?;
+    let plugin = plugin::find(name).with_context(|| {
+        format!("Unknown command {name}, no {prefix}{name} found on PATH", prefix = plugin::PREFIX)
+    })?;
+
+    let handshake = plugin.handshake().await?;
+
+    let connection = if handshake.connect {
+        let client = connector.connect().await?;
+        Some((client.url().to_string(), client.token().await))
+    } else {
+        None
+    };
+
+    let env = plugin::Environment {
+        config: config.path().to_path_buf(),
+        output: match output {
+            Output::Text => "text",
+            Output::Json => "json",
+        }
+        .to_string(),
+        ctl_socket: ctl::default_path(),
+        connection,
+    };
+
+    let status = plugin.run(args, &env).await?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "audio")]
+async fn audio(command: AudioCommand, config: &Config, output: Output) -> Result<()> {
+    match command {
+        AudioCommand::Devices => {
+            let devices = audio::devices()?;
+            match output {
+                Output::Json => println!("{}", serde_json::to_string_pretty(&devices)?),
+                Output::Text => {
+                    for device in devices {
+                        let configured = match device.direction {
+                            Direction::Input => &config.audio.input,
+                            Direction::Output => &config.audio.output,
+                        };
+
+                        let mut marks = Vec::new();
+                        if device.default {
+                            marks.push("default");
+                        }
+                        if configured.as_ref() == Some(&device.name) {
+                            marks.push("configured");
+                        }
+
+                        match marks.as_slice() {
+                            [] => println!("{:<6}  {}", device.direction, device.name),
+                            marks => println!("{:<6}  {} ({})", device.direction, device.name, marks.join(", ")),
+                        }
+                    }
+                }
+            }
+        }
+        AudioCommand::Loopback { delay } => {
+            let microphone = audio::input_device(config.audio.input.as_deref())?;
+            let speaker = audio::output_device(config.audio.output.as_deref())?;
+            let loopback = audio::Loopback::start(
+                &microphone,
+                &speaker,
+                *delay,
+                config.audio.input_volume.map_or(Volume::FULL, Volume),
+                config.audio.output_volume.map_or(Volume::FULL, Volume),
+            )?;
+            println!("Speak to hear yourself after {delay}, press Ctrl-C to stop");
+
+            let mut interval = tokio::time::interval(Duration::from_millis(100));
+            loop {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => break,
+                    _ = interval.tick() => {
+                        let level = loopback.level();
+                        print!("\r{meter} {dbfs:6.1} dBFS", meter = level.meter(40), dbfs = level.dbfs());
+                        std::io::stdout().flush()?;
+                    }
+                }
+            }
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "audio"))]
+async fn audio(_command: AudioCommand, _config: &Config, _output: Output) -> Result<()> {
+    bail!("Built without audio support, enable the audio feature");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MainArgs;
+
+    #[test]
+    fn command_line() {
+        ucware_cli::cmd::debug_assert::<MainArgs>();
+    }
+}
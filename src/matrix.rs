@@ -0,0 +1,312 @@
+//! Bridge between a running daemon and a Matrix room
+//!
+//! Posts incoming and missed calls as well as new voicemails to the room and
+//! accepts commands like `!dial 123` or `!dnd on` from authorized users.
+
+use crate::callstate::Call;
+use crate::config::MatrixConfig;
+use crate::ctl;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+use url::Url;
+
+/// Time the homeserver holds a sync request open if there is nothing new
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay before retrying after the homeserver failed
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const HELP: &str = "Commands: !status, !dial <number>, !dnd on|off";
+
+struct Client {
+    http: reqwest::Client,
+    config: MatrixConfig,
+
+    /// Transaction IDs deduplicate retried messages on the homeserver
+    txn: AtomicU64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sync {
+    next_batch: String,
+
+    #[serde(default)]
+    rooms: Rooms,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Rooms {
+    #[serde(default)]
+    join: HashMap<String, JoinedRoom>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinedRoom {
+    timeline: Timeline,
+}
+
+#[derive(Debug, Deserialize)]
+struct Timeline {
+    events: Vec<RoomEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomEvent {
+    sender: String,
+
+    #[serde(default)]
+    content: Value,
+}
+
+impl Client {
+    fn new(config: MatrixConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(SYNC_TIMEOUT * 2)
+            .build()?;
+
+        let txn = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+
+        Ok(Self {
+            http,
+            config,
+            txn: AtomicU64::new(txn),
+        })
+    }
+
+    fn url(&self, path: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(&self.config.homeserver).context("Invalid homeserver URL")?;
+        url.path_segments_mut()
+            .map_err(|()| anyhow!("Invalid homeserver URL"))?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(path);
+        Ok(url)
+    }
+
+    async fn send(&self, text: &str) -> Result<()> {
+        let txn = self.txn.fetch_add(1, Ordering::Relaxed).to_string();
+        let url = self.url(&["rooms", &self.config.room, "send", "m.room.message", &txn])?;
+
+        self.http
+            .put(url)
+            .bearer_auth(&self.config.token)
+            .json(&json!({ "msgtype": "m.notice", "body": text }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("Failed to post to Matrix room")?;
+
+        Ok(())
+    }
+
+    /// Waits for new messages in the room, returns the next sync token along with `(sender, body)` pairs
+    async fn sync(&self, since: Option<&str>) -> Result<(String, Vec<(String, String)>)> {
+        let filter = json!({
+            "presence": { "types": [] },
+            "account_data": { "types": [] },
+            "room": {
+                "rooms": [self.config.room],
+                "state": { "types": [] },
+                "ephemeral": { "types": [] },
+                "account_data": { "types": [] },
+                "timeline": { "types": ["m.room.message"] },
+            },
+        });
+
+        let mut request = self
+            .http
+            .get(self.url(&["sync"])?)
+            .bearer_auth(&self.config.token)
+            .query(&[("filter", filter.to_string())]);
+        request = match since {
+            Some(since) => request.query(&[
+                ("since", since.to_string()),
+                ("timeout", SYNC_TIMEOUT.as_millis().to_string()),
+            ]),
+            None => request.query(&[("timeout", "0")]),
+        };
+
+        let sync: Sync = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("Failed to sync with Matrix homeserver")?
+            .json()
+            .await?;
+
+        let messages = sync
+            .rooms
+            .join
+            .into_iter()
+            .filter(|(room, _)| *room == self.config.room)
+            .flat_map(|(_, room)| room.timeline.events)
+            .filter_map(|event| {
+                let body = event.content.get("body")?.as_str()?.to_string();
+                Some((event.sender, body))
+            })
+            .collect();
+
+        Ok((sync.next_batch, messages))
+    }
+}
+
+/// Runs the bridge until either side fails
+pub async fn run(socket: PathBuf, config: MatrixConfig, interval: Duration) -> Result<()> {
+    let client = Client::new(config)?;
+
+    info!("Bridging to Matrix room {room}", room = client.config.room);
+
+    tokio::try_join!(post_events(&client, &socket, interval), handle_commands(&client, &socket))?;
+    Ok(())
+}
+
+/// Polls the daemon and posts changes to the room
+async fn post_events(client: &Client, socket: &Path, interval: Duration) -> Result<()> {
+    let mut last: Option<ctl::Status> = None;
+
+    loop {
+        match ctl::request(socket, &ctl::Request::Status).await {
+            Ok(ctl::Response::Status(status)) => {
+                if let Some(last) = &last {
+                    for message in changes(last, &status) {
+                        if let Err(err) = client.send(&message).await {
+                            warn!("{err:#}");
+                        }
+                    }
+                }
+                last = Some(status);
+            }
+            Ok(response) => debug!("Unexpected response from daemon: {response:?}"),
+            Err(err) => debug!("Daemon not reachable: {err:#}"),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Messages describing what happened between two states of the daemon
+fn changes(last: &ctl::Status, status: &ctl::Status) -> Vec<String> {
+    let mut messages = Vec::new();
+
+    let known = |calls: &[Call], call: &Call| {
+        calls.iter().any(|other| other.key == call.key)
+    };
+
+    for call in status.calls.iter().filter(|call| !known(&last.calls, call)) {
+        let mut message = format!("📞 Incoming call from {caller}", caller = call.caller.display());
+        if let Some(forwarded) = &call.forwarded {
+            message.push_str(&format!(", {forwarded}", forwarded = forwarded.description()));
+        }
+        messages.push(message);
+    }
+
+    // Ended calls cannot be told apart, so only the count of missed ones is reliable
+    let ended = last.calls.iter().filter(|call| !known(&status.calls, call));
+    let missed = status.missed.saturating_sub(last.missed) as usize;
+    for call in ended.take(missed) {
+        messages.push(format!("Missed call from {caller}", caller = call.caller.display()));
+    }
+
+    if status.voicemail > last.voicemail {
+        messages.push(format!("✉ {voicemail} new voicemails", voicemail = status.voicemail));
+    }
+
+    messages
+}
+
+/// Follows the room and forwards commands of authorized users to the daemon
+async fn handle_commands(client: &Client, socket: &Path) -> Result<()> {
+    // Skip everything sent before the bridge started
+    let mut since = loop {
+        match client.sync(None).await {
+            Ok((since, _)) => break since,
+            Err(err) => {
+                warn!("{err:#}");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    };
+
+    loop {
+        let messages = match client.sync(Some(&since)).await {
+            Ok((next, messages)) => {
+                since = next;
+                messages
+            }
+            Err(err) => {
+                warn!("{err:#}");
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        for (sender, body) in messages {
+            let Some(command) = body.strip_prefix('!') else {
+                continue;
+            };
+
+            if !client.config.users.contains(&sender) {
+                warn!("Ignoring command from unauthorized user {sender}");
+                continue;
+            }
+
+            info!("Command from {sender}: {body}");
+            let reply = match parse(command) {
+                Ok(request) => match ctl::request(socket, &request).await {
+                    Ok(response) => describe(response),
+                    Err(err) => format!("Daemon not reachable: {err:#}"),
+                },
+                Err(err) => format!("{err:#}\n{HELP}"),
+            };
+
+            client.send(&reply).await?;
+        }
+    }
+}
+
+fn parse(command: &str) -> Result<ctl::Request> {
+    let mut args = command.split_whitespace();
+
+    let request = match (args.next(), args.next()) {
+        (Some("status"), None) => ctl::Request::Status,
+        (Some("dial"), Some(number)) => ctl::Request::Dial {
+            number: number.to_string(),
+        },
+        (Some("dnd"), Some("on")) => ctl::Request::Dnd { enabled: true },
+        (Some("dnd"), Some("off")) => ctl::Request::Dnd { enabled: false },
+        _ => bail!("Unknown command: !{command}"),
+    };
+
+    if args.next().is_some() {
+        bail!("Too many arguments: !{command}");
+    }
+
+    Ok(request)
+}
+
+fn describe(response: ctl::Response) -> String {
+    match response {
+        ctl::Response::Ok => "Done".to_string(),
+        ctl::Response::Error { message } => format!("Failed: {message}"),
+        ctl::Response::State(_) => "Unexpected state dump".to_string(),
+        ctl::Response::Trace { .. } => "Unexpected SIP trace".to_string(),
+        ctl::Response::Status(status) => {
+            let mut lines = vec![
+                format!("Registered: {}", if status.registered { "yes" } else { "no" }),
+                format!("DND: {}", if status.dnd { "on" } else { "off" }),
+                format!("Missed: {}", status.missed),
+                format!("Voicemails: {}", status.voicemail),
+            ];
+            for call in &status.calls {
+                lines.push(format!("Ringing: {caller}", caller = call.caller.display()));
+            }
+            lines.join("\n")
+        }
+    }
+}
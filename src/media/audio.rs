@@ -0,0 +1,226 @@
+use super::level::{Level, Volume};
+use anyhow::{bail, Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input => write!(f, "input"),
+            Self::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// An audio device of the default host
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub direction: Direction,
+
+    /// Whether the system uses the device unless configured otherwise
+    pub default: bool,
+}
+
+/// Input and output devices of the default host
+pub fn devices() -> Result<Vec<DeviceInfo>> {
+    let host = cpal::default_host();
+    let default_input = host.default_input_device().and_then(|device| device.name().ok());
+    let default_output = host.default_output_device().and_then(|device| device.name().ok());
+
+    let inputs = host
+        .input_devices()
+        .context("Failed to list input devices")?
+        .map(|device| (device, Direction::Input, &default_input));
+    let outputs = host
+        .output_devices()
+        .context("Failed to list output devices")?
+        .map(|device| (device, Direction::Output, &default_output));
+
+    inputs
+        .chain(outputs)
+        .map(|(device, direction, default)| {
+            let name = device.name().context("Failed to get device name")?;
+            Ok(DeviceInfo {
+                default: default.as_ref() == Some(&name),
+                name,
+                direction,
+            })
+        })
+        .collect()
+}
+
+/// The input device of the given name, the system default if none given
+pub fn input_device(name: Option<&str>) -> Result<cpal::Device> {
+    let host = cpal::default_host();
+    match name {
+        Some(name) => find(host.input_devices()?, name),
+        None => host.default_input_device().context("No default input device"),
+    }
+}
+
+/// The output device of the given name, the system default if none given
+pub fn output_device(name: Option<&str>) -> Result<cpal::Device> {
+    let host = cpal::default_host();
+    match name {
+        Some(name) => find(host.output_devices()?, name),
+        None => host.default_output_device().context("No default output device"),
+    }
+}
+
+fn find(mut devices: impl Iterator<Item = cpal::Device>, name: &str) -> Result<cpal::Device> {
+    devices
+        .find(|device| device.name().is_ok_and(|known| known == name))
+        .with_context(|| format!("No audio device named {name}"))
+}
+
+/// Records the input device and plays it back on the output device after a delay
+pub struct Loopback {
+    _input: cpal::Stream,
+    _output: cpal::Stream,
+    level: Arc<Mutex<Level>>,
+}
+
+impl Loopback {
+    pub fn start(
+        input: &cpal::Device,
+        output: &cpal::Device,
+        delay: Duration,
+        input_volume: Volume,
+        output_volume: Volume,
+    ) -> Result<Self> {
+        let input_config = input.default_input_config().context("No input config")?;
+        let output_config = output.default_output_config().context("No output config")?;
+        let input_rate = input_config.sample_rate().0;
+        let output_rate = output_config.sample_rate().0;
+
+        // Mono samples at the input rate, starting with silence as long as the delay
+        let delayed = (delay.as_secs_f64() * input_rate as f64) as usize;
+        let buffer = Arc::new(Mutex::new(VecDeque::from(vec![0; delayed])));
+        let level = Arc::new(Mutex::new(Level::default()));
+
+        let record = {
+            let buffer = buffer.clone();
+            let level = level.clone();
+            move |mono: &mut Vec<i16>| {
+                input_volume.apply(mono);
+                *level.lock().expect("Level poisoned") = Level::measure(mono);
+                buffer.lock().expect("Buffer poisoned").extend(mono.iter());
+            }
+        };
+
+        // Recorded samples consumed per played one
+        let step = input_rate as f64 / output_rate as f64;
+        let mut position = 0.0;
+        let mut current = 0;
+        let play = move |mono: &mut [i16]| {
+            let mut buffer = buffer.lock().expect("Buffer poisoned");
+            for sample in mono.iter_mut() {
+                position += step;
+                while position >= 1.0 {
+                    position -= 1.0;
+                    current = buffer.pop_front().unwrap_or(0);
+                }
+                *sample = current;
+            }
+            output_volume.apply(mono);
+        };
+
+        let input_format = input_config.sample_format();
+        let input_config = input_config.config();
+        let input_stream = match input_format {
+            SampleFormat::I16 => record_stream::<i16>(input, &input_config, record)?,
+            SampleFormat::F32 => record_stream::<f32>(input, &input_config, record)?,
+            format => bail!("Unsupported input sample format: {format}"),
+        };
+
+        let output_format = output_config.sample_format();
+        let output_config = output_config.config();
+        let output_stream = match output_format {
+            SampleFormat::I16 => play_stream::<i16>(output, &output_config, play)?,
+            SampleFormat::F32 => play_stream::<f32>(output, &output_config, play)?,
+            format => bail!("Unsupported output sample format: {format}"),
+        };
+
+        Ok(Self {
+            _input: input_stream,
+            _output: output_stream,
+            level,
+        })
+    }
+
+    /// Level of the most recently recorded frame
+    pub fn level(&self) -> Level {
+        *self.level.lock().expect("Level poisoned")
+    }
+}
+
+/// Stream passing recorded frames mixed down to mono to the given function
+fn record_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut record: impl FnMut(&mut Vec<i16>) + Send + 'static,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    i16: FromSample<T>,
+{
+    let channels = config.channels as usize;
+    let mut mono = Vec::new();
+    let stream = device.build_input_stream(
+        config,
+        move |samples: &[T], _: &_| {
+            mono.clear();
+            mono.extend(samples.chunks(channels).map(|frame| {
+                let sum = frame.iter().map(|&sample| sample.to_sample::<i16>() as i32).sum::<i32>();
+                (sum / frame.len() as i32) as i16
+            }));
+            record(&mut mono);
+        },
+        |err| warn!("Audio input failed: {err}"),
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
+
+/// Stream playing mono frames filled by the given function on all channels
+fn play_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut play: impl FnMut(&mut [i16]) + Send + 'static,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<i16>,
+{
+    let channels = config.channels as usize;
+    let mut mono = Vec::new();
+    let stream = device.build_output_stream(
+        config,
+        move |samples: &mut [T], _: &_| {
+            mono.clear();
+            mono.resize(samples.len() / channels, 0);
+            play(&mut mono);
+            for (frame, &sample) in samples.chunks_mut(channels).zip(&mono) {
+                frame.fill(T::from_sample(sample));
+            }
+        },
+        |err| warn!("Audio output failed: {err}"),
+        None,
+    )?;
+    stream.play()?;
+    Ok(stream)
+}
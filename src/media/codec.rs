@@ -0,0 +1,273 @@
+use anyhow::{bail, Result};
+use std::fmt;
+
+/// Audio format as described by the `a=rtpmap` and `a=fmtp` attributes of SDP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// Encoding name, like `PCMU`
+    pub name: &'static str,
+
+    /// RTP clock rate
+    pub clock_rate: u32,
+
+    /// Channels given in the rtpmap, e.g. always 2 for Opus even if it carries mono audio
+    pub channels: u16,
+
+    /// Payload type assigned by RFC 3551, dynamic formats get theirs during negotiation
+    pub payload_type: Option<u8>,
+
+    /// Parameters of the `a=fmtp` attribute
+    pub fmtp: Option<&'static str>,
+
+    /// Sample rate of the mono PCM frames passed to and from the codec
+    pub sample_rate: u32,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{name}/{rate}", name = self.name, rate = self.clock_rate)?;
+        if self.channels != 1 {
+            write!(f, "/{channels}", channels = self.channels)?;
+        }
+        Ok(())
+    }
+}
+
+pub const PCMU: Format = Format {
+    name: "PCMU",
+    clock_rate: 8000,
+    channels: 1,
+    payload_type: Some(0),
+    fmtp: None,
+    sample_rate: 8000,
+};
+
+pub const PCMA: Format = Format {
+    name: "PCMA",
+    clock_rate: 8000,
+    channels: 1,
+    payload_type: Some(8),
+    fmtp: None,
+    sample_rate: 8000,
+};
+
+pub const OPUS: Format = Format {
+    name: "opus",
+    clock_rate: 48000,
+    channels: 2,
+    payload_type: None,
+    fmtp: Some("minptime=10;useinbandfec=1"),
+    sample_rate: 48000,
+};
+
+/// Comfort noise of RFC 3389, sent during silence instead of a codec's frames
+pub const CN: Format = Format {
+    name: "CN",
+    clock_rate: 8000,
+    channels: 1,
+    payload_type: Some(13),
+    fmtp: None,
+    sample_rate: 8000,
+};
+
+/// Encoder and decoder of one format
+pub trait Codec: Send {
+    fn format(&self) -> &Format;
+
+    /// Appends the RTP payload encoding the given PCM frame
+    fn encode(&mut self, pcm: &[i16], payload: &mut Vec<u8>) -> Result<()>;
+
+    /// Appends the PCM samples decoded from the given RTP payload
+    fn decode(&mut self, payload: &[u8], pcm: &mut Vec<i16>) -> Result<()>;
+}
+
+/// Creates a codec for a call
+pub type Factory = fn() -> Result<Box<dyn Codec>>;
+
+/// Codecs available for negotiation, in order of preference
+#[derive(Clone)]
+pub struct Codecs {
+    entries: Vec<(Format, Factory)>,
+}
+
+impl Codecs {
+    pub fn empty() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Adds a codec, preferred less than those added before
+    pub fn with(mut self, format: Format, factory: Factory) -> Self {
+        self.entries.push((format, factory));
+        self
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &Format> {
+        self.entries.iter().map(|(format, _)| format)
+    }
+
+    pub fn create(&self, format: &Format) -> Result<Box<dyn Codec>> {
+        match self.entries.iter().find(|(known, _)| known == format) {
+            Some((_, factory)) => factory(),
+            None => bail!("Unsupported codec: {format}"),
+        }
+    }
+}
+
+impl Default for Codecs {
+    /// Opus if built with it, then G.711 as understood by every PBX
+    fn default() -> Self {
+        let codecs = Self::empty();
+
+        #[cfg(feature = "opus")]
+        let codecs = codecs.with(OPUS, || Ok(Box::new(opus::Opus::new()?)));
+
+        codecs
+            .with(PCMU, || Ok(Box::new(G711::ulaw())))
+            .with(PCMA, || Ok(Box::new(G711::alaw())))
+    }
+}
+
+/// G.711 in its µ-law or A-law variant
+pub struct G711 {
+    format: Format,
+    encode: fn(i16) -> u8,
+    decode: fn(u8) -> i16,
+}
+
+impl G711 {
+    pub fn ulaw() -> Self {
+        Self {
+            format: PCMU,
+            encode: ulaw_encode,
+            decode: ulaw_decode,
+        }
+    }
+
+    pub fn alaw() -> Self {
+        Self {
+            format: PCMA,
+            encode: alaw_encode,
+            decode: alaw_decode,
+        }
+    }
+}
+
+impl Codec for G711 {
+    fn format(&self) -> &Format {
+        &self.format
+    }
+
+    fn encode(&mut self, pcm: &[i16], payload: &mut Vec<u8>) -> Result<()> {
+        payload.extend(pcm.iter().map(|&sample| (self.encode)(sample)));
+        Ok(())
+    }
+
+    fn decode(&mut self, payload: &[u8], pcm: &mut Vec<i16>) -> Result<()> {
+        pcm.extend(payload.iter().map(|&byte| (self.decode)(byte)));
+        Ok(())
+    }
+}
+
+/// Added to magnitudes before µ-law compression
+const ULAW_BIAS: i32 = 0x84;
+
+/// Largest magnitude µ-law represents
+const ULAW_CLIP: i32 = 32635;
+
+fn ulaw_encode(sample: i16) -> u8 {
+    let sample = sample as i32;
+    let sign = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = sample.abs().min(ULAW_CLIP) + ULAW_BIAS;
+
+    // Position of the highest bit above the seven lowest ones
+    let exponent = (31 - magnitude.leading_zeros() as i32 - 7).max(0);
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0f;
+
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+fn ulaw_decode(byte: u8) -> i16 {
+    let byte = !byte as i32;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = byte & 0x0f;
+    let magnitude = (((mantissa << 3) + ULAW_BIAS) << exponent) - ULAW_BIAS;
+
+    (if byte & 0x80 != 0 { -magnitude } else { magnitude }) as i16
+}
+
+fn alaw_encode(sample: i16) -> u8 {
+    let sample = sample as i32;
+    let (sign, magnitude) = if sample >= 0 { (0x80, sample) } else { (0, -sample - 1) };
+
+    let (exponent, mantissa) = if magnitude < 256 {
+        (0, magnitude >> 4)
+    } else {
+        let exponent = 31 - magnitude.leading_zeros() as i32 - 7;
+        (exponent, (magnitude >> (exponent + 3)) & 0x0f)
+    };
+
+    ((sign | (exponent << 4) | mantissa) ^ 0x55) as u8
+}
+
+fn alaw_decode(byte: u8) -> i16 {
+    let byte = (byte ^ 0x55) as i32;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = byte & 0x0f;
+    let magnitude = match exponent {
+        0 => (mantissa << 4) + 8,
+        _ => ((mantissa << 4) + 0x108) << (exponent - 1),
+    };
+
+    (if byte & 0x80 != 0 { magnitude } else { -magnitude }) as i16
+}
+
+#[cfg(feature = "opus")]
+mod opus {
+    use super::{Codec, Format, OPUS};
+    use anyhow::Result;
+    use audiopus::coder::{Decoder, Encoder};
+    use audiopus::{Application, Channels, SampleRate};
+
+    /// Largest Opus packet, see RFC 6716, section 3.2.1
+    const MAX_PACKET: usize = 1275;
+
+    /// Samples of the longest Opus frame of 120ms at 48kHz
+    const MAX_FRAME: usize = 5760;
+
+    /// Opus carrying mono audio at 48kHz
+    pub struct Opus {
+        encoder: Encoder,
+        decoder: Decoder,
+    }
+
+    impl Opus {
+        pub fn new() -> Result<Self> {
+            Ok(Self {
+                encoder: Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip)?,
+                decoder: Decoder::new(SampleRate::Hz48000, Channels::Mono)?,
+            })
+        }
+    }
+
+    impl Codec for Opus {
+        fn format(&self) -> &Format {
+            &OPUS
+        }
+
+        fn encode(&mut self, pcm: &[i16], payload: &mut Vec<u8>) -> Result<()> {
+            let mut packet = [0; MAX_PACKET];
+            let len = self.encoder.encode(pcm, &mut packet)?;
+            payload.extend_from_slice(&packet[..len]);
+            Ok(())
+        }
+
+        fn decode(&mut self, payload: &[u8], pcm: &mut Vec<i16>) -> Result<()> {
+            let mut frame = [0; MAX_FRAME];
+            let len = self
+                .decoder
+                .decode(Some(payload.try_into()?), (&mut frame[..]).try_into()?, false)?;
+            pcm.extend_from_slice(&frame[..len]);
+            Ok(())
+        }
+    }
+}
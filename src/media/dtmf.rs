@@ -0,0 +1,51 @@
+use super::rtp::RtpPacket;
+use anyhow::{ensure, Context, Result};
+
+/// Digits by event code of RFC 4733, section 3.2
+const DIGITS: &[u8; 16] = b"0123456789*#ABCD";
+
+/// A DTMF digit sent as RTP event, see RFC 4733, section 2.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DtmfEvent {
+    pub digit: char,
+
+    /// Whether this is a packet ending the event
+    pub end: bool,
+
+    /// Time the digit has been pressed so far, in timestamp units
+    pub duration: u16,
+}
+
+impl DtmfEvent {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        ensure!(payload.len() >= 4, "DTMF event too short: {} bytes", payload.len());
+
+        let digit = DIGITS
+            .get(payload[0] as usize)
+            .with_context(|| format!("Unsupported event: {event}", event = payload[0]))?;
+
+        Ok(Self {
+            digit: *digit as char,
+            end: payload[1] & 0x80 != 0,
+            duration: u16::from_be_bytes([payload[2], payload[3]]),
+        })
+    }
+}
+
+/// Turns the packets of DTMF events into digits, each reported once
+#[derive(Debug, Default)]
+pub struct DtmfDetector {
+    /// Timestamp of the last event, shared by all of its packets
+    last: Option<u32>,
+}
+
+impl DtmfDetector {
+    /// The digit of a packet starting an event, none for further packets of it
+    pub fn push(&mut self, packet: &RtpPacket) -> Option<char> {
+        let event = DtmfEvent::parse(&packet.payload).ok()?;
+        if self.last.replace(packet.timestamp) == Some(packet.timestamp) {
+            return None;
+        }
+        Some(event.digit)
+    }
+}
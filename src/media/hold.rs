@@ -0,0 +1,45 @@
+use super::wav;
+use crate::config::HoldConfig;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Media sent while a call is held locally, so the PBX does not hang up for lack of RTP
+#[derive(Debug, Clone)]
+pub enum HoldMusic {
+    Silence,
+
+    /// Samples of an audio file, played in a loop
+    Looped { samples: Arc<[i16]>, position: usize },
+}
+
+impl HoldMusic {
+    /// Hold media as configured, at the sample rate of the call's codec
+    pub fn load(config: &HoldConfig, sample_rate: u32) -> Result<Self> {
+        let Some(path) = &config.music else {
+            return Ok(Self::Silence);
+        };
+
+        let samples = wav::read(path, sample_rate)?;
+        if samples.is_empty() {
+            return Ok(Self::Silence);
+        }
+
+        Ok(Self::Looped {
+            samples: samples.into(),
+            position: 0,
+        })
+    }
+
+    /// Fills the next frame
+    pub fn fill(&mut self, pcm: &mut [i16]) {
+        match self {
+            Self::Silence => pcm.fill(0),
+            Self::Looped { samples, position } => {
+                for sample in pcm {
+                    *sample = samples[*position];
+                    *position = (*position + 1) % samples.len();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use super::rtcp::{self, ReportBlock};
+use super::rtp::RtpPacket;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Quality of a received RTP stream, see RFC 3550, section 6.4.1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RtpStats {
+    /// Packets received, including late and duplicate ones
+    pub received: u64,
+
+    /// Packets expected from the range of sequence numbers seen
+    pub expected: u64,
+
+    /// Packets arriving after their turn to be played had passed
+    pub late: u64,
+
+    /// Interarrival jitter in milliseconds
+    pub jitter_ms: f64,
+
+    /// Round trip time in milliseconds from RTCP reports, if any were exchanged
+    pub rtt_ms: Option<f64>,
+}
+
+impl RtpStats {
+    /// Packets never received
+    pub fn lost(&self) -> u64 {
+        self.expected.saturating_sub(self.received)
+    }
+
+    /// Share of the expected packets lost, from 0 to 1
+    pub fn loss(&self) -> f64 {
+        match self.expected {
+            0 => 0.0,
+            expected => self.lost() as f64 / expected as f64,
+        }
+    }
+}
+
+impl fmt::Display for RtpStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "received {received}, lost {lost} ({loss:.1}%), late {late}, jitter {jitter:.1}ms",
+            received = self.received,
+            lost = self.lost(),
+            loss = self.loss() * 100.0,
+            late = self.late,
+            jitter = self.jitter_ms,
+        )?;
+        if let Some(rtt) = self.rtt_ms {
+            write!(f, ", rtt {rtt:.0}ms")?;
+        }
+        Ok(())
+    }
+}
+
+/// What to play for the next frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Playout {
+    Packet(RtpPacket),
+
+    /// The packet of the frame is missing, the codec should conceal it
+    Lost,
+}
+
+/// Reorders received packets and delays them to smooth out network jitter.
+///
+/// The delay adapts to the measured jitter between the given bounds. The
+/// buffer is drained one frame at a time by the player.
+pub struct JitterBuffer {
+    clock_rate: u32,
+    min_delay: Duration,
+    max_delay: Duration,
+
+    /// Packets by extended sequence number
+    packets: BTreeMap<u64, RtpPacket>,
+
+    /// Extended sequence number of the next packet to play, unset until playing
+    next: Option<u64>,
+
+    /// Whether enough was buffered to play, until the buffer runs dry
+    playing: bool,
+
+    first: Option<u64>,
+    highest: Option<u64>,
+
+    /// Arrival of the first packet, all arrivals are relative to it
+    epoch: Option<Instant>,
+
+    /// Transit time of the previous packet in seconds
+    transit: Option<f64>,
+
+    /// Interarrival jitter in seconds
+    jitter: f64,
+
+    stats: RtpStats,
+
+    /// Source of the stream, as of the last packet
+    ssrc: u32,
+
+    /// Expected and received packets as of the last report
+    reported: (u64, u64),
+
+    /// Middle of the NTP timestamp and arrival of the last sender report
+    last_sr: Option<(u32, Instant)>,
+}
+
+impl JitterBuffer {
+    pub fn new(clock_rate: u32, min_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            clock_rate,
+            min_delay,
+            max_delay,
+            packets: BTreeMap::new(),
+            next: None,
+            playing: false,
+            first: None,
+            highest: None,
+            epoch: None,
+            transit: None,
+            jitter: 0.0,
+            stats: RtpStats::default(),
+            ssrc: 0,
+            reported: (0, 0),
+            last_sr: None,
+        }
+    }
+
+    /// Takes a packet received at the given time
+    pub fn push(&mut self, packet: RtpPacket, arrival: Instant) {
+        self.stats.received += 1;
+        self.ssrc = packet.ssrc;
+
+        // RFC 3550, appendix A.8
+        let epoch = *self.epoch.get_or_insert(arrival);
+        let transit = arrival.duration_since(epoch).as_secs_f64() - packet.timestamp as f64 / self.clock_rate as f64;
+        if let Some(previous) = self.transit.replace(transit) {
+            self.jitter += ((transit - previous).abs() - self.jitter) / 16.0;
+        }
+        self.stats.jitter_ms = self.jitter * 1000.0;
+
+        let sequence = self.extend(packet.sequence);
+        let first = *self.first.get_or_insert(sequence);
+        if sequence < first {
+            self.first = Some(sequence);
+        }
+        let highest = self.highest.map_or(sequence, |highest| highest.max(sequence));
+        self.highest = Some(highest);
+        self.stats.expected = highest - self.first.unwrap_or(sequence) + 1;
+
+        if self.next.is_some_and(|next| sequence < next) {
+            self.stats.late += 1;
+            return;
+        }
+
+        self.packets.insert(sequence, packet);
+    }
+
+    /// The next frame to play, none while buffering
+    pub fn pop(&mut self) -> Option<Playout> {
+        if !self.playing {
+            // Start playing once enough is buffered to ride out the jitter
+            if self.packets.is_empty() || self.buffered() < self.delay() {
+                return None;
+            }
+            self.playing = true;
+            self.next = self.packets.keys().next().copied();
+        }
+
+        if self.packets.is_empty() {
+            // Ran dry, buffer again before going on
+            self.playing = false;
+            return None;
+        }
+
+        let next = self.next?;
+        self.next = Some(next + 1);
+        Some(match self.packets.remove(&next) {
+            Some(packet) => Playout::Packet(packet),
+            None => Playout::Lost,
+        })
+    }
+
+    /// Delay the buffer currently aims for
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter * 4.0).clamp(self.min_delay, self.max_delay)
+    }
+
+    pub fn stats(&self) -> RtpStats {
+        self.stats
+    }
+
+    /// Records the round trip time measured by RTCP
+    pub fn set_rtt(&mut self, rtt: Duration) {
+        self.stats.rtt_ms = Some(rtt.as_secs_f64() * 1000.0);
+    }
+
+    /// Records a sender report of the stream for the round trip time measured by the remote party
+    pub fn sender_report(&mut self, ntp_timestamp: u64, arrival: Instant) {
+        self.last_sr = Some((rtcp::ntp_middle(ntp_timestamp), arrival));
+    }
+
+    /// Report block about the stream for a receiver or sender report, none before any packet.
+    ///
+    /// The lost fraction covers the time since the previous report, see RFC 3550, appendix A.3.
+    pub fn report_block(&mut self, now: Instant) -> Option<ReportBlock> {
+        let highest = self.highest?;
+
+        let (expected, received) = (self.stats.expected, self.stats.received);
+        let (reported_expected, reported_received) = std::mem::replace(&mut self.reported, (expected, received));
+        let expected_interval = expected - reported_expected;
+        let lost_interval = expected_interval as i64 - (received - reported_received) as i64;
+        let fraction_lost = match expected_interval {
+            0 => 0,
+            _ if lost_interval <= 0 => 0,
+            _ => ((lost_interval << 8) / expected_interval as i64) as u8,
+        };
+
+        let (last_sr, delay_since_last_sr) = match self.last_sr {
+            Some((last_sr, arrival)) => {
+                let delay = now.duration_since(arrival).as_secs_f64();
+                (last_sr, (delay * 65536.0) as u32)
+            }
+            None => (0, 0),
+        };
+
+        Some(ReportBlock {
+            ssrc: self.ssrc,
+            fraction_lost,
+            cumulative_lost: (expected as i64 - received as i64).clamp(-0x80_0000, 0x7f_ffff) as i32,
+            // Extended numbers start a cycle in, see `extend`
+            highest_sequence: highest.saturating_sub(u16::MAX as u64 + 1) as u32,
+            jitter: (self.jitter * self.clock_rate as f64) as u32,
+            last_sr,
+            delay_since_last_sr,
+        })
+    }
+
+    /// Media time between the first and the last buffered packet
+    fn buffered(&self) -> Duration {
+        let (Some((_, first)), Some((_, last))) = (self.packets.first_key_value(), self.packets.last_key_value()) else {
+            return Duration::ZERO;
+        };
+        let ticks = last.timestamp.wrapping_sub(first.timestamp);
+        Duration::from_secs_f64(ticks as f64 / self.clock_rate as f64)
+    }
+
+    /// Sequence number extended by the count of wrap arounds, relative to the highest one seen
+    fn extend(&self, sequence: u16) -> u64 {
+        match self.highest {
+            // Starting a cycle in, so packets before the first do not wrap below zero
+            None => u16::MAX as u64 + 1 + sequence as u64,
+            Some(highest) => {
+                let delta = sequence.wrapping_sub(highest as u16) as i16;
+                highest.saturating_add_signed(delta as i64)
+            }
+        }
+    }
+}
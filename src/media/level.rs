@@ -0,0 +1,68 @@
+/// Loudness of a PCM frame relative to full scale
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Level {
+    /// Largest magnitude of a sample, from 0 to 1
+    pub peak: f32,
+
+    /// Root mean square of the samples, from 0 to 1
+    pub rms: f32,
+}
+
+/// Quietest level shown by a meter, in dBFS
+const METER_FLOOR: f32 = -60.0;
+
+impl Level {
+    pub fn measure(pcm: &[i16]) -> Self {
+        if pcm.is_empty() {
+            return Self::default();
+        }
+
+        let full_scale = -(i16::MIN as f32);
+        let peak = pcm.iter().map(|sample| sample.unsigned_abs()).max().unwrap_or(0) as f32 / full_scale;
+        let square_sum = pcm
+            .iter()
+            .map(|&sample| (sample as f32 / full_scale).powi(2))
+            .sum::<f32>();
+        let rms = (square_sum / pcm.len() as f32).sqrt();
+
+        Self { peak, rms }
+    }
+
+    /// Root mean square in dBFS, negative infinity for silence
+    pub fn dbfs(&self) -> f32 {
+        20.0 * self.rms.log10()
+    }
+
+    /// Bar of the given width filled according to the level between -60 dBFS and full scale
+    pub fn meter(&self, width: usize) -> String {
+        let fill = (1.0 - self.dbfs().max(METER_FLOOR) / METER_FLOOR).clamp(0.0, 1.0);
+        let filled = (fill * width as f32).round() as usize;
+        format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+    }
+}
+
+/// Volume in percent applied to PCM frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume(pub u16);
+
+impl Volume {
+    pub const FULL: Self = Self(100);
+
+    /// Scales the samples, clipping those getting out of range
+    pub fn apply(self, pcm: &mut [i16]) {
+        if self == Self::FULL {
+            return;
+        }
+
+        let factor = self.0 as f32 / 100.0;
+        for sample in pcm {
+            *sample = (*sample as f32 * factor).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
@@ -0,0 +1,37 @@
+//! Media of calls answered by this client
+//!
+//! A session sends and receives the RTP stream of an answered call. Codecs
+//! turn PCM frames into RTP payloads and back, SDP negotiation picks the
+//! codec both parties support, DTMF digits arrive as RTP events. Received
+//! packets pass a jitter buffer keeping the statistics of the stream, which
+//! RTCP reports to the remote party. Silence may be suppressed in favor of
+//! comfort noise, held calls get music or silence, announcements are
+//! synthesized from text and recordings transcribed. Local devices play and
+//! record the PCM frames if built with the `audio` feature, WebRTC slots get
+//! their media by ICE and DTLS-SRTP if built with the `webrtc` feature.
+
+pub mod codec;
+pub mod sdp;
+pub mod level;
+pub mod rtp;
+pub mod rtcp;
+pub mod jitter;
+pub mod vad;
+pub mod wav;
+pub mod hold;
+pub mod tts;
+pub mod transcribe;
+pub mod session;
+pub mod dtmf;
+
+#[cfg(feature = "audio")]
+pub mod audio;
+
+#[cfg(feature = "webrtc")]
+pub mod stun;
+
+#[cfg(feature = "webrtc")]
+pub mod srtp;
+
+#[cfg(feature = "webrtc")]
+pub mod webrtc;
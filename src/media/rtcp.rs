@@ -0,0 +1,297 @@
+use anyhow::{ensure, Context, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SENDER_REPORT: u8 = 200;
+const RECEIVER_REPORT: u8 = 201;
+const SOURCE_DESCRIPTION: u8 = 202;
+const BYE: u8 = 203;
+
+/// Item type of the canonical name in a source description
+const CNAME: u8 = 1;
+
+/// Seconds between the NTP epoch of 1900 and the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Reception quality of one source, see RFC 3550, section 6.4.1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+
+    /// Share of packets lost since the last report, in 1/256
+    pub fraction_lost: u8,
+
+    /// Packets lost in total, as 24 bit signed number
+    pub cumulative_lost: i32,
+
+    /// Highest sequence number received, extended by the count of wrap arounds
+    pub highest_sequence: u32,
+
+    /// Interarrival jitter in timestamp units
+    pub jitter: u32,
+
+    /// Middle 32 bits of the NTP timestamp of the last sender report received, zero if none
+    pub last_sr: u32,
+
+    /// Time since the last sender report was received, in 1/65536 seconds
+    pub delay_since_last_sr: u32,
+}
+
+/// An RTCP packet as part of a compound packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RtcpPacket {
+    SenderReport {
+        ssrc: u32,
+        ntp_timestamp: u64,
+        rtp_timestamp: u32,
+        packet_count: u32,
+        octet_count: u32,
+        reports: Vec<ReportBlock>,
+    },
+
+    ReceiverReport {
+        ssrc: u32,
+        reports: Vec<ReportBlock>,
+    },
+
+    /// Source description carrying only the canonical name
+    SourceDescription {
+        ssrc: u32,
+        cname: String,
+    },
+
+    Bye {
+        sources: Vec<u32>,
+        reason: Option<String>,
+    },
+}
+
+impl RtcpPacket {
+    /// Packets of a compound packet, skipping those of unknown types
+    pub fn parse_compound(mut data: &[u8]) -> Result<Vec<Self>> {
+        let mut packets = Vec::new();
+
+        while !data.is_empty() {
+            ensure!(data.len() >= 4, "RTCP header truncated");
+            ensure!(data[0] >> 6 == 2, "Unsupported RTCP version: {}", data[0] >> 6);
+
+            let count = (data[0] & 0x1f) as usize;
+            let len = (u16::from_be_bytes([data[2], data[3]]) as usize + 1) * 4;
+            ensure!(data.len() >= len, "RTCP packet truncated");
+
+            let mut body = &data[4..len];
+            if data[0] & 0x20 != 0 {
+                let padding = *body.last().context("RTCP padding without body")? as usize;
+                body = &body[..body.len().saturating_sub(padding)];
+            }
+
+            if let Some(packet) = Self::parse(data[1], count, body)? {
+                packets.push(packet);
+            }
+            data = &data[len..];
+        }
+
+        Ok(packets)
+    }
+
+    fn parse(packet_type: u8, count: usize, body: &[u8]) -> Result<Option<Self>> {
+        Ok(Some(match packet_type {
+            SENDER_REPORT => {
+                ensure!(body.len() >= 24 + count * 24, "RTCP sender report truncated");
+                Self::SenderReport {
+                    ssrc: word(body, 0),
+                    ntp_timestamp: (word(body, 4) as u64) << 32 | word(body, 8) as u64,
+                    rtp_timestamp: word(body, 12),
+                    packet_count: word(body, 16),
+                    octet_count: word(body, 20),
+                    reports: report_blocks(&body[24..], count),
+                }
+            }
+
+            RECEIVER_REPORT => {
+                ensure!(body.len() >= 4 + count * 24, "RTCP receiver report truncated");
+                Self::ReceiverReport {
+                    ssrc: word(body, 0),
+                    reports: report_blocks(&body[4..], count),
+                }
+            }
+
+            SOURCE_DESCRIPTION => {
+                // Only the first chunk is of interest, as sent by single source endpoints
+                ensure!(body.len() >= 4, "RTCP source description truncated");
+                let mut items = &body[4..];
+                let mut cname = None;
+                while let [kind, len, rest @ ..] = items {
+                    if *kind == 0 {
+                        break;
+                    }
+                    let len = *len as usize;
+                    ensure!(rest.len() >= len, "RTCP source description item truncated");
+                    if *kind == CNAME {
+                        cname = Some(String::from_utf8_lossy(&rest[..len]).into_owned());
+                    }
+                    items = &rest[len..];
+                }
+
+                let Some(cname) = cname else {
+                    return Ok(None);
+                };
+                Self::SourceDescription {
+                    ssrc: word(body, 0),
+                    cname,
+                }
+            }
+
+            BYE => {
+                ensure!(body.len() >= count * 4, "RTCP bye truncated");
+                let sources = (0..count).map(|index| word(body, index * 4)).collect();
+                let reason = match &body[count * 4..] {
+                    [len, rest @ ..] if rest.len() >= *len as usize => {
+                        Some(String::from_utf8_lossy(&rest[..*len as usize]).into_owned())
+                    }
+                    _ => None,
+                };
+                Self::Bye { sources, reason }
+            }
+
+            _ => return Ok(None),
+        }))
+    }
+
+    pub fn encode(&self, data: &mut BytesMut) {
+        let start = data.len();
+
+        let (packet_type, count) = match self {
+            Self::SenderReport { reports, .. } => (SENDER_REPORT, reports.len()),
+            Self::ReceiverReport { reports, .. } => (RECEIVER_REPORT, reports.len()),
+            Self::SourceDescription { .. } => (SOURCE_DESCRIPTION, 1),
+            Self::Bye { sources, .. } => (BYE, sources.len()),
+        };
+        data.put_u8(2 << 6 | count as u8 & 0x1f);
+        data.put_u8(packet_type);
+        data.put_u16(0);
+
+        match self {
+            Self::SenderReport {
+                ssrc,
+                ntp_timestamp,
+                rtp_timestamp,
+                packet_count,
+                octet_count,
+                reports,
+            } => {
+                data.put_u32(*ssrc);
+                data.put_u64(*ntp_timestamp);
+                data.put_u32(*rtp_timestamp);
+                data.put_u32(*packet_count);
+                data.put_u32(*octet_count);
+                reports.iter().for_each(|report| put_report_block(data, report));
+            }
+
+            Self::ReceiverReport { ssrc, reports } => {
+                data.put_u32(*ssrc);
+                reports.iter().for_each(|report| put_report_block(data, report));
+            }
+
+            Self::SourceDescription { ssrc, cname } => {
+                let cname = &cname.as_bytes()[..cname.len().min(255)];
+                data.put_u32(*ssrc);
+                data.put_u8(CNAME);
+                data.put_u8(cname.len() as u8);
+                data.put_slice(cname);
+
+                // The item list ends with at least one zero byte
+                data.put_u8(0);
+                pad(data);
+            }
+
+            Self::Bye { sources, reason } => {
+                sources.iter().for_each(|source| data.put_u32(*source));
+                if let Some(reason) = reason {
+                    let reason = &reason.as_bytes()[..reason.len().min(255)];
+                    data.put_u8(reason.len() as u8);
+                    data.put_slice(reason);
+                    pad(data);
+                }
+            }
+        }
+
+        let words = (data.len() - start) / 4 - 1;
+        data[start + 2..start + 4].copy_from_slice(&(words as u16).to_be_bytes());
+    }
+}
+
+/// Compound packet of the given packets, which should start with a report
+pub fn encode_compound(packets: &[RtcpPacket]) -> Bytes {
+    let mut data = BytesMut::new();
+    for packet in packets {
+        packet.encode(&mut data);
+    }
+    data.freeze()
+}
+
+/// NTP timestamp of the given time, in seconds since 1900 as 32.32 fixed point number
+pub fn ntp_timestamp(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (since_epoch.subsec_nanos() as u64) * (1 << 32) / 1_000_000_000;
+    seconds << 32 | fraction
+}
+
+/// Middle 32 bits of an NTP timestamp as used by report blocks
+pub fn ntp_middle(ntp_timestamp: u64) -> u32 {
+    (ntp_timestamp >> 16) as u32
+}
+
+/// Round trip time from a report block about the own stream received at the given time
+pub fn round_trip_time(block: &ReportBlock, arrival: SystemTime) -> Option<Duration> {
+    if block.last_sr == 0 {
+        return None;
+    }
+
+    let arrival = ntp_middle(ntp_timestamp(arrival));
+    let rtt = arrival
+        .checked_sub(block.last_sr)?
+        .checked_sub(block.delay_since_last_sr)?;
+    Some(Duration::from_secs_f64(rtt as f64 / 65536.0))
+}
+
+fn word(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn report_blocks(data: &[u8], count: usize) -> Vec<ReportBlock> {
+    data.chunks_exact(24)
+        .take(count)
+        .map(|block| {
+            let lost = word(block, 4);
+            ReportBlock {
+                ssrc: word(block, 0),
+                fraction_lost: (lost >> 24) as u8,
+
+                // Sign extension of the 24 bits
+                cumulative_lost: ((lost << 8) as i32) >> 8,
+                highest_sequence: word(block, 8),
+                jitter: word(block, 12),
+                last_sr: word(block, 16),
+                delay_since_last_sr: word(block, 20),
+            }
+        })
+        .collect()
+}
+
+fn put_report_block(data: &mut BytesMut, block: &ReportBlock) {
+    data.put_u32(block.ssrc);
+    data.put_u32((block.fraction_lost as u32) << 24 | block.cumulative_lost as u32 & 0x00ff_ffff);
+    data.put_u32(block.highest_sequence);
+    data.put_u32(block.jitter);
+    data.put_u32(block.last_sr);
+    data.put_u32(block.delay_since_last_sr);
+}
+
+/// Pads with zero bytes to the next 32 bit boundary
+fn pad(data: &mut BytesMut) {
+    while !data.len().is_multiple_of(4) {
+        data.put_u8(0);
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::{bail, ensure, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Size of the fixed header of RFC 3550, section 5.1
+const HEADER_LEN: usize = 12;
+
+/// An RTP packet, with CSRCs and header extensions dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpPacket {
+    pub payload_type: u8,
+    pub marker: bool,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub payload: Bytes,
+}
+
+impl RtpPacket {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= HEADER_LEN, "RTP packet too short: {} bytes", data.len());
+
+        let version = data[0] >> 6;
+        if version != 2 {
+            bail!("Unsupported RTP version: {version}");
+        }
+
+        let padding = data[0] & 0x20 != 0;
+        let extension = data[0] & 0x10 != 0;
+        let csrc_count = (data[0] & 0x0f) as usize;
+
+        let mut start = HEADER_LEN + csrc_count * 4;
+        if extension {
+            ensure!(data.len() >= start + 4, "RTP header extension truncated");
+            let words = u16::from_be_bytes([data[start + 2], data[start + 3]]) as usize;
+            start += 4 + words * 4;
+        }
+
+        let mut end = data.len();
+        if padding {
+            end = end.saturating_sub(data[end - 1] as usize);
+        }
+        ensure!(start <= end, "RTP packet truncated");
+
+        Ok(Self {
+            payload_type: data[1] & 0x7f,
+            marker: data[1] & 0x80 != 0,
+            sequence: u16::from_be_bytes([data[2], data[3]]),
+            timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            ssrc: u32::from_be_bytes([data[8], data[9], data[10], data[11]]),
+            payload: Bytes::copy_from_slice(&data[start..end]),
+        })
+    }
+
+    pub fn encode(&self) -> Bytes {
+        let mut data = BytesMut::with_capacity(HEADER_LEN + self.payload.len());
+        data.put_u8(2 << 6);
+        data.put_u8(self.payload_type & 0x7f | if self.marker { 0x80 } else { 0 });
+        data.put_u16(self.sequence);
+        data.put_u32(self.timestamp);
+        data.put_u32(self.ssrc);
+        data.put_slice(&self.payload);
+        data.freeze()
+    }
+}
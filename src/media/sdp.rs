@@ -0,0 +1,423 @@
+use super::codec::{Codecs, Format, CN};
+use anyhow::{Context, Result};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+/// A format of the remote party's audio stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFormat {
+    pub payload_type: u8,
+
+    /// Encoding name as given, like `PCMU` or `opus`
+    pub name: String,
+
+    pub clock_rate: u32,
+
+    /// Parameters of the `a=fmtp` attribute
+    pub fmtp: Option<String>,
+}
+
+/// The first audio stream of an SDP offer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioOffer {
+    /// Address to send media to, from the connection line of the stream or the session
+    pub address: Option<IpAddr>,
+
+    pub port: u16,
+
+    /// Transport protocol, like `RTP/AVP` or `UDP/TLS/RTP/SAVPF`
+    pub protocol: String,
+
+    /// Offered formats in the remote party's order of preference
+    pub formats: Vec<RemoteFormat>,
+
+    /// ICE and DTLS parameters of the stream or the session
+    pub transport: SecureTransport,
+}
+
+/// ICE and DTLS parameters of a WebRTC stream, see RFC 8839 and RFC 8842
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecureTransport {
+    pub ice_ufrag: Option<String>,
+    pub ice_pwd: Option<String>,
+
+    /// Values of the `a=candidate` attributes
+    pub candidates: Vec<String>,
+
+    /// Hash function and certificate fingerprint, like `sha-256 AB:CD:...`
+    pub fingerprint: Option<String>,
+
+    /// DTLS role offered by the remote party, `actpass`, `active` or `passive`
+    pub setup: Option<String>,
+
+    /// Identification of the stream, which the answer must repeat, see RFC 8843
+    pub mid: Option<String>,
+}
+
+impl SecureTransport {
+    /// Takes the value of an attribute if it is one of the transport, returns whether it was
+    fn apply(&mut self, attribute: &str) -> bool {
+        let (name, value) = attribute.split_once(':').unwrap_or((attribute, ""));
+        let value = Some(value.trim().to_string());
+        match name {
+            "ice-ufrag" => self.ice_ufrag = value,
+            "ice-pwd" => self.ice_pwd = value,
+            "fingerprint" => self.fingerprint = value,
+            "setup" => self.setup = value,
+            "candidate" => self.candidates.extend(value),
+            "mid" => self.mid = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Addresses of the offered UDP candidates of the RTP component, like `192.0.2.1:50000`
+    pub fn candidate_addresses(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.candidates.iter().filter_map(|candidate| {
+            let fields = candidate.split_whitespace().collect::<Vec<_>>();
+            match fields[..] {
+                [_, "1", transport, _, address, port, ..] if transport.eq_ignore_ascii_case("udp") => {
+                    Some(SocketAddr::new(address.parse().ok()?, port.parse().ok()?))
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Priority of the host candidate, see RFC 8445, section 5.1.2.1
+const HOST_PRIORITY: u32 = (126 << 24) | (65535 << 8) | 255;
+
+/// Encoding name of DTMF digits sent as RTP events, see RFC 4733
+const TELEPHONE_EVENT: &str = "telephone-event";
+
+/// Formats with payload types assigned by RFC 3551, which need no rtpmap
+const STATIC_FORMATS: &[(u8, &str, u32)] = &[
+    (0, "PCMU", 8000),
+    (3, "GSM", 8000),
+    (8, "PCMA", 8000),
+    (9, "G722", 8000),
+    (13, "CN", 8000),
+];
+
+impl AudioOffer {
+    pub fn parse(sdp: &str) -> Result<Self> {
+        let mut session_address = None;
+        let mut session_transport = SecureTransport::default();
+        let mut offer: Option<Self> = None;
+
+        // Lines of streams before the audio one are not of interest
+        let mut other_stream = false;
+
+        for line in sdp.lines().map(str::trim) {
+            let Some((kind, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            match (kind, offer.as_mut()) {
+                ("m", Some(_)) => break,
+
+                ("m", None) => {
+                    let mut fields = value.split_whitespace();
+                    if fields.next() != Some("audio") {
+                        other_stream = true;
+                        continue;
+                    }
+
+                    let port = fields
+                        .next()
+                        .and_then(|port| port.split('/').next()?.parse().ok())
+                        .context("Invalid port of audio stream")?;
+                    let protocol = fields.next().context("Missing protocol of audio stream")?;
+                    let formats = fields
+                        .map(|payload_type| {
+                            let payload_type = payload_type
+                                .parse()
+                                .with_context(|| format!("Invalid payload type: {payload_type}"))?;
+                            let (name, clock_rate) = STATIC_FORMATS
+                                .iter()
+                                .find(|(known, _, _)| *known == payload_type)
+                                .map_or(("", 0), |&(_, name, clock_rate)| (name, clock_rate));
+                            Ok(RemoteFormat {
+                                payload_type,
+                                name: name.to_string(),
+                                clock_rate,
+                                fmtp: None,
+                            })
+                        })
+                        .collect::<Result<_>>()?;
+
+                    offer = Some(Self {
+                        address: session_address,
+                        port,
+                        protocol: protocol.to_string(),
+                        formats,
+                        transport: session_transport.clone(),
+                    });
+                }
+
+                ("c", Some(offer)) => offer.address = Some(connection_address(value)?),
+
+                ("c", None) if !other_stream => session_address = Some(connection_address(value)?),
+
+                ("a", None) if !other_stream => {
+                    session_transport.apply(value);
+                }
+
+                ("a", Some(offer)) => {
+                    if offer.transport.apply(value) {
+                        continue;
+                    }
+
+                    if let Some(rtpmap) = value.strip_prefix("rtpmap:") {
+                        let (payload_type, encoding) = attribute(rtpmap)?;
+                        let mut encoding = encoding.split('/');
+                        if let Some(format) = offer.format_mut(payload_type) {
+                            format.name = encoding.next().unwrap_or_default().to_string();
+                            format.clock_rate = encoding
+                                .next()
+                                .and_then(|rate| rate.parse().ok())
+                                .with_context(|| format!("Invalid clock rate of payload type {payload_type}"))?;
+                        }
+                    } else if let Some(fmtp) = value.strip_prefix("fmtp:") {
+                        let (payload_type, params) = attribute(fmtp)?;
+                        if let Some(format) = offer.format_mut(payload_type) {
+                            format.fmtp = Some(params.to_string());
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        offer.context("No audio stream offered")
+    }
+
+    /// Whether the stream is secured by DTLS-SRTP with ICE as WebRTC slots require.
+    ///
+    /// Such offers cannot be answered with plain RTP, they need the WebRTC
+    /// transport of the `webrtc` feature.
+    pub fn is_webrtc(&self) -> bool {
+        self.protocol.starts_with("UDP/TLS/RTP/SAVP") && self.transport.fingerprint.is_some()
+    }
+
+    fn format_mut(&mut self, payload_type: u8) -> Option<&mut RemoteFormat> {
+        self.formats
+            .iter_mut()
+            .find(|format| format.payload_type == payload_type)
+    }
+}
+
+/// Address of a connection line like `IN IP4 192.0.2.1`
+fn connection_address(value: &str) -> Result<IpAddr> {
+    let address = value
+        .split_whitespace()
+        .nth(2)
+        .with_context(|| format!("Invalid connection line: {value}"))?;
+
+    // Multicast addresses may carry a TTL and count
+    let address = address.split('/').next().unwrap_or(address);
+    address
+        .parse()
+        .with_context(|| format!("Invalid connection address: {address}"))
+}
+
+/// Payload type and value of an attribute like `rtpmap:0 PCMU/8000`
+fn attribute(value: &str) -> Result<(u8, &str)> {
+    let (payload_type, value) = value.split_once(' ').unwrap_or((value, ""));
+    let payload_type = payload_type
+        .parse()
+        .with_context(|| format!("Invalid payload type: {payload_type}"))?;
+    Ok((payload_type, value.trim()))
+}
+
+/// The format both parties agreed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    /// Payload type of the format as offered by the remote party
+    pub payload_type: u8,
+
+    pub format: Format,
+
+    /// Payload type of comfort noise, if offered at the clock rate of the format
+    pub comfort_noise: Option<u8>,
+
+    /// Payload type of DTMF digits of RFC 4733, if offered at the clock rate of the format
+    pub telephone_event: Option<u8>,
+}
+
+/// Picks the most preferred local format the remote party offered
+pub fn negotiate(offer: &AudioOffer, codecs: &Codecs) -> Option<Negotiated> {
+    codecs.formats().find_map(|format| {
+        let remote = offer.formats.iter().find(|remote| {
+            remote.name.eq_ignore_ascii_case(format.name) && remote.clock_rate == format.clock_rate
+        })?;
+
+        let companion = |name: &str| {
+            offer
+                .formats
+                .iter()
+                .find(|remote| remote.name.eq_ignore_ascii_case(name) && remote.clock_rate == format.clock_rate)
+                .map(|remote| remote.payload_type)
+        };
+
+        Some(Negotiated {
+            payload_type: remote.payload_type,
+            format: format.clone(),
+            comfort_noise: companion(CN.name),
+            telephone_event: companion(TELEPHONE_EVENT),
+        })
+    })
+}
+
+/// SDP answering an offer with the negotiated format, receiving media on the given address
+pub fn answer(negotiated: &Negotiated, address: IpAddr, port: u16, protocol: &str) -> String {
+    LocalDescription::new(negotiated.clone(), address, port, protocol).render(Direction::SendRecv)
+}
+
+/// Direction of the media of a stream, see RFC 4566, section 6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    SendRecv,
+    SendOnly,
+    RecvOnly,
+    Inactive,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::SendRecv => "sendrecv",
+            Self::SendOnly => "sendonly",
+            Self::RecvOnly => "recvonly",
+            Self::Inactive => "inactive",
+        })
+    }
+}
+
+/// ICE and DTLS parameters of the local side of a WebRTC stream.
+///
+/// The local side is an ICE lite agent with a single host candidate and
+/// takes the passive DTLS role, see RFC 8445, section 2.5.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalTransport {
+    pub ice_ufrag: String,
+    pub ice_pwd: String,
+
+    /// Hash function and certificate fingerprint, like `sha-256 AB:CD:...`
+    pub fingerprint: String,
+
+    /// Identification of the stream as offered
+    pub mid: Option<String>,
+}
+
+/// The local side of a negotiated stream, offered again with a new version when it changes
+#[derive(Debug, Clone)]
+pub struct LocalDescription {
+    negotiated: Negotiated,
+    address: IpAddr,
+    port: u16,
+    protocol: String,
+    transport: Option<LocalTransport>,
+    session: u32,
+    version: u32,
+}
+
+impl LocalDescription {
+    pub fn new(negotiated: Negotiated, address: IpAddr, port: u16, protocol: &str) -> Self {
+        let session = rand::random::<u32>();
+        Self {
+            negotiated,
+            address,
+            port,
+            protocol: protocol.to_string(),
+            transport: None,
+            session,
+            version: session,
+        }
+    }
+
+    /// Secures the stream by DTLS-SRTP with ICE as offered on WebRTC slots
+    pub fn with_transport(mut self, transport: LocalTransport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// The description with a new version, e.g. for a re-INVITE holding the call
+    pub fn update(&mut self, direction: Direction) -> String {
+        self.version = self.version.wrapping_add(1);
+        self.render(direction)
+    }
+
+    pub fn render(&self, direction: Direction) -> String {
+        let Self {
+            negotiated,
+            address,
+            port,
+            protocol,
+            transport,
+            session,
+            version,
+        } = self;
+        let family = match address {
+            IpAddr::V4(_) => "IP4",
+            IpAddr::V6(_) => "IP6",
+        };
+        let Negotiated {
+            payload_type,
+            format,
+            comfort_noise,
+            telephone_event,
+        } = negotiated;
+        let payload_types = [Some(payload_type), comfort_noise.as_ref(), telephone_event.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut lines = vec![
+            "v=0".to_string(),
+            format!("o=- {session} {version} IN {family} {address}"),
+            "s=-".to_string(),
+            format!("c=IN {family} {address}"),
+            "t=0 0".to_string(),
+        ];
+        if let Some(transport) = transport {
+            lines.push("a=ice-lite".to_string());
+            if let Some(mid) = &transport.mid {
+                lines.push(format!("a=group:BUNDLE {mid}"));
+            }
+        }
+        lines.push(format!("m=audio {port} {protocol} {payload_types}"));
+        lines.push(format!("a=rtpmap:{payload_type} {format}"));
+        if let Some(fmtp) = format.fmtp {
+            lines.push(format!("a=fmtp:{payload_type} {fmtp}"));
+        }
+        if let Some(comfort_noise) = comfort_noise {
+            lines.push(format!("a=rtpmap:{comfort_noise} CN/{rate}", rate = format.clock_rate));
+        }
+        if let Some(telephone_event) = telephone_event {
+            lines.push(format!("a=rtpmap:{telephone_event} {TELEPHONE_EVENT}/{rate}", rate = format.clock_rate));
+            lines.push(format!("a=fmtp:{telephone_event} 0-15"));
+        }
+        lines.push("a=ptime:20".to_string());
+        lines.push(format!("a={direction}"));
+
+        if let Some(transport) = transport {
+            if let Some(mid) = &transport.mid {
+                lines.push(format!("a=mid:{mid}"));
+            }
+            lines.push("a=rtcp-mux".to_string());
+            lines.push(format!("a=ice-ufrag:{ufrag}", ufrag = transport.ice_ufrag));
+            lines.push(format!("a=ice-pwd:{pwd}", pwd = transport.ice_pwd));
+            lines.push(format!("a=fingerprint:{fingerprint}", fingerprint = transport.fingerprint));
+            lines.push("a=setup:passive".to_string());
+            lines.push(format!("a=candidate:1 1 udp {HOST_PRIORITY} {address} {port} typ host"));
+            lines.push("a=end-of-candidates".to_string());
+        }
+
+        lines.iter().map(|line| format!("{line}\r\n")).collect()
+    }
+}
@@ -0,0 +1,548 @@
+use super::codec::{Codec, Codecs};
+use super::dtmf::DtmfDetector;
+use super::hold::HoldMusic;
+use super::jitter::{JitterBuffer, Playout, RtpStats};
+use super::rtcp::{self, RtcpPacket};
+use super::rtp::RtpPacket;
+use super::sdp::{self, AudioOffer, Direction, LocalDescription, Negotiated};
+use super::vad::{Decision, SilenceSuppression};
+#[cfg(feature = "webrtc")]
+use super::webrtc::WebRtcTransport;
+use crate::config::MediaConfig;
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, trace, warn};
+
+/// Duration of the audio in each packet, as announced by `a=ptime`
+pub const PTIME: Duration = Duration::from_millis(20);
+
+/// Largest datagram expected on the media port
+const MAX_DATAGRAM: usize = 1500;
+
+/// Bounds of the delay received audio is buffered for
+const MIN_DELAY: Duration = Duration::from_millis(40);
+const MAX_DELAY: Duration = Duration::from_millis(300);
+
+/// Interval of RTCP reports, the minimum of RFC 3550, section 6.2
+const RTCP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Attempts to find a free pair of ports for RTP and RTCP
+const PORT_PAIR_ATTEMPTS: usize = 16;
+
+enum Command {
+    Play { pcm: Vec<i16>, done: oneshot::Sender<()> },
+    Listen(mpsc::UnboundedSender<Vec<i16>>),
+    Digits(mpsc::UnboundedSender<char>),
+    Clear,
+    Hold(Option<HoldMusic>),
+    Stop,
+}
+
+/// Media of an answered call, sending and receiving RTP on a local port.
+///
+/// Audio queued by [`play`](Self::play) is sent in order, silence while
+/// nothing is queued, so the remote party never sees the stream stall.
+/// Silence may be suppressed in favor of comfort noise if the remote party
+/// offered it. Received audio passes a jitter buffer, which keeps the statistics of the
+/// stream reported once the session stops. RTCP runs on the port above the
+/// one of RTP, reporting on both streams and measuring the round trip time.
+/// Offers of WebRTC slots are answered by ICE and DTLS-SRTP on a single port
+/// instead, if built with the `webrtc` feature.
+pub struct MediaSession {
+    description: LocalDescription,
+    sample_rate: u32,
+    commands: mpsc::UnboundedSender<Command>,
+    task: JoinHandle<Result<RtpStats>>,
+}
+
+impl MediaSession {
+    /// Negotiates the audio stream of an offer and starts the media, returning the SDP answer
+    pub async fn answer(offer: &str, codecs: &Codecs, config: &MediaConfig) -> Result<(Self, String)> {
+        let offer = AudioOffer::parse(offer)?;
+
+        let negotiated = sdp::negotiate(&offer, codecs).context("No common codec offered")?;
+        let codec = codecs.create(&negotiated.format)?;
+
+        let (transport, local, description) = if offer.is_webrtc() {
+            webrtc(&offer, &negotiated).await?
+        } else {
+            let address = offer.address.context("No connection address offered")?;
+            let remote = SocketAddr::new(address, offer.port);
+
+            let local = local_address(remote).await?;
+            let (rtp, rtcp) = bind_pair(local).await?;
+            let port = rtp.local_addr()?.port();
+            debug!("Sending {format} media from port {port} to {remote}", format = negotiated.format);
+
+            let transport = Transport::Rtp {
+                rtp,
+                rtcp,
+                remote,
+                remote_rtcp: SocketAddr::new(address, offer.port.wrapping_add(1)),
+            };
+            let description = LocalDescription::new(negotiated.clone(), local, port, &offer.protocol);
+            (transport, local, description)
+        };
+        let answer = description.render(Direction::SendRecv);
+
+        let sample_rate = negotiated.format.sample_rate;
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let peer = Peer {
+            transport,
+            cname: format!("ucware-cli@{local}"),
+        };
+        let suppression = negotiated.comfort_noise.and_then(|comfort_noise| {
+            SilenceSuppression::new(&config.silence_suppression, PTIME).map(|suppression| (suppression, comfort_noise))
+        });
+        let task = tokio::spawn(run(peer, codec, negotiated, suppression, commands_rx));
+
+        Ok((
+            Self {
+                description,
+                sample_rate,
+                commands,
+                task,
+            },
+            answer,
+        ))
+    }
+
+    /// Sample rate of the PCM frames played
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Plays mono samples at the session's sample rate, returning once all were sent
+    pub async fn play(&self, pcm: Vec<i16>) -> Result<()> {
+        let (done, played) = oneshot::channel();
+        self.commands
+            .send(Command::Play { pcm, done })
+            .ok()
+            .context("Media stopped")?;
+        played.await.ok().context("Media stopped")
+    }
+
+    /// Plays the hold music instead of the queued audio, returning the offer holding the call.
+    ///
+    /// Queued audio goes on once the call is resumed.
+    pub fn hold(&mut self, music: HoldMusic) -> String {
+        let _ = self.commands.send(Command::Hold(Some(music)));
+        self.description.update(Direction::SendOnly)
+    }
+
+    /// Goes on with the queued audio, returning the offer resuming the call
+    pub fn resume(&mut self) -> String {
+        let _ = self.commands.send(Command::Hold(None));
+        self.description.update(Direction::SendRecv)
+    }
+
+    /// Frames of received audio at the session's sample rate, one per packet time.
+    ///
+    /// Only frames played out after listening started are passed on.
+    pub fn listen(&self) -> mpsc::UnboundedReceiver<Vec<i16>> {
+        let (frames, listener) = mpsc::unbounded_channel();
+        let _ = self.commands.send(Command::Listen(frames));
+        listener
+    }
+
+    /// DTMF digits pressed by the remote party from now on, none unless it offered to send them as RTP events
+    pub fn digits(&self) -> mpsc::UnboundedReceiver<char> {
+        let (digits, receiver) = mpsc::unbounded_channel();
+        let _ = self.commands.send(Command::Digits(digits));
+        receiver
+    }
+
+    /// Drops the audio still queued, e.g. as the caller pressed a digit during a prompt
+    pub fn clear(&self) {
+        let _ = self.commands.send(Command::Clear);
+    }
+
+    /// Stops sending and receiving media, returning the quality of the received stream
+    pub async fn stop(self) -> Result<RtpStats> {
+        let _ = self.commands.send(Command::Stop);
+        self.task.await?
+    }
+}
+
+/// Binds the transport of a WebRTC offer, reaching the remote party by its offered candidates
+#[cfg(feature = "webrtc")]
+async fn webrtc(offer: &AudioOffer, negotiated: &Negotiated) -> Result<(Transport, IpAddr, LocalDescription)> {
+    let candidate = offer
+        .transport
+        .candidate_addresses()
+        .next()
+        .context("No UDP candidate offered")?;
+
+    let local = local_address(candidate).await?;
+    let transport = WebRtcTransport::bind(local, &offer.transport).await?;
+    let port = transport.port()?;
+    debug!("Awaiting ICE and DTLS for {format} media on port {port}", format = negotiated.format);
+
+    let description = LocalDescription::new(negotiated.clone(), local, port, &offer.protocol)
+        .with_transport(transport.local().clone());
+    Ok((Transport::WebRtc(Box::new(transport)), local, description))
+}
+
+#[cfg(not(feature = "webrtc"))]
+async fn webrtc(_: &AudioOffer, _: &Negotiated) -> Result<(Transport, IpAddr, LocalDescription)> {
+    bail!("Offer requires a WebRTC transport, which needs the `webrtc` feature");
+}
+
+/// Address of the local interface the remote party is reached by
+async fn local_address(remote: SocketAddr) -> Result<IpAddr> {
+    let unspecified = match remote {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+
+    // Connecting a datagram socket sends nothing but picks the route
+    let probe = UdpSocket::bind(SocketAddr::new(unspecified, 0)).await?;
+    probe.connect(remote).await?;
+    Ok(probe.local_addr()?.ip())
+}
+
+/// Binds an even port for RTP and the next one for RTCP, see RFC 3550, section 11
+async fn bind_pair(local: IpAddr) -> Result<(UdpSocket, UdpSocket)> {
+    for _ in 0..PORT_PAIR_ATTEMPTS {
+        let rtp = UdpSocket::bind(SocketAddr::new(local, 0)).await?;
+        let port = rtp.local_addr()?.port();
+        if port % 2 != 0 || port == u16::MAX - 1 {
+            continue;
+        }
+
+        if let Ok(rtcp) = UdpSocket::bind(SocketAddr::new(local, port + 1)).await {
+            return Ok((rtp, rtcp));
+        }
+    }
+
+    bail!("No free pair of media ports on {local}");
+}
+
+/// Sockets of the media and where they send to
+enum Transport {
+    /// Plain RTP with RTCP on the port above
+    Rtp {
+        rtp: UdpSocket,
+        rtcp: UdpSocket,
+        remote: SocketAddr,
+        remote_rtcp: SocketAddr,
+    },
+
+    /// SRTP with multiplexed RTCP on a port ICE and DTLS share
+    #[cfg(feature = "webrtc")]
+    WebRtc(Box<WebRtcTransport>),
+}
+
+/// A packet received on the transport
+enum Received {
+    Rtp(Vec<u8>, SocketAddr),
+    Rtcp(Vec<u8>, SocketAddr),
+}
+
+struct Peer {
+    transport: Transport,
+
+    /// Canonical name of this source in source descriptions
+    cname: String,
+}
+
+impl Peer {
+    /// Sends an RTP packet, returning whether it was sent as WebRTC sends nothing before DTLS completed
+    async fn send_rtp(&mut self, packet: &[u8]) -> Result<bool> {
+        match &mut self.transport {
+            Transport::Rtp { rtp, remote, .. } => {
+                rtp.send_to(packet, *remote)
+                    .await
+                    .with_context(|| format!("Failed to send RTP to {remote}"))?;
+                Ok(true)
+            }
+
+            #[cfg(feature = "webrtc")]
+            Transport::WebRtc(transport) => transport.send_rtp(packet).await,
+        }
+    }
+
+    async fn send_rtcp(&mut self, packet: &[u8]) -> Result<bool> {
+        match &mut self.transport {
+            Transport::Rtp { rtcp, remote_rtcp, .. } => {
+                rtcp.send_to(packet, *remote_rtcp)
+                    .await
+                    .with_context(|| format!("Failed to send RTCP to {remote_rtcp}"))?;
+                Ok(true)
+            }
+
+            #[cfg(feature = "webrtc")]
+            Transport::WebRtc(transport) => transport.send_rtcp(packet).await,
+        }
+    }
+
+    /// Receives the next packet of either kind, cancel safe to be used in `select!`
+    async fn recv(&mut self) -> Result<Received> {
+        match &mut self.transport {
+            Transport::Rtp { rtp, rtcp, .. } => {
+                let mut datagram = [0; MAX_DATAGRAM];
+                let mut control = [0; MAX_DATAGRAM];
+                tokio::select! {
+                    received = rtp.recv_from(&mut datagram) => {
+                        let (len, from) = received?;
+                        Ok(Received::Rtp(datagram[..len].to_vec(), from))
+                    }
+                    received = rtcp.recv_from(&mut control) => {
+                        let (len, from) = received?;
+                        Ok(Received::Rtcp(control[..len].to_vec(), from))
+                    }
+                }
+            }
+
+            #[cfg(feature = "webrtc")]
+            Transport::WebRtc(transport) => {
+                let (packet, from) = transport.recv().await?;
+
+                // RTCP is told apart from RTP by its packet type, see RFC 5761, section 4
+                Ok(match packet.get(1) {
+                    Some(192..=223) => Received::Rtcp(packet, from),
+                    _ => Received::Rtp(packet, from),
+                })
+            }
+        }
+    }
+}
+
+/// Audio waiting to be sent
+struct Clip {
+    pcm: Vec<i16>,
+    position: usize,
+    done: oneshot::Sender<()>,
+}
+
+async fn run(
+    mut peer: Peer,
+    mut codec: Box<dyn Codec>,
+    negotiated: Negotiated,
+    mut suppression: Option<(SilenceSuppression, u8)>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) -> Result<RtpStats> {
+    let format = &negotiated.format;
+    let samples = (format.sample_rate as u128 * PTIME.as_millis() / 1000) as usize;
+    let ticks = (format.clock_rate as u128 * PTIME.as_millis() / 1000) as u32;
+
+    let mut packet = RtpPacket {
+        payload_type: negotiated.payload_type,
+        marker: true,
+        sequence: rand::random(),
+        timestamp: rand::random(),
+        ssrc: rand::random(),
+        payload: Bytes::new(),
+    };
+
+    let mut queue = VecDeque::<Clip>::new();
+    let mut frame = vec![0; samples];
+    let mut payload = Vec::new();
+    let mut sent = (0u32, 0u32);
+
+    let mut jitter = JitterBuffer::new(format.clock_rate, MIN_DELAY, MAX_DELAY);
+    let mut listener = None::<mpsc::UnboundedSender<Vec<i16>>>;
+    let mut held = None::<HoldMusic>;
+    let mut dtmf = DtmfDetector::default();
+    let mut digits = None::<mpsc::UnboundedSender<char>>;
+
+    let mut interval = tokio::time::interval(PTIME);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut reports = tokio::time::interval_at(tokio::time::Instant::now() + RTCP_INTERVAL, RTCP_INTERVAL);
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Play { pcm, done }) => queue.push_back(Clip { pcm, position: 0, done }),
+                Some(Command::Listen(frames)) => listener = Some(frames),
+                Some(Command::Hold(music)) => held = music,
+                Some(Command::Digits(sender)) => digits = Some(sender),
+                Some(Command::Clear) => queue.clear(),
+                Some(Command::Stop) | None => {
+                    let mut packets = report(&peer, &packet, sent, &mut jitter);
+                    packets.push(RtcpPacket::Bye { sources: vec![packet.ssrc], reason: None });
+                    send_rtcp(&mut peer, &packets).await;
+                    return Ok(jitter.stats());
+                }
+            },
+
+            _ = interval.tick() => {
+                match &mut held {
+                    Some(music) => music.fill(&mut frame),
+                    None => fill(&mut queue, &mut frame),
+                }
+
+                let decision = match &mut suppression {
+                    Some((suppression, _)) => suppression.process(&frame),
+                    None => Decision::Voice { talkspurt: false },
+                };
+
+                match decision {
+                    Decision::Voice { talkspurt } => {
+                        payload.clear();
+                        codec.encode(&frame, &mut payload)?;
+                        packet.payload_type = negotiated.payload_type;
+                        packet.marker |= talkspurt;
+                        packet.payload = Bytes::copy_from_slice(&payload);
+                    }
+
+                    Decision::ComfortNoise(noise) => {
+                        packet.payload_type = suppression.as_ref().map_or(negotiated.payload_type, |(_, cn)| *cn);
+                        packet.payload = Bytes::copy_from_slice(&[noise]);
+                    }
+
+                    Decision::Skip => {}
+                }
+
+                if decision != Decision::Skip {
+                    match peer.send_rtp(&packet.encode()).await {
+                        Ok(true) => sent = (sent.0.wrapping_add(1), sent.1.wrapping_add(packet.payload.len() as u32)),
+                        Ok(false) => {}
+                        Err(err) => warn!("{err:#}"),
+                    }
+
+                    packet.marker = false;
+                    packet.sequence = packet.sequence.wrapping_add(1);
+                }
+
+                // Skipped frames still take their time
+                packet.timestamp = packet.timestamp.wrapping_add(ticks);
+
+                if let Some(playout) = jitter.pop() {
+                    let pcm = match playout {
+                        Playout::Packet(received) => {
+                            let mut pcm = Vec::with_capacity(samples);
+                            if let Err(err) = codec.decode(&received.payload, &mut pcm) {
+                                debug!("Failed to decode media: {err:#}");
+                            }
+                            pcm
+                        }
+
+                        // Gaps are filled with silence
+                        Playout::Lost => vec![0; samples],
+                    };
+
+                    if let Some(frames) = &listener
+                        && frames.send(pcm).is_err()
+                    {
+                        listener = None;
+                    }
+                }
+            }
+
+            _ = reports.tick() => {
+                let packets = report(&peer, &packet, sent, &mut jitter);
+                send_rtcp(&mut peer, &packets).await;
+            }
+
+            received = peer.recv() => match received? {
+                Received::Rtcp(control, from) => match RtcpPacket::parse_compound(&control) {
+                    Ok(packets) => receive_rtcp(packets, packet.ssrc, &mut jitter),
+                    Err(err) => trace!("Ignoring {len} bytes of RTCP from {from}: {err:#}", len = control.len()),
+                },
+
+                Received::Rtp(datagram, from) => match RtpPacket::parse(&datagram) {
+                    Ok(received) if received.payload_type == negotiated.payload_type => {
+                        jitter.push(received, Instant::now());
+                    }
+                    Ok(received) if Some(received.payload_type) == negotiated.telephone_event => {
+                        if let Some(digit) = dtmf.push(&received)
+                            && let Some(sender) = &digits
+                        {
+                            debug!("Received DTMF digit {digit}");
+                            let _ = sender.send(digit);
+                        }
+                    }
+                    Ok(received) => trace!("Ignoring RTP of payload type {pt}", pt = received.payload_type),
+                    Err(err) => trace!("Ignoring {len} bytes of media from {from}: {err:#}", len = datagram.len()),
+                },
+            }
+        }
+    }
+}
+
+/// Fills the frame from the queued clips, silence after they ran out
+fn fill(queue: &mut VecDeque<Clip>, frame: &mut [i16]) {
+    let mut filled = 0;
+    while filled < frame.len() {
+        let Some(clip) = queue.front_mut() else {
+            break;
+        };
+
+        let len = (clip.pcm.len() - clip.position).min(frame.len() - filled);
+        frame[filled..filled + len].copy_from_slice(&clip.pcm[clip.position..clip.position + len]);
+        clip.position += len;
+        filled += len;
+
+        if clip.position == clip.pcm.len()
+            && let Some(clip) = queue.pop_front()
+        {
+            let _ = clip.done.send(());
+        }
+    }
+
+    frame[filled..].fill(0);
+}
+
+/// Sender report on the stream sent and the one received, with the source description
+fn report(
+    peer: &Peer,
+    packet: &RtpPacket,
+    (packets, octets): (u32, u32),
+    jitter: &mut JitterBuffer,
+) -> Vec<RtcpPacket> {
+    vec![
+        RtcpPacket::SenderReport {
+            ssrc: packet.ssrc,
+            ntp_timestamp: rtcp::ntp_timestamp(SystemTime::now()),
+            rtp_timestamp: packet.timestamp,
+            packet_count: packets,
+            octet_count: octets,
+            reports: jitter.report_block(Instant::now()).into_iter().collect(),
+        },
+        RtcpPacket::SourceDescription {
+            ssrc: packet.ssrc,
+            cname: peer.cname.clone(),
+        },
+    ]
+}
+
+async fn send_rtcp(peer: &mut Peer, packets: &[RtcpPacket]) {
+    let data = rtcp::encode_compound(packets);
+    if let Err(err) = peer.send_rtcp(&data).await {
+        warn!("{err:#}");
+    }
+}
+
+/// Takes the sender reports of the remote party and the round trip time from its reports on the own stream
+fn receive_rtcp(packets: Vec<RtcpPacket>, ssrc: u32, jitter: &mut JitterBuffer) {
+    let arrival = (Instant::now(), SystemTime::now());
+    for packet in packets {
+        let reports = match packet {
+            RtcpPacket::SenderReport { ntp_timestamp, reports, .. } => {
+                jitter.sender_report(ntp_timestamp, arrival.0);
+                reports
+            }
+            RtcpPacket::ReceiverReport { reports, .. } => reports,
+            RtcpPacket::Bye { reason, .. } => {
+                debug!("Remote party left the media: {reason}", reason = reason.as_deref().unwrap_or("no reason"));
+                continue;
+            }
+            RtcpPacket::SourceDescription { .. } => continue,
+        };
+
+        if let Some(rtt) = reports
+            .iter()
+            .filter(|block| block.ssrc == ssrc)
+            .find_map(|block| rtcp::round_trip_time(block, arrival.1))
+        {
+            jitter.set_rtt(rtt);
+        }
+    }
+}
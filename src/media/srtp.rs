@@ -0,0 +1,253 @@
+use anyhow::{ensure, Context, Result};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use openssl::symm::{Cipher, Crypter, Mode};
+
+/// Protection profile negotiated by DTLS, see RFC 5764, section 4.1.2
+pub const PROFILE: &str = "SRTP_AES128_CM_SHA1_80";
+
+/// Label of the keying material exported by DTLS, see RFC 5764, section 4.2
+pub const EXPORTER_LABEL: &str = "EXTRACTOR-dtls_srtp";
+
+const KEY_LEN: usize = 16;
+const SALT_LEN: usize = 14;
+const AUTH_KEY_LEN: usize = 20;
+
+/// Length of the truncated HMAC-SHA1 of the profile
+const TAG_LEN: usize = 10;
+
+/// Length of the keying material exported for the profile, the keys and salts of both sides
+pub const KEYING_MATERIAL_LEN: usize = 2 * (KEY_LEN + SALT_LEN);
+
+/// Size of the fixed RTP header
+const RTP_HEADER_LEN: usize = 12;
+
+/// Size of the RTCP header and sender SSRC left unencrypted, see RFC 3711, section 3.4
+const RTCP_HEADER_LEN: usize = 8;
+
+/// Flag of encrypted SRTCP packets in front of their index
+const ENCRYPTED: u32 = 0x8000_0000;
+
+/// Session keys of one kind of packets, see RFC 3711, section 4.3
+struct SessionKeys {
+    cipher: [u8; KEY_LEN],
+    auth: PKey<Private>,
+    salt: [u8; SALT_LEN],
+}
+
+impl SessionKeys {
+    /// Derives the keys of RTP with the first label or RTCP with the fourth one
+    fn derive(master_key: &[u8], master_salt: &[u8], first_label: u8) -> Result<Self> {
+        let mut cipher = [0; KEY_LEN];
+        let mut auth = [0; AUTH_KEY_LEN];
+        let mut salt = [0; SALT_LEN];
+        derive(master_key, master_salt, first_label, &mut cipher)?;
+        derive(master_key, master_salt, first_label + 1, &mut auth)?;
+        derive(master_key, master_salt, first_label + 2, &mut salt)?;
+
+        Ok(Self {
+            cipher,
+            auth: PKey::hmac(&auth)?,
+            salt,
+        })
+    }
+
+    /// Encrypts or decrypts in place by AES in counter mode, see RFC 3711, section 4.1.1
+    fn apply(&self, ssrc: u32, index: u64, data: &mut [u8]) -> Result<()> {
+        let mut iv = [0; 16];
+        iv[..SALT_LEN].copy_from_slice(&self.salt);
+        for (iv, ssrc) in iv[4..8].iter_mut().zip(ssrc.to_be_bytes()) {
+            *iv ^= ssrc;
+        }
+        for (iv, index) in iv[8..14].iter_mut().zip(&index.to_be_bytes()[2..]) {
+            *iv ^= index;
+        }
+
+        keystream(&self.cipher, &iv, data)
+    }
+
+    fn tag(&self, parts: &[&[u8]]) -> Result<Vec<u8>> {
+        let mut signer = Signer::new(MessageDigest::sha1(), &self.auth)?;
+        for part in parts {
+            signer.update(part)?;
+        }
+        let mut tag = signer.sign_to_vec()?;
+        tag.truncate(TAG_LEN);
+        Ok(tag)
+    }
+}
+
+/// Protects or unprotects the packets of one direction of a stream with
+/// AES-CM and HMAC-SHA1, see RFC 3711.
+///
+/// Packets of a single source are expected, as answered calls carry one
+/// stream per direction. Replayed packets are not detected.
+pub struct SrtpContext {
+    rtp: SessionKeys,
+    rtcp: SessionKeys,
+
+    /// Rollover counter and highest sequence number seen, see RFC 3711, section 3.3.1
+    roc: u32,
+    last: Option<u16>,
+
+    /// Index of the next SRTCP packet sent
+    rtcp_index: u32,
+}
+
+impl SrtpContext {
+    pub fn new(master_key: &[u8], master_salt: &[u8]) -> Result<Self> {
+        ensure!(master_key.len() == KEY_LEN, "Invalid SRTP master key length: {}", master_key.len());
+        ensure!(master_salt.len() == SALT_LEN, "Invalid SRTP master salt length: {}", master_salt.len());
+
+        Ok(Self {
+            rtp: SessionKeys::derive(master_key, master_salt, 0)?,
+            rtcp: SessionKeys::derive(master_key, master_salt, 3)?,
+            roc: 0,
+            last: None,
+            rtcp_index: 0,
+        })
+    }
+
+    /// Contexts protecting sent and unprotecting received packets from the keying
+    /// material exported by DTLS, by whether this side is the DTLS server
+    pub fn from_keying_material(material: &[u8], server: bool) -> Result<(Self, Self)> {
+        ensure!(material.len() == KEYING_MATERIAL_LEN, "Invalid keying material length: {}", material.len());
+
+        let (keys, salts) = material.split_at(2 * KEY_LEN);
+        let client = Self::new(&keys[..KEY_LEN], &salts[..SALT_LEN])?;
+        let server_context = Self::new(&keys[KEY_LEN..], &salts[SALT_LEN..])?;
+
+        Ok(if server { (server_context, client) } else { (client, server_context) })
+    }
+
+    pub fn protect_rtp(&mut self, packet: &[u8]) -> Result<Vec<u8>> {
+        let header = rtp_header_len(packet)?;
+        let ssrc = u32::from_be_bytes(packet[8..12].try_into()?);
+        let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+        let roc = self.estimate(sequence);
+
+        let mut data = packet.to_vec();
+        self.rtp.apply(ssrc, index(roc, sequence), &mut data[header..])?;
+        let tag = self.rtp.tag(&[&data, &roc.to_be_bytes()])?;
+        data.extend(tag);
+
+        self.update(roc, sequence);
+        Ok(data)
+    }
+
+    pub fn unprotect_rtp(&mut self, packet: &[u8]) -> Result<Vec<u8>> {
+        ensure!(packet.len() >= RTP_HEADER_LEN + TAG_LEN, "SRTP packet too short: {} bytes", packet.len());
+        let (data, tag) = packet.split_at(packet.len() - TAG_LEN);
+        let header = rtp_header_len(data)?;
+        let ssrc = u32::from_be_bytes(data[8..12].try_into()?);
+        let sequence = u16::from_be_bytes([data[2], data[3]]);
+        let roc = self.estimate(sequence);
+
+        let expected = self.rtp.tag(&[data, &roc.to_be_bytes()])?;
+        ensure!(memcmp::eq(&expected, tag), "SRTP authentication failed");
+
+        let mut data = data.to_vec();
+        self.rtp.apply(ssrc, index(roc, sequence), &mut data[header..])?;
+
+        self.update(roc, sequence);
+        Ok(data)
+    }
+
+    pub fn protect_rtcp(&mut self, packet: &[u8]) -> Result<Vec<u8>> {
+        ensure!(packet.len() >= RTCP_HEADER_LEN, "RTCP packet too short: {} bytes", packet.len());
+        let ssrc = u32::from_be_bytes(packet[4..8].try_into()?);
+        let index = self.rtcp_index;
+        self.rtcp_index = (self.rtcp_index + 1) & !ENCRYPTED;
+
+        let mut data = packet.to_vec();
+        self.rtcp.apply(ssrc, index as u64, &mut data[RTCP_HEADER_LEN..])?;
+        data.extend((ENCRYPTED | index).to_be_bytes());
+        let tag = self.rtcp.tag(&[&data])?;
+        data.extend(tag);
+        Ok(data)
+    }
+
+    pub fn unprotect_rtcp(&mut self, packet: &[u8]) -> Result<Vec<u8>> {
+        ensure!(
+            packet.len() >= RTCP_HEADER_LEN + 4 + TAG_LEN,
+            "SRTCP packet too short: {} bytes",
+            packet.len()
+        );
+        let (data, tag) = packet.split_at(packet.len() - TAG_LEN);
+        let expected = self.rtcp.tag(&[data])?;
+        ensure!(memcmp::eq(&expected, tag), "SRTCP authentication failed");
+
+        let (data, index) = data.split_at(data.len() - 4);
+        let index = u32::from_be_bytes(index.try_into()?);
+        let ssrc = u32::from_be_bytes(data[4..8].try_into()?);
+
+        let mut data = data.to_vec();
+        if index & ENCRYPTED != 0 {
+            self.rtcp.apply(ssrc, (index & !ENCRYPTED) as u64, &mut data[RTCP_HEADER_LEN..])?;
+        }
+        Ok(data)
+    }
+
+    /// Rollover counter of a packet by its sequence number, see RFC 3711, appendix A
+    fn estimate(&self, sequence: u16) -> u32 {
+        let Some(last) = self.last else {
+            return self.roc;
+        };
+
+        if last < 0x8000 {
+            if sequence > last && sequence - last > 0x8000 {
+                return self.roc.wrapping_sub(1);
+            }
+        } else if sequence < last - 0x8000 {
+            return self.roc.wrapping_add(1);
+        }
+        self.roc
+    }
+
+    fn update(&mut self, roc: u32, sequence: u16) {
+        if roc == self.roc.wrapping_add(1) || self.last.is_none_or(|last| roc == self.roc && sequence > last) {
+            self.roc = roc;
+            self.last = Some(sequence);
+        }
+    }
+}
+
+/// Index of a packet, its rollover counter and sequence number
+fn index(roc: u32, sequence: u16) -> u64 {
+    (roc as u64) << 16 | sequence as u64
+}
+
+/// Length of the header of an RTP packet with its CSRCs and extension, which stay unencrypted
+fn rtp_header_len(packet: &[u8]) -> Result<usize> {
+    ensure!(packet.len() >= RTP_HEADER_LEN, "RTP packet too short: {} bytes", packet.len());
+
+    let mut len = RTP_HEADER_LEN + (packet[0] & 0x0f) as usize * 4;
+    if packet[0] & 0x10 != 0 {
+        let extension = packet.get(len..len + 4).context("RTP header extension truncated")?;
+        len += 4 + u16::from_be_bytes([extension[2], extension[3]]) as usize * 4;
+    }
+    ensure!(len <= packet.len(), "RTP packet truncated");
+    Ok(len)
+}
+
+/// Derives a session key by its label from the master key, see RFC 3711, section 4.3.1
+fn derive(master_key: &[u8], master_salt: &[u8], label: u8, key: &mut [u8]) -> Result<()> {
+    let mut iv = [0; 16];
+    iv[..SALT_LEN].copy_from_slice(master_salt);
+    iv[7] ^= label;
+
+    key.fill(0);
+    keystream(master_key, &iv, key)
+}
+
+/// XORs the data with the AES-128 keystream starting at the counter block
+fn keystream(key: &[u8], iv: &[u8; 16], data: &mut [u8]) -> Result<()> {
+    let mut crypter = Crypter::new(Cipher::aes_128_ctr(), Mode::Encrypt, key, Some(iv))?;
+    let mut output = vec![0; data.len() + 16];
+    let len = crypter.update(data, &mut output)?;
+    let len = len + crypter.finalize(&mut output[len..])?;
+    data.copy_from_slice(&output[..len]);
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use anyhow::{bail, ensure, Context, Result};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Size of the message header of RFC 5389, section 6
+const HEADER_LEN: usize = 20;
+
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+
+pub const BINDING_REQUEST: u16 = 0x0001;
+pub const BINDING_SUCCESS: u16 = 0x0101;
+
+pub const USERNAME: u16 = 0x0006;
+const MESSAGE_INTEGRITY: u16 = 0x0008;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+pub const PRIORITY: u16 = 0x0024;
+pub const USE_CANDIDATE: u16 = 0x0025;
+const FINGERPRINT: u16 = 0x8028;
+pub const ICE_CONTROLLING: u16 = 0x802a;
+
+/// Value the CRC of the fingerprint is XORed with, see RFC 5389, section 15.5
+const FINGERPRINT_XOR: u32 = 0x5354_554e;
+
+/// Length of the HMAC-SHA1 of the message integrity
+const INTEGRITY_LEN: usize = 20;
+
+/// A STUN message as ICE exchanges it, authenticated by the short-term
+/// credentials of RFC 5389, section 10.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StunMessage {
+    /// Class and method, like [`BINDING_REQUEST`]
+    pub kind: u16,
+
+    pub transaction: [u8; 12],
+
+    /// Attributes other than the message integrity and the fingerprint
+    pub attributes: Vec<(u16, Vec<u8>)>,
+}
+
+impl StunMessage {
+    pub fn new(kind: u16, transaction: [u8; 12]) -> Self {
+        Self {
+            kind,
+            transaction,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Whether a datagram is a STUN message rather than DTLS or RTP, see RFC 7983
+    pub fn is_stun(data: &[u8]) -> bool {
+        data.len() >= HEADER_LEN
+            && data[0] < 4
+            && u32::from_be_bytes([data[4], data[5], data[6], data[7]]) == MAGIC_COOKIE
+    }
+
+    pub fn with(mut self, kind: u16, value: impl Into<Vec<u8>>) -> Self {
+        self.attributes.push((kind, value.into()));
+        self
+    }
+
+    /// Adds the address the request was received from
+    pub fn with_mapped_address(self, address: SocketAddr) -> Self {
+        let mut value = vec![0, if address.is_ipv4() { 1 } else { 2 }];
+        value.extend((address.port() ^ (MAGIC_COOKIE >> 16) as u16).to_be_bytes());
+        let mask = self.mask();
+        match address.ip() {
+            IpAddr::V4(ip) => value.extend(ip.octets().iter().zip(&mask).map(|(octet, mask)| octet ^ mask)),
+            IpAddr::V6(ip) => value.extend(ip.octets().iter().zip(&mask).map(|(octet, mask)| octet ^ mask)),
+        }
+        self.with(XOR_MAPPED_ADDRESS, value)
+    }
+
+    pub fn attribute(&self, kind: u16) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|(known, _)| *known == kind)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        std::str::from_utf8(self.attribute(USERNAME)?).ok()
+    }
+
+    pub fn mapped_address(&self) -> Option<SocketAddr> {
+        let value = self.attribute(XOR_MAPPED_ADDRESS)?;
+        let port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]) ^ (MAGIC_COOKIE >> 16) as u16;
+        let mask = self.mask();
+        let octets = value.get(4..)?.iter().zip(&mask).map(|(octet, mask)| octet ^ mask).collect::<Vec<_>>();
+        let ip = match value[1] {
+            1 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?)),
+            2 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?)),
+            _ => return None,
+        };
+        Some(SocketAddr::new(ip, port))
+    }
+
+    /// Parses a message, verifying its fingerprint and its integrity by the password
+    pub fn parse(data: &[u8], password: &str) -> Result<Self> {
+        ensure!(Self::is_stun(data), "Not a STUN message");
+
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        ensure!(data.len() == HEADER_LEN + len, "STUN message of {} bytes announces {len}", data.len());
+
+        let mut message = Self::new(
+            u16::from_be_bytes([data[0], data[1]]),
+            data[8..HEADER_LEN].try_into().expect("Transaction of 12 bytes"),
+        );
+        let mut integrity = false;
+
+        let mut offset = HEADER_LEN;
+        while offset < data.len() {
+            ensure!(offset + 4 <= data.len(), "STUN attribute truncated");
+            let kind = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            let len = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+            let value = data.get(offset + 4..offset + 4 + len).context("STUN attribute truncated")?;
+
+            match kind {
+                MESSAGE_INTEGRITY => {
+                    ensure!(len == INTEGRITY_LEN, "Invalid message integrity");
+                    let expected = integrity_of(data, offset, password)?;
+                    ensure!(memcmp::eq(&expected, value), "Message integrity does not match");
+                    integrity = true;
+                }
+
+                FINGERPRINT => {
+                    ensure!(len == 4, "Invalid fingerprint");
+                    let expected = crc32fast::hash(&data[..offset]) ^ FINGERPRINT_XOR;
+                    ensure!(value == expected.to_be_bytes(), "Fingerprint does not match");
+                }
+
+                // Only the fingerprint may follow the message integrity
+                _ if integrity => {}
+
+                _ => message.attributes.push((kind, value.to_vec())),
+            }
+
+            offset += 4 + len.next_multiple_of(4);
+        }
+
+        if !integrity {
+            bail!("STUN message without message integrity");
+        }
+        Ok(message)
+    }
+
+    /// Encodes the message with its integrity by the password and its fingerprint
+    pub fn encode(&self, password: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(HEADER_LEN);
+        data.extend(self.kind.to_be_bytes());
+        data.extend([0, 0]);
+        data.extend(MAGIC_COOKIE.to_be_bytes());
+        data.extend(self.transaction);
+
+        for (kind, value) in &self.attributes {
+            data.extend(kind.to_be_bytes());
+            data.extend((value.len() as u16).to_be_bytes());
+            data.extend(value);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+
+        let offset = data.len();
+        let integrity = integrity_of(&data, offset, password)?;
+        data.extend(MESSAGE_INTEGRITY.to_be_bytes());
+        data.extend((INTEGRITY_LEN as u16).to_be_bytes());
+        data.extend(integrity);
+
+        let offset = data.len();
+        set_len(&mut data, offset + 8);
+        let fingerprint = crc32fast::hash(&data) ^ FINGERPRINT_XOR;
+        data.extend(FINGERPRINT.to_be_bytes());
+        data.extend(4u16.to_be_bytes());
+        data.extend(fingerprint.to_be_bytes());
+
+        Ok(data)
+    }
+
+    /// Bytes the mapped address is XORed with, see RFC 5389, section 15.2
+    fn mask(&self) -> [u8; 16] {
+        let mut mask = [0; 16];
+        mask[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        mask[4..].copy_from_slice(&self.transaction);
+        mask
+    }
+}
+
+/// HMAC-SHA1 of the message up to the integrity attribute at the given offset,
+/// with the length in the header ending right after it
+fn integrity_of(data: &[u8], offset: usize, password: &str) -> Result<Vec<u8>> {
+    let mut header = [0; HEADER_LEN];
+    header.copy_from_slice(&data[..HEADER_LEN]);
+    set_len(&mut header, offset + 4 + INTEGRITY_LEN);
+
+    let key = PKey::hmac(password.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&header)?;
+    signer.update(&data[HEADER_LEN..offset])?;
+    Ok(signer.sign_to_vec()?)
+}
+
+/// Sets the length in the header for a message ending at the given offset
+fn set_len(data: &mut [u8], end: usize) {
+    data[2..4].copy_from_slice(&((end - HEADER_LEN) as u16).to_be_bytes());
+}
@@ -0,0 +1,139 @@
+use super::wav;
+use crate::config::TranscriptionConfig;
+use crate::store::Store;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::process::Command;
+use tracing::debug;
+
+/// Length of the chunks recorded audio is transcribed in
+const CHUNK: Duration = Duration::from_secs(30);
+
+/// Speech recognition of recorded call audio
+pub struct Transcriber {
+    config: TranscriptionConfig,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    text: String,
+}
+
+impl Transcriber {
+    pub fn new(config: TranscriptionConfig) -> Self {
+        Self { config }
+    }
+
+    /// Text spoken in the given mono samples
+    pub async fn transcribe(&self, pcm: &[i16], sample_rate: u32) -> Result<String> {
+        let audio = wav::encode(pcm, sample_rate)?;
+
+        let text = match &self.config {
+            TranscriptionConfig::Whisper(config) => {
+                let path = std::env::temp_dir().join(format!("ucware-{:016x}.wav", rand::random::<u64>()));
+                tokio::fs::write(&path, audio).await?;
+
+                let mut command = Command::new(config.binary.as_deref().unwrap_or(Path::new("whisper-cli")));
+                command.arg("--model").arg(&config.model).arg("--file").arg(&path);
+                command.args(["--no-timestamps", "--no-prints"]);
+                if let Some(language) = &config.language {
+                    command.args(["--language", language]);
+                }
+
+                let output = command.output().await;
+                tokio::fs::remove_file(&path).await?;
+
+                let output = output.context("Failed to run whisper")?;
+                if !output.status.success() {
+                    bail!("Transcription failed with {status}", status = output.status);
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+
+            TranscriptionConfig::Http(config) => {
+                let mut request = reqwest::Client::new()
+                    .post(&config.url)
+                    .header(reqwest::header::CONTENT_TYPE, "audio/wav")
+                    .body(audio);
+                if let Some(token) = &config.token {
+                    request = request.bearer_auth(token);
+                }
+
+                request
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .context("Failed to request transcription")?
+                    .json::<Response>()
+                    .await?
+                    .text
+            }
+        };
+
+        Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    /// Transcription of a call's audio passed in as it is recorded
+    pub fn stream(&self, call_id: &str, sample_rate: u32) -> Transcript<'_> {
+        Transcript {
+            transcriber: self,
+            call_id: call_id.to_string(),
+            started: SystemTime::now(),
+            sample_rate,
+            pending: Vec::new(),
+            text: Vec::new(),
+        }
+    }
+}
+
+/// Transcribes recorded audio chunk by chunk, so the transcript is ready soon after the call
+pub struct Transcript<'a> {
+    transcriber: &'a Transcriber,
+    call_id: String,
+    started: SystemTime,
+    sample_rate: u32,
+
+    /// Samples not transcribed yet
+    pending: Vec<i16>,
+
+    /// Text of the transcribed chunks
+    text: Vec<String>,
+}
+
+impl Transcript<'_> {
+    pub async fn push(&mut self, pcm: &[i16]) -> Result<()> {
+        self.pending.extend_from_slice(pcm);
+
+        let chunk = (CHUNK.as_secs_f64() * self.sample_rate as f64) as usize;
+        if self.pending.len() >= chunk {
+            let pending = std::mem::take(&mut self.pending);
+            self.transcribe(&pending).await?;
+        }
+        Ok(())
+    }
+
+    /// Transcribes the rest and attaches the transcript to the call in the log
+    pub async fn finish(mut self, store: &Store) -> Result<String> {
+        let pending = std::mem::take(&mut self.pending);
+        self.transcribe(&pending).await?;
+
+        let transcript = self.text.join(" ");
+        debug!("Transcribed call {call_id}: {transcript}", call_id = self.call_id);
+        store.set_transcript(&self.call_id, self.started, &transcript)?;
+        Ok(transcript)
+    }
+
+    async fn transcribe(&mut self, pcm: &[i16]) -> Result<()> {
+        if pcm.is_empty() {
+            return Ok(());
+        }
+
+        let text = self.transcriber.transcribe(pcm, self.sample_rate).await?;
+        if !text.is_empty() {
+            self.text.push(text);
+        }
+        Ok(())
+    }
+}
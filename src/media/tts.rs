@@ -0,0 +1,107 @@
+use super::wav;
+use crate::config::TtsConfig;
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::debug;
+
+/// Speech synthesis for announcements, keeping synthesized audio for the next time
+pub struct Tts {
+    config: TtsConfig,
+
+    /// Directory keeping a WAV file per text and engine
+    cache: PathBuf,
+}
+
+impl Tts {
+    pub fn new(config: TtsConfig, cache: PathBuf) -> Self {
+        Self { config, cache }
+    }
+
+    pub fn open_default(config: TtsConfig) -> Result<Self> {
+        let cache = dirs::cache_dir().context("No cache directory")?.join("ucware").join("tts");
+        Ok(Self::new(config, cache))
+    }
+
+    /// Mono samples of the spoken text at the given sample rate
+    pub async fn speak(&self, text: &str, sample_rate: u32) -> Result<Vec<i16>> {
+        // The hash may change with the Rust version, costing only a new synthesis
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", self.config).hash(&mut hasher);
+        text.hash(&mut hasher);
+        let path = self.cache.join(format!("{:016x}.wav", hasher.finish()));
+
+        if tokio::fs::try_exists(&path).await? {
+            debug!("Using synthesized speech from {path}", path = path.display());
+        } else {
+            tokio::fs::create_dir_all(&self.cache).await?;
+
+            // Written aside first, so an aborted synthesis leaves nothing to be taken from the cache
+            let partial = path.with_extension("partial");
+            self.synthesize(text, &partial).await?;
+            tokio::fs::rename(&partial, &path).await?;
+        }
+
+        tokio::task::spawn_blocking(move || wav::read(path, sample_rate)).await?
+    }
+
+    async fn synthesize(&self, text: &str, path: &Path) -> Result<()> {
+        debug!("Synthesizing speech: {text}");
+
+        match &self.config {
+            TtsConfig::EspeakNg(config) => {
+                let mut command = Command::new("espeak-ng");
+                if let Some(voice) = &config.voice {
+                    command.args(["-v", voice]);
+                }
+                command.arg("-w").arg(path).arg("--").arg(text);
+                run(command, None).await.context("Failed to run espeak-ng")
+            }
+
+            TtsConfig::Piper(config) => {
+                let mut command = Command::new(config.binary.as_deref().unwrap_or(Path::new("piper")));
+                command.arg("--model").arg(&config.model).arg("--output_file").arg(path);
+                run(command, Some(text)).await.context("Failed to run piper")
+            }
+
+            TtsConfig::Http(config) => {
+                let mut request = reqwest::Client::new().post(&config.url).json(&json!({ "text": text }));
+                if let Some(token) = &config.token {
+                    request = request.bearer_auth(token);
+                }
+
+                let audio = request
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .context("Failed to request speech")?
+                    .bytes()
+                    .await?;
+                tokio::fs::write(path, audio).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Runs the command, passing the given input on stdin
+async fn run(mut command: Command, input: Option<&str>) -> Result<()> {
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).await?;
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        bail!("Synthesis failed with {status}");
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use super::level::Level;
+use crate::config::SilenceSuppressionConfig;
+use std::time::Duration;
+
+/// Level below which frames count as silence unless configured otherwise, in dBFS
+const DEFAULT_THRESHOLD: f32 = -50.0;
+
+const DEFAULT_HANGOVER: Duration = Duration::from_millis(200);
+
+const DEFAULT_REFRESH: Duration = Duration::from_secs(5);
+
+/// What to send for a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The encoded frame, with the marker bit set on the first of a talkspurt
+    Voice { talkspurt: bool },
+
+    /// A comfort noise payload of RFC 3389 with the noise level in -dBov
+    ComfortNoise(u8),
+
+    /// Nothing at all
+    Skip,
+}
+
+/// Voice activity detection deciding which frames are worth sending
+#[derive(Debug, Clone)]
+pub struct SilenceSuppression {
+    threshold: f32,
+
+    /// Silent frames still sent as voice, to not clip the ends of words
+    hangover: u32,
+
+    /// Silent frames between comfort noise updates
+    refresh: u32,
+
+    /// Silent frames in a row, none at the start so the first frame starts a talkspurt
+    silent: Option<u32>,
+}
+
+impl SilenceSuppression {
+    /// Suppression for frames of the given duration, none if disabled
+    pub fn new(config: &SilenceSuppressionConfig, ptime: Duration) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let frames = |duration: Duration| (duration.as_millis() / ptime.as_millis().max(1)).max(1) as u32;
+        Some(Self {
+            threshold: config.threshold.unwrap_or(DEFAULT_THRESHOLD),
+            hangover: frames(config.hangover.map_or(DEFAULT_HANGOVER, |hangover| hangover.0)),
+            refresh: frames(config.refresh.map_or(DEFAULT_REFRESH, |refresh| refresh.0)),
+            silent: None,
+        })
+    }
+
+    pub fn process(&mut self, pcm: &[i16]) -> Decision {
+        let level = Level::measure(pcm).dbfs();
+
+        if level >= self.threshold {
+            let talkspurt = self.silent.is_none_or(|silent| silent > self.hangover);
+            self.silent = Some(0);
+            return Decision::Voice { talkspurt };
+        }
+
+        let silent = self.silent.map_or(self.hangover + 1, |silent| silent + 1);
+        self.silent = Some(silent);
+
+        if silent <= self.hangover {
+            return Decision::Voice { talkspurt: false };
+        }
+
+        // Noise level right when silence starts and at every refresh
+        if (silent - self.hangover - 1).is_multiple_of(self.refresh) {
+            let noise = (-level).clamp(0.0, 127.0) as u8;
+            return Decision::ComfortNoise(noise);
+        }
+
+        Decision::Skip
+    }
+}
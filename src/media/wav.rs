@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Mono samples of a WAV file at the given sample rate
+pub fn read(path: impl AsRef<Path>, sample_rate: u32) -> Result<Vec<i16>> {
+    let path = path.as_ref();
+    let reader = WavReader::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+        SampleFormat::Int => reader
+            .into_samples::<i32>()
+            .map(|sample| {
+                let sample = sample?;
+                Ok(match spec.bits_per_sample {
+                    bits @ 0..16 => sample << (16 - bits),
+                    bits => sample >> (bits - 16),
+                } as i16)
+            })
+            .collect::<Result<Vec<_>>>(),
+        SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .map(|sample| Ok((sample?.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+            .collect::<Result<Vec<_>>>(),
+    }
+    .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mono = samples
+        .chunks(spec.channels.max(1) as usize)
+        .map(|frame| (frame.iter().map(|&sample| sample as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect::<Vec<_>>();
+
+    Ok(resample(&mono, spec.sample_rate, sample_rate))
+}
+
+/// WAV file of the given mono samples
+pub fn encode(pcm: &[i16], sample_rate: u32) -> Result<Vec<u8>> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    let mut data = Cursor::new(Vec::new());
+    let mut writer = WavWriter::new(&mut data, spec)?;
+    for &sample in pcm {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+
+    Ok(data.into_inner())
+}
+
+/// Samples converted to another rate by linear interpolation
+pub fn resample(samples: &[i16], from: u32, to: u32) -> Vec<i16> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let len = (samples.len() as u64 * to as u64 / from as u64) as usize;
+    let step = from as f64 / to as f64;
+    (0..len)
+        .map(|index| {
+            let position = index as f64 * step;
+            let before = samples[(position as usize).min(samples.len() - 1)];
+            let after = samples[(position as usize + 1).min(samples.len() - 1)];
+            let fraction = position.fract();
+            (before as f64 * (1.0 - fraction) + after as f64 * fraction) as i16
+        })
+        .collect()
+}
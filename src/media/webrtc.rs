@@ -0,0 +1,391 @@
+use super::sdp::{LocalTransport, SecureTransport};
+use super::srtp::{self, SrtpContext};
+use super::stun::{self, StunMessage};
+use anyhow::{bail, ensure, Context, Result};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{
+    ErrorCode, HandshakeError, MidHandshakeSslStream, Ssl, SslContext, SslMethod, SslOptions, SslStream,
+    SslVerifyMode,
+};
+use openssl::x509::{X509NameBuilder, X509Ref, X509};
+use rand::distr::{Alphanumeric, SampleString};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::UdpSocket;
+use tracing::{debug, trace};
+
+/// Largest datagram expected on the media port
+const MAX_DATAGRAM: usize = 1500;
+
+/// Size of DTLS records, leaving room for the headers of IPv6 and UDP on common links
+const DTLS_MTU: u32 = 1200;
+
+/// Validity of the certificates, which are generated for each session
+const CERTIFICATE_DAYS: u32 = 30;
+
+/// Lengths of the ICE credentials, above the minimum of RFC 8839, section 5.4
+const ICE_UFRAG_LEN: usize = 8;
+const ICE_PWD_LEN: usize = 24;
+
+/// A self-signed certificate identifying one side of DTLS by its fingerprint
+pub struct Certificate {
+    key: PKey<Private>,
+    x509: X509,
+}
+
+impl Certificate {
+    pub fn generate() -> Result<Self> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", "ucware-cli")?;
+        let name = name.build();
+
+        let mut serial = BigNum::new()?;
+        serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+        let serial = serial.to_asn1_integer()?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        let not_after = Asn1Time::days_from_now(CERTIFICATE_DAYS)?;
+
+        let mut x509 = X509::builder()?;
+        x509.set_version(2)?;
+        x509.set_serial_number(&serial)?;
+        x509.set_subject_name(&name)?;
+        x509.set_issuer_name(&name)?;
+        x509.set_pubkey(&key)?;
+        x509.set_not_before(&not_before)?;
+        x509.set_not_after(&not_after)?;
+        x509.sign(&key, MessageDigest::sha256())?;
+
+        Ok(Self {
+            key,
+            x509: x509.build(),
+        })
+    }
+
+    pub fn key(&self) -> &PKey<Private> {
+        &self.key
+    }
+
+    pub fn x509(&self) -> &X509Ref {
+        &self.x509
+    }
+
+    /// Fingerprint as announced by `a=fingerprint`
+    pub fn fingerprint(&self) -> Result<String> {
+        fingerprint(&self.x509, "sha-256")
+    }
+}
+
+/// Fingerprint of a certificate by the named hash function, like `sha-256 AB:CD:...`
+pub fn fingerprint(x509: &X509Ref, hash: &str) -> Result<String> {
+    let digest = MessageDigest::from_name(&hash.replace('-', ""))
+        .with_context(|| format!("Unsupported hash function of fingerprint: {hash}"))?;
+    let digest = x509.digest(digest)?;
+
+    let hex = digest.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>();
+    Ok(format!("{hash} {hex}", hex = hex.join(":")))
+}
+
+/// Datagrams passed to and from DTLS, which shares the socket with ICE and SRTP.
+///
+/// Reading without a datagram waiting blocks, so the handshake goes on as
+/// the remote party's next flight arrives.
+#[derive(Debug, Default)]
+pub struct Datagrams {
+    pub incoming: VecDeque<Vec<u8>>,
+    pub outgoing: Vec<Vec<u8>>,
+}
+
+impl Read for Datagrams {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(datagram) = self.incoming.pop_front() else {
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        Ok(len)
+    }
+}
+
+impl Write for Datagrams {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outgoing.push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Dtls {
+    /// Waiting for the remote party to start the handshake
+    Waiting(Ssl),
+    Handshaking(MidHandshakeSslStream<Datagrams>),
+    Established(SslStream<Datagrams>),
+    Closed,
+}
+
+/// Media on a WebRTC slot, see RFC 8827.
+///
+/// A single port carries the connectivity checks of ICE, the DTLS handshake
+/// and SRTP with multiplexed RTCP, told apart as of RFC 7983. This side is an
+/// ICE lite agent answering checks and sends media to the address the remote
+/// party nominated, once DTLS established the keys of SRTP.
+pub struct WebRtcTransport {
+    socket: UdpSocket,
+    local: LocalTransport,
+
+    /// Fingerprint of the certificate the remote party must present in DTLS
+    remote_fingerprint: String,
+
+    /// Address found by the connectivity checks of the remote party
+    remote: Option<SocketAddr>,
+
+    dtls: Dtls,
+
+    /// Contexts protecting sent and unprotecting received packets
+    srtp: Option<(SrtpContext, SrtpContext)>,
+}
+
+impl WebRtcTransport {
+    /// Binds the port answering the offered transport, with its local parameters for the answer
+    pub async fn bind(local: IpAddr, offer: &SecureTransport) -> Result<Self> {
+        let remote_fingerprint = offer
+            .fingerprint
+            .clone()
+            .context("No certificate fingerprint offered")?;
+        if offer.setup.as_deref() == Some("passive") {
+            bail!("Remote party only takes the passive DTLS role");
+        }
+
+        let certificate = Certificate::generate()?;
+        let mut context = SslContext::builder(SslMethod::dtls())?;
+        context.set_certificate(&certificate.x509)?;
+        context.set_private_key(&certificate.key)?;
+        context.set_tlsext_use_srtp(srtp::PROFILE)?;
+        context.set_options(SslOptions::NO_QUERY_MTU);
+
+        // Certificates are self-signed, they are checked by the offered fingerprint instead
+        context.set_verify_callback(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT, |_, _| true);
+
+        let mut ssl = Ssl::new(&context.build())?;
+        ssl.set_mtu(DTLS_MTU)?;
+
+        let socket = UdpSocket::bind(SocketAddr::new(local, 0)).await?;
+        let local = LocalTransport {
+            ice_ufrag: Alphanumeric.sample_string(&mut rand::rng(), ICE_UFRAG_LEN),
+            ice_pwd: Alphanumeric.sample_string(&mut rand::rng(), ICE_PWD_LEN),
+            fingerprint: certificate.fingerprint()?,
+            mid: offer.mid.clone(),
+        };
+
+        Ok(Self {
+            socket,
+            local,
+            remote_fingerprint,
+            remote: None,
+            dtls: Dtls::Waiting(ssl),
+            srtp: None,
+        })
+    }
+
+    pub fn port(&self) -> Result<u16> {
+        Ok(self.socket.local_addr()?.port())
+    }
+
+    /// ICE and DTLS parameters of the answer
+    pub fn local(&self) -> &LocalTransport {
+        &self.local
+    }
+
+    /// Whether DTLS established the keys and media can flow
+    pub fn is_connected(&self) -> bool {
+        self.remote.is_some() && self.srtp.is_some()
+    }
+
+    /// Receives the next RTP or RTCP packet, answering connectivity checks and
+    /// running the DTLS handshake on the way.
+    ///
+    /// Nothing is awaited but the socket, so this may be cancelled in `select!`.
+    pub async fn recv(&mut self) -> Result<(Vec<u8>, SocketAddr)> {
+        let mut datagram = [0; MAX_DATAGRAM];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut datagram).await?;
+            let data = &datagram[..len];
+
+            match data[0] {
+                _ if StunMessage::is_stun(data) => self.check(data, from)?,
+                20..=63 => self.dtls(data, from)?,
+                128..=191 => match self.unprotect(data) {
+                    Ok(Some(packet)) => return Ok((packet, from)),
+                    Ok(None) => trace!("Dropping media from {from} received before DTLS"),
+                    Err(err) => trace!("Dropping media from {from}: {err:#}"),
+                },
+                _ => trace!("Ignoring {len} bytes from {from}"),
+            }
+        }
+    }
+
+    /// Sends an RTP packet, returning whether it was sent as nothing is before DTLS established the keys
+    pub async fn send_rtp(&mut self, packet: &[u8]) -> Result<bool> {
+        let (Some(remote), Some((outbound, _))) = (self.remote, &mut self.srtp) else {
+            return Ok(false);
+        };
+
+        let data = outbound.protect_rtp(packet)?;
+        self.socket.send_to(&data, remote).await?;
+        Ok(true)
+    }
+
+    pub async fn send_rtcp(&mut self, packet: &[u8]) -> Result<bool> {
+        let (Some(remote), Some((outbound, _))) = (self.remote, &mut self.srtp) else {
+            return Ok(false);
+        };
+
+        let data = outbound.protect_rtcp(packet)?;
+        self.socket.send_to(&data, remote).await?;
+        Ok(true)
+    }
+
+    /// Answers a connectivity check, taking its source as the remote address if nominated
+    /// or the first one seen, see RFC 8445, section 7.3
+    fn check(&mut self, data: &[u8], from: SocketAddr) -> Result<()> {
+        let request = match StunMessage::parse(data, &self.local.ice_pwd) {
+            Ok(request) if request.kind == stun::BINDING_REQUEST => request,
+            Ok(message) => {
+                trace!("Ignoring STUN message of type {kind:#06x} from {from}", kind = message.kind);
+                return Ok(());
+            }
+            Err(err) => {
+                trace!("Ignoring STUN message from {from}: {err:#}");
+                return Ok(());
+            }
+        };
+
+        let username = request.username().unwrap_or_default();
+        if username.split(':').next() != Some(self.local.ice_ufrag.as_str()) {
+            trace!("Ignoring connectivity check from {from} for {username}");
+            return Ok(());
+        }
+
+        if self.remote != Some(from) && (self.remote.is_none() || request.attribute(stun::USE_CANDIDATE).is_some()) {
+            debug!("Sending media to {from}");
+            self.remote = Some(from);
+        }
+
+        let response = StunMessage::new(stun::BINDING_SUCCESS, request.transaction)
+            .with_mapped_address(from)
+            .encode(&self.local.ice_pwd)?;
+        if let Err(err) = self.socket.try_send_to(&response, from) {
+            debug!("Failed to answer connectivity check of {from}: {err}");
+        }
+        Ok(())
+    }
+
+    /// Passes a DTLS record on to the handshake, deriving the keys of SRTP once it completes
+    fn dtls(&mut self, data: &[u8], from: SocketAddr) -> Result<()> {
+        let handshake = match std::mem::replace(&mut self.dtls, Dtls::Closed) {
+            Dtls::Waiting(ssl) => ssl.accept(Datagrams {
+                incoming: VecDeque::from([data.to_vec()]),
+                outgoing: Vec::new(),
+            }),
+
+            Dtls::Handshaking(mut handshake) => {
+                handshake.get_mut().incoming.push_back(data.to_vec());
+                handshake.handshake()
+            }
+
+            // Records after the handshake are retransmissions or alerts
+            Dtls::Established(mut stream) => {
+                stream.get_mut().incoming.push_back(data.to_vec());
+                let mut buf = [0; MAX_DATAGRAM];
+                match stream.ssl_read(&mut buf) {
+                    Err(err) if err.code() == ErrorCode::ZERO_RETURN => {
+                        debug!("Remote party closed DTLS");
+                        self.srtp = None;
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                self.send_datagrams(&mut stream.get_mut().outgoing, from);
+                self.dtls = Dtls::Established(stream);
+                return Ok(());
+            }
+
+            Dtls::Closed => return Ok(()),
+        };
+
+        match handshake {
+            Ok(mut stream) => {
+                self.send_datagrams(&mut stream.get_mut().outgoing, from);
+                self.srtp = Some(self.keys(stream.ssl())?);
+                debug!("Established DTLS with {from}");
+                self.dtls = Dtls::Established(stream);
+            }
+
+            Err(HandshakeError::WouldBlock(mut handshake)) => {
+                self.send_datagrams(&mut handshake.get_mut().outgoing, from);
+                self.dtls = Dtls::Handshaking(handshake);
+            }
+
+            Err(HandshakeError::Failure(mut handshake)) => {
+                self.send_datagrams(&mut handshake.get_mut().outgoing, from);
+                bail!("DTLS handshake failed: {err}", err = handshake.error());
+            }
+
+            Err(HandshakeError::SetupFailure(err)) => bail!("Failed to set up DTLS: {err}"),
+        }
+        Ok(())
+    }
+
+    /// Keys of SRTP exported by DTLS, once the remote party proved its certificate
+    fn keys(&self, ssl: &openssl::ssl::SslRef) -> Result<(SrtpContext, SrtpContext)> {
+        let certificate = ssl.peer_certificate().context("Remote party presented no certificate")?;
+        let (hash, _) = self
+            .remote_fingerprint
+            .split_once(' ')
+            .context("Invalid fingerprint offered")?;
+        ensure!(
+            fingerprint(&certificate, hash)?.eq_ignore_ascii_case(self.remote_fingerprint.trim()),
+            "Certificate of remote party does not match the offered fingerprint"
+        );
+
+        let profile = ssl.selected_srtp_profile().context("No SRTP profile negotiated")?;
+        ensure!(profile.name() == srtp::PROFILE, "Unsupported SRTP profile: {}", profile.name());
+
+        let mut material = [0; srtp::KEYING_MATERIAL_LEN];
+        ssl.export_keying_material(&mut material, srtp::EXPORTER_LABEL, None)?;
+        SrtpContext::from_keying_material(&material, true)
+    }
+
+    fn unprotect(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some((_, inbound)) = &mut self.srtp else {
+            return Ok(None);
+        };
+
+        // RTCP is told apart from RTP by its packet type, see RFC 5761, section 4
+        let packet = match data.get(1) {
+            Some(192..=223) => inbound.unprotect_rtcp(data)?,
+            _ => inbound.unprotect_rtp(data)?,
+        };
+        Ok(Some(packet))
+    }
+
+    fn send_datagrams(&self, datagrams: &mut Vec<Vec<u8>>, to: SocketAddr) {
+        for datagram in datagrams.drain(..) {
+            if let Err(err) = self.socket.try_send_to(&datagram, to) {
+                debug!("Failed to send DTLS to {to}: {err}");
+            }
+        }
+    }
+}
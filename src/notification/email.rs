@@ -0,0 +1,41 @@
+//! Emails sent through an SMTP server
+
+use crate::config::{EmailConfig, SmtpSecurity};
+use anyhow::{Context, Result};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::debug;
+
+/// Sends a plain text email to all configured recipients
+pub async fn send(config: &EmailConfig, subject: &str, body: &str) -> Result<()> {
+    let mut message = Message::builder()
+        .from(config.from.parse().context("Invalid sender address")?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for to in &config.to {
+        message = message.to(to.parse().with_context(|| format!("Invalid recipient address: {to}"))?);
+    }
+    let message = message.body(body.to_string())?;
+
+    let mut transport = match config.security {
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.server)?,
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.server)?,
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.server),
+    };
+    if let Some(port) = config.port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    debug!("Sending email via {server}: {subject}", server = config.server);
+    transport
+        .build()
+        .send(message)
+        .await
+        .context("Failed to send email")?;
+
+    Ok(())
+}
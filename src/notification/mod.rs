@@ -0,0 +1,103 @@
+//! Notifications about incoming calls
+
+use crate::config::{NotificationConfig, Persistence, Urgency};
+use crate::store::Store;
+use anyhow::Result;
+use notify_rust::{Hint, Notification, Timeout};
+use tracing::debug;
+
+pub mod email;
+pub mod push;
+pub mod webhook;
+
+/// How an incoming call is announced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presentation {
+    /// Ringing on screen until the call ends
+    Ringing,
+
+    /// Announced briefly as another call is active
+    Waiting,
+
+    /// Announced briefly as the desktop asks not to be disturbed
+    Quiet,
+}
+
+/// Builds the notification for an incoming call from the given template variables
+pub fn incoming(
+    config: &NotificationConfig,
+    vars: &[(&str, &str)],
+    presentation: Presentation,
+    urgent: bool,
+) -> Notification {
+    let mut notification = Notification::new();
+    notification
+        .body(&config.body.render(vars))
+        .icon("phone")
+        .hint(Hint::Category(config.category.clone()));
+
+    if let Some(sound) = &config.sound {
+        notification.hint(Hint::SoundName(sound.clone()));
+    }
+
+    let urgency = if urgent { config.urgency.urgent } else { config.urgency.normal };
+
+    if presentation == Presentation::Waiting {
+        notification.summary("Call waiting");
+    } else {
+        notification.summary(&config.summary.render(vars));
+    }
+
+    if presentation == Presentation::Ringing {
+        notification.urgency(urgency.into());
+        persist(&mut notification, config.persistence);
+    } else {
+        notification.urgency(if urgent { urgency.into() } else { notify_rust::Urgency::Low });
+
+        // Brief announcements never stay on screen
+        persist(&mut notification, match config.persistence {
+            Persistence::Resident => Persistence::Timeout,
+            persistence => persistence,
+        });
+    }
+
+    notification
+}
+
+fn persist(notification: &mut Notification, persistence: Persistence) {
+    match persistence {
+        Persistence::Resident => notification.hint(Hint::Resident(true)).timeout(Timeout::Never),
+        Persistence::Timeout => notification.timeout(Timeout::Default),
+        Persistence::Transient => notification.hint(Hint::Transient(true)).timeout(Timeout::Default),
+    };
+}
+
+impl From<Urgency> for notify_rust::Urgency {
+    fn from(urgency: Urgency) -> Self {
+        match urgency {
+            Urgency::Low => Self::Low,
+            Urgency::Normal => Self::Normal,
+            Urgency::Critical => Self::Critical,
+        }
+    }
+}
+
+/// Closes notifications left open, e.g. by a previous run, as their calls are gone by now
+pub async fn close_stale(store: &Store) -> Result<()> {
+    for id in store.notifications()? {
+        debug!("Closing stale notification {id}");
+
+        // Notifications can only be closed by replacing them
+        Notification::new()
+            .id(id)
+            .summary("Call ended")
+            .timeout(Timeout::Milliseconds(1))
+            .show_async()
+            .await?
+            .close();
+
+        store.remove_notification(id)?;
+    }
+
+    Ok(())
+}
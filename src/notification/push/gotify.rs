@@ -0,0 +1,27 @@
+use super::Message;
+use crate::config::GotifyConfig;
+use anyhow::{Context, Result};
+use serde_json::json;
+use tracing::debug;
+
+/// Posts the message to the server as the application owning the token
+pub async fn send(config: &GotifyConfig, message: &Message) -> Result<()> {
+    debug!("Publishing to Gotify server {server}", server = config.server);
+
+    let body = json!({
+        "title": message.title,
+        "message": message.body,
+        "priority": if message.urgent { 10 } else { 8 },
+    });
+
+    reqwest::Client::new()
+        .post(format!("{server}/message", server = config.server.trim_end_matches('/')))
+        .header("X-Gotify-Key", &config.token)
+        .json(&body)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Failed to publish to Gotify")?;
+
+    Ok(())
+}
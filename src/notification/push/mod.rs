@@ -0,0 +1,26 @@
+//! Push notifications sent to phones through a third party service
+
+use crate::config::PushConfig;
+use anyhow::Result;
+
+pub(crate) mod gotify;
+pub(crate) mod ntfy;
+pub(crate) mod pushover;
+
+/// Summary of a call as sent to the phone
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub title: String,
+    pub body: String,
+
+    /// Caller marked as urgent by the screening rules
+    pub urgent: bool,
+}
+
+pub async fn send(config: &PushConfig, message: &Message) -> Result<()> {
+    match config {
+        PushConfig::Ntfy(config) => ntfy::send(config, message).await,
+        PushConfig::Gotify(config) => gotify::send(config, message).await,
+        PushConfig::Pushover(config) => pushover::send(config, message).await,
+    }
+}
@@ -0,0 +1,33 @@
+use super::Message;
+use crate::config::NtfyConfig;
+use anyhow::{Context, Result};
+use serde_json::json;
+use tracing::debug;
+
+/// Publishes the message to the topic
+pub async fn send(config: &NtfyConfig, message: &Message) -> Result<()> {
+    debug!("Publishing to ntfy topic {topic}", topic = config.topic);
+
+    let body = json!({
+        "topic": config.topic,
+        "title": message.title,
+        "message": message.body,
+        "priority": if message.urgent { 5 } else { 4 },
+        "tags": ["telephone_receiver"],
+    });
+
+    let mut request = reqwest::Client::new()
+        .post(config.server.trim_end_matches('/'))
+        .json(&body);
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+
+    request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Failed to publish to ntfy")?;
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use super::Message;
+use crate::config::PushoverConfig;
+use anyhow::{Context, Result};
+use serde_json::json;
+use tracing::debug;
+
+const API: &str = "https://api.pushover.net/1/messages.json";
+
+pub async fn send(config: &PushoverConfig, message: &Message) -> Result<()> {
+    debug!("Publishing to Pushover");
+
+    let mut body = json!({
+        "token": config.token,
+        "user": config.user,
+        "title": message.title,
+        "message": message.body,
+        "priority": if message.urgent { 1 } else { 0 },
+    });
+    if let Some(device) = &config.device {
+        body["device"] = json!(device);
+    }
+
+    reqwest::Client::new()
+        .post(API)
+        .json(&body)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Failed to publish to Pushover")?;
+
+    Ok(())
+}
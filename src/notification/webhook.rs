@@ -0,0 +1,46 @@
+//! Slack compatible incoming webhooks
+
+use crate::config::WebhookConfig;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// Posts a message, returns its timestamp for replies if the endpoint tells it
+pub async fn post(config: &WebhookConfig, text: &str, thread: Option<&str>) -> Result<Option<String>> {
+    let mut body = json!({ "text": text });
+    if let Some(channel) = &config.channel {
+        body["channel"] = json!(channel);
+    }
+    if let Some(thread) = thread {
+        body["thread_ts"] = json!(thread);
+    }
+
+    debug!("Posting to webhook: {text}");
+
+    let mut request = reqwest::Client::new().post(&config.url).json(&body);
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("Failed to post to webhook")?
+        .text()
+        .await?;
+
+    // Plain webhooks answer with `ok`, the Web API with a JSON object
+    let Ok(response) = serde_json::from_str::<Value>(&response) else {
+        return Ok(None);
+    };
+
+    if response["ok"] == false {
+        bail!(
+            "Webhook rejected message: {error}",
+            error = response["error"].as_str().unwrap_or("unknown error")
+        );
+    }
+
+    Ok(response["ts"].as_str().map(str::to_string))
+}
@@ -0,0 +1,207 @@
+//! External subcommands, like `ucware foo` running `ucware-foo` found on the `PATH`.
+//!
+//! # Handshake
+//!
+//! Before running a plugin, it is invoked with the single argument
+//! `--ucware-handshake` and must print one line of JSON to stdout and exit:
+//!
+//! ```json
+//! {"protocol": 1, "description": "Does foo", "connect": true}
+//! ```
+//!
+//! - `protocol`: version of this protocol the plugin speaks, must be 1
+//! - `description`: shown by `ucware plugins` - none if unset
+//! - `connect`: whether the plugin needs the server, which is then connected
+//!   to and its URL and a fresh token passed on - false if unset
+//!
+//! # Environment
+//!
+//! The plugin then runs with the remaining arguments and inherits stdio. It
+//! receives these variables:
+//!
+//! - `UCWARE_PLUGIN_PROTOCOL`: version of the protocol of the host
+//! - `UCWARE_CONFIG`: path of the config file
+//! - `UCWARE_OUTPUT`: requested output format, `text` or `json`
+//! - `UCWARE_CTL_SOCKET`: control socket of the notifier daemon, if available
+//! - `UCWARE_URL` and `UCWARE_TOKEN`: API base URL and token, if it asked to connect
+//!
+//! The exit code of the plugin becomes the one of `ucware`.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Version of the handshake and environment described above
+pub const PROTOCOL: u32 = 1;
+
+/// Prefix of the executables providing plugins
+pub const PREFIX: &str = "ucware-";
+
+/// Argument asking a plugin for its handshake
+pub const HANDSHAKE_ARG: &str = "--ucware-handshake";
+
+/// Time a plugin has to answer the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Executables shipped with this crate, which are not plugins
+const BUILTIN: &[&str] = &["ucware-cli", "ucware-call-notify"];
+
+/// A plugin executable found on the `PATH`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Plugin {
+    /// Subcommand the plugin provides, the executable name without prefix
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// What a plugin tells about itself in the handshake
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handshake {
+    pub protocol: u32,
+    pub description: Option<String>,
+    pub connect: bool,
+}
+
+/// Variables passed to a plugin
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub config: PathBuf,
+    pub output: String,
+    pub ctl_socket: Option<PathBuf>,
+
+    /// API base URL and token, if the plugin asked to connect
+    pub connection: Option<(String, String)>,
+}
+
+impl Environment {
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("UCWARE_PLUGIN_PROTOCOL", PROTOCOL.to_string()),
+            ("UCWARE_CONFIG", self.config.display().to_string()),
+            ("UCWARE_OUTPUT", self.output.clone()),
+        ];
+        if let Some(socket) = &self.ctl_socket {
+            vars.push(("UCWARE_CTL_SOCKET", socket.display().to_string()));
+        }
+        if let Some((url, token)) = &self.connection {
+            vars.push(("UCWARE_URL", url.clone()));
+            vars.push(("UCWARE_TOKEN", token.clone()));
+        }
+        vars
+    }
+}
+
+/// Plugins on the given search path, the first of each name taking precedence like the shell does
+pub fn discover(search_path: &std::ffi::OsStr) -> Vec<Plugin> {
+    let mut plugins = BTreeMap::new();
+
+    for dir in std::env::split_paths(search_path) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str().and_then(plugin_name) else {
+                continue;
+            };
+
+            let path = entry.path();
+            if !is_executable(&path) {
+                continue;
+            }
+
+            plugins.entry(name.to_string()).or_insert(Plugin {
+                name: name.to_string(),
+                path,
+            });
+        }
+    }
+
+    plugins.into_values().collect()
+}
+
+/// Plugins on the `PATH`
+pub fn installed() -> Vec<Plugin> {
+    std::env::var_os("PATH").map(|path| discover(&path)).unwrap_or_default()
+}
+
+/// The plugin providing the given subcommand
+pub fn find(name: &str) -> Option<Plugin> {
+    installed().into_iter().find(|plugin| plugin.name == name)
+}
+
+/// Subcommand provided by an executable of the given file name, if it is a plugin
+fn plugin_name(file_name: &str) -> Option<&str> {
+    if BUILTIN.iter().any(|builtin| file_name.strip_suffix(".exe").unwrap_or(file_name) == *builtin) {
+        return None;
+    }
+
+    let name = file_name.strip_prefix(PREFIX)?;
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    (!name.is_empty() && !name.contains('.')).then_some(name)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+impl Plugin {
+    /// Asks the plugin about itself, failing if it does not speak the protocol
+    pub async fn handshake(&self) -> Result<Handshake> {
+        let output = Command::new(&self.path)
+            .arg(HANDSHAKE_ARG)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(HANDSHAKE_TIMEOUT, output)
+            .await
+            .with_context(|| format!("Plugin {name} did not answer the handshake", name = self.name))?
+            .with_context(|| format!("Failed to run plugin {path}", path = self.path.display()))?;
+
+        if !output.status.success() {
+            bail!("Plugin {name} failed the handshake: {status}", name = self.name, status = output.status);
+        }
+
+        let line = String::from_utf8_lossy(&output.stdout);
+        let line = line.lines().next().unwrap_or_default();
+        let handshake: Handshake = serde_json::from_str(line)
+            .with_context(|| format!("Invalid handshake of plugin {name}", name = self.name))?;
+
+        if handshake.protocol != PROTOCOL {
+            bail!(
+                "Plugin {name} speaks protocol {theirs}, expected {PROTOCOL}",
+                name = self.name,
+                theirs = handshake.protocol
+            );
+        }
+
+        Ok(handshake)
+    }
+
+    /// Runs the plugin with the given arguments until it exits
+    pub async fn run(&self, args: &[String], env: &Environment) -> Result<ExitStatus> {
+        let status = Command::new(&self.path)
+            .args(args)
+            .envs(env.vars())
+            .status()
+            .await
+            .with_context(|| format!("Failed to run plugin {path}", path = self.path.display()))?;
+        Ok(status)
+    }
+}
@@ -0,0 +1,56 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables all progress output of the process
+pub fn init(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A spinner for operations of unknown length, cleared when dropped
+pub fn spinner(message: impl Into<Cow<'static, str>>) -> Progress {
+    if !enabled() {
+        return Progress(ProgressBar::hidden());
+    }
+
+    let bar = ProgressBar::new_spinner().with_message(message);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    Progress(bar)
+}
+
+/// A progress bar counting up to `len`, cleared when dropped
+pub fn bar(len: u64) -> Progress {
+    if !enabled() {
+        return Progress(ProgressBar::hidden());
+    }
+
+    let bar = ProgressBar::new(len).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} {msg}").expect("valid template"),
+    );
+    Progress(bar)
+}
+
+pub struct Progress(ProgressBar);
+
+impl Progress {
+    pub fn inc(&self, delta: u64) {
+        self.0.inc(delta);
+    }
+
+    pub fn set_message(&self, message: impl Into<Cow<'static, str>>) {
+        self.0.set_message(message);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.0.finish_and_clear();
+    }
+}
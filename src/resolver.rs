@@ -0,0 +1,67 @@
+use crate::config::DnsConfig;
+use anyhow::{anyhow, Context, Result};
+use hickory_resolver::config::{NameServerConfig, ResolverConfig};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::xfer::Protocol;
+use hickory_resolver::TokioResolver;
+use std::net::IpAddr;
+use tracing::debug;
+
+/// Caching DNS resolver shared by all connections of a client
+#[derive(Clone)]
+pub struct Resolver {
+    inner: TokioResolver,
+}
+
+impl Resolver {
+    /// Uses the configured name servers, or those of the system if there are none
+    pub fn new(config: &DnsConfig) -> Result<Self> {
+        let mut builder = if config.servers.is_empty() {
+            TokioResolver::builder_tokio().context("Failed to read system DNS config")?
+        } else {
+            let servers = config
+                .servers
+                .iter()
+                .flat_map(|server| {
+                    [Protocol::Udp, Protocol::Tcp]
+                        .map(|protocol| NameServerConfig::new(server.0, protocol))
+                })
+                .collect::<Vec<_>>();
+
+            TokioResolver::builder_with_config(
+                ResolverConfig::from_parts(None, vec![], servers),
+                TokioConnectionProvider::default(),
+            )
+        };
+
+        if let Some(cache_size) = config.cache_size {
+            builder.options_mut().cache_size = cache_size;
+        }
+
+        Ok(Self {
+            inner: builder.build(),
+        })
+    }
+
+    /// Addresses of a host, which may also be an IP literal
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let lookup = self.inner.lookup_ip(host).await.map_err(|err| {
+            let servers = self
+                .inner
+                .config()
+                .name_servers()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            anyhow!(err).context(format!(
+                "Failed to resolve {host} using {servers}",
+                servers = servers.join(", ")
+            ))
+        })?;
+
+        let addrs = lookup.iter().collect::<Vec<_>>();
+        debug!("Resolved {host} to {addrs:?}");
+
+        Ok(addrs)
+    }
+}
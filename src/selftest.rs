@@ -0,0 +1,161 @@
+use crate::sipsocket::Connection;
+use crate::ucware::Client;
+use anyhow::{bail, Context, Result};
+use rsip::{Method, StatusCodeKind};
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Time to wait for the server to answer a SIP request
+const SIP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Status {
+    Ok,
+    Failed {
+        error: String,
+    },
+
+    /// Not run because a check it depends on failed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+
+    #[serde(flatten)]
+    pub status: Status,
+
+    pub duration_ms: u128,
+}
+
+/// Results of all checks, in the order they were run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.status == Status::Ok)
+    }
+
+    /// Runs a check unless an earlier one failed
+    async fn check<T>(
+        &mut self,
+        name: &'static str,
+        f: impl Future<Output = Result<T>>,
+    ) -> Option<T> {
+        if !self.passed() {
+            self.checks.push(Check {
+                name,
+                status: Status::Skipped,
+                duration_ms: 0,
+            });
+            return None;
+        }
+
+        let start = Instant::now();
+        let result = f.await;
+
+        let (status, value) = match result {
+            Ok(value) => (Status::Ok, Some(value)),
+            Err(err) => (
+                Status::Failed {
+                    error: format!("{err:#}"),
+                },
+                None,
+            ),
+        };
+
+        self.checks.push(Check {
+            name,
+            status,
+            duration_ms: start.elapsed().as_millis(),
+        });
+
+        value
+    }
+}
+
+/// Formats the report as Test Anything Protocol
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TAP version 13")?;
+        writeln!(f, "1..{}", self.checks.len())?;
+
+        for (i, check) in self.checks.iter().enumerate() {
+            let number = i + 1;
+            match &check.status {
+                Status::Ok => writeln!(
+                    f,
+                    "ok {number} - {name} # {duration}ms",
+                    name = check.name,
+                    duration = check.duration_ms
+                )?,
+                Status::Failed { error } => {
+                    writeln!(f, "not ok {number} - {name}", name = check.name)?;
+                    writeln!(f, "  ---")?;
+                    writeln!(f, "  message: {error:?}")?;
+                    writeln!(f, "  ...")?;
+                }
+                Status::Skipped => writeln!(f, "ok {number} - {name} # SKIP", name = check.name)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Exercises the integration end to end: API access, SIP socket and registration
+pub async fn run(client: &Client) -> Report {
+    let mut report = Report::default();
+
+    report.check("token refresh", client.refresh_token()).await;
+
+    let slot = report.check("slot lookup", client.webrtc_slot()).await;
+
+    // Keep the receiving end of incoming requests alive while the socket is in use
+    let mut socket = report
+        .check("socket connect", async {
+            let slot = slot.as_ref().context("No slot")?;
+            let url = client.socket_urls(slot)?.into_iter().next().context("No SIP host")?;
+            Connection::builder(url, &slot.sip_username)
+                .resolver(client.resolver().clone())
+                .connect()
+                .await
+        })
+        .await;
+
+    report
+        .check("register", async {
+            let slot = slot.as_ref().context("No slot")?;
+            let (connection, _) = socket.as_mut().context("Not connected")?;
+            connection
+                .register(&slot.sip_username, &slot.sip_password)
+                .await
+        })
+        .await;
+
+    report
+        .check("options", async {
+            let (connection, _) = socket.as_ref().context("Not connected")?;
+            let dialog = connection.dialog();
+            let tx = dialog.request(Method::Options).send([]).await?;
+
+            let response = tokio::time::timeout(SIP_TIMEOUT, tx.receive())
+                .await
+                .context("No response to OPTIONS")??;
+            if response.status_code.kind() != StatusCodeKind::Successful {
+                bail!("OPTIONS failed: {status}", status = response.status_code);
+            }
+
+            Ok(())
+        })
+        .await;
+
+    report
+}
@@ -0,0 +1,91 @@
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::error;
+use windows_service::service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+    ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::{define_windows_service, service_dispatcher};
+
+type ServiceMain = Box<dyn FnOnce(oneshot::Receiver<()>) -> Result<()> + Send>;
+
+static NAME: OnceLock<String> = OnceLock::new();
+static MAIN: Mutex<Option<ServiceMain>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Runs the given function as Windows service.
+///
+/// The function receives a signal which is triggered as soon as the service
+/// manager requests the service to stop. This blocks until the service has
+/// been stopped.
+pub fn run(
+    name: &str,
+    main: impl FnOnce(oneshot::Receiver<()>) -> Result<()> + Send + 'static,
+) -> Result<()> {
+    NAME.set(name.to_string())
+        .map_err(|_| anyhow!("Service already running"))?;
+    *MAIN.lock().expect("not poisoned") = Some(Box::new(main));
+
+    service_dispatcher::start(name, ffi_service_main)
+        .context("Failed to start service dispatcher - not started by the service manager?")?;
+
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(err) = run_service() {
+        error!("Service failed: {err:#}");
+    }
+}
+
+fn run_service() -> Result<()> {
+    let name = NAME.get().context("Service name not set")?;
+    let main = MAIN
+        .lock()
+        .expect("not poisoned")
+        .take()
+        .context("Service main not set")?;
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let mut shutdown_tx = Some(shutdown_tx);
+
+    let status = service_control_handler::register(name, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            if let Some(shutdown_tx) = shutdown_tx.take() {
+                let _ = shutdown_tx.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+
+    let state = |current_state, exit_code| ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state,
+        controls_accepted: if current_state == ServiceState::Running {
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+        } else {
+            ServiceControlAccept::empty()
+        },
+        exit_code: ServiceExitCode::Win32(exit_code),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    };
+
+    status.set_service_status(state(ServiceState::Running, 0))?;
+
+    let result = main(shutdown_rx);
+
+    status.set_service_status(state(ServiceState::Stopped, if result.is_ok() { 0 } else { 1 }))?;
+
+    result
+}
@@ -0,0 +1,240 @@
+//! Dialogs established by answering an INVITE, see RFC 3261, section 12.1.1
+
+use super::ids::SipIdGenerator;
+use super::{send_request, ClientTransaction, ServerTransaction, SipTimers, TransactionKey};
+use anyhow::{bail, Context, Result};
+use dashmap::DashMap;
+use rsip::headers::typed::{Contact, From, To};
+use rsip::headers::{CallId, ContentType, ToTypedHeader, UntypedHeader};
+use rsip::message::HeadersExt;
+use rsip::{
+    Header, Headers, HostWithPort, Method, Param, Request, Response, StatusCode, StatusCodeKind, Transport, Uri,
+    Version,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Weak};
+use tokio::sync::mpsc;
+use tracing::trace;
+
+/// What a server transaction needs to take part in the dialog its request starts
+#[derive(Clone)]
+pub(super) struct DialogContext {
+    /// Weak so dialogs outliving the connection do not keep its socket open
+    pub sender: mpsc::WeakSender<Request>,
+    pub transactions: Weak<DashMap<TransactionKey, mpsc::Sender<Response>>>,
+    pub send_by: HostWithPort,
+
+    /// Address at which the server reaches this socket
+    pub contact: Contact,
+
+    pub ids: Arc<dyn SipIdGenerator>,
+    pub timers: SipTimers,
+}
+
+impl ServerTransaction {
+    /// Answers an INVITE with 200 and the given session description, establishing a dialog with the caller.
+    ///
+    /// The 200 is not retransmitted as the socket is reliable, so the ACK
+    /// need not be waited for before sending media.
+    pub async fn answer(self, sdp: impl Into<Vec<u8>>) -> Result<AnsweredDialog> {
+        let request = &self.request;
+
+        let remote = request.from_header()?.typed().context("Invalid From header")?;
+        let mut local = request.to_header()?.typed().context("Invalid To header")?;
+        local.params.retain(|param| !matches!(param, Param::Tag(_)));
+        local.params.push(Param::Tag(self.context.ids.tag().into()));
+
+        let target = request
+            .contact_header()
+            .context("Missing Contact header")?
+            .typed()
+            .context("Invalid Contact header")?
+            .uri;
+
+        // Routes towards the caller, in the order the proxies recorded them
+        let route = request
+            .headers
+            .iter()
+            .filter_map(|header| match header {
+                Header::RecordRoute(record_route) => Some(rsip::headers::Route::new(record_route.value()).into()),
+                _ => None,
+            })
+            .collect();
+
+        let headers = request
+            .headers
+            .iter()
+            .filter(|&header| matches!(header, Header::Via(_) | Header::From(_) | Header::CSeq(_) | Header::CallId(_)))
+            .cloned()
+            .chain([Header::from(local.clone()), self.context.contact.clone().into()])
+            .chain([ContentType::new("application/sdp").into()])
+            .chain(self.defaults.iter().cloned())
+            .collect::<Vec<_>>();
+
+        self.responses
+            .send(Response {
+                status_code: StatusCode::OK,
+                version: Version::V2,
+                headers: headers.into(),
+                body: sdp.into(),
+            })
+            .context("Connection closed")?;
+
+        let seq = AtomicU32::new(self.context.ids.cseq());
+        Ok(AnsweredDialog {
+            context: self.context,
+            defaults: self.defaults,
+            call_id: request.call_id_header()?.value().to_string(),
+            local,
+            remote,
+            target,
+            route,
+            seq,
+        })
+    }
+}
+
+/// A call answered here, taking requests of its own to the caller
+pub struct AnsweredDialog {
+    context: DialogContext,
+    defaults: Arc<Vec<Header>>,
+
+    call_id: String,
+
+    /// The callee as addressed by the INVITE, with the tag chosen here
+    local: To,
+
+    /// The caller with its tag
+    remote: From,
+
+    /// Contact of the caller, which in-dialog requests are sent to
+    target: Uri,
+
+    /// Route headers from the Record-Route headers of the INVITE
+    route: Vec<Header>,
+
+    seq: AtomicU32,
+}
+
+impl AnsweredDialog {
+    pub fn call_id(&self) -> &str {
+        &self.call_id
+    }
+
+    /// The callee as addressed by the INVITE
+    pub fn callee(&self) -> &Uri {
+        &self.local.uri
+    }
+
+    /// Ends the call
+    pub async fn bye(&self) -> Result<()> {
+        let response = self.exchange(Method::Bye, Vec::new(), Vec::new()).await?;
+        if response.status_code.kind() != StatusCodeKind::Successful {
+            bail!("Failed to hang up: {status}", status = response.status_code);
+        }
+        Ok(())
+    }
+
+    /// Asks the caller to call the given URI instead, see RFC 3515
+    pub async fn refer(&self, target: &str) -> Result<()> {
+        let headers = vec![
+            Header::Other("Refer-To".into(), format!("<{target}>")),
+            Header::Other("Referred-By".into(), format!("<{uri}>", uri = self.local.uri)),
+        ];
+
+        let response = self.exchange(Method::Refer, headers, Vec::new()).await?;
+        if response.status_code.kind() != StatusCodeKind::Successful {
+            bail!("Failed to transfer call to {target}: {status}", status = response.status_code);
+        }
+        Ok(())
+    }
+
+    /// Offers a new session description to the caller, e.g. to hold the call, see RFC 3261, section 14
+    pub async fn reinvite(&self, sdp: impl Into<Vec<u8>>) -> Result<()> {
+        let seq = self.seq.fetch_add(1, Ordering::Release);
+        let headers = vec![ContentType::new("application/sdp").into()];
+        let invite = self.request(Method::Invite, seq, headers, sdp.into());
+        let via = invite.via_header()?.clone();
+
+        let response = self.send(invite).await?.receive().await?;
+        let successful = response.status_code.kind() == StatusCodeKind::Successful;
+
+        // A 2xx is acknowledged in a transaction of its own, anything else within the INVITE transaction
+        let mut ack = self.request(Method::Ack, seq, Vec::new(), Vec::new());
+        if !successful {
+            for header in ack.headers.iter_mut() {
+                if let Header::Via(ack_via) = header {
+                    *ack_via = via.clone();
+                }
+            }
+        }
+        let sender = self.context.sender.upgrade().context("Connection closed")?;
+        sender.send(ack).await.ok().context("Connection closed")?;
+
+        if !successful {
+            bail!("Failed to update call: {status}", status = response.status_code);
+        }
+        Ok(())
+    }
+
+    /// Sends a request within the dialog and waits for the final response
+    async fn exchange(&self, method: Method, headers: Vec<Header>, body: Vec<u8>) -> Result<Response> {
+        let request = self.request(method, self.seq.fetch_add(1, Ordering::Release), headers, body);
+        self.send(request).await?.receive().await
+    }
+
+    fn request(&self, method: Method, seq: u32, headers: Vec<Header>, body: Vec<u8>) -> Request {
+        let mut all = Headers::default();
+        all.push(
+            rsip::headers::typed::Via {
+                version: Version::V2,
+                transport: Transport::Wss,
+                uri: Uri::from(self.context.send_by.clone()),
+                params: vec![Param::Branch(self.context.ids.branch().into())],
+            }
+            .into(),
+        );
+        for route in &self.route {
+            all.push(route.clone());
+        }
+        all.push(
+            From {
+                display_name: self.local.display_name.clone(),
+                uri: self.local.uri.clone(),
+                params: self.local.params.clone(),
+            }
+            .into(),
+        );
+        all.push(
+            To {
+                display_name: self.remote.display_name.clone(),
+                uri: self.remote.uri.clone(),
+                params: self.remote.params.clone(),
+            }
+            .into(),
+        );
+        all.push(CallId::new(self.call_id.clone()).into());
+        all.push(rsip::headers::typed::CSeq { seq, method }.into());
+        all.push(self.context.contact.clone().into());
+        for header in headers.into_iter().chain(self.defaults.iter().cloned()) {
+            all.push(header);
+        }
+
+        Request {
+            method,
+            uri: self.target.clone(),
+            version: Version::V2,
+            headers: all,
+            body,
+        }
+    }
+
+    async fn send(&self, request: Request) -> Result<ClientTransaction> {
+        trace!("Sending request in answered dialog: {request:#?}");
+
+        let transactions = self.context.transactions.upgrade().context("Connection closed")?;
+        let sender = self.context.sender.upgrade().context("Connection closed")?;
+        send_request(&transactions, &sender, &self.context.timers, request).await
+    }
+}
+
@@ -0,0 +1,96 @@
+use rsip::headers::UntypedHeader;
+use rsip::message::HeadersExt;
+use rsip::{Header, Response};
+use serde::Serialize;
+
+/// What a REGISTER asks the registrar to do with the bindings of the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Registration {
+    /// Binds the contact of the socket for the given number of seconds
+    Bind(u32),
+
+    /// Removes the binding of the contact of the socket
+    Unbind,
+
+    /// Removes all bindings of the user using the wildcard contact
+    UnbindAll,
+
+    /// Changes nothing but returns the current bindings
+    Query,
+}
+
+/// A contact registered for the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Binding {
+    pub contact: String,
+
+    /// Seconds until the binding expires
+    pub expires: Option<u32>,
+
+    /// The `+sip.instance` of the client which registered the binding
+    pub instance: Option<String>,
+}
+
+impl Binding {
+    /// Bindings listed by the registrar in a successful response
+    pub fn from_response(response: &Response) -> Vec<Self> {
+        let default_expires = response
+            .expires_header()
+            .and_then(|expires| expires.seconds().ok());
+
+        response
+            .headers
+            .iter()
+            .filter_map(|header| match header {
+                Header::Contact(contact) => Self::parse(contact.value()),
+                _ => None,
+            })
+            .map(|binding| Binding {
+                expires: binding.expires.or(default_expires),
+                ..binding
+            })
+            .collect()
+    }
+
+    /// Parses a single contact by hand as the typed header rejects quoted
+    /// parameter values like those of `+sip.instance`
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (contact, params) = match value.strip_prefix('<') {
+            Some(value) => value.split_once('>')?,
+            None => value.split_once(';').unwrap_or((value, "")),
+        };
+
+        let mut binding = Binding {
+            contact: contact.trim().to_string(),
+            expires: None,
+            instance: None,
+        };
+
+        for param in split_params(params) {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "expires" => binding.expires = value.parse().ok(),
+                "+sip.instance" => binding.instance = Some(value.trim_matches(['"', '<', '>']).to_string()),
+                _ => {}
+            }
+        }
+
+        Some(binding)
+    }
+}
+
+/// Parameters separated by `;`, ignoring those within quotes
+fn split_params(params: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    params
+        .split(move |c| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ';' && !quoted
+        })
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+}
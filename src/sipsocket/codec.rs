@@ -0,0 +1,21 @@
+//! Conversion between WebSocket frames and SIP messages
+
+use super::compact;
+use bytes::Bytes;
+use rsip::message::HasHeaders;
+use rsip::SipMessage;
+
+/// Parses the payload of a text or binary frame.
+///
+/// The payload is parsed in place, only the parts ending up in the message
+/// are copied. Compact headers are expanded to their typed form.
+pub fn decode(frame: Bytes) -> Result<SipMessage, rsip::Error> {
+    let mut message = SipMessage::try_from(frame)?;
+    compact::expand(message.headers_mut());
+    Ok(message)
+}
+
+/// Renders a message into the payload of a single text frame
+pub fn encode(message: &SipMessage) -> String {
+    message.to_string()
+}
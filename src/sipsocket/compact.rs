@@ -0,0 +1,71 @@
+//! Compact header forms (RFC 3261 section 7.3.3)
+
+use super::Middleware;
+use rsip::headers::{self, UntypedHeader};
+use rsip::message::HasHeaders;
+use rsip::{Header, Headers, SipMessage};
+
+/// Sends headers in their compact form and strips headers which are redundant
+/// on a WebSocket, to keep frames small on constrained links.
+///
+/// `Content-Length` is dropped for messages without body as each frame
+/// carries exactly one message.
+pub struct CompactHeaders;
+
+impl Middleware for CompactHeaders {
+    fn outgoing(&self, mut message: SipMessage) -> Option<SipMessage> {
+        let empty = message.body().is_empty();
+
+        let headers = std::mem::take(message.headers_mut());
+        *message.headers_mut() = headers
+            .into_iter()
+            .filter(|header| !(empty && matches!(header, Header::ContentLength(_))))
+            .map(compact)
+            .collect::<Vec<_>>()
+            .into();
+
+        Some(message)
+    }
+}
+
+fn compact(header: Header) -> Header {
+    let (name, value) = match &header {
+        Header::CallId(h) => ("i", h.value()),
+        Header::Contact(h) => ("m", h.value()),
+        Header::ContentEncoding(h) => ("e", h.value()),
+        Header::ContentLength(h) => ("l", h.value()),
+        Header::ContentType(h) => ("c", h.value()),
+        Header::From(h) => ("f", h.value()),
+        Header::Subject(h) => ("s", h.value()),
+        Header::Supported(h) => ("k", h.value()),
+        Header::To(h) => ("t", h.value()),
+        Header::Via(h) => ("v", h.value()),
+        _ => return header,
+    };
+
+    Header::Other(name.to_string(), value.to_string())
+}
+
+/// Replaces compact headers by their full typed form, as rsip only parses full names
+pub fn expand(headers: &mut Headers) {
+    for header in headers.iter_mut() {
+        let Header::Other(name, value) = header else {
+            continue;
+        };
+
+        let value = value.clone();
+        *header = match name.to_ascii_lowercase().as_str() {
+            "i" => Header::CallId(headers::CallId::new(value)),
+            "m" => Header::Contact(headers::Contact::new(value)),
+            "e" => Header::ContentEncoding(headers::ContentEncoding::new(value)),
+            "l" => Header::ContentLength(headers::ContentLength::new(value)),
+            "c" => Header::ContentType(headers::ContentType::new(value)),
+            "f" => Header::From(headers::From::new(value)),
+            "s" => Header::Subject(headers::Subject::new(value)),
+            "k" => Header::Supported(headers::Supported::new(value)),
+            "t" => Header::To(headers::To::new(value)),
+            "v" => Header::Via(headers::Via::new(value)),
+            _ => continue,
+        };
+    }
+}
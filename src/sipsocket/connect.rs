@@ -0,0 +1,101 @@
+use crate::resolver::Resolver;
+use anyhow::{anyhow, bail, Result};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::select;
+use tracing::debug;
+
+/// Time to wait for an attempt before racing the next address (RFC 8305)
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn of(addr: &SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(_) => Self::Ipv4,
+            SocketAddr::V6(_) => Self::Ipv6,
+        }
+    }
+}
+
+/// Orders addresses alternating between families, starting with the
+/// preferred one or the one the resolver returned first
+fn interleave(addrs: Vec<SocketAddr>, prefer: Option<AddressFamily>) -> VecDeque<SocketAddr> {
+    let Some(first) = prefer.or_else(|| addrs.first().map(AddressFamily::of)) else {
+        return VecDeque::new();
+    };
+
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) =
+        addrs.into_iter().partition(|addr| AddressFamily::of(addr) == first);
+
+    let mut ordered = VecDeque::with_capacity(preferred.len() + other.len());
+    while !preferred.is_empty() || !other.is_empty() {
+        ordered.extend(preferred.pop_front());
+        ordered.extend(other.pop_front());
+    }
+
+    ordered
+}
+
+/// Connects to all addresses of a host using Happy Eyeballs (RFC 8305).
+///
+/// Attempts are started one after another, each one as soon as the previous
+/// failed or did not succeed within a short delay. The first established
+/// connection wins.
+pub(super) async fn connect(
+    resolver: &Resolver,
+    host: &str,
+    port: u16,
+    prefer: Option<AddressFamily>,
+) -> Result<TcpStream> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addrs = resolver
+        .lookup(host)
+        .await?
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect::<Vec<_>>();
+    if addrs.is_empty() {
+        bail!("No addresses found for {host}");
+    }
+
+    let mut pending = interleave(addrs, prefer);
+    let mut attempts = FuturesUnordered::new();
+    let mut failures = Vec::new();
+
+    loop {
+        if let Some(addr) = pending.pop_front() {
+            debug!("Connecting to {addr}");
+            attempts.push(async move { (addr, TcpStream::connect(addr).await) });
+        }
+
+        select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(err) => {
+                    debug!("Failed to connect to {addr}: {err}");
+                    failures.push(format!("{addr}: {err}"));
+                }
+            },
+
+            _ = tokio::time::sleep(ATTEMPT_DELAY), if !pending.is_empty() => {}
+
+            else => break,
+        }
+    }
+
+    Err(anyhow!(
+        "Failed to connect to {host}: {failures}",
+        failures = failures.join(", ")
+    ))
+}
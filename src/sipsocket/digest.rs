@@ -0,0 +1,83 @@
+use rsip::headers::auth::{self, Algorithm};
+use rsip::headers::typed::{Authorization, WwwAuthenticate};
+use rsip::services::DigestGenerator;
+use rsip::Method;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Cached nonces older than this are not used, as servers expire them eventually
+const NONCE_LIFETIME: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+struct Challenge {
+    authenticate: WwwAuthenticate,
+    received: Instant,
+}
+
+/// Last digest challenge of the registrar.
+///
+/// Shared by all connections of a client, so re-registrations and
+/// reconnects can authenticate without an unauthenticated round trip.
+#[derive(Debug, Clone, Default)]
+pub struct DigestCache(Arc<Mutex<Option<Challenge>>>);
+
+impl DigestCache {
+    /// The cached challenge, unless its nonce is likely to be expired
+    pub fn get(&self) -> Option<WwwAuthenticate> {
+        let challenge = self.0.lock().expect("not poisoned");
+        challenge
+            .as_ref()
+            .filter(|challenge| challenge.received.elapsed() < NONCE_LIFETIME)
+            .map(|challenge| challenge.authenticate.clone())
+    }
+
+    pub fn set(&self, authenticate: WwwAuthenticate) {
+        *self.0.lock().expect("not poisoned") = Some(Challenge {
+            authenticate,
+            received: Instant::now(),
+        });
+    }
+
+    pub fn clear(&self) {
+        *self.0.lock().expect("not poisoned") = None;
+    }
+}
+
+/// Answers a challenge for a REGISTER request
+pub(super) fn authorization(
+    authenticate: &WwwAuthenticate,
+    username: &str,
+    password: &str,
+) -> Authorization {
+    let response = DigestGenerator {
+        username,
+        password,
+        nonce: authenticate.nonce.as_str(),
+        uri: &Default::default(),
+        realm: authenticate.realm.as_str(),
+        method: &Method::Register,
+        qop: None,
+        algorithm: authenticate.algorithm.unwrap_or(Algorithm::Md5),
+    }
+    .compute();
+
+    Authorization {
+        scheme: auth::Scheme::Digest,
+        username: username.to_string(),
+        realm: authenticate.realm.clone(),
+        nonce: authenticate.nonce.clone(),
+        uri: Default::default(),
+        response,
+        algorithm: authenticate.algorithm,
+        opaque: authenticate.opaque.clone(),
+        qop: None,
+    }
+}
+
+/// Whether the server rejected the nonce only because it expired
+pub(super) fn is_stale(authenticate: &WwwAuthenticate) -> bool {
+    authenticate
+        .stale
+        .as_deref()
+        .is_some_and(|stale| stale.eq_ignore_ascii_case("true"))
+}
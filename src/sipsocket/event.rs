@@ -0,0 +1,25 @@
+use serde::Serialize;
+
+/// Lifecycle changes of a SIP socket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ConnectionEvent {
+    /// The WebSocket has been established
+    Connected { url: String },
+
+    /// The server accepted the registration
+    Registered { username: String },
+
+    /// Registering failed, incoming calls are no longer delivered
+    RegistrationLost { reason: String },
+
+    /// The server redirected a request, which was followed unless vetoed
+    Redirected {
+        method: String,
+        target: String,
+        followed: bool,
+    },
+
+    /// The socket has been closed, by the server or due to an error
+    Closed { reason: String },
+}
@@ -0,0 +1,439 @@
+use rsip::headers::UntypedHeader;
+use rsip::{Header, Headers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Values of all headers with the given name which rsip does not know about
+pub fn other<'a>(headers: &'a Headers, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    headers.iter().filter_map(move |header| match header {
+        Header::Other(key, value) if key.eq_ignore_ascii_case(name) => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+/// Texts of all Warning headers
+pub fn warnings(headers: &Headers) -> impl Iterator<Item = &str> {
+    headers.iter().filter_map(|header| match header {
+        Header::Warning(warning) => Some(warning.value()),
+        _ => None,
+    })
+}
+
+/// Package of the Event header, without parameters
+pub fn event(headers: &Headers) -> Option<&str> {
+    headers.iter().find_map(|header| match header {
+        Header::Event(event) => event.value().split(';').next().map(str::trim),
+        _ => None,
+    })
+}
+
+/// Whether a Privacy header (RFC 3323) asks to withhold the caller identity
+pub fn withheld(headers: &Headers) -> bool {
+    other(headers, "Privacy")
+        .flat_map(|value| value.split([';', ',']))
+        .map(|value| value.trim().to_ascii_lowercase())
+        .any(|value| matches!(value.as_str(), "id" | "user" | "header"))
+}
+
+/// Addresses of all hops in the Via headers, including `received` parameters.
+///
+/// Hops given by host name are skipped.
+pub fn via_addresses(headers: &Headers) -> Vec<IpAddr> {
+    headers
+        .iter()
+        .filter_map(|header| match header {
+            Header::Via(via) => Some(via.value()),
+            _ => None,
+        })
+        .flat_map(|value| value.split(','))
+        .flat_map(|via| {
+            let mut parts = via.split(';');
+            let sent_by = parts
+                .next()
+                .and_then(|protocol| protocol.split_whitespace().nth(1))
+                .map(|sent_by| match sent_by.strip_prefix('[') {
+                    Some(v6) => v6.split(']').next().unwrap_or_default(),
+                    None => sent_by.split(':').next().unwrap_or_default(),
+                });
+            let received = parts.filter_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                key.trim().eq_ignore_ascii_case("received").then_some(value.trim())
+            });
+            sent_by.into_iter().chain(received).collect::<Vec<_>>()
+        })
+        .filter_map(|addr| addr.parse().ok())
+        .collect()
+}
+
+/// A parsed Reason header (RFC 3326)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reason {
+    /// Protocol of the cause code, usually `SIP` or `Q.850`
+    pub protocol: String,
+    pub cause: Option<u16>,
+    pub text: Option<String>,
+}
+
+impl Reason {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(';');
+
+        let protocol = parts.next()?.trim();
+        if protocol.is_empty() {
+            return None;
+        }
+
+        let mut cause = None;
+        let mut text = None;
+
+        for param in parts {
+            let Some((key, value)) = param.split_once('=') else {
+                continue;
+            };
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "cause" => cause = value.trim().parse().ok(),
+                "text" => text = Some(value.trim().trim_matches('"').to_string()),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            protocol: protocol.to_string(),
+            cause,
+            text,
+        })
+    }
+
+    /// All Reason headers of a message
+    pub fn from_headers(headers: &Headers) -> Vec<Self> {
+        other(headers, "Reason")
+            .flat_map(|value| value.split(','))
+            .filter_map(Self::parse)
+            .collect()
+    }
+
+    /// Whether the call was picked up by another device
+    pub fn answered_elsewhere(&self) -> bool {
+        self.protocol.eq_ignore_ascii_case("SIP") && self.cause == Some(200)
+    }
+
+    /// Short human readable description
+    pub fn description(&self) -> String {
+        let known = match (self.protocol.to_ascii_uppercase().as_str(), self.cause) {
+            ("SIP", Some(200)) => Some("answered elsewhere"),
+            ("SIP", Some(480)) => Some("not available"),
+            ("SIP", Some(486)) => Some("busy"),
+            ("SIP", Some(487)) => Some("cancelled by caller"),
+            ("SIP", Some(603)) => Some("declined"),
+            ("Q.850", Some(16)) => Some("normal call clearing"),
+            ("Q.850", Some(17)) => Some("busy"),
+            ("Q.850", Some(19)) => Some("no answer"),
+            ("Q.850", Some(21)) => Some("call rejected"),
+            ("Q.850", Some(31)) => Some("normal, unspecified"),
+            _ => None,
+        };
+
+        match (known, &self.text, self.cause) {
+            (Some(known), _, _) => known.to_string(),
+            (None, Some(text), _) => text.clone(),
+            (None, None, Some(cause)) => format!("{protocol} cause {cause}", protocol = self.protocol),
+            (None, None, None) => self.protocol.clone(),
+        }
+    }
+}
+
+/// Reference to a dialog as carried by Replaces (RFC 3891) and Join
+/// (RFC 3911) headers.
+///
+/// Tags are seen from the receiver of the header: `to_tag` is the local tag
+/// and `from_tag` the remote one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DialogRef {
+    pub call_id: String,
+    pub to_tag: Option<String>,
+    pub from_tag: Option<String>,
+
+    /// Only replace the dialog if it has not been answered yet
+    pub early_only: bool,
+}
+
+impl DialogRef {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(';');
+
+        let call_id = parts.next()?.trim();
+        if call_id.is_empty() {
+            return None;
+        }
+
+        let mut dialog = Self {
+            call_id: call_id.to_string(),
+            to_tag: None,
+            from_tag: None,
+            early_only: false,
+        };
+
+        for param in parts {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key.trim().to_ascii_lowercase().as_str() {
+                "to-tag" => dialog.to_tag = Some(value.trim().to_string()),
+                "from-tag" => dialog.from_tag = Some(value.trim().to_string()),
+                "early-only" => dialog.early_only = true,
+                _ => {}
+            }
+        }
+
+        Some(dialog)
+    }
+
+    /// The dialog referenced by a Replaces header of the message
+    pub fn replaces(headers: &Headers) -> Option<Self> {
+        other(headers, "Replaces").next().and_then(Self::parse)
+    }
+
+    /// The dialog referenced by a Join header of the message
+    pub fn join(headers: &Headers) -> Option<Self> {
+        other(headers, "Join").next().and_then(Self::parse)
+    }
+
+    /// A Replaces header, e.g. for the INVITE of an attended transfer
+    pub fn to_replaces(&self) -> Header {
+        Header::Other("Replaces".into(), self.to_string())
+    }
+
+    pub fn to_join(&self) -> Header {
+        Header::Other("Join".into(), self.to_string())
+    }
+}
+
+impl fmt::Display for DialogRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.call_id)?;
+        if let Some(to_tag) = &self.to_tag {
+            write!(f, ";to-tag={to_tag}")?;
+        }
+        if let Some(from_tag) = &self.from_tag {
+            write!(f, ";from-tag={from_tag}")?;
+        }
+        if self.early_only {
+            f.write_str(";early-only")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where an incoming call was forwarded from.
+///
+/// Taken from Diversion (RFC 5806) or History-Info (RFC 7044) headers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redirection {
+    /// The originally called URI
+    pub uri: String,
+
+    /// User part of the originally called URI
+    pub number: Option<String>,
+
+    /// Why the call was forwarded, e.g. `no-answer`
+    pub reason: Option<String>,
+}
+
+/// Splits a header value into its `<uri>;params` entries
+fn entries(value: &str) -> impl Iterator<Item = (&str, &str)> {
+    value.split(',').filter_map(|entry| {
+        let start = entry.find('<')?;
+        let end = start + entry[start..].find('>')?;
+        Some((&entry[start + 1..end], &entry[end + 1..]))
+    })
+}
+
+fn param<'a>(params: &'a str, name: &str) -> Option<&'a str> {
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+fn user(uri: &str) -> Option<String> {
+    let uri = uri.split_once(':').map_or(uri, |(_, rest)| rest);
+    let user = uri.split(['@', ';', '?']).next()?;
+    (!user.is_empty()).then(|| user.to_string())
+}
+
+/// Maps a redirection cause code (RFC 4458) to the Diversion reason names
+fn cause_reason(cause: u16) -> Option<&'static str> {
+    Some(match cause {
+        302 => "unconditional",
+        404 => "unknown",
+        408 => "no-answer",
+        480 => "unavailable",
+        486 => "user-busy",
+        487 => "deflection",
+        503 => "out-of-service",
+        _ => return None,
+    })
+}
+
+impl Redirection {
+    pub fn from_headers(headers: &Headers) -> Option<Self> {
+        Self::from_diversion(headers).or_else(|| Self::from_history_info(headers))
+    }
+
+    fn from_diversion(headers: &Headers) -> Option<Self> {
+        // The last entry is the first diversion, i.e. the original target
+        let (uri, params) = other(headers, "Diversion").flat_map(entries).last()?;
+
+        Some(Self {
+            uri: uri.to_string(),
+            number: user(uri),
+            reason: param(params, "reason").map(ToString::to_string),
+        })
+    }
+
+    fn from_history_info(headers: &Headers) -> Option<Self> {
+        let entries = other(headers, "History-Info")
+            .flat_map(entries)
+            .collect::<Vec<_>>();
+
+        // Nothing was forwarded if the history has only the original target
+        let ((original, _), retargets) = entries.split_first()?;
+        if retargets.is_empty() {
+            return None;
+        }
+
+        let (uri, headers) = original.split_once('?').unwrap_or((original, ""));
+
+        let reason = retargets
+            .iter()
+            .find_map(|(_, params)| param(params, "cause")?.parse().ok())
+            .or_else(|| {
+                url::form_urlencoded::parse(headers.as_bytes())
+                    .find(|(key, _)| key.eq_ignore_ascii_case("Reason"))
+                    .and_then(|(_, value)| Reason::parse(&value)?.cause)
+            })
+            .and_then(cause_reason);
+
+        Some(Self {
+            uri: uri.to_string(),
+            number: user(uri),
+            reason: reason.map(ToString::to_string),
+        })
+    }
+
+    /// Human readable summary, e.g. "forwarded from 500 - no answer"
+    pub fn description(&self) -> String {
+        let from = self.number.as_deref().unwrap_or(&self.uri);
+        match &self.reason {
+            Some(reason) => format!("forwarded from {from} - {}", reason.replace('-', " ")),
+            None => format!("forwarded from {from}"),
+        }
+    }
+}
+
+/// A `"Display Name" <uri>;params` value as used by identity headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameAddr {
+    pub display_name: Option<String>,
+    pub uri: String,
+}
+
+impl NameAddr {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+
+        let Some((name, rest)) = value.split_once('<') else {
+            let uri = value.split(';').next()?.trim();
+            return (!uri.is_empty()).then(|| Self {
+                display_name: None,
+                uri: uri.to_string(),
+            });
+        };
+
+        let uri = rest.split_once('>')?.0.trim();
+        let name = name.trim().trim_matches('"').trim();
+
+        Some(Self {
+            display_name: (!name.is_empty()).then(|| name.to_string()),
+            uri: uri.to_string(),
+        })
+    }
+
+    /// User part of the URI, usually the number
+    pub fn user(&self) -> Option<String> {
+        user(&self.uri)
+    }
+
+    /// Whether the URI is a placeholder for a withheld identity (RFC 3323)
+    pub fn is_anonymous(&self) -> bool {
+        let host = self.uri.split_once('@').and_then(|(_, rest)| rest.split([':', ';', '?']).next());
+
+        host.is_some_and(|host| host.eq_ignore_ascii_case("anonymous.invalid"))
+            || self.user().is_some_and(|user| user.eq_ignore_ascii_case("anonymous"))
+    }
+
+    /// The first P-Asserted-Identity (RFC 3325)
+    pub fn p_asserted_identity(headers: &Headers) -> Option<Self> {
+        other(headers, "P-Asserted-Identity")
+            .flat_map(|value| value.split(','))
+            .find_map(Self::parse)
+    }
+
+    /// The calling party of a Remote-Party-ID header
+    pub fn remote_party_id(headers: &Headers) -> Option<Self> {
+        other(headers, "Remote-Party-ID")
+            .flat_map(|value| value.split(','))
+            .find(|value| {
+                param(value, "party").is_none_or(|party| party.eq_ignore_ascii_case("calling"))
+            })
+            .and_then(Self::parse)
+    }
+}
+
+/// Proprietary `X-UCware-*` headers the server attaches to requests
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UcwareHeaders {
+    /// All headers by their lower case name without the prefix, e.g. `queue`
+    pub values: BTreeMap<String, String>,
+}
+
+impl UcwareHeaders {
+    const PREFIX: &'static str = "x-ucware-";
+
+    pub fn from_headers(headers: &Headers) -> Self {
+        let values = headers
+            .iter()
+            .filter_map(|header| match header {
+                Header::Other(key, value) => {
+                    let key = key.to_ascii_lowercase();
+                    let name = key.strip_prefix(Self::PREFIX)?;
+                    Some((name.to_string(), value.trim().to_string()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Self { values }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// Server side identifier of the call, stable across forks and transfers
+    pub fn call_id(&self) -> Option<&str> {
+        self.get("call-id")
+    }
+
+    /// Name of the queue which distributed the call
+    pub fn queue(&self) -> Option<&str> {
+        self.get("queue")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
@@ -0,0 +1,186 @@
+use rand::distr::{Alphanumeric, SampleString};
+use rsip::HostWithPort;
+
+/// Alphanumeric characters needed for 128 bits of entropy
+const RANDOM_LEN: usize = 22;
+
+/// Prefix of branches compliant to RFC 3261, section 8.1.1.7
+const BRANCH_COOKIE: &str = "z9hG4bK";
+
+/// Source of the identifiers put into messages
+pub trait SipIdGenerator: Send + Sync {
+    /// A globally unique Call-ID for a dialog started by the given host
+    fn call_id(&self, host: &HostWithPort) -> String;
+
+    /// Branch of the Via header, unique per transaction
+    fn branch(&self) -> String;
+
+    /// Tag of the From or To header, unique per dialog
+    fn tag(&self) -> String;
+
+    /// First CSeq number of requests within a dialog
+    fn cseq(&self) -> u32;
+
+    /// User part of the contact address registered for a socket
+    fn contact_user(&self) -> String;
+
+    /// Host part of the contact address, which is never resolved as the server answers on the socket
+    fn contact_host(&self) -> String;
+
+    /// Instance ID telling the registrar which bindings belong to the same client, see RFC 5626
+    fn instance(&self) -> String;
+}
+
+/// Identity of the registered contact, kept across restarts so the registrar
+/// replaces the binding of an earlier run instead of adding another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactId {
+    /// User part of the contact address
+    pub user: String,
+
+    /// Host part of the contact address, also sent as Via sent-by
+    pub host: String,
+
+    /// Value of the `+sip.instance` parameter, like `urn:uuid:...`
+    pub instance: String,
+}
+
+impl ContactId {
+    pub fn generate(ids: &dyn SipIdGenerator) -> Self {
+        Self {
+            user: ids.contact_user(),
+            host: ids.contact_host(),
+            instance: ids.instance(),
+        }
+    }
+}
+
+/// Identifiers from the thread's cryptographically secure generator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomIds;
+
+impl SipIdGenerator for RandomIds {
+    /// Call-IDs like `<random>@<host>` as recommended by RFC 3261, section 8.1.1.4
+    fn call_id(&self, host: &HostWithPort) -> String {
+        let random = Alphanumeric.sample_string(&mut rand::rng(), RANDOM_LEN);
+        format!("{random}@{host}", host = host.host)
+    }
+
+    fn branch(&self) -> String {
+        let random = Alphanumeric.sample_string(&mut rand::rng(), 16);
+        format!("{BRANCH_COOKIE}{random}")
+    }
+
+    fn tag(&self) -> String {
+        Alphanumeric.sample_string(&mut rand::rng(), 10)
+    }
+
+    /// Random, but leaving plenty of room to count up before the limit of 2^31
+    fn cseq(&self) -> u32 {
+        rand::random::<u16>() as u32
+    }
+
+    fn contact_user(&self) -> String {
+        Alphanumeric.sample_string(&mut rand::rng(), 16)
+    }
+
+    /// Random name in the reserved `.invalid` domain, see RFC 7118, section 5
+    fn contact_host(&self) -> String {
+        let random = Alphanumeric.sample_string(&mut rand::rng(), 16);
+        format!("{random}.invalid")
+    }
+
+    /// Random version 4 UUID
+    fn instance(&self) -> String {
+        let mut bytes = rand::random::<[u8; 16]>();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+        format!(
+            "urn:uuid:{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32],
+        )
+    }
+}
+
+#[cfg(feature = "deterministic-ids")]
+pub use seeded::SeededIds;
+
+#[cfg(feature = "deterministic-ids")]
+mod seeded {
+    use super::{SipIdGenerator, BRANCH_COOKIE};
+    use rsip::HostWithPort;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Predictable identifiers, so tests can assert exact message contents.
+    ///
+    /// Each identifier is derived from the seed and a counter shared by all
+    /// kinds, so the same sequence of calls yields the same identifiers.
+    #[derive(Debug, Default)]
+    pub struct SeededIds {
+        seed: u64,
+        next: AtomicU64,
+    }
+
+    impl SeededIds {
+        pub fn new(seed: u64) -> Self {
+            Self {
+                seed,
+                next: AtomicU64::new(0),
+            }
+        }
+
+        fn count(&self) -> u64 {
+            self.next.fetch_add(1, Ordering::Relaxed)
+        }
+
+        fn next(&self) -> String {
+            format!("{seed:x}{n:06}", seed = self.seed, n = self.count())
+        }
+    }
+
+    impl SipIdGenerator for SeededIds {
+        fn call_id(&self, host: &HostWithPort) -> String {
+            format!("call{id}@{host}", id = self.next(), host = host.host)
+        }
+
+        fn branch(&self) -> String {
+            format!("{BRANCH_COOKIE}{id}", id = self.next())
+        }
+
+        fn tag(&self) -> String {
+            format!("tag{id}", id = self.next())
+        }
+
+        fn cseq(&self) -> u32 {
+            self.count() as u32 + 1
+        }
+
+        fn contact_user(&self) -> String {
+            format!("contact{id}", id = self.next())
+        }
+
+        fn contact_host(&self) -> String {
+            format!("host{id}.invalid", id = self.next())
+        }
+
+        /// The seed fills the groups before the last one around the version and variant,
+        /// the counter the last group of 48 bits, so neither overwrites the other
+        fn instance(&self) -> String {
+            let seed = self.seed;
+            format!(
+                "urn:uuid:{:08x}-{:04x}-4{:03x}-8{:03x}-{:012x}",
+                seed >> 32,
+                (seed >> 16) & 0xffff,
+                (seed >> 4) & 0xfff,
+                seed & 0xf,
+                self.count() & 0xffff_ffff_ffff,
+            )
+        }
+    }
+}
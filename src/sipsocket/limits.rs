@@ -0,0 +1,202 @@
+use bytes::Bytes;
+use rsip::headers::UntypedHeader;
+use rsip::message::HeadersExt;
+use rsip::{Method, SipMessage, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Protection against peers flooding the socket
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Larger requests are rejected with 513, larger responses dropped
+    pub max_message_size: usize,
+
+    /// Further requests of the same call within a second are rejected with 503
+    pub max_requests_per_second: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_message_size: 64 * 1024,
+            max_requests_per_second: 10,
+        }
+    }
+}
+
+/// Counters of received messages, shared by all connections of a client
+#[derive(Debug, Default)]
+pub struct Metrics {
+    received: AtomicU64,
+    oversized: AtomicU64,
+    flooded: AtomicU64,
+    unparsable: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub received: u64,
+
+    /// Messages rejected or dropped for exceeding the size limit
+    pub oversized: u64,
+
+    /// Requests rejected for exceeding the rate limit
+    pub flooded: u64,
+
+    /// Messages ignored as they could not be parsed
+    #[serde(default)]
+    pub unparsable: u64,
+}
+
+impl Metrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            received: self.received.load(Ordering::Relaxed),
+            oversized: self.oversized.load(Ordering::Relaxed),
+            flooded: self.flooded.load(Ordering::Relaxed),
+            unparsable: self.unparsable.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(super) fn received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn oversized(&self) {
+        self.oversized.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn flooded(&self) {
+        self.flooded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn unparsable(&self) {
+        self.unparsable.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts requests per Call-ID in fixed windows of one second
+struct RateLimiter {
+    limit: u32,
+    window: Instant,
+    counts: HashMap<String, u32>,
+}
+
+impl RateLimiter {
+    fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            window: Instant::now(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Counts a request and tells whether it is within the limit
+    fn allow(&mut self, call_id: &str) -> bool {
+        if self.window.elapsed() >= Duration::from_secs(1) {
+            self.window = Instant::now();
+            self.counts.clear();
+        }
+
+        let count = self.counts.entry(call_id.to_string()).or_default();
+        *count += 1;
+        *count <= self.limit
+    }
+}
+
+/// What to decode of a received frame
+pub(super) enum Frame {
+    /// The whole frame is within the limit
+    Whole(Bytes),
+
+    /// Only the head of an oversized request, enough to reject it
+    Head(Bytes),
+
+    /// An oversized frame not worth parsing at all
+    Drop,
+}
+
+/// What to do with a received message
+pub(super) enum Verdict {
+    Accept,
+    Reject(StatusCode),
+    Drop,
+}
+
+/// Applies the limits to received messages and counts them
+pub(super) struct Guard {
+    limits: Limits,
+    metrics: Arc<Metrics>,
+    rate: RateLimiter,
+}
+
+impl Guard {
+    pub fn new(limits: Limits, metrics: Arc<Metrics>) -> Self {
+        Self {
+            limits,
+            metrics,
+            rate: RateLimiter::new(limits.max_requests_per_second),
+        }
+    }
+
+    /// Checks the size of a frame before anything of it is parsed.
+    ///
+    /// Oversized responses are dropped right away. Of oversized requests only
+    /// the head is passed on, unless the head alone exceeds the limit.
+    pub fn check_frame(&mut self, frame: Bytes) -> Frame {
+        self.metrics.received();
+
+        let max = self.limits.max_message_size;
+        if frame.len() <= max {
+            return Frame::Whole(frame);
+        }
+
+        self.metrics.oversized();
+
+        if frame.starts_with(b"SIP/") {
+            return Frame::Drop;
+        }
+
+        match frame[..max].windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(end) => Frame::Head(frame.slice(..end + 4)),
+            None => Frame::Drop,
+        }
+    }
+
+    /// Counts a frame which could not be parsed
+    pub fn unparsable(&mut self) {
+        self.metrics.unparsable();
+    }
+
+    /// Checks a parsed message, `oversized` if only its head was parsed
+    pub fn check(&mut self, oversized: bool, message: &SipMessage) -> Verdict {
+        let request = match message {
+            SipMessage::Request(request) => request,
+            SipMessage::Response(_) => return Verdict::Accept,
+        };
+
+        // ACKs are never answered
+        let reject = |status| match request.method {
+            Method::Ack => Verdict::Drop,
+            _ => Verdict::Reject(status),
+        };
+
+        if oversized {
+            return reject(StatusCode::MessageTooLarge);
+        }
+
+        let call_id = request
+            .call_id_header()
+            .map(|call_id| call_id.value())
+            .unwrap_or_default();
+        if !self.rate.allow(call_id) {
+            self.metrics.flooded();
+            return reject(StatusCode::ServiceUnavailable);
+        }
+
+        Verdict::Accept
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Event package of message waiting indications (RFC 3842)
+pub const EVENT: &str = "message-summary";
+
+/// Content type of message summary bodies
+pub const CONTENT_TYPE: &str = "application/simple-message-summary";
+
+/// Voice message counts of a `message-summary` NOTIFY
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageSummary {
+    pub waiting: bool,
+    pub new: u32,
+    pub old: u32,
+    pub urgent_new: u32,
+    pub urgent_old: u32,
+}
+
+impl MessageSummary {
+    /// Parses a `simple-message-summary` body, ignoring message classes other than voice
+    pub fn parse(body: &str) -> Option<Self> {
+        let mut summary = Self::default();
+        let mut valid = false;
+
+        for line in body.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            match key.trim().to_ascii_lowercase().as_str() {
+                "messages-waiting" => {
+                    summary.waiting = value.trim().eq_ignore_ascii_case("yes");
+                    valid = true;
+                }
+
+                // Format: new/old (urgent-new/urgent-old)
+                "voice-message" => {
+                    let (counts, urgent) = match value.split_once('(') {
+                        Some((counts, urgent)) => {
+                            (counts, Some(urgent.trim_end().trim_end_matches(')')))
+                        }
+                        None => (value, None),
+                    };
+
+                    (summary.new, summary.old) = counts_of(counts)?;
+                    if let Some(urgent) = urgent {
+                        (summary.urgent_new, summary.urgent_old) = counts_of(urgent)?;
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        valid.then_some(summary)
+    }
+}
+
+/// Adds up the voice messages of several boxes, waiting if any of them is
+impl std::iter::Sum for MessageSummary {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, summary| Self {
+            waiting: total.waiting || summary.waiting,
+            new: total.new + summary.new,
+            old: total.old + summary.old,
+            urgent_new: total.urgent_new + summary.urgent_new,
+            urgent_old: total.urgent_old + summary.urgent_old,
+        })
+    }
+}
+
+fn counts_of(value: &str) -> Option<(u32, u32)> {
+    let (new, old) = value.split_once('/')?;
+    Some((new.trim().parse().ok()?, old.trim().parse().ok()?))
+}
@@ -0,0 +1,36 @@
+use rsip::SipMessage;
+use std::sync::Arc;
+
+/// Hook into every message passing a connection, e.g. to rewrite headers or inject faults.
+///
+/// Returning `None` drops the message.
+pub trait Middleware: Send + Sync {
+    /// Called for every request and response before it is sent
+    fn outgoing(&self, message: SipMessage) -> Option<SipMessage> {
+        Some(message)
+    }
+
+    /// Called for every received message before it is dispatched
+    fn incoming(&self, message: SipMessage) -> Option<SipMessage> {
+        Some(message)
+    }
+}
+
+/// Middlewares wrapping the socket like layers: outgoing messages pass them
+/// in order, incoming messages in reverse order
+#[derive(Clone, Default)]
+pub(super) struct Chain(Arc<Vec<Arc<dyn Middleware>>>);
+
+impl Chain {
+    pub fn new(middlewares: Vec<Arc<dyn Middleware>>) -> Self {
+        Self(Arc::new(middlewares))
+    }
+
+    pub fn outgoing(&self, message: SipMessage) -> Option<SipMessage> {
+        self.0.iter().try_fold(message, |message, middleware| middleware.outgoing(message))
+    }
+
+    pub fn incoming(&self, message: SipMessage) -> Option<SipMessage> {
+        self.0.iter().rev().try_fold(message, |message, middleware| middleware.incoming(message))
+    }
+}
@@ -1,25 +1,66 @@
-use anyhow::{anyhow, bail, Result};
+use crate::config::DnsConfig;
+use crate::resolver::Resolver;
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
 use dashmap::DashMap;
 use futures::{Sink, SinkExt, Stream, StreamExt, TryStreamExt};
-use rand::distr::{Alphanumeric, SampleString};
-use rsip::headers::auth::Algorithm;
-use rsip::headers::{auth, CallId, ToTypedHeader, UntypedHeader};
+use rsip::headers::typed::{Authorization, WwwAuthenticate};
+use rsip::headers::{CallId, ToTypedHeader, UntypedHeader, UserAgent};
 use rsip::message::HeadersExt;
-use rsip::services::DigestGenerator;
 use rsip::{
     Auth, Header, Headers, Host, HostWithPort, Method, Param, Request, Response, Scheme,
     SipMessage, StatusCode, StatusCodeKind, Transport, Uri, Version,
 };
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 use tokio::select;
-use tokio::sync::mpsc;
-use tracing::{info, trace, warn};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, trace, warn};
 use url::Url;
 
 use tungstenite::client::IntoClientRequest;
+use tungstenite::protocol::WebSocketConfig;
 use tungstenite::Message;
 
+mod answered;
+mod bindings;
+pub mod codec;
+pub mod compact;
+mod connect;
+mod digest;
+mod event;
+mod limits;
+pub mod headers;
+pub mod ids;
+pub mod message_summary;
+mod middleware;
+pub mod redirect;
+mod retry;
+pub mod outbound;
+mod timers;
+mod trace;
+
+pub use answered::AnsweredDialog;
+pub use connect::AddressFamily;
+pub use digest::DigestCache;
+pub use event::ConnectionEvent;
+pub use limits::{Limits, Metrics, MetricsSnapshot};
+use limits::{Frame, Guard, Verdict};
+pub use middleware::Middleware;
+pub use bindings::Binding;
+pub use retry::RetryAfter;
+pub use timers::SipTimers;
+pub use trace::{Trace, TraceDirection, TracedMessage};
+use answered::DialogContext;
+use bindings::Registration;
+use ids::{ContactId, RandomIds, SipIdGenerator};
+use middleware::Chain;
+use outbound::{Outbound, Priority};
+use redirect::Redirects;
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 struct TransactionKey {
     method: String,
@@ -79,31 +120,49 @@ impl TransactionKey {
 /// A transaction as seen from the server (the participant receiving the request)
 pub struct ServerTransaction {
     pub request: Request,
-    responses: mpsc::Sender<Response>,
+    responses: mpsc::UnboundedSender<Response>,
+    defaults: Arc<Vec<Header>>,
+    context: DialogContext,
 }
 
 impl ServerTransaction {
+    /// Sequence number of the request
+    pub fn seq(&self) -> Result<u32> {
+        Ok(self.request.cseq_header()?.seq()?)
+    }
+
+    /// Answers a request which could not be made sense of with 400
+    pub async fn reject_malformed(&mut self, err: anyhow::Error) {
+        warn!("Rejecting malformed {method} request: {err:#}", method = self.request.method);
+        trace!("Malformed request: {request:?}", request = self.request);
+        self.respond(StatusCode::BadRequest).send([]).await;
+    }
+
+    /// Proprietary headers of the UCware server on the request
+    pub fn ucware_headers(&self) -> headers::UcwareHeaders {
+        headers::UcwareHeaders::from_headers(&self.request.headers)
+    }
+
     pub fn respond(&mut self, status_code: StatusCode) -> ResponseBuilder<'_> {
-        let headers = self.request.headers.iter()
-            .filter(|&header| matches!(header, Header::Via(_) | Header::From(_) | Header::To(_) | Header::CSeq(_) | Header::CallId(_)))
-            .cloned()
-            .collect::<Vec<_>>();
+        let headers = response_headers(&self.request, &self.defaults);
 
-        let builder = ResponseBuilder {
+        ResponseBuilder {
             tx: self,
             status_code,
             headers: headers.into(),
-        };
-
-        let builder = builder.header(rsip::headers::UserAgent::new(format!(
-            "ucware-cli/{version}",
-            version = env!("CARGO_PKG_VERSION")
-        )));
-
-        builder
+        }
     }
 }
 
+/// Headers of a response to the request: those identifying the transaction followed by the defaults
+fn response_headers(request: &Request, defaults: &[Header]) -> Vec<Header> {
+    request.headers.iter()
+        .filter(|&header| matches!(header, Header::Via(_) | Header::From(_) | Header::To(_) | Header::CSeq(_) | Header::CallId(_)))
+        .chain(defaults)
+        .cloned()
+        .collect()
+}
+
 pub struct ResponseBuilder<'tx> {
     tx: &'tx ServerTransaction,
     status_code: StatusCode,
@@ -124,32 +183,43 @@ impl<'tx> ResponseBuilder<'tx> {
             body: body.into(),
         };
 
-        self.tx.responses.send(response).await.expect("responses receiver closed");
+        self.tx.responses.send(response).expect("responses receiver closed");
     }
 }
 
 /// A transaction as seen from the client (the participant sending the request)
 pub struct ClientTransaction {
-    request: Request,
+    key: TransactionKey,
 
     responses: mpsc::Receiver<Response>,
 
+    /// Time to wait for the final response, Timer B or F
+    timeout: Duration,
+
     transactions: Weak<DashMap<TransactionKey, mpsc::Sender<Response>>>,
 }
 
 impl ClientTransaction {
     pub async fn receive(mut self) -> Result<Response> {
-        loop {
-            let Some(response) = self.responses.recv().await else {
-                bail!("Transaction closed without response");
-            };
+        let timeout = self.timeout;
+        let method = self.key.method.clone();
+        let final_response = async {
+            loop {
+                let Some(response) = self.responses.recv().await else {
+                    bail!("Transaction closed without response");
+                };
+
+                if response.status_code.kind() == StatusCodeKind::Provisional {
+                    continue;
+                }
 
-            if response.status_code.kind() == StatusCodeKind::Provisional {
-                continue;
+                return Ok(response);
             }
+        };
 
-            return Ok(response);
-        }
+        tokio::time::timeout(timeout, final_response)
+            .await
+            .with_context(|| format!("Timed out waiting {timeout:?} for final response to {method}"))?
     }
 }
 
@@ -159,53 +229,258 @@ impl Drop for ClientTransaction {
             return;
         };
 
-        transactions.remove(&TransactionKey::from_request(&self.request));
+        transactions.remove(&self.key);
     }
 }
 
+/// Why the loop of a connection ended without failing
+enum Ended {
+    ByServer,
+
+    /// The [`Connection`] was dropped, so nothing can be sent anymore
+    Dropped,
+}
+
+/// A SIP socket, closed once dropped
 pub struct Connection {
     url: Url,
 
+    /// SIP domain of the user and registrar, independent of the socket host failed over to
+    domain: Host,
+
     user: Uri,
     send_by: HostWithPort,
 
     sender: mpsc::Sender<Request>,
 
+    /// Loop running the socket, ending once the sender is dropped
+    task: JoinHandle<()>,
+
     transactions: Arc<DashMap<TransactionKey, mpsc::Sender<Response>>>,
+
+    events: broadcast::Sender<ConnectionEvent>,
+
+    /// Headers added to every outgoing request and response
+    defaults: Arc<Vec<Header>>,
+
+    /// Identity of the contact address registered for this socket
+    contact: ContactId,
+
+    digest: DigestCache,
+
+    ids: Arc<dyn SipIdGenerator>,
+
+    redirects: Redirects,
+
+    timers: SipTimers,
+
+    /// Interval granted by the registrar for the last registration
+    registered_for: Option<Duration>,
 }
 
-impl Connection {
-    pub async fn connect(
-        url: Url,
-        username: &str,
-    ) -> Result<(Self, mpsc::Receiver<ServerTransaction>)> {
-        info!("Connecting to: {url}");
+/// Seconds a registration is valid for unless asked otherwise
+const REGISTRATION_EXPIRES: u32 = 6000;
 
-        let mut request = url.clone().into_client_request()?;
+/// Number of events buffered for slow subscribers
+const CONNECTION_EVENTS: usize = 16;
+
+/// Time given to write what is queued and close the WebSocket before aborting
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Options of a SIP socket to be connected
+pub struct ConnectionBuilder {
+    url: Url,
+    username: String,
+    user_agent: String,
+    headers: Vec<Header>,
+    events: Option<broadcast::Sender<ConnectionEvent>>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    digest: DigestCache,
+    prefer: Option<AddressFamily>,
+    resolver: Option<Resolver>,
+    limits: Limits,
+    metrics: Arc<Metrics>,
+    trace: Arc<Trace>,
+    domain: Option<Host>,
+    ids: Arc<dyn SipIdGenerator>,
+    contact: Option<ContactId>,
+    redirects: Redirects,
+    timers: SipTimers,
+}
+
+impl ConnectionBuilder {
+    /// Replaces the default `ucware-cli/<version>` User-Agent
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Adds a header to every outgoing request and response
+    pub fn header(mut self, header: impl Into<Header>) -> Self {
+        self.headers.push(header.into());
+        self
+    }
+
+    /// Adds a middleware seeing every message after those added before
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Reports the lifecycle to the given event channel
+    pub fn events(mut self, events: broadcast::Sender<ConnectionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Tries addresses of this family first instead of following the resolver's order
+    pub fn prefer(mut self, family: Option<AddressFamily>) -> Self {
+        self.prefer = family;
+        self
+    }
+
+    /// Resolves the host with the given resolver instead of a new one using the system config
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Limits received messages to protect against misbehaving peers
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Counts received messages in the given metrics, e.g. those shared with earlier connections
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Records sent and received messages in the given trace, e.g. one shared with earlier connections
+    pub fn trace(mut self, trace: Arc<Trace>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// SIP domain of the user and registrar - the host of the URL if unset
+    pub fn domain(mut self, domain: impl Into<Host>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Shares digest challenges with other connections, e.g. earlier ones of the same slot
+    pub fn digest_cache(mut self, digest: DigestCache) -> Self {
+        self.digest = digest;
+        self
+    }
+
+    /// Generates identifiers with the given generator, e.g. a deterministic one in tests
+    pub fn ids(mut self, ids: impl SipIdGenerator + 'static) -> Self {
+        self.ids = Arc::new(ids);
+        self
+    }
+
+    /// Follows 3xx responses to own requests as given instead of up to three times
+    pub fn redirects(mut self, redirects: Redirects) -> Self {
+        self.redirects = redirects;
+        self
+    }
+
+    /// Waits for responses and refreshes registrations as given instead of the RFC defaults
+    pub fn timers(mut self, timers: SipTimers) -> Self {
+        self.timers = timers;
+        self
+    }
+
+    /// Registers the given contact instead of a new one, e.g. that of an earlier run
+    pub fn contact(mut self, contact: Option<ContactId>) -> Self {
+        self.contact = contact;
+        self
+    }
+
+    pub async fn connect(self) -> Result<(Connection, mpsc::Receiver<ServerTransaction>)> {
+        info!("Connecting to: {url}", url = self.url);
+
+        let mut request = self.url.clone().into_client_request()?;
         request.headers_mut().append(
             "Sec-WebSocket-Protocol",
             "sip".parse().expect("valid header value"),
         );
 
-        let (stream, _response) = tokio_tungstenite::connect_async(request).await?;
+        let host = self.url.host_str().context("URL must have host")?;
+        let port = self.url.port_or_known_default().context("URL must have port")?;
+        let resolver = match &self.resolver {
+            Some(resolver) => resolver.clone(),
+            None => Resolver::new(&DnsConfig::default())?,
+        };
+        let stream = connect::connect(&resolver, host, port, self.prefer).await?;
+
+        // Frames far beyond the limit are not even buffered but close the connection
+        let config = WebSocketConfig::default().max_message_size(Some(self.limits.max_message_size * 4));
+        let (stream, _response) =
+            tokio_tungstenite::client_async_tls_with_config(request, stream, Some(config), None)
+                .await?;
 
         let (proto_tx, proto_rx) = stream.split();
-        let proto_tx = proto_tx.sink_map_err(anyhow::Error::from);
-        let proto_rx = proto_rx.map_err(anyhow::Error::from).fuse();
+        self.attach(proto_tx.sink_map_err(anyhow::Error::from), proto_rx.map_err(anyhow::Error::from))
+    }
+
+    /// Runs the connection over an established stream of WebSocket messages instead of connecting to the URL
+    pub fn attach(
+        self,
+        proto_tx: impl Sink<Message, Error = anyhow::Error> + Send + Unpin + 'static,
+        proto_rx: impl Stream<Item = Result<Message>> + Send + Unpin + 'static,
+    ) -> Result<(Connection, mpsc::Receiver<ServerTransaction>)> {
+        let Self {
+            url,
+            username,
+            user_agent,
+            headers,
+            events,
+            middlewares,
+            digest,
+            prefer: _,
+            resolver: _,
+            limits,
+            metrics,
+            trace,
+            domain,
+            ids,
+            contact,
+            redirects,
+            timers,
+        } = self;
+
+        let middlewares = Chain::new(middlewares);
+
+        let events = events.unwrap_or_else(|| broadcast::Sender::new(CONNECTION_EVENTS));
+
+        let defaults = Arc::new(
+            std::iter::once(Header::from(UserAgent::new(user_agent)))
+                .chain(headers)
+                .collect::<Vec<_>>(),
+        );
+
+        let domain = match domain {
+            Some(domain) => domain,
+            None => url_host(&url)?,
+        };
+
+        let proto_rx = proto_rx.fuse();
 
-        let send_by = HostWithPort::from(Host::from(format!(
-            "{}.invalid",
-            Alphanumeric.sample_string(&mut rand::rng(), 16)
-        )));
+        // Stable along with the rest of the contact, so the registrar replaces the binding of an earlier run
+        let contact = contact.unwrap_or_else(|| ContactId::generate(&*ids));
+        let send_by = HostWithPort::from(Host::from(contact.host.as_str()));
 
         let user = Uri {
             scheme: Some(Scheme::Sip),
             auth: Some(Auth {
-                user: username.to_string(),
+                user: username,
                 password: None,
             }),
             host_with_port: HostWithPort {
-                host: Host::from(url.domain().expect("URL must have domain")),
+                host: domain.clone(),
                 port: None,
             },
             params: Default::default(),
@@ -217,58 +492,229 @@ impl Connection {
         let (receiver_tx, receiver_rx) = mpsc::channel(1);
         let (sender_tx, sender_rx) = mpsc::channel(1);
 
-        tokio::spawn(Self::run(
-            proto_tx,
-            proto_rx,
-            sender_rx,
-            receiver_tx,
-            transactions.clone(),
-        ));
+        let context = DialogContext {
+            sender: sender_tx.downgrade(),
+            transactions: Arc::downgrade(&transactions),
+            send_by: send_by.clone(),
+            contact: contact_header(&contact, &send_by),
+            ids: ids.clone(),
+            timers,
+        };
+
+        let _ = events.send(ConnectionEvent::Connected { url: url.to_string() });
+
+        let task = tokio::spawn({
+            let transactions = transactions.clone();
+            let events = events.clone();
+            let defaults = defaults.clone();
+            async move {
+                let result = Connection::run(
+                    proto_tx,
+                    proto_rx,
+                    sender_rx,
+                    receiver_tx,
+                    transactions,
+                    defaults,
+                    context,
+                    middlewares,
+                    Guard::new(limits, metrics),
+                    trace,
+                )
+                .await;
+                let reason = match result {
+                    Ok(Ended::ByServer) => "closed by server".to_string(),
+                    Ok(Ended::Dropped) => {
+                        // Nobody follows the events of a connection no longer used
+                        info!("Connection closed as no longer used");
+                        return;
+                    }
+                    Err(err) => format!("{err:#}"),
+                };
+
+                info!("Connection closed: {reason}");
+                let _ = events.send(ConnectionEvent::Closed { reason });
+            }
+        });
 
         Ok((
-            Self {
+            Connection {
                 url,
+                domain,
                 user,
                 send_by,
                 sender: sender_tx,
+                task,
                 transactions,
+                events,
+                defaults,
+                contact,
+                digest,
+                ids,
+                redirects,
+                timers,
+                registered_for: None,
             },
             receiver_rx,
         ))
     }
 
+}
+
+impl Connection {
+    pub fn builder(url: Url, username: &str) -> ConnectionBuilder {
+        ConnectionBuilder {
+            url,
+            username: username.to_string(),
+            user_agent: format!("ucware-cli/{version}", version = env!("CARGO_PKG_VERSION")),
+            headers: Vec::new(),
+            events: None,
+            middlewares: Vec::new(),
+            digest: DigestCache::default(),
+            prefer: None,
+            resolver: None,
+            limits: Limits::default(),
+            metrics: Default::default(),
+            trace: Default::default(),
+            domain: None,
+            ids: Arc::new(RandomIds),
+            contact: None,
+            redirects: Redirects::default(),
+            timers: SipTimers::default(),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn run(
         mut proto_tx: impl Sink<Message, Error = anyhow::Error> + Unpin,
         mut proto_rx: impl Stream<Item = Result<Message>> + Unpin,
         mut sender_rx: mpsc::Receiver<Request>,
         receiver_tx: mpsc::Sender<ServerTransaction>,
         transactions: Arc<DashMap<TransactionKey, mpsc::Sender<Response>>>,
-    ) -> Result<()> {
-        let (sender_res_tx, mut sender_res_rx) = mpsc::channel(1);
+        defaults: Arc<Vec<Header>>,
+        context: DialogContext,
+        middlewares: Chain,
+        mut guard: Guard,
+        trace: Arc<Trace>,
+    ) -> Result<Ended> {
+        // Unbounded so handlers answering never wait for the loop handing out the next request
+        let (sender_res_tx, mut sender_res_rx) = mpsc::unbounded_channel();
+
+        // Outgoing messages are collected first and written by priority, so
+        // answering the server is not held up by a burst of own requests
+        let mut outbound = Outbound::default();
 
         loop {
             select! {
+                biased;
+
+                Some(msg) = sender_res_rx.recv() => {
+                    trace!("Outgoing msg(response): {msg:?}");
+                    let Some(msg) = middlewares.outgoing(SipMessage::Response(msg)) else {
+                        trace!("Outgoing response dropped by middleware");
+                        continue;
+                    };
+                    let text = codec::encode(&msg);
+                    trace.record(TraceDirection::Sent, text.as_bytes());
+                    outbound.push(Priority::Response, Message::text(text));
+                }
+
+                () = std::future::ready(()), if !outbound.is_empty() => {
+                    let msg = outbound.pop().expect("Queue not empty");
+                    proto_tx.send(msg).await?;
+                }
+
                 msg = proto_rx.next() => {
                     trace!("Got message from WS: {msg:?}");
 
                     let Some(msg) = msg else {
-                        return Ok(());
+                        return Ok(Ended::ByServer);
                     };
 
                     let msg = msg?;
 
                     match msg {
-                        Message::Text(msg) => {
-                            let msg = SipMessage::try_from(msg.as_str())?;
+                        Message::Text(_) | Message::Binary(_) => {
+                            let frame = match msg {
+                                Message::Text(text) => Bytes::from(text),
+                                Message::Binary(data) => data,
+                                _ => unreachable!(),
+                            };
+                            trace.record(TraceDirection::Received, &frame);
+
+                            let (frame, oversized) = match guard.check_frame(frame) {
+                                Frame::Whole(frame) => (frame, false),
+                                Frame::Head(head) => (head, true),
+                                Frame::Drop => {
+                                    warn!("Dropping message exceeding limits");
+                                    continue;
+                                }
+                            };
+
+                            let msg = match codec::decode(frame.clone()) {
+                                Ok(msg) => msg,
+                                Err(err) => {
+                                    // A single broken message must not take down the connection
+                                    guard.unparsable();
+                                    warn!("Ignoring unparsable message: {err}");
+                                    trace!("Unparsable message: {frame:?}");
+                                    continue;
+                                }
+                            };
+
+                            match guard.check(oversized, &msg) {
+                                Verdict::Accept => {}
+
+                                Verdict::Drop => {
+                                    warn!("Dropping message exceeding limits");
+                                    continue;
+                                }
+
+                                Verdict::Reject(status) => {
+                                    let SipMessage::Request(request) = &msg else {
+                                        continue;
+                                    };
+                                    warn!("Rejecting {method} exceeding limits with {status}", method = request.method);
+
+                                    let mut headers = response_headers(request, &defaults);
+                                    if status == StatusCode::ServiceUnavailable {
+                                        headers.push(rsip::headers::RetryAfter::new("1").into());
+                                    }
+
+                                    let response = SipMessage::Response(Response {
+                                        status_code: status,
+                                        version: Version::V2,
+                                        headers: headers.into(),
+                                        body: Vec::new(),
+                                    });
+                                    if let Some(response) = middlewares.outgoing(response) {
+                                        let text = codec::encode(&response);
+                                        trace.record(TraceDirection::Sent, text.as_bytes());
+                                        outbound.push(Priority::Response, Message::text(text));
+                                    }
+                                    continue;
+                                }
+                            }
+
+                            let Some(msg) = middlewares.incoming(msg) else {
+                                trace!("Incoming message dropped by middleware");
+                                continue;
+                            };
+
                             match msg {
                                 SipMessage::Request(request) => {
                                     // Got a new request starting a new transaction
                                     let tx = ServerTransaction {
                                         request,
                                         responses: sender_res_tx.clone(),
+                                        defaults: defaults.clone(),
+                                        context: context.clone(),
                                     };
 
-                                    receiver_tx.send(tx).await.expect("Request handler available");
+                                    // Nobody takes requests anymore, e.g. as the connection is being closed
+                                    if let Err(mpsc::error::SendError(mut tx)) = receiver_tx.send(tx).await {
+                                        warn!("Rejecting {method} as no handler is available", method = tx.request.method);
+                                        tx.respond(StatusCode::ServiceUnavailable).send([]).await;
+                                    }
                                 }
 
                                 SipMessage::Response(response) => {
@@ -280,153 +726,343 @@ impl Connection {
                                         continue;
                                     };
 
-                                    tx.send(response).await.expect("Traansaction response handler available");
+                                    if tx.send(response).await.is_err() {
+                                        debug!("Dropping response to abandoned transaction {tx_key:?}");
+                                    }
                                 }
                             }
                         }
 
                         Message::Ping(payload) => {
-                            proto_tx.send(Message::Pong(payload)).await?;
+                            outbound.push(Priority::Keepalive, Message::Pong(payload));
                             continue;
                         }
 
                         Message::Close(_) => {
-                            return Ok(());
+                            return Ok(Ended::ByServer);
                         }
 
                         _ => {}
                     }
                 }
 
-                Some(msg) = sender_rx.recv() => {
-                    trace!("Outgoing msg(request): {msg:?}");
-                    let msg = Message::text(String::from(msg));
-                    proto_tx.send(msg).await?;
-                }
+                // Only taken while nothing is waiting to be written or read
+                msg = sender_rx.recv() => {
+                    // The connection and all dialogs sending through it are gone
+                    let Some(msg) = msg else {
+                        while let Some(msg) = outbound.pop() {
+                            proto_tx.send(msg).await?;
+                        }
+                        proto_tx.close().await?;
+                        return Ok(Ended::Dropped);
+                    };
 
-                Some(msg) = sender_res_rx.recv() => {
-                    trace!("Outgoing msg(response): {msg:?}");
-                    let msg = Message::text(String::from(msg));
-                    proto_tx.send(msg).await?;
+                    trace!("Outgoing msg(request): {msg:?}");
+                    let Some(msg) = middlewares.outgoing(SipMessage::Request(msg)) else {
+                        trace!("Outgoing request dropped by middleware");
+                        continue;
+                    };
+                    let text = codec::encode(&msg);
+                    trace.record(TraceDirection::Sent, text.as_bytes());
+                    outbound.push(Priority::Request, Message::text(text));
                 }
             }
         }
     }
 
-    pub async fn send(&self, request: Request) -> Result<ClientTransaction> {
-        let (tx, rx) = mpsc::channel(1);
+    /// Closes the WebSocket after writing what is queued, aborting it if that takes too long.
+    ///
+    /// Dropping the connection closes it as well, but without waiting for it.
+    pub async fn close(self) {
+        let Self { sender, mut task, .. } = self;
+        drop(sender);
 
-        let t = ClientTransaction {
-            request: request.clone(),
-            responses: rx,
-            transactions: Arc::downgrade(&self.transactions),
-        };
-
-        let tx_key = TransactionKey::from_request(&t.request);
-        trace!("Register transaction with: {tx_key:?}");
-
-        self.transactions
-            .insert(tx_key, tx);
+        if tokio::time::timeout(CLOSE_TIMEOUT, &mut task).await.is_err() {
+            warn!("Aborting connection not closed within {CLOSE_TIMEOUT:?}");
+            task.abort();
+        }
+    }
 
-        self.sender.send(request).await.expect("Request pipe closed");
+    /// Receives lifecycle changes from now on
+    pub fn events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
 
-        Ok(t)
+    pub async fn send(&self, request: Request) -> Result<ClientTransaction> {
+        send_request(&self.transactions, &self.sender, &self.timers, request).await
     }
 
     pub fn dialog(&self) -> Dialog<'_> {
-        let call_id = Alphanumeric.sample_string(&mut rand::rng(), 16);
-        let seq = AtomicU32::new(rand::random::<u16>() as u32);
+        let call_id = self.ids.call_id(&self.send_by);
+        let seq = AtomicU32::new(self.ids.cseq());
 
         Dialog {
             connection: self,
             call_id,
+            tag: self.ids.tag(),
             seq,
         }
     }
 
     pub async fn register(&mut self, username: &str, password: &str) -> Result<()> {
-        let contact = Alphanumeric.sample_string(&mut rand::rng(), 16);
+        self.register_for(username, password, REGISTRATION_EXPIRES).await
+    }
+
+    /// Registers the contact of this socket for the given number of seconds
+    pub async fn register_for(&mut self, username: &str, password: &str, expires: u32) -> Result<()> {
+        let result = self.try_register(username, password, expires).await;
+
+        let _ = self.events.send(match &result {
+            Ok(()) => ConnectionEvent::Registered {
+                username: username.to_string(),
+            },
+            Err(err) => ConnectionEvent::RegistrationLost {
+                reason: format!("{err:#}"),
+            },
+        });
+
+        result
+    }
+
+    async fn try_register(&mut self, username: &str, password: &str, expires: u32) -> Result<()> {
+        let mut expires = expires;
+        let mut response = self
+            .registrar(Registration::Bind(expires), username, password)
+            .await?;
+
+        if response.status_code == StatusCode::IntervalTooBrief {
+            let min_expires = response
+                .min_expires_header()
+                .and_then(|header| header.value().trim().parse::<u32>().ok())
+                .filter(|&min_expires| min_expires > expires)
+                .ok_or_else(|| self.registration_error(username, &response, true))?;
+
+            info!("Registrar requires registrations of at least {min_expires}s, registering again");
+            expires = min_expires;
+
+            response = self
+                .registrar(Registration::Bind(expires), username, password)
+                .await?;
+            if response.status_code == StatusCode::IntervalTooBrief {
+                return Err(self.registration_error(username, &response, true));
+            }
+        }
+
+        // The registrar may grant less than asked for
+        let granted = Binding::from_response(&response)
+            .into_iter()
+            .find(|binding| {
+                binding.instance.as_deref() == Some(self.contact.instance.as_str())
+                    || binding.contact.contains(&format!(":{user}@", user = self.contact.user))
+            })
+            .and_then(|binding| binding.expires)
+            .unwrap_or(expires);
+
+        debug!("Registered for {granted}s");
+        self.registered_for = Some(Duration::from_secs(granted.into()));
+
+        Ok(())
+    }
+
+    /// Time the last registration is valid for, to be refreshed before it runs out
+    pub fn registered_for(&self) -> Option<Duration> {
+        self.registered_for
+    }
+
+    pub fn timers(&self) -> &SipTimers {
+        &self.timers
+    }
 
+    /// Removes the binding of this socket's contact, or all bindings of the user
+    pub async fn unregister(&mut self, username: &str, password: &str, all: bool) -> Result<()> {
+        let registration = if all { Registration::UnbindAll } else { Registration::Unbind };
+        self.registrar(registration, username, password).await?;
+        Ok(())
+    }
+
+    /// Bindings of the user currently known to the registrar
+    pub async fn bindings(&mut self, username: &str, password: &str) -> Result<Vec<Binding>> {
+        let response = self.registrar(Registration::Query, username, password).await?;
+        Ok(Binding::from_response(&response))
+    }
+
+    /// Sends a REGISTER authenticated with the cached or a fresh challenge and returns the accepted response
+    async fn registrar(
+        &mut self,
+        registration: Registration,
+        username: &str,
+        password: &str,
+    ) -> Result<Response> {
         let dialog = self.dialog();
 
-        let response = dialog
-            .request(Method::Register)
-            .send([])
-            .await?
-            .receive()
+        let (mut authenticate, cached) = match self.digest.get() {
+            Some(authenticate) => (authenticate, true),
+            None => {
+                let response = self.send_register(&dialog, registration, None).await?;
+
+                if Self::answered(registration, &response) {
+                    return Ok(response);
+                }
+
+                if response.status_code != StatusCode::Unauthorized {
+                    return Err(self.registration_error(username, &response, false));
+                }
+
+                (Self::challenge(&response)?, false)
+            }
+        };
+
+        let mut response = self
+            .send_register(&dialog, registration, Some(digest::authorization(&authenticate, username, password)))
             .await?;
 
-        if response.status_code.kind() == StatusCodeKind::Successful {
-            return Ok(());
+        // The cached nonce may have been expired or dropped by the server
+        if cached && response.status_code == StatusCode::Unauthorized {
+            authenticate = Self::challenge(&response)?;
+            debug!(
+                "Cached digest challenge rejected{stale}, retrying with new one",
+                stale = if digest::is_stale(&authenticate) { " as stale" } else { "" }
+            );
+
+            response = self
+                .send_register(&dialog, registration, Some(digest::authorization(&authenticate, username, password)))
+                .await?;
         }
 
-        if response.status_code != StatusCode::Unauthorized {
-            bail!("Failed to register: {}", response.status_code);
+        if !Self::answered(registration, &response) {
+            self.digest.clear();
+            return Err(self.registration_error(username, &response, true));
         }
 
-        let authenticate = response
+        self.digest.set(authenticate);
+
+        Ok(response)
+    }
+
+    /// Whether the registrar accepted the REGISTER or asked for a longer interval
+    fn answered(registration: Registration, response: &Response) -> bool {
+        response.status_code.kind() == StatusCodeKind::Successful
+            || (matches!(registration, Registration::Bind(_)) && response.status_code == StatusCode::IntervalTooBrief)
+    }
+
+    fn challenge(response: &Response) -> Result<WwwAuthenticate> {
+        Ok(response
             .www_authenticate_header()
             .ok_or_else(|| anyhow!("No 'WWW-Authenticate' header received"))?
-            .typed()?;
+            .typed()?)
+    }
 
-        let response = DigestGenerator {
-            username,
-            password,
-            nonce: authenticate.nonce.as_str(),
-            uri: &Default::default(),
-            realm: authenticate.realm.as_str(),
-            method: &Method::Register,
-            qop: None,
-            algorithm: authenticate.algorithm.unwrap_or(Algorithm::Md5),
+    async fn send_register(
+        &self,
+        dialog: &Dialog<'_>,
+        registration: Registration,
+        authorization: Option<Authorization>,
+    ) -> Result<Response> {
+        let mut request = dialog.request(Method::Register);
+
+        request = match registration {
+            Registration::Bind(expires) => {
+                let mut contact = self.contact();
+                contact.params.push(Param::Expires(expires.to_string().into()));
+                request.header(contact)
+            }
+            Registration::Unbind => {
+                let mut contact = self.contact();
+                contact.params.push(Param::Expires("0".into()));
+                request.header(contact)
+            }
+            Registration::UnbindAll => request
+                .header(rsip::headers::Contact::new("*"))
+                .header(rsip::headers::Expires::new("0")),
+            Registration::Query => request,
+        };
+
+        if let Some(authorization) = authorization {
+            request = request.header(authorization);
         }
-        .compute();
 
-        let authorization = rsip::headers::typed::Authorization {
-            scheme: auth::Scheme::Digest,
-            username: username.to_string(),
-            realm: authenticate.realm,
-            nonce: authenticate.nonce,
-            uri: Default::default(),
-            response,
-            algorithm: authenticate.algorithm,
-            opaque: authenticate.opaque,
-            qop: None,
-        };
+        request.exchange([]).await
+    }
 
-        let response = dialog
-            .request(Method::Register)
-            .header(rsip::headers::typed::Contact {
-                display_name: None,
-                uri: Uri {
-                    scheme: Some(Scheme::Sip),
-                    auth: Some(Auth {
-                        user: contact.clone(),
-                        password: None,
-                    }),
-                    host_with_port: self.send_by.clone(),
-                    params: vec![Param::Transport(Transport::Ws)],
-                    headers: vec![],
-                },
-                params: vec![Param::Expires("6000".into())],
-            })
-            .header(authorization)
-            .send([])
-            .await?
-            .receive()
+    /// Address at which the server reaches this socket
+    fn contact(&self) -> rsip::headers::typed::Contact {
+        contact_header(&self.contact, &self.send_by)
+    }
+
+    /// Identity of the contact address registered for this socket
+    pub fn contact_id(&self) -> &ContactId {
+        &self.contact
+    }
+
+    /// Subscribes to an event package of the own user for the given number of seconds
+    pub async fn subscribe(&self, event: &str, accept: &str, expires: u32) -> Result<()> {
+        let response = self
+            .dialog()
+            .request(Method::Subscribe)
+            .header(self.contact())
+            .header(rsip::headers::Event::new(event))
+            .header(rsip::headers::Accept::new(accept))
+            .header(rsip::headers::Expires::new(expires.to_string()))
+            .exchange([])
             .await?;
 
         if response.status_code.kind() != StatusCodeKind::Successful {
-            bail!("Failed to register: {}", response.status_code);
+            bail!("Failed to subscribe to {event}: {status}", status = response.status_code);
         }
 
         Ok(())
     }
+
+    /// Describes a failed registration including everything the server told us
+    fn registration_error(
+        &self,
+        username: &str,
+        response: &Response,
+        authenticated: bool,
+    ) -> anyhow::Error {
+        let mut message = format!(
+            "Failed to register {username} at {url}: {status}",
+            url = self.url,
+            status = response.status_code,
+        );
+
+        for warning in headers::warnings(&response.headers) {
+            message.push_str(&format!("; Warning: {warning}"));
+        }
+
+        for reason in headers::other(&response.headers, "Reason") {
+            message.push_str(&format!("; Reason: {reason}"));
+        }
+
+        let hint = match response.status_code {
+            StatusCode::Unauthorized | StatusCode::Forbidden if authenticated => Some(
+                "the SIP password was rejected - it may have been rotated, try again or rotate it explicitly",
+            ),
+            StatusCode::Forbidden => Some("the slot may be disabled or not allowed to register"),
+            StatusCode::NotFound => Some("the SIP user is unknown - the slot may have been deleted"),
+            StatusCode::ServiceUnavailable => Some("the server is temporarily unavailable"),
+            _ => None,
+        };
+
+        if let Some(hint) = hint {
+            message.push_str(&format!(" ({hint})"));
+        }
+
+        match RetryAfter::from_response(response) {
+            Some(retry_after) => anyhow::Error::new(retry_after).context(message),
+            None => anyhow!(message),
+        }
+    }
 }
 
 pub struct Dialog<'c> {
     connection: &'c Connection,
 
     call_id: String,
+
+    /// Tag of the From header
+    tag: String,
+
     seq: AtomicU32,
 }
 
@@ -442,7 +1078,7 @@ impl<'c> Dialog<'c> {
             version: Version::V2,
             transport: Transport::Wss,
             uri: Uri::from(self.connection.send_by.clone()),
-            params: vec![],
+            params: vec![Param::Branch(self.connection.ids.branch().into())],
         });
 
         let builder = builder.header(rsip::headers::typed::To {
@@ -454,7 +1090,7 @@ impl<'c> Dialog<'c> {
         let builder = builder.header(rsip::headers::typed::From {
             display_name: None,
             uri: self.connection.user.clone(),
-            params: vec![],
+            params: vec![Param::Tag(self.tag.clone().into())],
         });
 
         let builder = builder.header(rsip::headers::typed::CSeq {
@@ -464,12 +1100,8 @@ impl<'c> Dialog<'c> {
 
         let builder = builder.header(CallId::new(self.call_id.clone()));
 
-        let builder = builder.header(rsip::headers::UserAgent::new(format!(
-            "ucware-cli/{version}",
-            version = env!("CARGO_PKG_VERSION")
-        )));
-
-        builder
+        let defaults = self.connection.defaults.iter().cloned();
+        defaults.fold(builder, RequestBuilder::header)
     }
 }
 
@@ -486,26 +1118,170 @@ impl<'c, 'd> RequestBuilder<'c, 'd> {
         self
     }
 
-    pub async fn send(self, body: impl Into<Vec<u8>>) -> Result<ClientTransaction> {
-        let request = Request {
+    fn build(self, body: impl Into<Vec<u8>>) -> Request {
+        Request {
             method: self.method,
-            uri: Uri {
-                scheme: Some(Scheme::Sip),
-                auth: None,
-                host_with_port: Host::from(
-                    self.dialog.connection.url.domain().expect("URL must have domain"),
-                )
-                .into(),
-                params: Vec::default(),
-                headers: Vec::default(),
-            },
+            uri: self.uri(),
             headers: self.headers,
             version: Version::V2,
             body: body.into(),
-        };
+        }
+    }
+
+    /// Requests go to the domain of the user, the proxy behind the socket routes them
+    fn uri(&self) -> Uri {
+        Uri {
+            scheme: Some(Scheme::Sip),
+            auth: None,
+            host_with_port: self.dialog.connection.domain.clone().into(),
+            params: Vec::default(),
+            headers: Vec::default(),
+        }
+    }
+
+    pub async fn send(self, body: impl Into<Vec<u8>>) -> Result<ClientTransaction> {
+        let connection = self.dialog.connection;
+        let request = self.build(body);
 
         trace!("Sending request: {request:#?}");
 
-        self.dialog.connection.send(request).await
+        connection.send(request).await
     }
+
+    /// Sends the request and waits for the final response, following redirects
+    pub async fn exchange(self, body: impl Into<Vec<u8>>) -> Result<Response> {
+        let dialog = self.dialog;
+        let connection = dialog.connection;
+        let method = self.method;
+        let mut uri = self.uri();
+        let mut headers = self.headers;
+        let mut body = body.into();
+
+        let mut visited = vec![uri.clone()];
+        loop {
+            // The parts are only copied while a redirect may still be followed, the last attempt takes them
+            let retry = visited.len() <= connection.redirects.max as usize;
+            let request = Request {
+                method,
+                uri: uri.clone(),
+                headers: if retry { headers.clone() } else { std::mem::take(&mut headers) },
+                version: Version::V2,
+                body: if retry { body.clone() } else { std::mem::take(&mut body) },
+            };
+
+            trace!("Sending request: {request:#?}");
+            let response = connection.send(request).await?.receive().await?;
+
+            if response.status_code.kind() != StatusCodeKind::Redirection {
+                return Ok(response);
+            }
+
+            let Some(target) = redirect::target(&response) else {
+                warn!("Redirect of {method} without contact");
+                return Ok(response);
+            };
+
+            if visited.contains(&target) {
+                warn!("Not following redirect loop of {method} to {target}");
+                return Ok(response);
+            }
+
+            if !retry {
+                warn!("Not following more than {max} redirects of {method}", max = connection.redirects.max);
+                return Ok(response);
+            }
+
+            let followed = connection.redirects.allows(&method, &target);
+            let _ = connection.events.send(ConnectionEvent::Redirected {
+                method: method.to_string(),
+                target: target.to_string(),
+                followed,
+            });
+            if !followed {
+                debug!("Redirect of {method} to {target} vetoed");
+                return Ok(response);
+            }
+
+            info!("Following redirect of {method} to {target}");
+
+            // A new transaction to the new target within the same dialog
+            let seq = dialog.seq.fetch_add(1, Ordering::Release);
+            for header in headers.iter_mut() {
+                match header {
+                    Header::Via(_) => {
+                        *header = rsip::headers::typed::Via {
+                            version: Version::V2,
+                            transport: Transport::Wss,
+                            uri: Uri::from(connection.send_by.clone()),
+                            params: vec![Param::Branch(connection.ids.branch().into())],
+                        }
+                        .into();
+                    }
+                    Header::CSeq(_) => {
+                        *header = rsip::headers::typed::CSeq { seq, method }.into();
+                    }
+                    _ => {}
+                }
+            }
+
+            uri = target.clone();
+            visited.push(target);
+        }
+    }
+}
+
+/// Starts a client transaction, whose responses the connection hands to it
+async fn send_request(
+    transactions: &Arc<DashMap<TransactionKey, mpsc::Sender<Response>>>,
+    sender: &mpsc::Sender<Request>,
+    timers: &SipTimers,
+    request: Request,
+) -> Result<ClientTransaction> {
+    let (tx, rx) = mpsc::channel(1);
+
+    let tx_key = TransactionKey::from_request(&request);
+    trace!("Register transaction with: {tx_key:?}");
+
+    transactions.insert(tx_key.clone(), tx);
+
+    let t = ClientTransaction {
+        key: tx_key,
+        responses: rx,
+        timeout: timers.transaction_timeout(&request.method),
+        transactions: Arc::downgrade(transactions),
+    };
+
+    sender.send(request).await.ok().context("Connection closed")?;
+
+    Ok(t)
+}
+
+/// Address at which the server reaches the socket with the given contact
+fn contact_header(contact: &ContactId, send_by: &HostWithPort) -> rsip::headers::typed::Contact {
+    rsip::headers::typed::Contact {
+        display_name: None,
+        uri: Uri {
+            scheme: Some(Scheme::Sip),
+            auth: Some(Auth {
+                user: contact.user.clone(),
+                password: None,
+            }),
+            host_with_port: send_by.clone(),
+            params: vec![Param::Transport(Transport::Ws)],
+            headers: vec![],
+        },
+        params: vec![Param::Other(
+            "+sip.instance".into(),
+            Some(format!("\"<{instance}>\"", instance = contact.instance).into()),
+        )],
+    }
+}
+
+/// The host of a URL as SIP host, which may be an IP address as well as a domain
+fn url_host(url: &Url) -> Result<Host> {
+    Ok(match url.host().context("URL must have host")? {
+        url::Host::Domain(domain) => Host::from(domain),
+        url::Host::Ipv4(ip) => Host::from(IpAddr::from(ip)),
+        url::Host::Ipv6(ip) => Host::from(IpAddr::from(ip)),
+    })
 }
@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+use tungstenite::Message;
+
+/// Urgency of an outgoing message, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// WebSocket control frames keeping the connection alive
+    Keepalive,
+
+    /// Answers to requests of the server, which retransmits or gives up if they are late
+    Response,
+
+    /// New requests of our own
+    Request,
+}
+
+/// Messages waiting to be written to the socket.
+///
+/// Messages are handed out by priority and in the order they were queued
+/// within the same priority.
+#[derive(Debug, Default)]
+pub struct Outbound {
+    queues: [VecDeque<Message>; 3],
+}
+
+impl Outbound {
+    pub fn push(&mut self, priority: Priority, message: Message) {
+        self.queues[priority as usize].push_back(message);
+    }
+
+    /// The most urgent message
+    pub fn pop(&mut self) -> Option<Message> {
+        self.queues.iter_mut().find_map(VecDeque::pop_front)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+}
@@ -0,0 +1,63 @@
+use rsip::headers::UntypedHeader;
+use rsip::message::HeadersExt;
+use rsip::{Method, Response, Uri};
+use std::fmt;
+use std::sync::Arc;
+
+/// Redirects followed per request unless configured otherwise
+pub const DEFAULT_MAX_REDIRECTS: u32 = 3;
+
+type Filter = dyn Fn(&Method, &Uri) -> bool + Send + Sync;
+
+/// How 3xx responses to own requests are followed
+#[derive(Clone)]
+pub struct Redirects {
+    /// Redirects followed per request, zero treats 3xx as final
+    pub max: u32,
+
+    filter: Option<Arc<Filter>>,
+}
+
+impl Redirects {
+    pub fn new(max: u32) -> Self {
+        Self { max, filter: None }
+    }
+
+    /// Asks the given function before following a redirect, which vetoes it by returning `false`
+    pub fn filter(mut self, filter: impl Fn(&Method, &Uri) -> bool + Send + Sync + 'static) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    pub fn allows(&self, method: &Method, target: &Uri) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter(method, target))
+    }
+}
+
+impl Default for Redirects {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_REDIRECTS)
+    }
+}
+
+impl fmt::Debug for Redirects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Redirects")
+            .field("max", &self.max)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+/// The first contact a 3xx response redirects to
+pub fn target(response: &Response) -> Option<Uri> {
+    let contact = response.contact_header().ok()?.value().trim();
+
+    // Parsed by hand as the typed header rejects some parameter values
+    let uri = match contact.split_once('<') {
+        Some((_, rest)) => rest.split_once('>')?.0,
+        None => contact.split(';').next()?,
+    };
+
+    Uri::try_from(uri.trim()).ok()
+}
@@ -0,0 +1,53 @@
+use rsip::headers::UntypedHeader;
+use rsip::{Header, Response, StatusCode};
+use std::fmt;
+use std::time::Duration;
+
+/// The server failed a request but asked to try again after some time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryAfter(pub Duration);
+
+impl RetryAfter {
+    /// The delay requested by a 500 or 503 response
+    pub fn from_response(response: &Response) -> Option<Self> {
+        if !matches!(
+            response.status_code,
+            StatusCode::ServerInternalError | StatusCode::ServiceUnavailable
+        ) {
+            return None;
+        }
+
+        // Like `120 (maintenance);duration=3600`, only the seconds are of interest
+        let value = response.headers.iter().find_map(|header| match header {
+            Header::RetryAfter(retry_after) => Some(retry_after.value()),
+            _ => None,
+        })?;
+        let seconds = value
+            .trim()
+            .split(|c: char| !c.is_ascii_digit())
+            .next()?
+            .parse()
+            .ok()?;
+
+        Some(Self(Duration::from_secs(seconds)))
+    }
+
+    /// The delay found anywhere in the chain of an error
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.chain().find_map(|err| err.downcast_ref::<Self>()).copied()
+    }
+
+    /// The delay with up to a tenth added at random, so clients turned away
+    /// together do not come back at the same time
+    pub fn jittered(self) -> Duration {
+        self.0 + self.0.mul_f64(rand::random::<f64>() / 10.0)
+    }
+}
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "retry after {}s", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for RetryAfter {}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+/// Timers of RFC 3261, section 17, and the margin for refreshing registrations.
+///
+/// There is no T2 as requests are not retransmitted over the reliable WebSocket transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipTimers {
+    /// Estimate of the round trip time
+    pub t1: Duration,
+
+    /// Time to wait for the final response to an INVITE
+    pub timer_b: Duration,
+
+    /// Time to wait for the final response to other requests
+    pub timer_f: Duration,
+
+    /// Time before a registration expires to refresh it at
+    pub refresh_margin: Duration,
+}
+
+impl SipTimers {
+    /// Timers derived from the given T1 as recommended by the RFC
+    pub fn with_t1(t1: Duration) -> Self {
+        Self {
+            t1,
+            timer_b: t1 * 64,
+            timer_f: t1 * 64,
+            refresh_margin: Duration::from_secs(300),
+        }
+    }
+
+    /// Time to wait for the final response to a request of the given method
+    pub fn transaction_timeout(&self, method: &rsip::Method) -> Duration {
+        match method {
+            rsip::Method::Invite => self.timer_b,
+            _ => self.timer_f,
+        }
+    }
+
+    /// Time after which a registration granted for the given interval is refreshed.
+    ///
+    /// At least half of the interval passes, so short registrations are not
+    /// refreshed all the time.
+    pub fn refresh_after(&self, granted: Duration) -> Duration {
+        granted.saturating_sub(self.refresh_margin).max(granted / 2)
+    }
+}
+
+impl Default for SipTimers {
+    fn default() -> Self {
+        Self::with_t1(Duration::from_millis(500))
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+
+/// Number of messages kept unless asked otherwise
+pub const DEFAULT_CAPACITY: usize = 200;
+
+/// Number of messages buffered for slow followers
+const LIVE: usize = 64;
+
+/// Headers whose values are replaced, as they carry credentials
+const REDACTED: &[&str] = &["authorization", "proxy-authorization"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TraceDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TracedMessage {
+    pub time: SystemTime,
+    pub direction: TraceDirection,
+
+    /// The message as on the wire, with credentials redacted
+    pub message: String,
+}
+
+/// The last messages sent and received, shared by all connections of a client
+#[derive(Debug)]
+pub struct Trace {
+    capacity: usize,
+    messages: Mutex<VecDeque<TracedMessage>>,
+    live: broadcast::Sender<TracedMessage>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Trace {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+            live: broadcast::Sender::new(LIVE),
+        }
+    }
+
+    pub fn record(&self, direction: TraceDirection, message: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let message = TracedMessage {
+            time: SystemTime::now(),
+            direction,
+            message: redact(&String::from_utf8_lossy(message)),
+        };
+
+        {
+            let mut messages = self.messages.lock().expect("not poisoned");
+            if messages.len() == self.capacity {
+                messages.pop_front();
+            }
+            messages.push_back(message.clone());
+        }
+
+        // Nobody following is fine
+        let _ = self.live.send(message);
+    }
+
+    /// The kept messages, oldest first
+    pub fn recent(&self) -> Vec<TracedMessage> {
+        self.messages.lock().expect("not poisoned").iter().cloned().collect()
+    }
+
+    /// Receives messages from now on
+    pub fn follow(&self) -> broadcast::Receiver<TracedMessage> {
+        self.live.subscribe()
+    }
+}
+
+fn redact(message: &str) -> String {
+    message
+        .split_inclusive('\n')
+        .map(|line| match line.split_once(':') {
+            Some((name, _)) if REDACTED.contains(&name.trim().to_ascii_lowercase().as_str()) => {
+                format!("{name}: <redacted>\r\n")
+            }
+            _ => line.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,147 @@
+use crate::callstate::AgentState;
+use crate::ctl;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Format {
+    /// One JSON object per line (waybar custom module)
+    Json,
+
+    /// One plain text line per update (i3blocks persist mode, polybar tail)
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Block {
+    text: String,
+    tooltip: String,
+    class: &'static str,
+}
+
+impl Block {
+    fn from_status(status: Option<&ctl::Status>) -> Self {
+        let Some(status) = status else {
+            return Self {
+                text: "☎ offline".to_string(),
+                tooltip: "Notifier daemon not running".to_string(),
+                class: "offline",
+            };
+        };
+
+        let mut text = vec!["☎".to_string()];
+        let mut tooltip = Vec::new();
+
+        if let Some(call) = status.calls.first() {
+            let caller = call.caller.display();
+            text.push(caller.to_string());
+            match &call.forwarded {
+                Some(forwarded) => tooltip.push(format!(
+                    "Incoming call from {caller}, {forwarded}",
+                    forwarded = forwarded.description()
+                )),
+                None => tooltip.push(format!("Incoming call from {caller}")),
+            }
+        }
+
+        if status.missed > 0 {
+            text.push(format!("{missed} missed", missed = status.missed));
+            tooltip.push(format!("{missed} missed calls", missed = status.missed));
+        }
+
+        if status.voicemail > 0 {
+            text.push(format!("✉ {voicemail}", voicemail = status.voicemail));
+            tooltip.push(format!("{voicemail} new voicemails", voicemail = status.voicemail));
+        }
+
+        match &status.agent {
+            AgentState::Available => {}
+            AgentState::Paused { reason } => {
+                text.push("Paused".to_string());
+                tooltip.push(format!(
+                    "Paused in queues ({reason})",
+                    reason = reason.as_deref().unwrap_or("no reason")
+                ));
+            }
+            AgentState::WrapUp { until } => {
+                let remaining = until
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+                    .as_secs();
+                text.push(format!("Wrap-up {remaining}s"));
+                tooltip.push("Wrap-up after call".to_string());
+            }
+        }
+
+        if status.dnd {
+            text.push("DND".to_string());
+            tooltip.push("Do not disturb".to_string());
+        }
+
+        if !status.registered {
+            tooltip.push("Not registered".to_string());
+        }
+
+        let class = if !status.registered {
+            "unregistered"
+        } else if !status.calls.is_empty() {
+            "ringing"
+        } else if status.agent != AgentState::Available {
+            "paused"
+        } else if status.dnd {
+            "dnd"
+        } else if status.missed > 0 {
+            "missed"
+        } else if status.voicemail > 0 {
+            "voicemail"
+        } else {
+            "idle"
+        };
+
+        Self {
+            text: text.join(" "),
+            tooltip: tooltip.join("\n"),
+            class,
+        }
+    }
+}
+
+/// Continuously prints the daemon state as single lines, one per change
+pub async fn run(socket: PathBuf, format: Format, interval: Duration) -> Result<()> {
+    let mut last = None;
+
+    loop {
+        let status = match ctl::request(&socket, &ctl::Request::Status).await {
+            Ok(ctl::Response::Status(status)) => Some(status),
+            Ok(response) => {
+                debug!("Unexpected response from daemon: {response:?}");
+                None
+            }
+            Err(err) => {
+                debug!("Daemon not reachable: {err:#}");
+                None
+            }
+        };
+
+        let block = Block::from_status(status.as_ref());
+        if last.as_ref() != Some(&block) {
+            let line = match format {
+                Format::Json => serde_json::to_string(&block)?,
+                Format::Text => block.text.clone(),
+            };
+
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{line}")?;
+            stdout.flush()?;
+
+            last = Some(block);
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+/// A call rejected by the blocklist
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockedCall {
+    /// Seconds since the epoch
+    pub time: u64,
+    pub rule: String,
+    pub name: Option<String>,
+    pub number: Option<String>,
+    pub uri: String,
+
+    /// Account and slot the call arrived on, unknown for calls logged before they were recorded
+    pub account: Option<String>,
+    pub slot: Option<String>,
+}
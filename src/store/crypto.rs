@@ -0,0 +1,98 @@
+use crate::config::Encryption;
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+/// Environment variable holding the passphrase for [`Encryption::Passphrase`]
+const PASSPHRASE_ENV: &str = "UCWARE_CALL_LOG_PASSPHRASE";
+
+const KEYRING_SERVICE: &str = "ucware-cli";
+const KEYRING_USER: &str = "call-log";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// Source of the key encrypting the call log
+#[derive(Clone)]
+pub enum CallLogKey {
+    /// Random key stored in the system keyring
+    Keyring,
+
+    /// Key derived from a passphrase
+    Passphrase(String),
+}
+
+impl CallLogKey {
+    /// The source given by the config, reading the passphrase from the environment
+    pub fn configured(encryption: Encryption) -> Result<Self> {
+        match encryption {
+            Encryption::Keyring => Ok(Self::Keyring),
+            Encryption::Passphrase => std::env::var(PASSPHRASE_ENV)
+                .map(Self::Passphrase)
+                .with_context(|| format!("Call log is encrypted but {PASSPHRASE_ENV} is not set")),
+        }
+    }
+}
+
+/// Authenticated encryption of single values, each with a random nonce
+pub(super) struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    /// Obtains the key from the given source, `salt` is used to derive it from a passphrase
+    pub fn new(key: &CallLogKey, salt: &[u8]) -> Result<Self> {
+        let key = match key {
+            CallLogKey::Keyring => keyring_key()?,
+            CallLogKey::Passphrase(passphrase) => {
+                let mut key = [0u8; KEY_LEN];
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                    .map_err(|err| anyhow!("Failed to derive call log key: {err}"))?;
+                key
+            }
+        };
+
+        Ok(Self(XChaCha20Poly1305::new(&key.into())))
+    }
+
+    /// Returns the nonce followed by the ciphertext
+    pub fn encrypt(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let cipher = self
+            .0
+            .encrypt(XNonce::from_slice(&nonce), plain)
+            .expect("encryption of in-memory data");
+
+        [nonce.as_slice(), &cipher].concat()
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            bail!("Encrypted value too short");
+        }
+
+        let (nonce, cipher) = data.split_at(NONCE_LEN);
+        self.0
+            .decrypt(XNonce::from_slice(nonce), cipher)
+            .map_err(|_| anyhow!("Failed to decrypt call log - wrong key?"))
+    }
+}
+
+/// Random key stored in the system keyring, created on first use
+fn keyring_key() -> Result<[u8; KEY_LEN]> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?;
+
+    match entry.get_secret() {
+        Ok(key) => key
+            .try_into()
+            .map_err(|_| anyhow!("Invalid call log key in keyring")),
+        Err(keyring::Error::NoEntry) => {
+            let key = rand::random::<[u8; KEY_LEN]>();
+            entry
+                .set_secret(&key)
+                .context("Failed to store call log key in keyring")?;
+            Ok(key)
+        }
+        Err(err) => Err(anyhow!(err).context("Failed to read call log key from keyring")),
+    }
+}
@@ -0,0 +1,255 @@
+use super::{BlockedCall, CacheEntry, Favorite, LastNumber, Storage};
+use crate::callstate::blocklist::BlockRule;
+use crate::callstate::Call;
+use crate::sipsocket::ids::ContactId;
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+struct Inner {
+    token: Option<String>,
+    account_tokens: HashMap<String, String>,
+    favorites: BTreeMap<String, String>,
+    last_numbers: HashMap<LastNumber, String>,
+    contacts: HashMap<String, ContactId>,
+    checkpoints: HashMap<String, SystemTime>,
+    notifications: BTreeMap<u32, String>,
+    blocklist: Vec<BlockRule>,
+    blocked_calls: Vec<BlockedCall>,
+    transcripts: HashMap<String, (SystemTime, String)>,
+    recordings: Vec<(PathBuf, String, SystemTime)>,
+    cache: HashMap<String, CacheEntry>,
+}
+
+/// State kept in memory only, gone when dropped
+#[derive(Default)]
+pub struct Memory {
+    inner: Mutex<Inner>,
+}
+
+impl Memory {
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().expect("not poisoned")
+    }
+}
+
+impl Storage for Memory {
+    fn token(&self) -> Result<Option<String>> {
+        Ok(self.inner().token.clone())
+    }
+
+    fn set_token(&self, token: &str) -> Result<()> {
+        self.inner().token = Some(token.to_string());
+        Ok(())
+    }
+
+    fn account_token(&self, account: &str) -> Result<Option<String>> {
+        Ok(self.inner().account_tokens.get(account).cloned())
+    }
+
+    fn set_account_token(&self, account: &str, token: &str) -> Result<()> {
+        self.inner().account_tokens.insert(account.to_string(), token.to_string());
+        Ok(())
+    }
+
+    fn favorites(&self) -> Result<Vec<Favorite>> {
+        Ok(self
+            .inner()
+            .favorites
+            .iter()
+            .map(|(name, number)| Favorite {
+                name: name.clone(),
+                number: number.clone(),
+            })
+            .collect())
+    }
+
+    fn favorite(&self, name: &str) -> Result<Option<Favorite>> {
+        Ok(self.inner().favorites.get(name).map(|number| Favorite {
+            name: name.to_string(),
+            number: number.clone(),
+        }))
+    }
+
+    fn add_favorite(&self, favorite: &Favorite) -> Result<()> {
+        self.inner().favorites.insert(favorite.name.clone(), favorite.number.clone());
+        Ok(())
+    }
+
+    fn remove_favorite(&self, name: &str) -> Result<bool> {
+        Ok(self.inner().favorites.remove(name).is_some())
+    }
+
+    fn last_number(&self, kind: LastNumber) -> Result<Option<String>> {
+        Ok(self.inner().last_numbers.get(&kind).cloned())
+    }
+
+    fn set_last_number(&self, kind: LastNumber, number: &str) -> Result<()> {
+        self.inner().last_numbers.insert(kind, number.to_string());
+        Ok(())
+    }
+
+    fn contact(&self, username: &str) -> Result<Option<ContactId>> {
+        Ok(self.inner().contacts.get(username).cloned())
+    }
+
+    fn set_contact(&self, username: &str, contact: &ContactId) -> Result<()> {
+        self.inner().contacts.insert(username.to_string(), contact.clone());
+        Ok(())
+    }
+
+    fn checkpoint(&self, name: &str) -> Result<Option<SystemTime>> {
+        Ok(self.inner().checkpoints.get(name).copied())
+    }
+
+    fn set_checkpoint(&self, name: &str, time: SystemTime) -> Result<()> {
+        self.inner().checkpoints.insert(name.to_string(), time);
+        Ok(())
+    }
+
+    fn notifications(&self) -> Result<Vec<u32>> {
+        Ok(self.inner().notifications.keys().copied().collect())
+    }
+
+    fn add_notification(&self, id: u32, call_id: &str) -> Result<()> {
+        self.inner().notifications.insert(id, call_id.to_string());
+        Ok(())
+    }
+
+    fn remove_notification(&self, id: u32) -> Result<()> {
+        self.inner().notifications.remove(&id);
+        Ok(())
+    }
+
+    fn blocklist(&self) -> Result<Vec<BlockRule>> {
+        Ok(self.inner().blocklist.clone())
+    }
+
+    fn add_block(&self, rule: &BlockRule) -> Result<bool> {
+        let blocklist = &mut self.inner().blocklist;
+        if blocklist.contains(rule) {
+            return Ok(false);
+        }
+
+        blocklist.push(rule.clone());
+        Ok(true)
+    }
+
+    fn remove_block(&self, rule: &BlockRule) -> Result<bool> {
+        let blocklist = &mut self.inner().blocklist;
+        let len = blocklist.len();
+        blocklist.retain(|existing| existing != rule);
+        Ok(blocklist.len() < len)
+    }
+
+    fn log_blocked(&self, call: &Call, rule: &BlockRule) -> Result<()> {
+        let time = call.since.duration_since(UNIX_EPOCH)?.as_secs();
+        self.inner().blocked_calls.push(BlockedCall {
+            time,
+            rule: rule.to_string(),
+            name: call.caller.name.clone(),
+            number: call.caller.number.clone(),
+            uri: call.caller.uri.clone(),
+            account: Some(call.origin.account.clone()),
+            slot: call.origin.slot.clone(),
+        });
+        Ok(())
+    }
+
+    fn blocked_calls(&self) -> Result<Vec<BlockedCall>> {
+        let mut calls = self.inner().blocked_calls.clone();
+        calls.sort_by_key(|call| std::cmp::Reverse(call.time));
+        Ok(calls)
+    }
+
+    fn purge_blocked_calls(&self, before: SystemTime) -> Result<usize> {
+        let before = before.duration_since(UNIX_EPOCH)?.as_secs();
+        let calls = &mut self.inner().blocked_calls;
+        let len = calls.len();
+        calls.retain(|call| call.time >= before);
+        Ok(len - calls.len())
+    }
+
+    fn transcript(&self, call_id: &str) -> Result<Option<String>> {
+        Ok(self.inner().transcripts.get(call_id).map(|(_, transcript)| transcript.clone()))
+    }
+
+    fn set_transcript(&self, call_id: &str, time: SystemTime, transcript: &str) -> Result<()> {
+        self.inner()
+            .transcripts
+            .insert(call_id.to_string(), (time, transcript.to_string()));
+        Ok(())
+    }
+
+    fn purge_transcripts(&self, before: SystemTime) -> Result<usize> {
+        let transcripts = &mut self.inner().transcripts;
+        let len = transcripts.len();
+        transcripts.retain(|_, (time, _)| *time >= before);
+        Ok(len - transcripts.len())
+    }
+
+    fn add_recording(&self, call_id: &str, time: SystemTime, path: &Path) -> Result<()> {
+        let recordings = &mut self.inner().recordings;
+        recordings.retain(|(recorded, _, _)| recorded != path);
+        recordings.push((path.to_path_buf(), call_id.to_string(), time));
+        Ok(())
+    }
+
+    fn recordings(&self, call_id: &str) -> Result<Vec<PathBuf>> {
+        let mut recordings = self.inner().recordings.clone();
+        recordings.retain(|(_, recorded, _)| recorded == call_id);
+        recordings.sort_by_key(|(_, _, time)| *time);
+        Ok(recordings.into_iter().map(|(path, _, _)| path).collect())
+    }
+
+    fn recordings_before(&self, before: SystemTime) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .inner()
+            .recordings
+            .iter()
+            .filter(|(_, _, time)| *time < before)
+            .map(|(path, _, _)| path.clone())
+            .collect())
+    }
+
+    fn remove_recording(&self, path: &Path) -> Result<()> {
+        self.inner().recordings.retain(|(recorded, _, _)| recorded != path);
+        Ok(())
+    }
+
+    fn seal_recording(&self, _data: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn unseal_recording(&self, _data: &[u8]) -> Result<Vec<u8>> {
+        bail!("Recording is encrypted but the store has no key")
+    }
+
+    fn cache_entry(&self, key: &str) -> Result<Option<CacheEntry>> {
+        Ok(self.inner().cache.get(key).cloned())
+    }
+
+    fn cache_entries(&self) -> Result<Vec<CacheEntry>> {
+        Ok(self.inner().cache.values().cloned().collect())
+    }
+
+    fn put_cache_entry(&self, entry: &CacheEntry) -> Result<()> {
+        self.inner().cache.insert(entry.key.clone(), entry.clone());
+        Ok(())
+    }
+
+    fn invalidate_cache(&self, prefix: &str) -> Result<()> {
+        self.inner().cache.retain(|key, _| !key.starts_with(prefix));
+        Ok(())
+    }
+
+    fn purge_cache(&self, before: SystemTime) -> Result<usize> {
+        let cache = &mut self.inner().cache;
+        let len = cache.len();
+        cache.retain(|_, entry| entry.stored >= before);
+        Ok(len - cache.len())
+    }
+}
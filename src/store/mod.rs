@@ -0,0 +1,197 @@
+use crate::callstate::blocklist::BlockRule;
+use crate::callstate::Call;
+use crate::config::CallLogConfig;
+use crate::sipsocket::ids::ContactId;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+mod blocklist;
+mod crypto;
+mod favorites;
+mod memory;
+mod recordings;
+mod retention;
+mod sqlite;
+
+pub use blocklist::BlockedCall;
+pub use crypto::CallLogKey;
+pub use favorites::Favorite;
+pub use memory::Memory;
+pub use retention::Purged;
+pub use sqlite::Sqlite;
+
+/// A cached API response
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub key: String,
+    pub stored: SystemTime,
+    pub value: Value,
+}
+
+/// Numbers remembered for redial and callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LastNumber {
+    Dialed,
+    Missed,
+}
+
+impl LastNumber {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dialed => "dialed",
+            Self::Missed => "missed",
+        }
+    }
+}
+
+/// Backend persisting the local state.
+///
+/// Implemented by [`Sqlite`] for the state on disk and by [`Memory`] for
+/// state which is gone with the process, e.g. in tests. Library users may
+/// provide their own and wrap it with [`Store::new`].
+pub trait Storage: Send + Sync {
+    /// Access token of the API
+    fn token(&self) -> Result<Option<String>>;
+    fn set_token(&self, token: &str) -> Result<()>;
+
+    /// Access token of a further account, see [`crate::config::AccountConfig`]
+    fn account_token(&self, account: &str) -> Result<Option<String>>;
+    fn set_account_token(&self, account: &str, token: &str) -> Result<()>;
+
+    /// All favorites ordered by name
+    fn favorites(&self) -> Result<Vec<Favorite>>;
+    fn favorite(&self, name: &str) -> Result<Option<Favorite>>;
+
+    /// Adds a favorite, replacing an existing one with the same name
+    fn add_favorite(&self, favorite: &Favorite) -> Result<()>;
+
+    /// Removes a favorite and returns whether it existed
+    fn remove_favorite(&self, name: &str) -> Result<bool>;
+
+    fn last_number(&self, kind: LastNumber) -> Result<Option<String>>;
+    fn set_last_number(&self, kind: LastNumber, number: &str) -> Result<()>;
+
+    /// Contact registered for a SIP user by an earlier run
+    fn contact(&self, username: &str) -> Result<Option<ContactId>>;
+    fn set_contact(&self, username: &str, contact: &ContactId) -> Result<()>;
+
+    /// Time stored for a named checkpoint, e.g. the last sync of some data
+    fn checkpoint(&self, name: &str) -> Result<Option<SystemTime>>;
+    fn set_checkpoint(&self, name: &str, time: SystemTime) -> Result<()>;
+
+    /// IDs of desktop notifications which have been shown but not closed yet
+    fn notifications(&self) -> Result<Vec<u32>>;
+
+    /// Remembers an open notification for the call with the given Call-ID
+    fn add_notification(&self, id: u32, call_id: &str) -> Result<()>;
+    fn remove_notification(&self, id: u32) -> Result<()>;
+
+    /// Rules of the blocklist in the order they were added
+    fn blocklist(&self) -> Result<Vec<BlockRule>>;
+
+    /// Adds a rule and returns whether it was new
+    fn add_block(&self, rule: &BlockRule) -> Result<bool>;
+
+    /// Removes a rule and returns whether it existed
+    fn remove_block(&self, rule: &BlockRule) -> Result<bool>;
+
+    /// Records a call rejected by the given rule
+    fn log_blocked(&self, call: &Call, rule: &BlockRule) -> Result<()>;
+
+    /// Calls rejected by the blocklist, most recent first
+    fn blocked_calls(&self) -> Result<Vec<BlockedCall>>;
+
+    /// Removes calls logged before the given time and returns their number
+    fn purge_blocked_calls(&self, before: SystemTime) -> Result<usize>;
+
+    /// Transcript of the audio of the call with the given Call-ID
+    fn transcript(&self, call_id: &str) -> Result<Option<String>>;
+
+    /// Attaches a transcript to a call taken at the given time, replacing an earlier one
+    fn set_transcript(&self, call_id: &str, time: SystemTime, transcript: &str) -> Result<()>;
+
+    /// Removes transcripts of calls before the given time and returns their number
+    fn purge_transcripts(&self, before: SystemTime) -> Result<usize>;
+
+    /// Remembers the file of a recording of the call with the given Call-ID
+    fn add_recording(&self, call_id: &str, time: SystemTime, path: &Path) -> Result<()>;
+
+    /// Files of the recordings of the call with the given Call-ID, oldest first
+    fn recordings(&self, call_id: &str) -> Result<Vec<PathBuf>>;
+
+    /// Files of recordings made before the given time
+    fn recordings_before(&self, before: SystemTime) -> Result<Vec<PathBuf>>;
+
+    /// Forgets a recording once its file is gone
+    fn remove_recording(&self, path: &Path) -> Result<()>;
+
+    /// Encrypts the audio of a recording like the call log, none if the call log is not encrypted
+    fn seal_recording(&self, data: &[u8]) -> Option<Vec<u8>>;
+
+    /// Decrypts the audio of a recording sealed before
+    fn unseal_recording(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    fn cache_entry(&self, key: &str) -> Result<Option<CacheEntry>>;
+    fn cache_entries(&self) -> Result<Vec<CacheEntry>>;
+
+    /// Adds an entry, replacing an existing one with the same key
+    fn put_cache_entry(&self, entry: &CacheEntry) -> Result<()>;
+
+    /// Drops all entries with keys starting with the given prefix
+    fn invalidate_cache(&self, prefix: &str) -> Result<()>;
+
+    /// Drops all entries stored before the given time and returns their number
+    fn purge_cache(&self, before: SystemTime) -> Result<usize>;
+}
+
+/// Local persistent state, shared by all users of a clone
+#[derive(Clone)]
+pub struct Store(Arc<dyn Storage>);
+
+impl Store {
+    pub fn new(storage: impl Storage + 'static) -> Self {
+        Self(Arc::new(storage))
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("ucware").join("state.db"))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(Sqlite::open(path)?))
+    }
+
+    pub fn open_default() -> Result<Self> {
+        let path = Self::default_path().context("No default location for the store available")?;
+        Self::open(path)
+    }
+
+    /// Opens the default store with the call log encrypted as configured
+    pub fn open_configured(config: &CallLogConfig) -> Result<Self> {
+        let path = Self::default_path().context("No default location for the store available")?;
+
+        let mut sqlite = Sqlite::open(path)?;
+        if let Some(encryption) = config.encryption {
+            sqlite.encrypt_call_log(&CallLogKey::configured(encryption)?)?;
+        }
+
+        Ok(Self::new(sqlite))
+    }
+
+    /// State which is not persisted at all
+    pub fn memory() -> Self {
+        Self::new(Memory::default())
+    }
+}
+
+impl Deref for Store {
+    type Target = dyn Storage;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
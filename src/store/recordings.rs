@@ -0,0 +1,56 @@
+use super::sqlite::{create_private_dir, create_private_file};
+use crate::store::Store;
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Extension appended to recordings encrypted like the call log
+pub(super) const SEALED_EXTENSION: &str = "sealed";
+
+impl Store {
+    /// Writes a WAV recording of a call into the directory, encrypted if the call log is
+    pub fn save_recording(&self, dir: &Path, name: &str, call_id: &str, wav: &[u8]) -> Result<PathBuf> {
+        let (path, data) = match self.seal_recording(wav) {
+            Some(sealed) => (dir.join(format!("{name}.wav.{SEALED_EXTENSION}")), sealed),
+            None => (dir.join(format!("{name}.wav")), wav.to_vec()),
+        };
+
+        create_private_dir(dir)?;
+        create_private_file(&path)?;
+        std::fs::write(&path, data)
+            .with_context(|| format!("Failed to write recording {path}", path = path.display()))?;
+
+        self.add_recording(call_id, SystemTime::now(), &path)?;
+        Ok(path)
+    }
+
+    /// Reads a recording as WAV, decrypting it if it was sealed
+    pub fn load_recording(&self, path: &Path) -> Result<Vec<u8>> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read recording {path}", path = path.display()))?;
+
+        match path.extension() == Some(OsStr::new(SEALED_EXTENSION)) {
+            true => self.unseal_recording(&data),
+            false => Ok(data),
+        }
+    }
+
+    /// Deletes recordings made before the given time and returns their number
+    pub fn purge_recordings(&self, before: SystemTime) -> Result<usize> {
+        let recordings = self.recordings_before(before)?;
+        for path in &recordings {
+            match std::fs::remove_file(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Failed to delete recording {path}", path = path.display()));
+                }
+            }
+            self.remove_recording(path)?;
+        }
+
+        Ok(recordings.len())
+    }
+}
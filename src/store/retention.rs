@@ -0,0 +1,50 @@
+use crate::config::{Age, RetentionConfig};
+use crate::store::Store;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::time::SystemTime;
+
+/// Number of entries removed by [`Store::enforce_retention`]
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Purged {
+    pub call_log: usize,
+    pub cache: usize,
+    pub recordings: usize,
+}
+
+impl fmt::Display for Purged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{call_log} call log entries, {cache} cache entries, {recordings} recordings",
+            call_log = self.call_log,
+            cache = self.cache,
+            recordings = self.recordings,
+        )
+    }
+}
+
+impl Store {
+    /// Removes all data older than the retention config allows
+    pub fn enforce_retention(&self, config: &RetentionConfig, now: SystemTime) -> Result<Purged> {
+        let before = |age: Age| now.checked_sub(age.0).context("Age exceeds the epoch");
+
+        let mut purged = Purged::default();
+
+        if let Some(age) = config.call_log {
+            let before = before(age)?;
+            purged.call_log = self.purge_blocked_calls(before)? + self.purge_transcripts(before)?;
+        }
+
+        if let Some(age) = config.cache {
+            purged.cache = self.purge_cache(before(age)?)?;
+        }
+
+        if let Some(age) = config.recordings {
+            purged.recordings = self.purge_recordings(before(age)?)?;
+        }
+
+        Ok(purged)
+    }
+}
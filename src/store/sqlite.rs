@@ -0,0 +1,714 @@
+use super::crypto::{CallLogKey, Cipher};
+use super::recordings::SEALED_EXTENSION;
+use super::{BlockedCall, CacheEntry, Favorite, LastNumber, Storage};
+use crate::callstate::blocklist::BlockRule;
+use crate::callstate::Call;
+use crate::sipsocket::ids::ContactId;
+use anyhow::{bail, Context, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Time to wait for other processes, e.g. the daemon, to finish writing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Schema migrations - applied in order, never change existing entries
+const MIGRATIONS: &[&str] = &[
+    // 1: Favorites
+    "CREATE TABLE favorites (
+        name TEXT PRIMARY KEY NOT NULL,
+        number TEXT NOT NULL
+    )",
+    // 2: Checkpoints
+    "CREATE TABLE checkpoints (
+        name TEXT PRIMARY KEY NOT NULL,
+        time INTEGER NOT NULL
+    )",
+    // 3: Open notifications
+    "CREATE TABLE notifications (
+        id INTEGER PRIMARY KEY NOT NULL,
+        call_id TEXT NOT NULL
+    )",
+    // 4: Blocklist and calls rejected by it
+    "CREATE TABLE blocklist (
+        rule TEXT PRIMARY KEY NOT NULL,
+        added INTEGER NOT NULL
+    );
+    CREATE TABLE blocked_calls (
+        time INTEGER NOT NULL,
+        rule TEXT NOT NULL,
+        name TEXT,
+        number TEXT,
+        uri TEXT NOT NULL
+    )",
+    // 5: Token and cached API responses, formerly plain files
+    "CREATE TABLE token (
+        id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+        token TEXT NOT NULL
+    );
+    CREATE TABLE cache (
+        key TEXT PRIMARY KEY NOT NULL,
+        stored INTEGER NOT NULL,
+        value TEXT NOT NULL
+    )",
+    // 6: Salt and verifier of the key encrypting the call log
+    "CREATE TABLE call_log_key (
+        id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+        salt BLOB NOT NULL,
+        verifier BLOB NOT NULL
+    )",
+    // 7: Numbers for redial and callback
+    "CREATE TABLE last_numbers (
+        kind TEXT PRIMARY KEY NOT NULL,
+        number TEXT NOT NULL
+    )",
+    // 8: Registered contacts
+    "CREATE TABLE contacts (
+        username TEXT PRIMARY KEY NOT NULL,
+        user TEXT NOT NULL,
+        instance TEXT NOT NULL
+    )",
+    // 9: Tokens of further accounts
+    "CREATE TABLE account_tokens (
+        account TEXT PRIMARY KEY NOT NULL,
+        token TEXT NOT NULL
+    )",
+    // 10: Origin of blocked calls
+    "ALTER TABLE blocked_calls ADD COLUMN account TEXT;
+    ALTER TABLE blocked_calls ADD COLUMN slot TEXT",
+    // 11: Transcripts of call audio
+    "CREATE TABLE transcripts (
+        call_id TEXT PRIMARY KEY NOT NULL,
+        time INTEGER NOT NULL,
+        transcript TEXT NOT NULL
+    )",
+    // 12: Slots with their SIP credentials were cached before
+    "DELETE FROM cache WHERE key LIKE '%/slot/getAll%'",
+    // 13: Host of registered contacts, unknown for contacts stored before
+    "ALTER TABLE contacts ADD COLUMN host TEXT",
+    // 14: Files of recordings, kept to purge them with the call log
+    "CREATE TABLE recordings (
+        path TEXT PRIMARY KEY NOT NULL,
+        call_id TEXT NOT NULL,
+        time INTEGER NOT NULL
+    )",
+];
+
+/// Known plaintext telling whether the call log key is the one used before
+const VERIFIER: &[u8] = b"ucware-cli call log";
+
+/// State stored in an SQLite database
+pub struct Sqlite {
+    conn: Mutex<Connection>,
+
+    /// Encrypts callers in the call log if enabled
+    cipher: Option<Cipher>,
+}
+
+impl Sqlite {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        // An in-memory database has no files to protect
+        if path != Path::new(":memory:") {
+            if let Some(dir) = path.parent() {
+                create_private_dir(dir)?;
+            }
+            create_private_file(path)?;
+        }
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open store: {path}", path = path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        // Purged entries must not linger in free pages
+        conn.pragma_update(None, "secure_delete", true)?;
+
+        Self::migrate(&conn)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            cipher: None,
+        })
+    }
+
+    /// Encrypts callers, transcripts and recordings in the call log from now on, including those logged before.
+    ///
+    /// Fails if the key differs from the one used before.
+    pub fn encrypt_call_log(&mut self, key: &CallLogKey) -> Result<()> {
+        let stored: Option<(Vec<u8>, Vec<u8>)> = self.with(|conn| {
+            conn.query_row("SELECT salt, verifier FROM call_log_key WHERE id = 0", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+        })?;
+
+        let cipher = match stored {
+            Some((salt, verifier)) => {
+                let cipher = Cipher::new(key, &salt)?;
+                cipher.decrypt(&verifier)?;
+                cipher
+            }
+            None => {
+                let salt = rand::random::<[u8; 16]>();
+                let cipher = Cipher::new(key, &salt)?;
+                let verifier = cipher.encrypt(VERIFIER);
+                self.with(|conn| {
+                    conn.execute(
+                        "INSERT INTO call_log_key (id, salt, verifier) VALUES (0, ?1, ?2)",
+                        params![salt, verifier],
+                    )
+                })?;
+                cipher
+            }
+        };
+
+        let encrypt = |value: Option<String>| value.map(|value| cipher.encrypt(value.as_bytes()));
+        self.with(|conn| {
+            let tx = conn.unchecked_transaction()?;
+
+            let plain = tx
+                .prepare("SELECT rowid, name, number, uri FROM blocked_calls WHERE typeof(uri) = 'text'")?
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            for (rowid, name, number, uri) in plain {
+                tx.execute(
+                    "UPDATE blocked_calls SET name = ?2, number = ?3, uri = ?4 WHERE rowid = ?1",
+                    params![rowid, encrypt(name), encrypt(number), encrypt(uri)],
+                )?;
+            }
+
+            let plain = tx
+                .prepare("SELECT call_id, transcript FROM transcripts WHERE typeof(transcript) = 'text'")?
+                .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            for (call_id, transcript) in plain {
+                tx.execute(
+                    "UPDATE transcripts SET transcript = ?2 WHERE call_id = ?1",
+                    params![call_id, encrypt(transcript)],
+                )?;
+            }
+
+            tx.commit()
+        })?;
+
+        self.seal_plain_recordings(&cipher)?;
+
+        self.cipher = Some(cipher);
+        Ok(())
+    }
+
+    /// Replaces the files of recordings made before the call log was encrypted by sealed ones
+    fn seal_plain_recordings(&self, cipher: &Cipher) -> Result<()> {
+        let plain = self.with(|conn| {
+            conn.prepare("SELECT path FROM recordings WHERE path NOT LIKE '%.' || ?1")?
+                .query_map(params![SEALED_EXTENSION], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+
+        for path in plain {
+            let data = match std::fs::read(&path) {
+                Ok(data) => data,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    self.with(|conn| conn.execute("DELETE FROM recordings WHERE path = ?1", params![path]))?;
+                    continue;
+                }
+                Err(err) => return Err(err).with_context(|| format!("Failed to read recording {path}")),
+            };
+
+            let sealed = format!("{path}.{SEALED_EXTENSION}");
+            create_private_file(Path::new(&sealed))?;
+            std::fs::write(&sealed, cipher.encrypt(&data))
+                .with_context(|| format!("Failed to write recording {sealed}"))?;
+            self.with(|conn| conn.execute("UPDATE recordings SET path = ?2 WHERE path = ?1", params![path, sealed]))?;
+            std::fs::remove_file(&path).with_context(|| format!("Failed to delete recording {path}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts a call log value if enabled
+    fn seal(&self, value: Option<&str>) -> Value {
+        match (value, &self.cipher) {
+            (None, _) => Value::Null,
+            (Some(value), Some(cipher)) => Value::Blob(cipher.encrypt(value.as_bytes())),
+            (Some(value), None) => Value::Text(value.to_string()),
+        }
+    }
+
+    /// Decrypts a call log value, which may have been stored before encryption was enabled
+    fn unseal(&self, value: Value) -> Result<Option<String>> {
+        match value {
+            Value::Null => Ok(None),
+            Value::Text(value) => Ok(Some(value)),
+            Value::Blob(data) => {
+                let cipher = self
+                    .cipher
+                    .as_ref()
+                    .context("Call log is encrypted but call-log.encryption is not configured")?;
+                Ok(Some(String::from_utf8(cipher.decrypt(&data)?)?))
+            }
+            value => bail!("Unexpected value in call log: {value:?}"),
+        }
+    }
+
+    fn migrate(conn: &Connection) -> Result<()> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            debug!("Migrating store to version {version}", version = i + 1);
+            conn.execute_batch(migration)?;
+            conn.pragma_update(None, "user_version", i + 1)?;
+        }
+
+        Ok(())
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<R>) -> Result<R> {
+        let conn = self.conn.lock().expect("not poisoned");
+        Ok(f(&conn)?)
+    }
+}
+
+/// Creates the directory accessible by the owner only, as the store holds tokens
+#[cfg(unix)]
+pub(super) fn create_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(super) fn create_private_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+/// Creates the file readable by the owner only and restricts an existing one created before
+#[cfg(unix)]
+pub(super) fn create_private_file(path: &Path) -> Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to open store: {path}", path = path.display()))?;
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(super) fn create_private_file(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn favorite(row: &Row) -> rusqlite::Result<Favorite> {
+    Ok(Favorite {
+        name: row.get(0)?,
+        number: row.get(1)?,
+    })
+}
+
+/// Columns of a blocked call, with the caller possibly sealed
+type BlockedRow = (i64, String, Value, Value, Value, Option<String>, Option<String>);
+
+fn cache_entry(row: &Row) -> rusqlite::Result<(String, i64, String)> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+}
+
+impl Storage for Sqlite {
+    fn token(&self) -> Result<Option<String>> {
+        self.with(|conn| {
+            conn.query_row("SELECT token FROM token WHERE id = 0", [], |row| row.get(0))
+                .optional()
+        })
+    }
+
+    fn set_token(&self, token: &str) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO token (id, token) VALUES (0, ?1)",
+                params![token],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn account_token(&self, account: &str) -> Result<Option<String>> {
+        self.with(|conn| {
+            conn.query_row(
+                "SELECT token FROM account_tokens WHERE account = ?1",
+                params![account],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+    }
+
+    fn set_account_token(&self, account: &str, token: &str) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO account_tokens (account, token) VALUES (?1, ?2)",
+                params![account, token],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn favorites(&self) -> Result<Vec<Favorite>> {
+        self.with(|conn| {
+            conn.prepare("SELECT name, number FROM favorites ORDER BY name")?
+                .query_map([], favorite)?
+                .collect()
+        })
+    }
+
+    fn favorite(&self, name: &str) -> Result<Option<Favorite>> {
+        self.with(|conn| {
+            conn.query_row(
+                "SELECT name, number FROM favorites WHERE name = ?1",
+                params![name],
+                favorite,
+            )
+            .optional()
+        })
+    }
+
+    fn add_favorite(&self, favorite: &Favorite) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO favorites (name, number) VALUES (?1, ?2)",
+                params![favorite.name, favorite.number],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn remove_favorite(&self, name: &str) -> Result<bool> {
+        let removed = self.with(|conn| {
+            conn.execute("DELETE FROM favorites WHERE name = ?1", params![name])
+        })?;
+        Ok(removed > 0)
+    }
+
+    fn last_number(&self, kind: LastNumber) -> Result<Option<String>> {
+        self.with(|conn| {
+            conn.query_row(
+                "SELECT number FROM last_numbers WHERE kind = ?1",
+                params![kind.as_str()],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+    }
+
+    fn set_last_number(&self, kind: LastNumber, number: &str) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO last_numbers (kind, number) VALUES (?1, ?2)",
+                params![kind.as_str(), number],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn contact(&self, username: &str) -> Result<Option<ContactId>> {
+        self.with(|conn| {
+            conn.query_row(
+                "SELECT user, host, instance FROM contacts WHERE username = ?1 AND host IS NOT NULL",
+                params![username],
+                |row| {
+                    Ok(ContactId {
+                        user: row.get(0)?,
+                        host: row.get(1)?,
+                        instance: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+        })
+    }
+
+    fn set_contact(&self, username: &str, contact: &ContactId) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO contacts (username, user, host, instance) VALUES (?1, ?2, ?3, ?4)",
+                params![username, contact.user, contact.host, contact.instance],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn checkpoint(&self, name: &str) -> Result<Option<SystemTime>> {
+        let time: Option<i64> = self.with(|conn| {
+            conn.query_row(
+                "SELECT time FROM checkpoints WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+
+        Ok(time.map(|time| UNIX_EPOCH + Duration::from_secs(time.max(0) as u64)))
+    }
+
+    fn set_checkpoint(&self, name: &str, time: SystemTime) -> Result<()> {
+        let time = time.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO checkpoints (name, time) VALUES (?1, ?2)",
+                params![name, time],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn notifications(&self) -> Result<Vec<u32>> {
+        self.with(|conn| {
+            conn.prepare("SELECT id FROM notifications")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+    }
+
+    fn add_notification(&self, id: u32, call_id: &str) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO notifications (id, call_id) VALUES (?1, ?2)",
+                params![id, call_id],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn remove_notification(&self, id: u32) -> Result<()> {
+        self.with(|conn| conn.execute("DELETE FROM notifications WHERE id = ?1", params![id]))?;
+        Ok(())
+    }
+
+    fn blocklist(&self) -> Result<Vec<BlockRule>> {
+        let rules: Vec<String> = self.with(|conn| {
+            conn.prepare("SELECT rule FROM blocklist ORDER BY added")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })?;
+
+        rules.iter().map(|rule| rule.parse()).collect()
+    }
+
+    fn add_block(&self, rule: &BlockRule) -> Result<bool> {
+        let added = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let inserted = self.with(|conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO blocklist (rule, added) VALUES (?1, ?2)",
+                params![rule.to_string(), added],
+            )
+        })?;
+        Ok(inserted > 0)
+    }
+
+    fn remove_block(&self, rule: &BlockRule) -> Result<bool> {
+        let removed = self.with(|conn| {
+            conn.execute("DELETE FROM blocklist WHERE rule = ?1", params![rule.to_string()])
+        })?;
+        Ok(removed > 0)
+    }
+
+    fn log_blocked(&self, call: &Call, rule: &BlockRule) -> Result<()> {
+        let time = call.since.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.with(|conn| {
+            conn.execute(
+                "INSERT INTO blocked_calls (time, rule, name, number, uri, account, slot)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    time,
+                    rule.to_string(),
+                    self.seal(call.caller.name.as_deref()),
+                    self.seal(call.caller.number.as_deref()),
+                    self.seal(Some(&call.caller.uri)),
+                    call.origin.account,
+                    call.origin.slot
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn blocked_calls(&self) -> Result<Vec<BlockedCall>> {
+        let rows = self.with(|conn| {
+            conn.prepare(
+                "SELECT time, rule, name, number, uri, account, slot FROM blocked_calls ORDER BY time DESC",
+            )?
+            .query_map([], |row| {
+                let time: i64 = row.get(0)?;
+                Ok((time, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?))
+            })?
+            .collect::<rusqlite::Result<Vec<BlockedRow>>>()
+        })?;
+
+        rows.into_iter()
+            .map(|(time, rule, name, number, uri, account, slot)| {
+                Ok(BlockedCall {
+                    time: time.max(0) as u64,
+                    rule,
+                    name: self.unseal(name)?,
+                    number: self.unseal(number)?,
+                    uri: self.unseal(uri)?.unwrap_or_default(),
+                    account,
+                    slot,
+                })
+            })
+            .collect()
+    }
+
+    fn purge_blocked_calls(&self, before: SystemTime) -> Result<usize> {
+        let before = before.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.with(|conn| conn.execute("DELETE FROM blocked_calls WHERE time < ?1", params![before]))
+    }
+
+    fn transcript(&self, call_id: &str) -> Result<Option<String>> {
+        let transcript = self.with(|conn| {
+            conn.query_row(
+                "SELECT transcript FROM transcripts WHERE call_id = ?1",
+                params![call_id],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+
+        match transcript {
+            Some(transcript) => self.unseal(transcript),
+            None => Ok(None),
+        }
+    }
+
+    fn set_transcript(&self, call_id: &str, time: SystemTime, transcript: &str) -> Result<()> {
+        let time = time.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO transcripts (call_id, time, transcript) VALUES (?1, ?2, ?3)",
+                params![call_id, time, self.seal(Some(transcript))],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn purge_transcripts(&self, before: SystemTime) -> Result<usize> {
+        let before = before.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.with(|conn| conn.execute("DELETE FROM transcripts WHERE time < ?1", params![before]))
+    }
+
+    fn add_recording(&self, call_id: &str, time: SystemTime, path: &Path) -> Result<()> {
+        let time = time.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let path = path.to_str().context("Recording path is not valid UTF-8")?;
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO recordings (path, call_id, time) VALUES (?1, ?2, ?3)",
+                params![path, call_id, time],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn recordings(&self, call_id: &str) -> Result<Vec<PathBuf>> {
+        self.with(|conn| {
+            conn.prepare("SELECT path FROM recordings WHERE call_id = ?1 ORDER BY time")?
+                .query_map(params![call_id], |row| Ok(PathBuf::from(row.get::<_, String>(0)?)))?
+                .collect()
+        })
+    }
+
+    fn recordings_before(&self, before: SystemTime) -> Result<Vec<PathBuf>> {
+        let before = before.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.with(|conn| {
+            conn.prepare("SELECT path FROM recordings WHERE time < ?1")?
+                .query_map(params![before], |row| Ok(PathBuf::from(row.get::<_, String>(0)?)))?
+                .collect()
+        })
+    }
+
+    fn remove_recording(&self, path: &Path) -> Result<()> {
+        let path = path.to_str().context("Recording path is not valid UTF-8")?;
+        self.with(|conn| conn.execute("DELETE FROM recordings WHERE path = ?1", params![path]))?;
+        Ok(())
+    }
+
+    fn seal_recording(&self, data: &[u8]) -> Option<Vec<u8>> {
+        Some(self.cipher.as_ref()?.encrypt(data))
+    }
+
+    fn unseal_recording(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.cipher
+            .as_ref()
+            .context("Recording is encrypted but call-log.encryption is not configured")?
+            .decrypt(data)
+    }
+
+    fn cache_entry(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let row = self.with(|conn| {
+            conn.query_row(
+                "SELECT key, stored, value FROM cache WHERE key = ?1",
+                params![key],
+                cache_entry,
+            )
+            .optional()
+        })?;
+
+        row.map(from_cache_row).transpose()
+    }
+
+    fn cache_entries(&self) -> Result<Vec<CacheEntry>> {
+        let rows = self.with(|conn| {
+            conn.prepare("SELECT key, stored, value FROM cache")?
+                .query_map([], cache_entry)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+
+        rows.into_iter().map(from_cache_row).collect()
+    }
+
+    fn put_cache_entry(&self, entry: &CacheEntry) -> Result<()> {
+        let stored = entry.stored.duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let value = serde_json::to_string(&entry.value)?;
+        self.with(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO cache (key, stored, value) VALUES (?1, ?2, ?3)",
+                params![entry.key, stored, value],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn invalidate_cache(&self, prefix: &str) -> Result<()> {
+        self.with(|conn| {
+            conn.execute(
+                "DELETE FROM cache WHERE substr(key, 1, length(?1)) = ?1",
+                params![prefix],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn purge_cache(&self, before: SystemTime) -> Result<usize> {
+        let before = before.duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        self.with(|conn| conn.execute("DELETE FROM cache WHERE stored < ?1", params![before]))
+    }
+}
+
+fn from_cache_row((key, stored, value): (String, i64, String)) -> Result<CacheEntry> {
+    Ok(CacheEntry {
+        key,
+        stored: UNIX_EPOCH + Duration::from_millis(stored.max(0) as u64),
+        value: serde_json::from_str(&value)?,
+    })
+}
@@ -0,0 +1,247 @@
+use crate::callstate::AgentState;
+use crate::daemon::Daemon;
+use anyhow::Result;
+use ksni::menu::{CheckmarkItem, StandardItem, SubMenu};
+use ksni::{MenuItem, TrayMethods};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::select;
+use tracing::warn;
+
+/// Status notifier item showing the call state of the notifier
+pub struct Tray {
+    daemon: Arc<Daemon>,
+    runtime: Handle,
+}
+
+impl Tray {
+    fn dial(&self, number: String) {
+        let daemon = self.daemon.clone();
+        self.runtime.spawn(async move {
+            if let Err(err) = daemon.dial(&number).await {
+                warn!("Failed to dial {number}: {err:#}");
+            }
+        });
+    }
+
+    fn set_paused(&self, paused: bool) {
+        let daemon = self.daemon.clone();
+        self.runtime.spawn(async move {
+            let result = if paused {
+                daemon.pause(None).await
+            } else {
+                daemon.resume().await
+            };
+
+            if let Err(err) = result {
+                warn!("Failed to change agent state: {err:#}");
+            }
+        });
+    }
+}
+
+impl ksni::Tray for Tray {
+    fn id(&self) -> String {
+        env!("CARGO_PKG_NAME").into()
+    }
+
+    fn title(&self) -> String {
+        "UCware".into()
+    }
+
+    fn icon_name(&self) -> String {
+        if !self.daemon.calls.registered() {
+            "network-offline".into()
+        } else if !self.daemon.calls.calls().is_empty() {
+            "call-start".into()
+        } else if self.daemon.calls.dnd(&self.daemon.config.get()) {
+            "user-busy".into()
+        } else {
+            "phone".into()
+        }
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        let mut description = Vec::new();
+        match self.daemon.calls.missed() {
+            0 => {}
+            missed => description.push(format!("{missed} missed calls")),
+        }
+        match self.daemon.calls.voicemail().new {
+            0 => {}
+            voicemail => description.push(format!("{voicemail} new voicemails")),
+        }
+        let description = description.join("\n");
+
+        ksni::ToolTip {
+            title: self.title(),
+            description,
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let mut menu = Vec::new();
+
+        menu.push(
+            StandardItem {
+                label: if self.daemon.calls.registered() {
+                    "Registered".into()
+                } else {
+                    "Not registered".into()
+                },
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        menu.push(
+            CheckmarkItem {
+                label: "Do not disturb".into(),
+                checked: self.daemon.calls.dnd(&self.daemon.config.get()),
+                activate: Box::new(|tray: &mut Self| {
+                    let dnd = tray.daemon.calls.dnd(&tray.daemon.config.get());
+                    tray.daemon.calls.set_dnd(!dnd);
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        let agent = self.daemon.calls.agent();
+        menu.push(
+            CheckmarkItem {
+                label: match &agent {
+                    AgentState::Paused { reason: Some(reason) } => format!("Paused ({reason})"),
+                    AgentState::WrapUp { .. } => "Paused (wrap-up)".into(),
+                    _ => "Paused".into(),
+                },
+                checked: agent != AgentState::Available,
+                activate: Box::new(move |tray: &mut Self| {
+                    tray.set_paused(agent == AgentState::Available);
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        let missed = self.daemon.calls.recent_missed();
+
+        let mut submenu = missed
+            .iter()
+            .map(|call| {
+                let number = call.caller.number.clone();
+                StandardItem {
+                    label: match (&call.caller.name, &call.caller.number) {
+                        (Some(name), Some(number)) => format!("{name} ({number})"),
+                        (Some(name), None) => name.clone(),
+                        (None, Some(number)) => number.clone(),
+                        (None, None) => "Unknown".into(),
+                    },
+                    icon_name: "call-start".into(),
+                    enabled: number.is_some(),
+                    activate: Box::new(move |tray: &mut Self| {
+                        if let Some(number) = &number {
+                            tray.dial(number.clone());
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect::<Vec<_>>();
+
+        if !missed.is_empty() {
+            submenu.push(MenuItem::Separator);
+            submenu.push(
+                StandardItem {
+                    label: "Clear".into(),
+                    icon_name: "edit-clear".into(),
+                    activate: Box::new(|tray: &mut Self| tray.daemon.calls.clear_missed()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        menu.push(
+            SubMenu {
+                label: format!("Missed calls ({missed})", missed = self.daemon.calls.missed()),
+                enabled: !missed.is_empty(),
+                submenu,
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        let voicemail = self.daemon.calls.voicemail();
+        menu.push(
+            StandardItem {
+                label: format!(
+                    "Voicemail ({new} new, {old} old)",
+                    new = voicemail.new,
+                    old = voicemail.old
+                ),
+                icon_name: "mail-message-new".into(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        let favorites = self.daemon.store.favorites().unwrap_or_else(|err| {
+            warn!("Failed to load favorites: {err:#}");
+            Vec::new()
+        });
+
+        menu.push(
+            SubMenu {
+                label: "Favorites".into(),
+                enabled: !favorites.is_empty(),
+                submenu: favorites
+                    .into_iter()
+                    .map(|favorite| {
+                        StandardItem {
+                            label: format!("{name} ({number})", name = favorite.name, number = favorite.number),
+                            icon_name: "call-start".into(),
+                            activate: Box::new(move |tray: &mut Self| tray.dial(favorite.number.clone())),
+                            ..Default::default()
+                        }
+                        .into()
+                    })
+                    .collect(),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        menu
+    }
+}
+
+/// Shows the tray icon and keeps it updated until the daemon goes away
+pub async fn run(daemon: Arc<Daemon>) -> Result<()> {
+    let mut call_changes = daemon.calls.subscribe();
+    let mut config_changes = daemon.config.subscribe();
+
+    let tray = Tray {
+        daemon,
+        runtime: Handle::current(),
+    };
+
+    let handle = tray.spawn().await?;
+
+    loop {
+        select! {
+            changed = call_changes.changed() => if changed.is_err() { break },
+            changed = config_changes.changed() => if changed.is_err() { break },
+        }
+
+        handle.update(|_| {}).await;
+    }
+
+    handle.shutdown().await;
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::ucware::admin::AdminNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Device {
+    pub mac: String,
+    pub model: String,
+    pub firmware: Option<String>,
+
+    /// Address the device last registered from
+    pub ip: Option<String>,
+
+    pub registered: bool,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct DeviceInterface;
+
+impl Interface for DeviceInterface {
+    const PATH: &'static str = "device";
+}
+
+pub type DeviceInterfaceClient = InterfaceClient<AdminNamespace, DeviceInterface>;
+
+impl DeviceInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<Device>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    /// Regenerates the provisioning config and tells the device to fetch it
+    pub async fn reprovision(&self, mac: &str) -> Result<()> {
+        let _: IgnoredAny = self.request("reprovision", rpc_params![mac]).await?;
+        Ok(())
+    }
+
+    pub async fn reboot(&self, mac: &str) -> Result<()> {
+        let _: IgnoredAny = self.request("reboot", rpc_params![mac]).await?;
+        Ok(())
+    }
+}
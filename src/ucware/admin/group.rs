@@ -0,0 +1,53 @@
+use crate::ucware::admin::AdminNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Group {
+    pub id: u64,
+    pub name: String,
+
+    #[serde(default)]
+    pub permissions: Vec<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct GroupInterface;
+
+impl Interface for GroupInterface {
+    const PATH: &'static str = "group";
+}
+
+pub type GroupInterfaceClient = InterfaceClient<AdminNamespace, GroupInterface>;
+
+impl GroupInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<Group>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    /// Returns the IDs of all users in the group
+    pub async fn get_members(&self, group_id: u64) -> Result<Vec<u64>> {
+        self.query("getMembers", rpc_params![group_id]).await
+    }
+
+    pub async fn add_member(&self, group_id: u64, user_id: u64) -> Result<()> {
+        let _: IgnoredAny = self
+            .request("addMember", rpc_params![group_id, user_id])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_member(&self, group_id: u64, user_id: u64) -> Result<()> {
+        let _: IgnoredAny = self
+            .request("removeMember", rpc_params![group_id, user_id])
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use crate::ucware::admin::AdminNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct License {
+    pub name: String,
+    pub total: u64,
+    pub used: u64,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct LicenseInterface;
+
+impl Interface for LicenseInterface {
+    const PATH: &'static str = "license";
+}
+
+pub type LicenseInterfaceClient = InterfaceClient<AdminNamespace, LicenseInterface>;
+
+impl LicenseInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<License>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    pub async fn assign(&self, license: &str, user_id: u64) -> Result<()> {
+        let _: IgnoredAny = self
+            .request("assign", rpc_params![license, user_id])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unassign(&self, license: &str, user_id: u64) -> Result<()> {
+        let _: IgnoredAny = self
+            .request("unassign", rpc_params![license, user_id])
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::ucware::admin::device::DeviceInterfaceClient;
+use crate::ucware::admin::group::GroupInterfaceClient;
+use crate::ucware::admin::license::LicenseInterfaceClient;
+use crate::ucware::admin::queue::QueueInterfaceClient;
+use crate::ucware::admin::slot::SlotInterfaceClient;
+use crate::ucware::admin::user::UserInterfaceClient;
+use crate::ucware::{Derive, Namespace, NamespaceClient};
+
+mod device;
+mod group;
+mod license;
+mod queue;
+mod slot;
+mod user;
+
+pub use group::Group;
+pub use queue::{AdminQueue, NewQueue};
+pub use slot::{AdminSlot, NewSlot};
+pub use user::{NewUser, User};
+
+pub struct AdminNamespace;
+
+impl Namespace for AdminNamespace {
+    const PATH: &'static str = "admin";
+}
+
+pub type AdminNamespaceClient = NamespaceClient<AdminNamespace>;
+
+impl AdminNamespaceClient {
+    pub fn devices(&self) -> DeviceInterfaceClient {
+        self.derive()
+    }
+
+    pub fn users(&self) -> UserInterfaceClient {
+        self.derive()
+    }
+
+    pub fn groups(&self) -> GroupInterfaceClient {
+        self.derive()
+    }
+
+    pub fn licenses(&self) -> LicenseInterfaceClient {
+        self.derive()
+    }
+
+    pub fn slots(&self) -> SlotInterfaceClient {
+        self.derive()
+    }
+
+    pub fn queues(&self) -> QueueInterfaceClient {
+        self.derive()
+    }
+}
@@ -0,0 +1,57 @@
+use crate::ucware::admin::AdminNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminQueue {
+    pub id: u64,
+    pub name: String,
+    pub extension: String,
+
+    /// IDs of the users serving the queue
+    #[serde(default)]
+    pub members: Vec<u64>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Attributes for creating a queue
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewQueue {
+    pub name: String,
+    pub extension: String,
+    pub members: Vec<u64>,
+}
+
+pub struct QueueInterface;
+
+impl Interface for QueueInterface {
+    const PATH: &'static str = "queue";
+}
+
+pub type QueueInterfaceClient = InterfaceClient<AdminNamespace, QueueInterface>;
+
+impl QueueInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<AdminQueue>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    pub async fn create(&self, queue: &NewQueue) -> Result<AdminQueue> {
+        self.request("create", rpc_params![queue]).await
+    }
+
+    pub async fn update(&self, id: u64, queue: &NewQueue) -> Result<AdminQueue> {
+        self.request("update", rpc_params![id, queue]).await
+    }
+
+    pub async fn delete(&self, id: u64) -> Result<()> {
+        let _: IgnoredAny = self.request("delete", rpc_params![id]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::ucware::admin::AdminNamespace;
+use crate::ucware::user::DeviceType;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminSlot {
+    pub id: u64,
+    pub name: String,
+
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+
+    #[serde(rename = "deviceType")]
+    pub device_type: DeviceType,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Attributes for creating a slot
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewSlot {
+    pub name: String,
+
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+
+    #[serde(rename = "deviceType")]
+    pub device_type: DeviceType,
+}
+
+pub struct SlotInterface;
+
+impl Interface for SlotInterface {
+    const PATH: &'static str = "slot";
+}
+
+pub type SlotInterfaceClient = InterfaceClient<AdminNamespace, SlotInterface>;
+
+impl SlotInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<AdminSlot>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    pub async fn create(&self, slot: &NewSlot) -> Result<AdminSlot> {
+        self.request("create", rpc_params![slot]).await
+    }
+
+    pub async fn update(&self, id: u64, slot: &NewSlot) -> Result<AdminSlot> {
+        self.request("update", rpc_params![id, slot]).await
+    }
+
+    pub async fn delete(&self, id: u64) -> Result<()> {
+        let _: IgnoredAny = self.request("delete", rpc_params![id]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::ucware::admin::AdminNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct User {
+    pub id: u64,
+    pub username: String,
+
+    #[serde(rename = "firstName")]
+    pub first_name: String,
+
+    #[serde(rename = "lastName")]
+    pub last_name: String,
+
+    pub email: Option<String>,
+    pub extension: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Attributes for creating a user
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewUser {
+    pub username: String,
+
+    #[serde(rename = "firstName")]
+    pub first_name: String,
+
+    #[serde(rename = "lastName")]
+    pub last_name: String,
+
+    pub email: Option<String>,
+    pub extension: Option<String>,
+
+    /// Initial password, generated by the server if missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+pub struct UserInterface;
+
+impl Interface for UserInterface {
+    const PATH: &'static str = "user";
+}
+
+pub type UserInterfaceClient = InterfaceClient<AdminNamespace, UserInterface>;
+
+impl UserInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<User>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    pub async fn create(&self, user: &NewUser) -> Result<User> {
+        self.request("create", rpc_params![user]).await
+    }
+
+    pub async fn update(&self, id: u64, user: &NewUser) -> Result<User> {
+        self.request("update", rpc_params![id, user]).await
+    }
+
+    pub async fn delete(&self, id: u64) -> Result<()> {
+        let _: IgnoredAny = self.request("delete", rpc_params![id]).await?;
+        Ok(())
+    }
+}
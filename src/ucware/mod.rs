@@ -1,21 +1,44 @@
+use crate::cache::Cache;
+use crate::progress;
+use crate::resolver::Resolver;
+use crate::sipsocket::compact::CompactHeaders;
+use crate::store::Store;
 use crate::sipsocket;
-use crate::sipsocket::ServerTransaction;
+use crate::sipsocket::ids::ContactId;
+use crate::sipsocket::redirect::Redirects;
+use crate::sipsocket::{
+    AddressFamily, ConnectionEvent, DigestCache, Limits, Metrics, MetricsSnapshot, RetryAfter,
+    ServerTransaction, SipTimers, Trace,
+};
+pub use crate::ucware::slot_cache::SlotCache;
 pub use crate::ucware::token::TokenStore;
-use crate::ucware::user::UserNamespaceClient;
-use anyhow::{Context, Result};
+use crate::ucware::admin::AdminNamespaceClient;
+use crate::ucware::system::SystemNamespaceClient;
+use crate::ucware::user::{DeviceType, Slot, UserNamespaceClient};
+use anyhow::{anyhow, bail, Context, Result};
 use http::header::AUTHORIZATION;
 use http::HeaderMap;
 use jsonrpsee::core::client::ClientT;
 use jsonrpsee::core::traits::ToRpcParams;
-use jsonrpsee::http_client::HttpClient;
+use jsonrpsee::http_client::{transport, HttpClient};
+use serde::Serialize;
 use serde::de::DeserializeOwned;
+use serde_json::value::RawValue;
+use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, broadcast, mpsc};
+use tracing::{debug, warn};
 use url::Url;
 
-mod token;
-mod user;
+pub mod admin;
+mod slot_cache;
+pub mod system;
+pub mod token;
+pub mod util;
+pub mod user;
 
 trait Derive<T> {
     fn derive(&self) -> T;
@@ -76,6 +99,8 @@ where
         let Inner {
             ref base_url,
             ref token,
+            ref policy,
+            ..
         } = *self.inner;
 
         let url = base_url
@@ -94,6 +119,7 @@ where
 
         let client = HttpClient::builder()
             .set_headers(headers)
+            .request_timeout(policy.timeout)
             .build(&url)
             .with_context(|| format!("Failed to init client: {url}"))?;
 
@@ -107,11 +133,229 @@ where
         let client = self.client().await?;
         Ok(client.request(method, params).await?)
     }
+
+    /// Like `request` but retried on transient failures, for methods without side effects
+    async fn query<T>(&self, method: &str, params: impl ToRpcParams + Send) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let params = RawParams(params.to_rpc_params()?);
+        let policy = &self.inner.policy;
+
+        let client = self.client().await?;
+        let mut attempt = 0;
+        loop {
+            match client.request(method, params.clone()).await {
+                Err(err) if attempt < policy.retries && transient(&err) => {
+                    attempt += 1;
+                    let delay = policy.backoff * 2u32.saturating_pow(attempt - 1);
+                    debug!(
+                        "Retrying {method} in {delay:?} ({attempt}/{retries}): {err}",
+                        retries = policy.retries,
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Prefix of the cache keys of this interface
+    fn cache_prefix(&self) -> String {
+        format!(
+            "{base_url}{namespace}/{interface}/",
+            base_url = self.inner.base_url,
+            namespace = Namespace::PATH,
+            interface = Interface::PATH,
+        )
+    }
+
+    /// Like `request` but served from the cache if a response younger than `ttl` exists
+    async fn cached_request<T>(
+        &self,
+        method: &str,
+        params: impl ToRpcParams + Send,
+        ttl: Duration,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let params = RawParams(params.to_rpc_params()?);
+
+        let Some(ref cache) = self.inner.cache else {
+            return self.query(method, params).await;
+        };
+
+        let key = format!(
+            "{prefix}{method}{params}",
+            prefix = self.cache_prefix(),
+            params = params.0.as_deref().map_or("", RawValue::get),
+        );
+
+        if let Some(value) = cache.get(&key, ttl) {
+            debug!("Serving {method} from cache");
+            return Ok(value);
+        }
+
+        let value = self.query(method, params).await?;
+        if let Err(err) = cache.put(&key, &value) {
+            warn!("Failed to update cache: {err:#}");
+        }
+
+        Ok(value)
+    }
+
+    /// Drops all cached responses of this interface after it was modified
+    fn invalidate_cache(&self) {
+        if let Some(ref cache) = self.inner.cache
+            && let Err(err) = cache.invalidate(&self.cache_prefix())
+        {
+            warn!("Failed to invalidate cache: {err:#}");
+        }
+    }
+}
+
+/// Parameters already serialized for a request
+#[derive(Clone)]
+struct RawParams(Option<Box<RawValue>>);
+
+impl ToRpcParams for RawParams {
+    fn to_rpc_params(self) -> Result<Option<Box<RawValue>>, serde_json::Error> {
+        Ok(self.0)
+    }
+}
+
+/// Whether a failed request may succeed if sent again.
+///
+/// Covers connection failures and gateway errors of reverse proxies, but not
+/// timeouts as the server may still be busy with the request.
+fn transient(err: &jsonrpsee::core::ClientError) -> bool {
+    let jsonrpsee::core::ClientError::Transport(err) = err else {
+        return false;
+    };
+
+    match err.downcast_ref::<transport::Error>() {
+        Some(transport::Error::Http(_)) => true,
+        Some(transport::Error::Rejected { status_code }) => matches!(status_code, 502..=504),
+        _ => false,
+    }
+}
+
+/// Timeout and retries of JSON-RPC requests
+#[derive(Debug, Clone, Copy)]
+pub struct RequestPolicy {
+    /// Time after which a request is abandoned
+    pub timeout: Duration,
+
+    /// Number of times requests without side effects are repeated after transient failures
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled for each further one
+    pub backoff: Duration,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
 }
 
 struct Inner {
     base_url: Url,
     token: TokenStore,
+    cache: Option<Cache>,
+
+    slots: SlotCache,
+
+    /// Time of the last token refresh, locked while refreshing
+    refreshed: Mutex<Option<Instant>>,
+
+    /// Lifecycle of all SIP sockets opened by this client
+    events: broadcast::Sender<ConnectionEvent>,
+
+    /// Digest challenge of the registrar, reused across reconnects
+    digest: DigestCache,
+
+    socket: SocketOptions,
+
+    policy: RequestPolicy,
+
+    /// Local state kept across runs, e.g. the registered contact
+    store: Option<Store>,
+
+    /// Counters of messages received by all SIP sockets
+    metrics: Arc<Metrics>,
+
+    /// Last messages sent and received by all SIP sockets
+    trace: Arc<Trace>,
+}
+
+/// How SIP sockets of a client connect
+#[derive(Clone)]
+pub struct SocketOptions {
+    pub resolver: Resolver,
+
+    /// SIP hosts tried if the one of the slot fails
+    pub fallback_hosts: Vec<String>,
+
+    /// Address family tried first, the resolver's order if unset
+    pub prefer: Option<AddressFamily>,
+
+    /// Send headers in their compact form
+    pub compact_headers: bool,
+
+    /// Limits on received messages
+    pub limits: Limits,
+
+    /// Register a new contact instead of reusing the one of the last run
+    pub fresh_contact: bool,
+
+    /// Redirects of own requests followed before giving up
+    pub max_redirects: u32,
+
+    /// Transaction timers and registration refresh margin
+    pub timers: SipTimers,
+}
+
+/// Number of connection events buffered for slow subscribers
+const CONNECTION_EVENTS: usize = 16;
+
+/// Longest Retry-After of a SIP server waited for before trying the next one
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// Token refreshes requested more often than this are skipped
+const TOKEN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Refreshes the token shortly before it expires instead of waiting for requests to be rejected.
+///
+/// Ends with the client or if the token does not tell when it expires.
+async fn refresh_before_expiry(inner: Weak<Inner>) {
+    loop {
+        let Some(refresh_at) = (match inner.upgrade() {
+            Some(inner) => inner.token.refresh_at().await,
+            None => None,
+        }) else {
+            return;
+        };
+
+        let delay = refresh_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .max(TOKEN_REFRESH_INTERVAL);
+        debug!("Refreshing token in {delay:?}");
+        tokio::time::sleep(delay).await;
+
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if let Err(err) = (Client { inner }).refresh_token().await {
+            warn!("Failed to refresh expiring token: {err:#}");
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -119,8 +363,43 @@ pub struct Client {
     inner: Arc<Inner>,
 }
 
-impl Client {
-    pub fn new(mut base_url: Url, token: TokenStore) -> Result<Self> {
+pub struct ClientBuilder {
+    base_url: Url,
+    token: TokenStore,
+    socket: SocketOptions,
+    cache: Option<Cache>,
+    policy: RequestPolicy,
+    store: Option<Store>,
+}
+
+impl ClientBuilder {
+    /// Serves responses from the cache where possible
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn request_policy(mut self, policy: RequestPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Keeps the registered contact in the store to reuse it on the next run
+    pub fn store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let Self {
+            mut base_url,
+            token,
+            socket,
+            cache,
+            policy,
+            store,
+        } = self;
+
         if !base_url.path().ends_with("/") {
             base_url.set_path(&format!("{}/", base_url.path()));
         }
@@ -129,54 +408,300 @@ impl Client {
             base_url.set_path(&format!("{}api/2/", base_url.path()));
         }
 
-        let inner = Inner { base_url, token };
+        let inner = Inner {
+            base_url,
+            token,
+            cache,
+            slots: SlotCache::default(),
+            refreshed: Mutex::new(None),
+            events: broadcast::Sender::new(CONNECTION_EVENTS),
+            digest: DigestCache::default(),
+            socket,
+            policy,
+            store,
+            metrics: Default::default(),
+            trace: Default::default(),
+        };
 
-        Ok(Self {
-            inner: Arc::new(inner),
-        })
+        let inner = Arc::new(inner);
+        tokio::spawn(refresh_before_expiry(Arc::downgrade(&inner)));
+
+        Ok(Client { inner })
+    }
+}
+
+impl Client {
+    pub fn builder(base_url: Url, token: TokenStore, socket: SocketOptions) -> ClientBuilder {
+        ClientBuilder {
+            base_url,
+            token,
+            socket,
+            cache: None,
+            policy: RequestPolicy::default(),
+            store: None,
+        }
     }
 
     pub fn url(&self) -> &Url {
         &self.inner.base_url
     }
 
+    /// The current API token, e.g. to hand on to other programs
+    pub async fn token(&self) -> String {
+        self.inner.token.get().await.clone()
+    }
+
     pub fn user(&self) -> UserNamespaceClient {
         self.derive()
     }
 
+    pub fn admin(&self) -> AdminNamespaceClient {
+        self.derive()
+    }
+
+    pub fn system(&self) -> SystemNamespaceClient {
+        self.derive()
+    }
+
+    /// Fetches a fresh token.
+    ///
+    /// Concurrent callers share a single refresh and refreshes shortly after
+    /// the previous one are skipped.
     pub async fn refresh_token(&self) -> Result<()> {
+        let mut refreshed = self.inner.refreshed.lock().await;
+        if refreshed.is_some_and(|refreshed| refreshed.elapsed() < TOKEN_REFRESH_INTERVAL) {
+            return Ok(());
+        }
+
         let token = self.user().authentication().get_token().await?;
-        self.inner.token.update(token).await
+        self.inner.token.update(token).await?;
+
+        *refreshed = Some(Instant::now());
+
+        Ok(())
+    }
+
+    /// Counters of messages received by SIP sockets
+    pub fn socket_metrics(&self) -> MetricsSnapshot {
+        self.inner.metrics.snapshot()
     }
 
+    /// Last messages sent and received by SIP sockets
+    pub fn sip_trace(&self) -> &Arc<Trace> {
+        &self.inner.trace
+    }
+
+    /// DNS resolver used for SIP sockets
+    pub(crate) fn resolver(&self) -> &Resolver {
+        &self.inner.socket.resolver
+    }
+
+    /// Slots of the user as used for sockets
+    pub fn slot_cache(&self) -> &SlotCache {
+        &self.inner.slots
+    }
+
+    /// Receives lifecycle changes of the SIP sockets opened by this client
+    pub fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// The slot used for the SIP socket
+    pub async fn webrtc_slot(&self) -> Result<Slot> {
+        let slots = self.user().slots();
+        self.inner
+            .slots
+            .get_or_fetch(slots.get_all())
+            .await?
+            .iter()
+            .find(|slot| matches!(slot.device_type, DeviceType::Webrtc))
+            .cloned()
+            .context("No WebRTC slot found - create one for this user to receive calls")
+    }
+
+    /// WebSocket URLs of the SIP socket for a slot in the order to try them.
+    ///
+    /// The slot's SIP host comes first, followed by the configured fallback
+    /// hosts and the API server itself.
+    pub(crate) fn socket_urls(&self, slot: &Slot) -> Result<Vec<Url>> {
+        let server = self.url().host_str().unwrap_or_default();
+
+        let mut urls = Vec::<Url>::new();
+        for host in std::iter::once(slot.sip_host.as_str())
+            .chain(self.inner.socket.fallback_hosts.iter().map(String::as_str))
+            .chain(std::iter::once(server))
+            .filter(|host| !host.is_empty())
+        {
+            // IPv6 literals need brackets within URLs
+            let authority = match host.parse::<Ipv6Addr>() {
+                Ok(_) => format!("[{host}]"),
+                Err(_) => host.to_string(),
+            };
+
+            let mut url: Url = match format!("wss://{authority}/sipsockets/").parse() {
+                Ok(url) => url,
+                Err(err) => {
+                    warn!("Skipping invalid SIP host {host}: {err}");
+                    continue;
+                }
+            };
+            if url.port().is_none() {
+                url.set_port(Some(slot.sip_port)).expect("URL with host");
+            }
+
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+
+        if urls.is_empty() {
+            bail!("No valid SIP host for slot {slot}", slot = slot.name);
+        }
+
+        Ok(urls)
+    }
+
+    /// Connects and registers a SIP socket, failing over to the next host on errors
     pub async fn socket(
         &self,
     ) -> Result<(sipsocket::Connection, mpsc::Receiver<ServerTransaction>)> {
-        let slot = self
-            .user()
-            .slots()
-            .get_all()
-            .await?
-            .into_iter()
-            .find(|slot| slot.device_type == "webrtc")
-            .context("No matching slot found")?;
+        let slot = &self.webrtc_slot_with_progress().await?;
 
-        let (mut connection, requests) = sipsocket::Connection::connect(
+        // Not an async closure, whose future could not be shown to be Send for spawning
+        self.failover(slot, |url| async move {
+            let (mut connection, requests) = self.connect_socket(slot, url.clone()).await?;
+
+            connection
+                .register(&slot.sip_username, &slot.sip_password)
+                .await
+                .with_context(|| {
+                    format!("Failed to register slot {slot} at {url}", slot = slot.name)
+                })?;
+
+            if let Some(ref store) = self.inner.store
+                && let Err(err) = store.set_contact(&slot.sip_username, connection.contact_id())
+            {
+                warn!("Failed to store contact: {err:#}");
+            }
+
+            Ok((connection, requests))
+        })
+        .await
+    }
+
+    /// Connects a SIP socket without registering it, e.g. to manage the bindings at the registrar
+    pub async fn registrar(&self) -> Result<(sipsocket::Connection, Slot)> {
+        let slot = self.webrtc_slot_with_progress().await?;
+
+        let (connection, _requests) = self
+            .failover(&slot, |url| self.connect_socket(&slot, url))
+            .await?;
+
+        Ok((connection, slot))
+    }
+
+    async fn webrtc_slot_with_progress(&self) -> Result<Slot> {
+        let _progress = progress::spinner("Looking up slot");
+        self.webrtc_slot().await
+    }
+
+    /// Tries the socket URLs of the slot in order until connecting to one succeeds
+    async fn failover<T, F>(&self, slot: &Slot, connect: impl Fn(Url) -> F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let progress = progress::spinner("Connecting SIP socket");
+
+        let mut failure = None;
+        for url in self.socket_urls(slot)? {
+            progress.set_message(format!("Connecting SIP socket to {host}", host = url.authority()));
+            let mut result = connect(url.clone()).await;
+
+            // A busy server asking to come back soon is waited for once instead of failing over
+            if let Err(ref err) = result
+                && let Some(retry_after) = RetryAfter::of(err)
+                && retry_after.0 <= MAX_RETRY_AFTER
+            {
+                let delay = retry_after.jittered();
+                warn!("{err:#}, trying again in {delay:.0?}");
+                tokio::time::sleep(delay).await;
+                result = connect(url).await;
+            }
+
+            match result {
+                Ok(socket) => return Ok(socket),
+                Err(err) => {
+                    warn!("{err:#}");
+                    failure = Some(err);
+                }
+            }
+        }
+
+        Err(failure.unwrap_or_else(|| anyhow!("No SIP host available")))
+    }
+
+    async fn connect_socket(
+        &self,
+        slot: &Slot,
+        url: Url,
+    ) -> Result<(sipsocket::Connection, mpsc::Receiver<ServerTransaction>)> {
+        let events = self.inner.events.clone();
+        let mut builder = sipsocket::Connection::builder(url.clone(), &slot.sip_username)
+            .domain(self.sip_domain(slot)?)
+            .events(events)
+            .digest_cache(self.inner.digest.clone())
+            .prefer(self.inner.socket.prefer)
+            .resolver(self.inner.socket.resolver.clone())
+            .limits(self.inner.socket.limits)
+            .metrics(self.inner.metrics.clone())
+            .trace(self.inner.trace.clone())
+            .redirects(Redirects::new(self.inner.socket.max_redirects))
+            .timers(self.inner.socket.timers)
+            .contact(self.stored_contact(&slot.sip_username));
+        if self.inner.socket.compact_headers {
+            builder = builder.middleware(CompactHeaders);
+        }
+
+        builder.connect().await.with_context(|| {
             format!(
-                "wss://{host}:{port}/sipsockets/",
-                host = self.url().domain().expect("domain required"),
-                port = slot.sip_port
+                "Failed to connect SIP socket of slot {slot} to {url}",
+                slot = slot.name
             )
-            .parse()
-            .expect("valid URL"),
-            &slot.sip_username,
-        )
-        .await?;
+        })
+    }
+
+    /// Domain the slot is registered in, which stays the same whichever socket host is connected
+    fn sip_domain(&self, slot: &Slot) -> Result<String> {
+        if slot.sip_host.is_empty() {
+            let host = self.url().host_str().context("Server URL has no host")?;
+            return Ok(host.trim_matches(['[', ']']).to_string());
+        }
 
+        Ok(slot.sip_host.clone())
+    }
+
+    /// Contact registered by an earlier run, unless a fresh one is requested
+    fn stored_contact(&self, username: &str) -> Option<ContactId> {
+        if self.inner.socket.fresh_contact {
+            return None;
+        }
+
+        let store = self.inner.store.as_ref()?;
+        store.contact(username).unwrap_or_else(|err| {
+            warn!("Failed to load stored contact: {err:#}");
+            None
+        })
+    }
+
+    /// Registers an existing socket again with freshly fetched credentials
+    pub async fn reregister(&self, connection: &mut sipsocket::Connection) -> Result<()> {
+        self.user().slots().invalidate_cache();
+        self.inner.slots.invalidate().await;
+
+        let slot = self.webrtc_slot().await?;
         connection
             .register(&slot.sip_username, &slot.sip_password)
-            .await?;
-
-        Ok((connection, requests))
+            .await
+            .with_context(|| format!("Failed to register slot {slot}", slot = slot.name))
     }
 }
@@ -0,0 +1,37 @@
+use crate::ucware::user::Slot;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Slots of the user kept in memory for the lifetime of a client.
+///
+/// Shared by everything using the same client so that reconnecting sockets
+/// don't query the server again.
+#[derive(Default)]
+pub struct SlotCache {
+    slots: Mutex<Option<Arc<Vec<Slot>>>>,
+}
+
+impl SlotCache {
+    /// Forgets the cached slots so that they are fetched again on next use
+    pub async fn invalidate(&self) {
+        *self.slots.lock().await = None;
+    }
+
+    /// Returns the cached slots or fetches them.
+    ///
+    /// Concurrent callers wait for a single fetch.
+    pub(super) async fn get_or_fetch<F>(&self, fetch: F) -> Result<Arc<Vec<Slot>>>
+    where
+        F: Future<Output = Result<Vec<Slot>>>,
+    {
+        let mut slots = self.slots.lock().await;
+        if let Some(ref slots) = *slots {
+            return Ok(slots.clone());
+        }
+
+        let fetched = Arc::new(fetch.await?);
+        *slots = Some(fetched.clone());
+        Ok(fetched)
+    }
+}
@@ -0,0 +1,72 @@
+use crate::ucware::system::SystemNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Version {
+    pub version: String,
+    pub build: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LicenseUsage {
+    pub name: String,
+    pub total: u64,
+    pub used: u64,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl LicenseUsage {
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.total
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Ok,
+    Degraded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Health {
+    pub service: String,
+    pub state: HealthState,
+    pub message: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct InfoInterface;
+
+impl Interface for InfoInterface {
+    const PATH: &'static str = "info";
+}
+
+pub type InfoInterfaceClient = InterfaceClient<SystemNamespace, InfoInterface>;
+
+impl InfoInterfaceClient {
+    pub async fn get_version(&self) -> Result<Version> {
+        self.query("getVersion", rpc_params![]).await
+    }
+
+    pub async fn get_licenses(&self) -> Result<Vec<LicenseUsage>> {
+        self.query("getLicenses", rpc_params![]).await
+    }
+
+    pub async fn get_health(&self) -> Result<Vec<Health>> {
+        self.query("getHealth", rpc_params![]).await
+    }
+}
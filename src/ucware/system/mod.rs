@@ -0,0 +1,20 @@
+use crate::ucware::system::info::InfoInterfaceClient;
+use crate::ucware::{Derive, Namespace, NamespaceClient};
+
+mod info;
+
+pub use info::{Health, HealthState, LicenseUsage, Version};
+
+pub struct SystemNamespace;
+
+impl Namespace for SystemNamespace {
+    const PATH: &'static str = "system";
+}
+
+pub type SystemNamespaceClient = NamespaceClient<SystemNamespace>;
+
+impl SystemNamespaceClient {
+    pub fn info(&self) -> InfoInterfaceClient {
+        self.derive()
+    }
+}
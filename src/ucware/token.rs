@@ -1,55 +1,166 @@
+use crate::store::Store;
 use anyhow::Result;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use serde::Deserialize;
 use std::ops::Deref;
-use std::path::{Path, PathBuf};
-use tokio::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{RwLock, RwLockReadGuard};
 use tracing::debug;
 
+/// File the token was kept in before it moved to the store
+const LEGACY_PATH: &str = ".token";
+
+/// Time before the token expires to refresh it at unless configured otherwise
+pub const DEFAULT_LEEWAY: Duration = Duration::from_secs(60);
+
 pub struct TokenStore {
-    token: RwLock<String>,
-    path: PathBuf,
+    token: RwLock<Token>,
+    store: Store,
+    leeway: Duration,
+
+    /// Name of the further account the token belongs to, the main one if unset
+    account: Option<String>,
 }
 
-impl TokenStore {
-    pub async fn with_token(path: impl AsRef<Path>, token: String) -> Result<Self> {
-        let path = path.as_ref().to_path_buf();
+struct Token {
+    value: String,
 
-        tokio::fs::write(&path, token.as_bytes()).await?;
+    /// Expiry in local time, if the token tells
+    expires_at: Option<SystemTime>,
+}
 
-        Ok(Self {
-            path,
-            token: RwLock::new(token),
+/// Registered claims of a JWT relevant for refreshing it
+#[derive(Debug, Default, Deserialize)]
+pub struct TokenClaims {
+    /// Expiry in seconds since the epoch
+    pub exp: Option<u64>,
+
+    /// Issue time in seconds since the epoch
+    pub iat: Option<u64>,
+}
+
+impl TokenClaims {
+    /// TokenClaims of the given token without verifying its signature, none if it is not a JWT
+    pub fn decode(token: &str) -> Option<Self> {
+        let payload = token.split('.').nth(1)?;
+        let payload = BASE64_URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+
+    /// Expiry in local time.
+    ///
+    /// For a token issued just now, the difference between the issue time and
+    /// the local clock is the skew of the server's clock, which shifts the expiry.
+    pub fn expires_at(&self, issued_now: bool) -> Option<SystemTime> {
+        let exp = UNIX_EPOCH + Duration::from_secs(self.exp?);
+
+        let Some(iat) = self.iat.filter(|_| issued_now) else {
+            return Some(exp);
+        };
+
+        let iat = UNIX_EPOCH + Duration::from_secs(iat);
+        let now = SystemTime::now();
+        Some(match now.duration_since(iat) {
+            Ok(behind) => exp + behind,
+            Err(ahead) => exp.checked_sub(ahead.duration())?,
         })
     }
+}
+
+impl Token {
+    fn new(value: String, issued_now: bool) -> Self {
+        let expires_at = TokenClaims::decode(&value).and_then(|claims| claims.expires_at(issued_now));
+        Self { value, expires_at }
+    }
+}
 
-    pub async fn open(path: impl AsRef<Path>) -> Result<Option<Self>> {
-        let path = path.as_ref().to_path_buf();
+impl TokenStore {
+    pub fn with_token(store: Store, token: String) -> Result<Self> {
+        store.set_token(&token)?;
+
+        Ok(Self {
+            store,
+            token: RwLock::new(Token::new(token, false)),
+            leeway: DEFAULT_LEEWAY,
+            account: None,
+        })
+    }
 
-        let token = if tokio::fs::try_exists(&path).await? {
+    pub async fn open(store: Store) -> Result<Option<Self>> {
+        if let Some(token) = store.token()? {
             debug!("Loading existing token from store");
-            tokio::fs::read_to_string(&path).await?.trim().to_string()
-        } else {
-            return Ok(None);
+            return Ok(Some(Self {
+                store,
+                token: RwLock::new(Token::new(token, false)),
+                leeway: DEFAULT_LEEWAY,
+                account: None,
+            }));
+        }
+
+        if tokio::fs::try_exists(LEGACY_PATH).await? {
+            debug!("Importing token from {LEGACY_PATH}");
+            let token = tokio::fs::read_to_string(LEGACY_PATH).await?.trim().to_string();
+            return Ok(Some(Self::with_token(store, token)?));
+        }
+
+        Ok(None)
+    }
+
+    /// The token of a further account kept in the store, or the given initial one
+    pub fn open_account(store: Store, account: &str, initial: Option<String>) -> Result<Option<Self>> {
+        let token = match store.account_token(account)? {
+            Some(token) => token,
+            None => {
+                let Some(token) = initial else {
+                    return Ok(None);
+                };
+                store.set_account_token(account, &token)?;
+                token
+            }
         };
 
         Ok(Some(Self {
-            path,
-            token: RwLock::new(token),
+            store,
+            token: RwLock::new(Token::new(token, false)),
+            leeway: DEFAULT_LEEWAY,
+            account: Some(account.to_string()),
         }))
     }
 
+    /// Refreshes the token this long before it expires, to tolerate clocks being off
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
     pub async fn get(&self) -> impl Deref<Target = String> {
-        self.token.read().await
+        RwLockReadGuard::map(self.token.read().await, |token| &token.value)
+    }
+
+    /// Local time the token expires at, if it is a JWT telling so
+    pub async fn expires_at(&self) -> Option<SystemTime> {
+        self.token.read().await.expires_at
+    }
+
+    /// Local time the token should be refreshed at
+    pub async fn refresh_at(&self) -> Option<SystemTime> {
+        let expires_at = self.expires_at().await?;
+        Some(expires_at.checked_sub(self.leeway).unwrap_or(UNIX_EPOCH))
     }
 
     pub async fn update(&self, next_token: String) -> Result<()> {
         let mut curr_token = self.token.write().await;
-        if *curr_token == next_token {
+        if curr_token.value == next_token {
             return Ok(());
         }
 
-        *curr_token = next_token;
+        *curr_token = Token::new(next_token, true);
 
-        tokio::fs::write(&self.path, curr_token.as_bytes()).await?;
+        match &self.account {
+            Some(account) => self.store.set_account_token(account, &curr_token.value)?,
+            None => self.store.set_token(&curr_token.value)?,
+        }
 
         Ok(())
     }
@@ -13,10 +13,10 @@ pub type AuthenticationInterfaceClient = InterfaceClient<UserNamespace, Authenti
 
 impl AuthenticationInterfaceClient {
     pub async fn get_token(&self) -> Result<String> {
-        self.request("getToken", rpc_params![]).await
+        self.query("getToken", rpc_params![]).await
     }
 
     pub async fn validate_token(&self) -> Result<String> {
-        self.request("validateToken", rpc_params![]).await
+        self.query("validateToken", rpc_params![]).await
     }
 }
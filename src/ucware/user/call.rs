@@ -0,0 +1,21 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+
+pub struct CallInterface;
+
+impl Interface for CallInterface {
+    const PATH: &'static str = "call";
+}
+
+pub type CallInterfaceClient = InterfaceClient<UserNamespace, CallInterface>;
+
+impl CallInterfaceClient {
+    /// Originates a call to the given number from the user's primary device
+    pub async fn dial(&self, number: &str) -> Result<()> {
+        let _: IgnoredAny = self.request("dial", rpc_params![number]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use jsonrpsee::rpc_params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FaxState {
+    Queued,
+    Sending,
+    Sent,
+    Failed,
+}
+
+impl FaxState {
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Sent | Self::Failed)
+    }
+
+    pub fn is_failed(self) -> bool {
+        self == Self::Failed
+    }
+}
+
+impl fmt::Display for FaxState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Queued => "queued",
+            Self::Sending => "sending",
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FaxJob {
+    pub id: u64,
+    pub state: FaxState,
+
+    #[serde(rename = "pagesSent")]
+    pub pages_sent: u32,
+
+    #[serde(rename = "pagesTotal")]
+    pub pages_total: Option<u32>,
+
+    pub error: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReceivedFax {
+    pub id: u64,
+
+    #[serde(rename = "callerNumber")]
+    pub number: String,
+
+    /// Unix timestamp of reception
+    pub received: i64,
+
+    pub pages: u32,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct FaxInterface;
+
+impl Interface for FaxInterface {
+    const PATH: &'static str = "fax";
+}
+
+pub type FaxInterfaceClient = InterfaceClient<UserNamespace, FaxInterface>;
+
+impl FaxInterfaceClient {
+    /// Queues a PDF document for sending and returns the job to poll
+    pub async fn send(&self, number: &str, filename: &str, document: &[u8]) -> Result<FaxJob> {
+        let document = BASE64_STANDARD.encode(document);
+        self.request("send", rpc_params![number, filename, document])
+            .await
+    }
+
+    pub async fn get_job(&self, id: u64) -> Result<FaxJob> {
+        self.query("getJob", rpc_params![id]).await
+    }
+
+    pub async fn get_received(&self) -> Result<Vec<ReceivedFax>> {
+        self.query("getReceived", rpc_params![]).await
+    }
+
+    /// Fetches the PDF document of a received fax
+    pub async fn download(&self, id: u64) -> Result<Vec<u8>> {
+        let document: String = self.query("download", rpc_params![id]).await?;
+        BASE64_STANDARD
+            .decode(document)
+            .context("Invalid fax document encoding")
+    }
+}
@@ -0,0 +1,56 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// A call in the call history of the user
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JournalEntry {
+    pub id: String,
+    pub direction: Direction,
+
+    #[serde(rename = "remoteNumber")]
+    pub number: Option<String>,
+
+    #[serde(rename = "remoteName")]
+    pub name: Option<String>,
+
+    /// Unix timestamp of the start of the call
+    pub started: i64,
+
+    pub answered: bool,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl JournalEntry {
+    pub fn is_missed(&self) -> bool {
+        self.direction == Direction::Inbound && !self.answered
+    }
+}
+
+pub struct JournalInterface;
+
+impl Interface for JournalInterface {
+    const PATH: &'static str = "journal";
+}
+
+pub type JournalInterfaceClient = InterfaceClient<UserNamespace, JournalInterface>;
+
+impl JournalInterfaceClient {
+    /// Calls started after the given unix timestamp, oldest first
+    pub async fn get_since(&self, since: i64) -> Result<Vec<JournalEntry>> {
+        self.query("getSince", rpc_params![since]).await
+    }
+}
@@ -1,9 +1,26 @@
 use crate::ucware::user::authentication::AuthenticationInterfaceClient;
+use crate::ucware::user::call::CallInterfaceClient;
+use crate::ucware::user::fax::FaxInterfaceClient;
+use crate::ucware::user::journal::JournalInterfaceClient;
+use crate::ucware::user::phonebook::PhonebookInterfaceClient;
 use crate::ucware::{Derive, Namespace, NamespaceClient};
+use crate::ucware::user::queue::QueueInterfaceClient;
 use crate::ucware::user::slot::SlotInterfaceClient;
+use crate::ucware::user::sms::SmsInterfaceClient;
+use crate::ucware::user::speed_dial::SpeedDialInterfaceClient;
 
 mod authentication;
+mod call;
+mod fax;
+mod journal;
+mod phonebook;
+mod queue;
 mod slot;
+mod sms;
+mod speed_dial;
+
+pub use journal::{Direction, JournalEntry};
+pub use slot::{DeviceType, Slot};
 
 pub struct UserNamespace;
 
@@ -21,4 +38,32 @@ impl UserNamespaceClient {
     pub fn slots(&self) -> SlotInterfaceClient {
         self.derive()
     }
+
+    pub fn calls(&self) -> CallInterfaceClient {
+        self.derive()
+    }
+
+    pub fn speed_dials(&self) -> SpeedDialInterfaceClient {
+        self.derive()
+    }
+
+    pub fn queues(&self) -> QueueInterfaceClient {
+        self.derive()
+    }
+
+    pub fn faxes(&self) -> FaxInterfaceClient {
+        self.derive()
+    }
+
+    pub fn sms(&self) -> SmsInterfaceClient {
+        self.derive()
+    }
+
+    pub fn phonebook(&self) -> PhonebookInterfaceClient {
+        self.derive()
+    }
+
+    pub fn journal(&self) -> JournalInterfaceClient {
+        self.derive()
+    }
 }
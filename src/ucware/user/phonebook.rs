@@ -0,0 +1,48 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Contact {
+    pub id: u64,
+    pub name: String,
+
+    #[serde(default)]
+    pub numbers: Vec<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The phonebook is large and changes rarely
+const CONTACTS_TTL: Duration = Duration::from_secs(3600);
+
+pub struct PhonebookInterface;
+
+impl Interface for PhonebookInterface {
+    const PATH: &'static str = "phonebook";
+}
+
+pub type PhonebookInterfaceClient = InterfaceClient<UserNamespace, PhonebookInterface>;
+
+impl PhonebookInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<Contact>> {
+        self.cached_request("getAll", rpc_params![], CONTACTS_TTL)
+            .await
+    }
+
+    pub async fn search(&self, query: &str) -> Result<Vec<Contact>> {
+        self.query("search", rpc_params![query]).await
+    }
+
+    pub async fn add(&self, name: &str, numbers: &[String]) -> Result<Contact> {
+        let contact = self.request("add", rpc_params![name, numbers]).await?;
+        self.invalidate_cache();
+        Ok(contact)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::{Context, Result};
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Queue {
+    pub id: u64,
+    pub name: String,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueueCaller {
+    #[serde(rename = "callerName")]
+    pub name: Option<String>,
+
+    #[serde(rename = "callerNumber")]
+    pub number: String,
+
+    /// Seconds the caller is waiting
+    #[serde(rename = "waitTime")]
+    pub wait_time: u64,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueueAgent {
+    pub name: String,
+    pub state: String,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueueStatistics {
+    pub answered: u64,
+    pub abandoned: u64,
+
+    /// Percentage of calls answered within the service level time
+    #[serde(rename = "serviceLevel")]
+    pub service_level: f64,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Queue configuration rarely changes and is served from the cache for this long
+const QUEUES_TTL: Duration = Duration::from_secs(600);
+
+pub struct QueueInterface;
+
+impl Interface for QueueInterface {
+    const PATH: &'static str = "queue";
+}
+
+pub type QueueInterfaceClient = InterfaceClient<UserNamespace, QueueInterface>;
+
+impl QueueInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<Queue>> {
+        self.cached_request("getAll", rpc_params![], QUEUES_TTL).await
+    }
+
+    /// Looks up a queue by name or ID
+    pub async fn find(&self, queue: &str) -> Result<Queue> {
+        self.get_all()
+            .await?
+            .into_iter()
+            .find(|q| q.name == queue || q.id.to_string() == queue)
+            .with_context(|| format!("No such queue: {queue}"))
+    }
+
+    pub async fn get_callers(&self, queue_id: u64) -> Result<Vec<QueueCaller>> {
+        self.query("getCallers", rpc_params![queue_id]).await
+    }
+
+    pub async fn get_agents(&self, queue_id: u64) -> Result<Vec<QueueAgent>> {
+        self.query("getAgents", rpc_params![queue_id]).await
+    }
+
+    pub async fn get_statistics(&self, queue_id: u64) -> Result<QueueStatistics> {
+        self.query("getStatistics", rpc_params![queue_id]).await
+    }
+
+    /// Joins the queue as agent
+    pub async fn login(&self, queue_id: u64) -> Result<()> {
+        let _: IgnoredAny = self.request("login", rpc_params![queue_id]).await?;
+        Ok(())
+    }
+
+    pub async fn logout(&self, queue_id: u64) -> Result<()> {
+        let _: IgnoredAny = self.request("logout", rpc_params![queue_id]).await?;
+        Ok(())
+    }
+
+    /// Pauses the user in all queues
+    pub async fn pause(&self, reason: Option<&str>) -> Result<()> {
+        let _: IgnoredAny = self.request("pause", rpc_params![reason]).await?;
+        Ok(())
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        let _: IgnoredAny = self.request("resume", rpc_params![]).await?;
+        Ok(())
+    }
+}
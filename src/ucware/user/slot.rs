@@ -1,10 +1,52 @@
 use crate::ucware::user::UserNamespace;
 use crate::ucware::{Interface, InterfaceClient};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use jsonrpsee::rpc_params;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+
+/// Kind of device a slot is meant for
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum DeviceType {
+    Webrtc,
+    DeskPhone,
+    Dect,
+    Softphone,
+    Unknown(String),
+}
+
+impl From<String> for DeviceType {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "webrtc" => Self::Webrtc,
+            "deskphone" => Self::DeskPhone,
+            "dect" => Self::Dect,
+            "softphone" => Self::Softphone,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<DeviceType> for String {
+    fn from(value: DeviceType) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceType::Webrtc => "webrtc",
+            DeviceType::DeskPhone => "deskphone",
+            DeviceType::Dect => "dect",
+            DeviceType::Softphone => "softphone",
+            DeviceType::Unknown(value) => value,
+        })
+    }
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Slot {
@@ -15,7 +57,15 @@ pub struct Slot {
     pub user_id: u64,
 
     #[serde(rename = "deviceType")]
-    pub device_type: String,
+    pub device_type: DeviceType,
+
+    /// Human readable label as shown on the device, if set
+    #[serde(rename = "displayLabel", default)]
+    pub label: Option<String>,
+
+    /// Whether a device is currently registered on the slot, if reported
+    #[serde(default)]
+    pub registered: Option<bool>,
 
     #[serde(rename = "deviceId")]
     pub device_id: u64,
@@ -45,7 +95,26 @@ impl Interface for SlotInterface {
 pub type SlotInterfaceClient = InterfaceClient<UserNamespace, SlotInterface>;
 
 impl SlotInterfaceClient {
+    /// Not served from the persistent cache, as slots carry SIP credentials
     pub async fn get_all(&self) -> Result<Vec<Slot>> {
-        self.request("getAll", rpc_params![]).await
+        self.query("getAll", rpc_params![]).await
+    }
+
+    /// Looks up a slot by name or ID
+    pub async fn find(&self, slot: &str) -> Result<Slot> {
+        self.get_all()
+            .await?
+            .into_iter()
+            .find(|s| s.name == slot || s.id.to_string() == slot)
+            .with_context(|| format!("No such slot: {slot}"))
+    }
+
+    /// Generates new SIP credentials, invalidating the current ones
+    pub async fn rotate_sip_password(&self, slot_id: u64) -> Result<Slot> {
+        let slot = self
+            .request("rotateSipPassword", rpc_params![slot_id])
+            .await?;
+        self.invalidate_cache();
+        Ok(slot)
     }
 }
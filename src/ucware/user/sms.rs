@@ -0,0 +1,96 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmsState {
+    Pending,
+    Sent,
+    Delivered,
+    Failed,
+}
+
+impl SmsState {
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Delivered | Self::Failed)
+    }
+
+    pub fn is_failed(self) -> bool {
+        self == Self::Failed
+    }
+}
+
+impl fmt::Display for SmsState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "pending",
+            Self::Sent => "sent",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmsGateway {
+    pub id: u64,
+    pub name: String,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SmsMessage {
+    pub id: u64,
+
+    /// Whether the message was received rather than sent
+    pub incoming: bool,
+
+    pub number: String,
+    pub text: String,
+    pub state: SmsState,
+
+    /// Unix timestamp of the last state change
+    pub timestamp: i64,
+
+    pub error: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct SmsInterface;
+
+impl Interface for SmsInterface {
+    const PATH: &'static str = "sms";
+}
+
+pub type SmsInterfaceClient = InterfaceClient<UserNamespace, SmsInterface>;
+
+impl SmsInterfaceClient {
+    /// Lists the SMS gateways available to the user
+    pub async fn get_gateways(&self) -> Result<Vec<SmsGateway>> {
+        self.query("getGateways", rpc_params![]).await
+    }
+
+    /// Sends a message using the given gateway or the default one
+    pub async fn send(&self, number: &str, text: &str, gateway: Option<u64>) -> Result<SmsMessage> {
+        self.request("send", rpc_params![number, text, gateway])
+            .await
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<SmsMessage>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    pub async fn get(&self, id: u64) -> Result<SmsMessage> {
+        self.query("get", rpc_params![id]).await
+    }
+}
@@ -0,0 +1,37 @@
+use crate::ucware::user::UserNamespace;
+use crate::ucware::{Interface, InterfaceClient};
+use anyhow::Result;
+use jsonrpsee::rpc_params;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpeedDial {
+    pub key: u32,
+    pub name: String,
+    pub number: String,
+
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+pub struct SpeedDialInterface;
+
+impl Interface for SpeedDialInterface {
+    const PATH: &'static str = "speedDial";
+}
+
+pub type SpeedDialInterfaceClient = InterfaceClient<UserNamespace, SpeedDialInterface>;
+
+impl SpeedDialInterfaceClient {
+    pub async fn get_all(&self) -> Result<Vec<SpeedDial>> {
+        self.query("getAll", rpc_params![]).await
+    }
+
+    pub async fn set(&self, key: u32, name: &str, number: &str) -> Result<()> {
+        let _: IgnoredAny = self.request("set", rpc_params![key, name, number]).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use crate::progress;
+use futures::{StreamExt, stream};
+use std::future::Future;
+
+/// Default number of concurrent requests for per-item RPCs
+pub const CONCURRENCY: usize = 8;
+
+/// Runs the given futures with at most `limit` of them in flight.
+///
+/// Progress is shown on stderr if enabled. Results are returned in
+/// the order of the input.
+pub async fn parallel<I>(iter: I, limit: usize) -> Vec<<I::Item as Future>::Output>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let futures = iter.into_iter().collect::<Vec<_>>();
+
+    let progress = progress::bar(futures.len() as u64);
+
+    let results = stream::iter(futures)
+        .map(|future| {
+            let progress = &progress;
+            async move {
+                let result = future.await;
+                progress.inc(1);
+                result
+            }
+        })
+        .buffered(limit.max(1))
+        .collect()
+        .await;
+
+    results
+}
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Capabilities and provenance of this build
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+
+    /// Enabled optional cargo features
+    pub features: Vec<&'static str>,
+
+    pub rsip: Option<&'static str>,
+
+    /// Git commit the build was made from, if known
+    pub commit: Option<&'static str>,
+
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+impl BuildInfo {
+    pub fn get() -> Self {
+        let features = [
+            ("tray", cfg!(feature = "tray")),
+            ("opus", cfg!(feature = "opus")),
+            ("webrtc", cfg!(feature = "webrtc")),
+            ("audio", cfg!(feature = "audio")),
+            ("wasm", cfg!(feature = "wasm")),
+            ("grpc", cfg!(feature = "grpc")),
+            ("graphql", cfg!(feature = "graphql")),
+        ]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features,
+            rsip: option_env!("UCWARE_RSIP_VERSION"),
+            commit: option_env!("UCWARE_GIT_COMMIT"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        }
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Version:  {}", self.version)?;
+        writeln!(f, "Commit:   {}", self.commit.unwrap_or("unknown"))?;
+        match self.features.as_slice() {
+            [] => writeln!(f, "Features: none")?,
+            features => writeln!(f, "Features: {}", features.join(", "))?,
+        }
+        writeln!(f, "rsip:     {}", self.rsip.unwrap_or("unknown"))?;
+        write!(f, "Platform: {}-{}", self.os, self.arch)
+    }
+}
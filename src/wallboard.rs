@@ -0,0 +1,80 @@
+use crate::ucware::Client;
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::Duration;
+use tokio::select;
+
+const ALTERNATE_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const MAIN_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+const CLEAR: &str = "\x1b[H\x1b[2J";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+fn duration(secs: u64) -> String {
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+/// Renders a live full-screen view of a queue until interrupted
+pub async fn run(client: &Client, queue: &str, interval: Duration) -> Result<()> {
+    let queues = client.user().queues();
+
+    let queue = queues.find(queue).await?;
+
+    print!("{ALTERNATE_SCREEN}");
+
+    let result = async {
+        loop {
+            let (callers, agents, statistics) = tokio::try_join!(
+                queues.get_callers(queue.id),
+                queues.get_agents(queue.id),
+                queues.get_statistics(queue.id),
+            )?;
+
+            let mut screen = String::new();
+
+            writeln!(screen, "{BOLD}{name}{RESET}", name = queue.name)?;
+            writeln!(
+                screen,
+                "SLA: {sla:.1}%   Answered: {answered}   Abandoned: {abandoned}",
+                sla = statistics.service_level,
+                answered = statistics.answered,
+                abandoned = statistics.abandoned,
+            )?;
+            writeln!(screen)?;
+
+            writeln!(screen, "{BOLD}Waiting ({count}){RESET}", count = callers.len())?;
+            for caller in &callers {
+                writeln!(
+                    screen,
+                    "  {wait}  {name:<24} {number}",
+                    wait = duration(caller.wait_time),
+                    name = caller.name.as_deref().unwrap_or("Unknown"),
+                    number = caller.number,
+                )?;
+            }
+            writeln!(screen)?;
+
+            writeln!(screen, "{BOLD}Agents ({count}){RESET}", count = agents.len())?;
+            for agent in &agents {
+                writeln!(screen, "  {state:<12} {name}", state = agent.state, name = agent.name)?;
+            }
+
+            let mut stdout = std::io::stdout().lock();
+            write!(stdout, "{CLEAR}{screen}")?;
+            stdout.flush()?;
+            drop(stdout);
+
+            select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = tokio::signal::ctrl_c() => return anyhow::Ok(()),
+            }
+        }
+    }
+    .await;
+
+    print!("{MAIN_SCREEN}");
+    std::io::stdout().flush()?;
+
+    result
+}
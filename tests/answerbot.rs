@@ -0,0 +1,146 @@
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use rsip::headers::UntypedHeader;
+use rsip::message::HeadersExt;
+use rsip::{Header, Method, Request, Response, StatusCode, Version};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tungstenite::Message;
+use ucware_cli::answerbot::AnswerBot;
+use ucware_cli::callstate::DialogKey;
+use ucware_cli::config::{HttpTranscriptionConfig, MediaConfig, TranscriptionConfig};
+use ucware_cli::ivr::Ivr;
+use ucware_cli::media::rtp::RtpPacket;
+use ucware_cli::media::sdp::AudioOffer;
+use ucware_cli::sipsocket::Connection;
+use ucware_cli::store::Store;
+
+const FLOW: &str = r#"
+start: voicemail
+nodes:
+  voicemail:
+    type: record
+    max: 500ms
+    next: bye
+  bye:
+    type: hangup
+"#;
+
+fn invite(media_port: u16) -> String {
+    let sdp = format!(
+        "v=0\r\n\
+         o=- 1 1 IN IP4 127.0.0.1\r\n\
+         s=-\r\n\
+         c=IN IP4 127.0.0.1\r\n\
+         t=0 0\r\n\
+         m=audio {media_port} RTP/AVP 0\r\n"
+    );
+
+    format!(
+        "INVITE sip:1001@pbx.invalid SIP/2.0\r\n\
+         Via: SIP/2.0/WSS pbx.invalid;branch=z9hG4bKinvite\r\n\
+         From: <sip:1002@pbx.invalid>;tag=caller\r\n\
+         To: <sip:1001@pbx.invalid>\r\n\
+         Call-ID: voicemail@pbx.invalid\r\n\
+         CSeq: 1 INVITE\r\n\
+         Contact: <sip:1002@192.0.2.2:5060>\r\n\
+         Content-Type: application/sdp\r\n\
+         Content-Length: {len}\r\n\r\n{sdp}",
+        len = sdp.len(),
+    )
+}
+
+fn text(message: Message) -> String {
+    match message {
+        Message::Text(text) => text.to_string(),
+        other => panic!("Expected text message, got {other:?}"),
+    }
+}
+
+/// A response to a request with the given status
+fn reply(request: &Request, status_code: StatusCode) -> String {
+    let headers = request
+        .headers
+        .iter()
+        .filter(|&header| {
+            matches!(header, Header::Via(_) | Header::From(_) | Header::To(_) | Header::CSeq(_) | Header::CallId(_))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Response {
+        status_code,
+        version: Version::V2,
+        headers: headers.into(),
+        body: Vec::new(),
+    }
+    .to_string()
+}
+
+#[tokio::test]
+async fn recording_is_kept_and_transcribed() {
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::post(|| async { axum::Json(serde_json::json!({ "text": " Please call me back\n" })) }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let media = MediaConfig {
+        transcription: Some(TranscriptionConfig::Http(HttpTranscriptionConfig { url, token: None })),
+        ..MediaConfig::default()
+    };
+    let recordings = std::env::temp_dir().join(format!("ucware-recordings-{}", std::process::id()));
+    let store = Store::memory();
+    let ivr: Ivr = serde_yaml::from_str(FLOW).unwrap();
+    let bot = Arc::new(AnswerBot::new(ivr, media, store.clone(), recordings.clone()).unwrap());
+
+    let (sink_tx, mut sink_rx) = mpsc::unbounded::<Message>();
+    let (mut stream_tx, stream_rx) = mpsc::unbounded::<anyhow::Result<Message>>();
+    let (_connection, mut requests) = Connection::builder("wss://pbx.invalid/".parse().unwrap(), "1001")
+        .attach(sink_tx.sink_map_err(anyhow::Error::from), stream_rx)
+        .unwrap();
+
+    let caller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    stream_tx.send(Ok(Message::text(invite(caller.local_addr().unwrap().port())))).await.unwrap();
+
+    let tx = requests.recv().await.unwrap();
+    bot.answer(DialogKey::from_request(&tx.request).unwrap(), tx);
+
+    let ok = Response::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(ok.status_code, StatusCode::OK);
+    let answer = AudioOffer::parse(&String::from_utf8_lossy(&ok.body)).unwrap();
+    let session = SocketAddr::new(answer.address.unwrap(), answer.port);
+
+    for sequence in 0..10u16 {
+        let packet = RtpPacket {
+            payload_type: 0,
+            marker: sequence == 0,
+            sequence,
+            timestamp: sequence as u32 * 160,
+            ssrc: 0x1234,
+            payload: vec![0xff; 160].into(),
+        };
+        caller.send_to(&packet.encode(), session).await.unwrap();
+    }
+
+    // Hangs up once the recording is done
+    let bye = Request::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(bye.method, Method::Bye);
+    assert_eq!(bye.call_id_header().unwrap().value(), "voicemail@pbx.invalid");
+    stream_tx.send(Ok(Message::text(reply(&bye, StatusCode::OK)))).await.unwrap();
+
+    let recording = recordings.join("voicemail_pbx.invalid-voicemail.wav");
+    let wav = hound::WavReader::open(&recording).unwrap();
+    assert_eq!(wav.spec().sample_rate, 8000);
+    assert!(wav.len() > 0);
+
+    assert_eq!(
+        store.transcript("voicemail@pbx.invalid").unwrap().as_deref(),
+        Some("Please call me back")
+    );
+
+    std::fs::remove_dir_all(recordings).unwrap();
+}
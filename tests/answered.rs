@@ -0,0 +1,442 @@
+//! Calls answered by the client, from the 200 to the media and the hang-up
+
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use rsip::headers::ToTypedHeader;
+use rsip::message::HeadersExt;
+use rsip::{Header, Method, Request, Response, StatusCode, Version};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+use tokio::net::UdpSocket;
+use tungstenite::Message;
+use ucware_cli::config::{Age, MediaConfig};
+use ucware_cli::media::codec::Codecs;
+use ucware_cli::media::hold::HoldMusic;
+use ucware_cli::media::rtcp::{self, ReportBlock, RtcpPacket};
+use ucware_cli::media::rtp::RtpPacket;
+use ucware_cli::media::sdp::AudioOffer;
+use ucware_cli::media::session::MediaSession;
+use ucware_cli::sipsocket::Connection;
+
+fn invite(media_port: u16) -> String {
+    let sdp = format!(
+        "v=0\r\n\
+         o=- 1 1 IN IP4 127.0.0.1\r\n\
+         s=-\r\n\
+         c=IN IP4 127.0.0.1\r\n\
+         t=0 0\r\n\
+         m=audio {media_port} RTP/AVP 0\r\n"
+    );
+
+    format!(
+        "INVITE sip:1001@pbx.invalid SIP/2.0\r\n\
+         Via: SIP/2.0/WSS pbx.invalid;branch=z9hG4bKinvite\r\n\
+         Record-Route: <sip:proxy.pbx.invalid;lr>\r\n\
+         From: <sip:1002@pbx.invalid>;tag=caller\r\n\
+         To: <sip:1001@pbx.invalid>\r\n\
+         Call-ID: answered@pbx.invalid\r\n\
+         CSeq: 1 INVITE\r\n\
+         Contact: <sip:1002@192.0.2.2:5060>\r\n\
+         Content-Type: application/sdp\r\n\
+         Content-Length: {len}\r\n\r\n{sdp}",
+        len = sdp.len(),
+    )
+}
+
+fn text(message: Message) -> String {
+    match message {
+        Message::Text(text) => text.to_string(),
+        other => panic!("Expected text message, got {other:?}"),
+    }
+}
+
+/// A response to a request with the given status
+fn reply(request: &Request, status_code: StatusCode) -> String {
+    let headers = request
+        .headers
+        .iter()
+        .filter(|&header| {
+            matches!(header, Header::Via(_) | Header::From(_) | Header::To(_) | Header::CSeq(_) | Header::CallId(_))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Response {
+        status_code,
+        version: Version::V2,
+        headers: headers.into(),
+        body: Vec::new(),
+    }
+    .to_string()
+}
+
+#[tokio::test]
+async fn answer_play_and_hang_up() {
+    let (sink_tx, mut sink_rx) = mpsc::unbounded::<Message>();
+    let (mut stream_tx, stream_rx) = mpsc::unbounded::<anyhow::Result<Message>>();
+
+    let (_connection, mut requests) = Connection::builder("wss://pbx.invalid/".parse().unwrap(), "1001")
+        .attach(sink_tx.sink_map_err(anyhow::Error::from), stream_rx)
+        .unwrap();
+
+    let caller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let invite = invite(caller.local_addr().unwrap().port());
+    stream_tx.send(Ok(Message::text(invite))).await.unwrap();
+
+    let tx = requests.recv().await.unwrap();
+    let offer = String::from_utf8_lossy(&tx.request.body).into_owned();
+    let (media, sdp) = MediaSession::answer(&offer, &Codecs::default(), &MediaConfig::default()).await.unwrap();
+    assert_eq!(media.sample_rate(), 8000);
+    assert!(sdp.contains("a=rtpmap:0 PCMU/8000"), "{sdp}");
+
+    let dialog = tx.answer(sdp).await.unwrap();
+    assert_eq!(dialog.call_id(), "answered@pbx.invalid");
+
+    let ok = Response::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(ok.status_code, StatusCode::OK);
+    let local_tag = ok.to_header().unwrap().typed().unwrap().tag().cloned().expect("To tag");
+    assert!(ok.contact_header().is_ok());
+    assert!(String::from_utf8_lossy(&ok.body).starts_with("v=0"));
+
+    // A clip of two packets, then silence
+    let playing = tokio::spawn(async move {
+        media.play(vec![1000; 320]).await.unwrap();
+        media
+    });
+
+    let mut datagram = [0; 1500];
+    let mut packets = Vec::new();
+    for _ in 0..3 {
+        let len = caller.recv(&mut datagram).await.unwrap();
+        packets.push(RtpPacket::parse(&datagram[..len]).unwrap());
+    }
+    let media = playing.await.unwrap();
+
+    assert!(packets[0].marker);
+    assert!(packets.iter().all(|packet| packet.payload_type == 0 && packet.payload.len() == 160));
+    assert_eq!(packets[1].sequence, packets[0].sequence.wrapping_add(1));
+    assert_eq!(packets[1].timestamp, packets[0].timestamp.wrapping_add(160));
+    assert_ne!(packets[0].payload, packets[2].payload);
+
+    let hangup = tokio::spawn(async move { dialog.bye().await });
+
+    let bye = Request::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(bye.method, Method::Bye);
+    assert_eq!(bye.uri.to_string(), "sip:1002@192.0.2.2:5060");
+    assert!(bye.to_string().contains("Route: <sip:proxy.pbx.invalid;lr>"), "{bye}");
+    assert_eq!(bye.from_header().unwrap().typed().unwrap().tag(), Some(&local_tag));
+    assert_eq!(bye.to_header().unwrap().typed().unwrap().tag().map(ToString::to_string).as_deref(), Some("caller"));
+
+    stream_tx.send(Ok(Message::text(reply(&bye, StatusCode::OK)))).await.unwrap();
+    hangup.await.unwrap().unwrap();
+
+    media.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn webrtc_offers_are_not_answered() {
+    let offer = "v=0\r\n\
+        o=- 1 1 IN IP4 127.0.0.1\r\n\
+        s=-\r\n\
+        c=IN IP4 127.0.0.1\r\n\
+        t=0 0\r\n\
+        m=audio 9 UDP/TLS/RTP/SAVPF 0\r\n\
+        a=fingerprint:sha-256 00:11\r\n\
+        a=setup:actpass\r\n";
+
+    assert!(MediaSession::answer(offer, &Codecs::default(), &MediaConfig::default()).await.is_err());
+}
+
+#[tokio::test]
+async fn received_media_is_buffered_and_counted() {
+    let caller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let offer = invite(caller.local_addr().unwrap().port());
+    let offer = &offer[offer.find("v=0").unwrap()..];
+
+    let (media, sdp) = MediaSession::answer(offer, &Codecs::default(), &MediaConfig::default()).await.unwrap();
+    let answer = AudioOffer::parse(&sdp).unwrap();
+    let session = SocketAddr::new(answer.address.unwrap(), answer.port);
+    let mut frames = media.listen();
+
+    // Every third packet is lost
+    for sequence in (0..30u16).filter(|sequence| sequence % 3 != 1) {
+        let packet = RtpPacket {
+            payload_type: 0,
+            marker: sequence == 0,
+            sequence,
+            timestamp: sequence as u32 * 160,
+            ssrc: 0x1234,
+            payload: vec![0xff; 160].into(),
+        };
+        caller.send_to(&packet.encode(), session).await.unwrap();
+    }
+
+    let frame = frames.recv().await.unwrap();
+    assert_eq!(frame.len(), 160);
+
+    let stats = media.stop().await.unwrap();
+    assert_eq!(stats.received, 20);
+    assert_eq!(stats.expected, 30);
+    assert_eq!(stats.lost(), 10);
+}
+
+/// Binds the pair of RTP and RTCP ports of the caller
+async fn caller_pair() -> (UdpSocket, UdpSocket) {
+    loop {
+        let rtp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = rtp.local_addr().unwrap().port();
+        if port % 2 == 0
+            && let Ok(rtcp) = UdpSocket::bind(("127.0.0.1", port + 1)).await
+        {
+            return (rtp, rtcp);
+        }
+    }
+}
+
+#[tokio::test]
+async fn rtcp_measures_round_trip_and_says_bye() {
+    let (caller, caller_rtcp) = caller_pair().await;
+    let offer = invite(caller.local_addr().unwrap().port());
+    let offer = &offer[offer.find("v=0").unwrap()..];
+
+    let (media, sdp) = MediaSession::answer(offer, &Codecs::default(), &MediaConfig::default()).await.unwrap();
+    let answer = AudioOffer::parse(&sdp).unwrap();
+    assert_eq!(answer.port % 2, 0);
+    let session_rtcp = SocketAddr::new(answer.address.unwrap(), answer.port + 1);
+
+    let mut datagram = [0; 1500];
+    let len = caller.recv(&mut datagram).await.unwrap();
+    let ssrc = RtpPacket::parse(&datagram[..len]).unwrap().ssrc;
+
+    // As if a sender report of the session had been received 200ms ago and answered right away
+    let last_sr = rtcp::ntp_middle(rtcp::ntp_timestamp(SystemTime::now() - Duration::from_millis(200)));
+    let report = RtcpPacket::ReceiverReport {
+        ssrc: 0x1234,
+        reports: vec![ReportBlock {
+            ssrc,
+            last_sr,
+            ..ReportBlock::default()
+        }],
+    };
+    caller_rtcp.send_to(&rtcp::encode_compound(&[report]), session_rtcp).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let stats = media.stop().await.unwrap();
+    let rtt = stats.rtt_ms.unwrap();
+    assert!((200.0..400.0).contains(&rtt), "{rtt}");
+
+    let len = caller_rtcp.recv(&mut datagram).await.unwrap();
+    let packets = RtcpPacket::parse_compound(&datagram[..len]).unwrap();
+    assert!(matches!(
+        packets[0],
+        RtcpPacket::SenderReport { ssrc: sender, packet_count, .. } if sender == ssrc && packet_count > 0
+    ));
+    assert!(matches!(&packets[1], RtcpPacket::SourceDescription { cname, .. } if cname.starts_with("ucware-cli@")));
+    assert_eq!(packets[2], RtcpPacket::Bye { sources: vec![ssrc], reason: None });
+}
+
+#[tokio::test]
+async fn silence_is_suppressed_with_comfort_noise() {
+    let caller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let offer = format!(
+        "v=0\r\n\
+         o=- 1 1 IN IP4 127.0.0.1\r\n\
+         s=-\r\n\
+         c=IN IP4 127.0.0.1\r\n\
+         t=0 0\r\n\
+         m=audio {port} RTP/AVP 0 13\r\n",
+        port = caller.local_addr().unwrap().port(),
+    );
+
+    let mut config = MediaConfig::default();
+    config.silence_suppression.enabled = true;
+    config.silence_suppression.refresh = Some(Age(Duration::from_millis(100)));
+
+    let (media, sdp) = MediaSession::answer(&offer, &Codecs::default(), &config).await.unwrap();
+    assert!(sdp.contains("m=audio ") && sdp.contains(" RTP/AVP 0 13\r\n"), "{sdp}");
+
+    let mut datagram = [0; 1500];
+    let mut receive = async || {
+        let len = caller.recv(&mut datagram).await.unwrap();
+        RtpPacket::parse(&datagram[..len]).unwrap()
+    };
+
+    // Silence from the start, refreshed every five frames
+    let first = receive().await;
+    let second = receive().await;
+    for noise in [&first, &second] {
+        assert_eq!((noise.payload_type, noise.payload.len()), (13, 1));
+    }
+    assert_eq!(second.sequence, first.sequence.wrapping_add(1));
+    assert_eq!(second.timestamp, first.timestamp.wrapping_add(5 * 160));
+
+    media.play(vec![1000; 160]).await.unwrap();
+    let voice = loop {
+        let packet = receive().await;
+        if packet.payload_type == 0 {
+            break packet;
+        }
+    };
+    assert!(voice.marker);
+    assert_eq!(voice.payload.len(), 160);
+
+    media.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn hold_reinvites_and_plays_music() {
+    let (sink_tx, mut sink_rx) = mpsc::unbounded::<Message>();
+    let (mut stream_tx, stream_rx) = mpsc::unbounded::<anyhow::Result<Message>>();
+
+    let (_connection, mut requests) = Connection::builder("wss://pbx.invalid/".parse().unwrap(), "1001")
+        .attach(sink_tx.sink_map_err(anyhow::Error::from), stream_rx)
+        .unwrap();
+
+    let caller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    stream_tx.send(Ok(Message::text(invite(caller.local_addr().unwrap().port())))).await.unwrap();
+
+    let tx = requests.recv().await.unwrap();
+    let offer = String::from_utf8_lossy(&tx.request.body).into_owned();
+    let (mut media, sdp) = MediaSession::answer(&offer, &Codecs::default(), &MediaConfig::default()).await.unwrap();
+    let version = |sdp: &str| {
+        let origin = sdp.lines().find(|line| line.starts_with("o=")).unwrap();
+        origin.split(' ').nth(2).unwrap().to_string()
+    };
+    let answered = version(&sdp);
+
+    let dialog = std::sync::Arc::new(tx.answer(sdp).await.unwrap());
+    sink_rx.next().await.unwrap();
+
+    let music = HoldMusic::Looped {
+        samples: vec![1000; 80].into(),
+        position: 0,
+    };
+    let offer = media.hold(music);
+    assert!(offer.contains("a=sendonly\r\n"), "{offer}");
+    assert_ne!(version(&offer), answered);
+
+    let holding = tokio::spawn({
+        let dialog = dialog.clone();
+        async move { dialog.reinvite(offer).await }
+    });
+
+    let invite = Request::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(invite.method, Method::Invite);
+    assert!(String::from_utf8_lossy(&invite.body).contains("a=sendonly"));
+    stream_tx.send(Ok(Message::text(reply(&invite, StatusCode::OK)))).await.unwrap();
+    holding.await.unwrap().unwrap();
+
+    // The 2xx is acknowledged in a transaction of its own
+    let ack = Request::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(ack.method, Method::Ack);
+    assert_eq!(ack.cseq_header().unwrap().typed().unwrap().seq, invite.cseq_header().unwrap().typed().unwrap().seq);
+    assert_ne!(ack.via_header().unwrap(), invite.via_header().unwrap());
+
+    // Music rather than silence once held
+    let mut datagram = [0; 1500];
+    let music = loop {
+        let len = caller.recv(&mut datagram).await.unwrap();
+        let packet = RtpPacket::parse(&datagram[..len]).unwrap();
+        // Silence is 0xff in µ-law
+        if packet.payload[0] != 0xff {
+            break packet;
+        }
+    };
+    assert!(music.payload.iter().all(|&byte| byte == music.payload[0]));
+
+    // A rejected offer is acknowledged within its transaction
+    let offer = media.resume();
+    assert!(offer.contains("a=sendrecv\r\n"), "{offer}");
+    let resuming = tokio::spawn({
+        let dialog = dialog.clone();
+        async move { dialog.reinvite(offer).await }
+    });
+    let invite = Request::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    stream_tx.send(Ok(Message::text(reply(&invite, StatusCode::RequestPending)))).await.unwrap();
+    assert!(resuming.await.unwrap().is_err());
+
+    let ack = Request::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(ack.method, Method::Ack);
+    assert_eq!(ack.via_header().unwrap(), invite.via_header().unwrap());
+
+    media.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn digits_are_received_as_events() {
+    let caller = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let offer = format!(
+        "v=0\r\n\
+         c=IN IP4 127.0.0.1\r\n\
+         m=audio {port} RTP/AVP 0 101\r\n\
+         a=rtpmap:101 telephone-event/8000\r\n",
+        port = caller.local_addr().unwrap().port(),
+    );
+
+    let (media, sdp) = MediaSession::answer(&offer, &Codecs::default(), &MediaConfig::default()).await.unwrap();
+    assert!(sdp.contains("a=rtpmap:101 telephone-event/8000\r\n"), "{sdp}");
+    let answer = AudioOffer::parse(&sdp).unwrap();
+    let session = SocketAddr::new(answer.address.unwrap(), answer.port);
+    let mut digits = media.digits();
+
+    // Events are sent repeatedly while the key is held
+    let events = [(7, 1000), (7, 1000), (7, 1000), (11, 2000), (11, 2000)];
+    for (sequence, (digit, timestamp)) in events.into_iter().enumerate() {
+        let packet = RtpPacket {
+            payload_type: 101,
+            marker: false,
+            sequence: sequence as u16,
+            timestamp,
+            ssrc: 0x1234,
+            payload: vec![digit, 0x0a, 0x00, 0xa0].into(),
+        };
+        caller.send_to(&packet.encode(), session).await.unwrap();
+    }
+
+    assert_eq!(digits.recv().await, Some('7'));
+    assert_eq!(digits.recv().await, Some('#'));
+
+    media.stop().await.unwrap();
+    assert!(digits.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn dialogs_do_not_keep_dropped_connection_open() {
+    let (sink_tx, mut sink_rx) = mpsc::unbounded::<Message>();
+    let (mut stream_tx, stream_rx) = mpsc::unbounded::<anyhow::Result<Message>>();
+
+    let (connection, mut requests) = Connection::builder("wss://pbx.invalid/".parse().unwrap(), "1001")
+        .attach(sink_tx.sink_map_err(anyhow::Error::from), stream_rx)
+        .unwrap();
+
+    stream_tx.send(Ok(Message::text(invite(9)))).await.unwrap();
+    let tx = requests.recv().await.unwrap();
+    let dialog = tx.answer("v=0\r\n").await.unwrap();
+    let ok = Response::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(ok.status_code, StatusCode::OK);
+
+    drop(connection);
+
+    // The socket is closed although the dialog is still around
+    let closed = tokio::time::timeout(Duration::from_secs(1), sink_rx.next()).await.unwrap();
+    assert_eq!(closed, None);
+    assert!(dialog.bye().await.is_err());
+}
+
+#[tokio::test]
+async fn requests_without_handler_are_rejected() {
+    let (sink_tx, mut sink_rx) = mpsc::unbounded::<Message>();
+    let (mut stream_tx, stream_rx) = mpsc::unbounded::<anyhow::Result<Message>>();
+
+    let (connection, requests) = Connection::builder("wss://pbx.invalid/".parse().unwrap(), "1001")
+        .attach(sink_tx.sink_map_err(anyhow::Error::from), stream_rx)
+        .unwrap();
+    drop(requests);
+
+    stream_tx.send(Ok(Message::text(invite(9)))).await.unwrap();
+    let response = Response::try_from(text(sink_rx.next().await.unwrap()).as_str()).unwrap();
+    assert_eq!(response.status_code, StatusCode::ServiceUnavailable);
+
+    connection.close().await;
+    assert_eq!(sink_rx.next().await, None);
+}
@@ -0,0 +1,41 @@
+use rsip::{Response, SipMessage};
+use ucware_cli::sipsocket::Binding;
+
+const OK: &str = "SIP/2.0 200 OK\r\n\
+    Via: SIP/2.0/WSS abc.invalid;branch=z9hG4bK1\r\n\
+    From: <sip:1001@pbx.example.com>;tag=a\r\n\
+    To: <sip:1001@pbx.example.com>;tag=b\r\n\
+    Call-ID: call-1@abc.invalid\r\n\
+    CSeq: 2 REGISTER\r\n\
+    Contact: <sip:first@abc.invalid;transport=ws>;expires=3540;+sip.instance=\"<urn:uuid:00000000-0000-4000-8000-000000000001>\"\r\n\
+    Contact: <sip:second@def.invalid;transport=ws>\r\n\
+    Expires: 600\r\n\
+    Content-Length: 0\r\n\r\n";
+
+fn response(message: &str) -> Response {
+    match SipMessage::try_from(message).expect("parsable message") {
+        SipMessage::Response(response) => response,
+        SipMessage::Request(_) => panic!("expected response"),
+    }
+}
+
+#[test]
+fn bindings_from_register_response() {
+    let bindings = Binding::from_response(&response(OK));
+
+    assert_eq!(
+        bindings,
+        [
+            Binding {
+                contact: "sip:first@abc.invalid;transport=ws".to_string(),
+                expires: Some(3540),
+                instance: Some("urn:uuid:00000000-0000-4000-8000-000000000001".to_string()),
+            },
+            Binding {
+                contact: "sip:second@def.invalid;transport=ws".to_string(),
+                expires: Some(600),
+                instance: None,
+            },
+        ]
+    );
+}
@@ -0,0 +1,85 @@
+use rsip::{Request, SipMessage, StatusCode};
+use ucware_cli::callstate::blocklist::BlockRule;
+use ucware_cli::callstate::policy::{self, Screening};
+use ucware_cli::callstate::{CallState, Incoming, Origin};
+use ucware_cli::config::Config;
+
+fn invite(from: &str, via: &str) -> Request {
+    let message = format!(
+        "INVITE sip:alice@example.com SIP/2.0\r\n\
+         Via: {via}\r\n\
+         From: {from};tag=a\r\n\
+         To: <sip:alice@example.com>\r\n\
+         Call-ID: call-1\r\n\
+         CSeq: 1 INVITE\r\n\
+         Content-Length: 0\r\n\r\n"
+    );
+
+    match SipMessage::try_from(message.as_str()).expect("valid message") {
+        SipMessage::Request(request) => request,
+        SipMessage::Response(_) => unreachable!(),
+    }
+}
+
+fn matching(request: &Request) -> Vec<String> {
+    let Incoming::New(call) = CallState::new().incoming(request, &Config::default(), &Origin::default()).unwrap() else {
+        panic!("expected new call");
+    };
+
+    ["anonymous", "10.1.0.0/16", "2001:db8::/32", "+4930*", "/^Spam( Inc)?$/"]
+        .into_iter()
+        .map(|rule| rule.parse::<BlockRule>().unwrap())
+        .filter(|rule| rule.matches(&call))
+        .map(|rule| rule.to_string())
+        .collect()
+}
+
+#[test]
+fn rules_round_trip() {
+    for rule in ["anonymous", "10.1.0.0/16", "2001:db8::/32", "/^\\+49/", "+4930*"] {
+        assert_eq!(rule.parse::<BlockRule>().unwrap().to_string(), rule);
+    }
+
+    assert_eq!("192.0.2.1".parse::<BlockRule>().unwrap().to_string(), "192.0.2.1/32");
+    assert!("[".parse::<BlockRule>().is_err());
+    assert!("/(/".parse::<BlockRule>().is_err());
+}
+
+#[test]
+fn rules_match_calls() {
+    let via = "SIP/2.0/WSS proxy.example.com;branch=z9hG4bK1";
+
+    let request = invite("Spam <sip:+4930123@example.com>", via);
+    assert_eq!(matching(&request), ["+4930*", "/^Spam( Inc)?$/"]);
+
+    let request = invite("\"Anonymous\" <sip:anonymous@anonymous.invalid>", via);
+    assert_eq!(matching(&request), ["anonymous"]);
+
+    let via = "SIP/2.0/UDP 10.1.2.3:5060;branch=z9hG4bK1";
+    let request = invite("<sip:+4989123@example.com>", via);
+    assert_eq!(matching(&request), ["10.1.0.0/16"]);
+
+    let via = "SIP/2.0/UDP [2001:db8::1]:5060;branch=z9hG4bK1";
+    let request = invite("<sip:+4989123@example.com>", via);
+    assert_eq!(matching(&request), ["2001:db8::/32"]);
+
+    let via = "SIP/2.0/UDP proxy.example.com;received=10.1.2.3;branch=z9hG4bK1";
+    let request = invite("<sip:+4989123@example.com>", via);
+    assert_eq!(matching(&request), ["10.1.0.0/16"]);
+}
+
+#[test]
+fn blocked_calls_are_declined() {
+    let config = Config::default();
+    let request = invite("Spam <sip:+4930123@example.com>", "SIP/2.0/WSS proxy.example.com;branch=z9hG4bK1");
+    let Incoming::New(call) = CallState::new().incoming(&request, &config, &Origin::default()).unwrap() else {
+        panic!("expected new call");
+    };
+
+    let rule: BlockRule = "+4930*".parse().unwrap();
+    assert_eq!(
+        policy::blocked(&call, &config, std::slice::from_ref(&rule)),
+        Some(Screening::Blocked { rule, status: StatusCode::Decline })
+    );
+    assert_eq!(policy::blocked(&call, &config, &["anonymous".parse().unwrap()]), None);
+}
@@ -0,0 +1,197 @@
+use chrono::{NaiveDate, Weekday};
+use rsip::{Request, SipMessage};
+use ucware_cli::callstate::policy::{self, Screening};
+use ucware_cli::callstate::{CallState, DialogKey, Event, Incoming, Origin, DEFAULT_ACCOUNT};
+use ucware_cli::sipsocket::message_summary::MessageSummary;
+use ucware_cli::config::{Config, TimeProfile};
+
+fn request(method: &str, call_id: &str, from_tag: &str, seq: u32) -> Request {
+    let message = format!(
+        "{method} sip:alice@example.com SIP/2.0\r\n\
+         Via: SIP/2.0/WSS proxy.example.com;branch=z9hG4bK{call_id}\r\n\
+         From: \"Bob\" <sip:bob-{call_id}@example.com>;tag={from_tag}\r\n\
+         To: <sip:alice@example.com>\r\n\
+         Call-ID: {call_id}\r\n\
+         CSeq: {seq} {method}\r\n\
+         Content-Length: 0\r\n\r\n"
+    );
+
+    match SipMessage::try_from(message.as_str()).expect("valid message") {
+        SipMessage::Request(request) => request,
+        SipMessage::Response(_) => unreachable!(),
+    }
+}
+
+fn key(call_id: &str, from_tag: &str) -> DialogKey {
+    DialogKey {
+        call_id: call_id.to_string(),
+        from_tag: Some(from_tag.to_string()),
+    }
+}
+
+#[test]
+fn cancel_matches_invite_by_dialog() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    let origin = Origin {
+        account: "customer".to_string(),
+        slot: Some("Desk".to_string()),
+    };
+
+    let invite = request("INVITE", "call-1", "a", 1);
+    let Incoming::New(call) = calls.incoming(&invite, &config, &origin).unwrap() else {
+        panic!("expected new call");
+    };
+    assert_eq!(call.key, key("call-1", "a"));
+
+    let cancel = request("CANCEL", "call-1", "a", 1);
+    let cancelled = calls.cancelled(&cancel).unwrap().expect("call tracked");
+    assert_eq!(cancelled.key, call.key);
+    assert_eq!(cancelled.origin, origin);
+    assert!(calls.calls().is_empty());
+    assert_eq!(calls.missed(), 1);
+}
+
+#[test]
+fn overlapping_calls_with_same_cseq() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    calls.incoming(&request("INVITE", "call-1", "a", 1), &config, &Origin::default()).unwrap();
+    let second = calls.incoming(&request("INVITE", "call-2", "b", 1), &config, &Origin::default()).unwrap();
+    assert!(matches!(second, Incoming::Waiting { .. }));
+    assert_eq!(calls.calls().len(), 2);
+
+    // Cancelling the second call must leave the first one alone
+    let cancelled = calls
+        .cancelled(&request("CANCEL", "call-2", "b", 1))
+        .unwrap()
+        .expect("call tracked");
+    assert_eq!(cancelled.key, key("call-2", "b"));
+
+    let remaining = calls.calls();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].key, key("call-1", "a"));
+
+    let cancelled = calls
+        .cancelled(&request("CANCEL", "call-1", "a", 1))
+        .unwrap()
+        .expect("call tracked");
+    assert_eq!(cancelled.key, key("call-1", "a"));
+    assert!(calls.calls().is_empty());
+}
+
+#[test]
+fn forked_calls_differ_by_from_tag() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    calls.incoming(&request("INVITE", "call-1", "a", 1), &config, &Origin::default()).unwrap();
+    calls.incoming(&request("INVITE", "call-1", "b", 1), &config, &Origin::default()).unwrap();
+
+    let cancelled = calls
+        .cancelled(&request("CANCEL", "call-1", "b", 1))
+        .unwrap()
+        .expect("call tracked");
+    assert_eq!(cancelled.key, key("call-1", "b"));
+    assert_eq!(calls.calls()[0].key, key("call-1", "a"));
+}
+
+#[test]
+fn cancel_of_unknown_call() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    calls.incoming(&request("INVITE", "call-1", "a", 1), &config, &Origin::default()).unwrap();
+
+    assert!(calls.cancelled(&request("CANCEL", "call-2", "a", 1)).unwrap().is_none());
+    assert!(calls.cancelled(&request("CANCEL", "call-1", "b", 1)).unwrap().is_none());
+    assert_eq!(calls.calls().len(), 1);
+    assert_eq!(calls.missed(), 0);
+}
+
+#[test]
+fn calls_over_limit_are_busy() {
+    let calls = CallState::new();
+    let mut config = Config::default();
+    config.policy.max_calls = Some(1);
+
+    calls.incoming(&request("INVITE", "call-1", "a", 1), &config, &Origin::default()).unwrap();
+    let second = calls.incoming(&request("INVITE", "call-2", "b", 1), &config, &Origin::default()).unwrap();
+    assert!(matches!(second, Incoming::Busy(call) if call.key == key("call-2", "b")));
+    assert_eq!(calls.calls().len(), 1);
+}
+
+#[test]
+fn recent_events_are_kept_for_dumps() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    for n in 0..120 {
+        let invite = request("INVITE", &format!("call-{n}"), "a", 1);
+        calls.incoming(&invite, &config, &Origin::default()).unwrap();
+        calls.screened(&key(&format!("call-{n}"), "a"));
+    }
+    calls.set_registered(DEFAULT_ACCOUNT, true);
+
+    let events = calls.recent_events();
+    assert_eq!(events.len(), 100);
+    assert!(matches!(&events[0].event, Event::Screened { call } if call.key.call_id == "call-70"));
+    assert!(matches!(events[99].event, Event::Registered { registered: true }));
+}
+
+#[test]
+fn accounts_tracked_separately() {
+    let calls = CallState::new();
+    let mut events = calls.events();
+
+    calls.set_registered(DEFAULT_ACCOUNT, true);
+    calls.set_registered("support", false);
+    assert!(!calls.registered());
+    assert!(matches!(events.try_recv().unwrap(), Event::Registered { registered: true }));
+    assert!(matches!(events.try_recv().unwrap(), Event::Registered { registered: false }));
+
+    calls.set_registered("support", true);
+    assert!(calls.registered());
+    assert_eq!(calls.registrations().len(), 2);
+
+    let summary = |new| MessageSummary {
+        waiting: new > 0,
+        new,
+        ..MessageSummary::default()
+    };
+    calls.set_voicemail(DEFAULT_ACCOUNT, summary(2));
+    assert_eq!(calls.set_voicemail("support", summary(1)), MessageSummary::default());
+    assert_eq!(calls.set_voicemail(DEFAULT_ACCOUNT, summary(0)), summary(2));
+    assert_eq!(calls.voicemail(), summary(1));
+}
+
+#[test]
+fn closed_office_answers_with_announcement() {
+    let calls = CallState::new();
+    let mut config = Config::default();
+    config.out_of_office.announcement_text = Some("We are closed".to_string());
+    config.out_of_office.closed.push(TimeProfile {
+        days: vec![Weekday::Sat, Weekday::Sun],
+        from: None,
+        until: None,
+        redirect: Some("sip:weekend@example.com".to_string()),
+    });
+
+    let Incoming::New(call) = calls.incoming(&request("INVITE", "call-1", "a", 1), &config, &Origin::default()).unwrap()
+    else {
+        panic!("expected new call");
+    };
+
+    let saturday = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap().and_hms_opt(12, 0, 0).unwrap();
+    let Screening::OutOfOffice(closed) = policy::screen(&call, &config, &[], saturday, false, false) else {
+        panic!("expected office closed");
+    };
+    assert!(closed.answers());
+    assert_eq!(closed.announcement_text.as_deref(), Some("We are closed"));
+    assert_eq!(closed.redirect.as_deref(), Some("sip:weekend@example.com"));
+
+    let monday = saturday + chrono::Duration::days(2);
+    assert!(matches!(policy::screen(&call, &config, &[], monday, false, false), Screening::Notify { .. }));
+}
@@ -0,0 +1,91 @@
+use rsip::message::{HasHeaders, HeadersExt};
+use rsip::headers::UntypedHeader;
+use rsip::{Header, SipMessage};
+use ucware_cli::sipsocket::compact::{self, CompactHeaders};
+use ucware_cli::sipsocket::Middleware;
+
+const INVITE: &str = "INVITE sip:alice@example.com SIP/2.0\r\n\
+    Via: SIP/2.0/WSS proxy.example.com;branch=z9hG4bK1\r\n\
+    From: \"Bob\" <sip:bob@example.com>;tag=a\r\n\
+    To: <sip:alice@example.com>\r\n\
+    Call-ID: call-1\r\n\
+    CSeq: 1 INVITE\r\n\
+    Contact: <sip:bob@192.0.2.1;transport=ws>\r\n\
+    Supported: replaces\r\n\
+    Content-Type: application/sdp\r\n\
+    Content-Length: 4\r\n\r\n\
+    v=0\n";
+
+const BYE: &str = "BYE sip:alice@example.com SIP/2.0\r\n\
+    Via: SIP/2.0/WSS proxy.example.com;branch=z9hG4bK2\r\n\
+    From: <sip:bob@example.com>;tag=a\r\n\
+    To: <sip:alice@example.com>;tag=b\r\n\
+    Call-ID: call-1\r\n\
+    CSeq: 2 BYE\r\n\
+    Content-Length: 0\r\n\r\n";
+
+fn parse(message: &str) -> SipMessage {
+    SipMessage::try_from(message).expect("valid message")
+}
+
+/// Sends a message through the middleware and parses it like the receiving side
+fn round_trip(message: &str) -> (String, SipMessage) {
+    let compacted = CompactHeaders.outgoing(parse(message)).expect("not dropped");
+    let wire = String::from(compacted);
+
+    let mut received = parse(&wire);
+    compact::expand(received.headers_mut());
+
+    (wire, received)
+}
+
+fn without_length(message: &SipMessage) -> Vec<&Header> {
+    message
+        .headers()
+        .iter()
+        .filter(|header| !matches!(header, Header::ContentLength(_)))
+        .collect()
+}
+
+#[test]
+fn headers_are_sent_compact() {
+    let (wire, _) = round_trip(INVITE);
+
+    for header in ["v: ", "f: ", "t: ", "i: ", "m: ", "k: ", "c: ", "l: "] {
+        assert!(wire.contains(&format!("\r\n{header}")), "{header:?} missing in {wire}");
+    }
+    for header in ["Via:", "From:", "To:", "Call-ID:", "Contact:"] {
+        assert!(!wire.contains(header), "{header:?} not compacted in {wire}");
+    }
+
+    assert!(wire.len() < INVITE.len());
+}
+
+#[test]
+fn compact_headers_round_trip() {
+    for message in [INVITE, BYE] {
+        let original = parse(message);
+        let (_, received) = round_trip(message);
+
+        assert_eq!(received.body(), original.body());
+
+        // Content-Length may be stripped, everything else must survive
+        assert_eq!(without_length(&received), without_length(&original));
+
+        assert_eq!(received.call_id_header().unwrap().value(), "call-1");
+    }
+}
+
+#[test]
+fn empty_content_length_is_stripped() {
+    let (wire, received) = round_trip(BYE);
+    assert!(!wire.contains("l: 0") && !wire.contains("Content-Length"), "{wire}");
+    assert!(received.body().is_empty());
+
+    let (_, received) = round_trip(INVITE);
+    let content_length = received.headers().iter().find_map(|header| match header {
+        Header::ContentLength(length) => Some(length.value().to_string()),
+        _ => None,
+    });
+    assert_eq!(content_length.as_deref(), Some("4"));
+}
@@ -0,0 +1,29 @@
+use std::time::SystemTime;
+use ucware_cli::daemon::CrashReport;
+
+#[test]
+fn reports_are_written_as_json() {
+    let dir = std::env::temp_dir().join(format!("ucware-crashes-{}", std::process::id()));
+
+    let report = CrashReport {
+        time: SystemTime::now(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        thread: Some("main".to_string()),
+        message: "index out of bounds".to_string(),
+        location: Some("src/daemon/mod.rs:1:1".to_string()),
+        backtrace: String::new(),
+        state: None,
+        cleanup: vec!["unregister: done".to_string()],
+    };
+
+    let path = report.write(&dir).expect("written report");
+    assert!(path.starts_with(&dir));
+
+    let written: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).expect("readable report")).expect("JSON report");
+    assert_eq!(written["message"], "index out of bounds");
+    assert_eq!(written["cleanup"][0], "unregister: done");
+    assert!(written["state"].is_null());
+
+    std::fs::remove_dir_all(dir).expect("removed reports");
+}
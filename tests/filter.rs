@@ -0,0 +1,62 @@
+#![cfg(feature = "wasm")]
+
+use std::path::PathBuf;
+use ucware_cli::callstate::Event;
+use ucware_cli::config::EventFilterConfig;
+use ucware_cli::filter::Filters;
+
+/// Module answering every event with the given result of `filter`
+fn module(name: &str, body: &str) -> EventFilterConfig {
+    let wat = format!(
+        r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "{{\"event\":\"registered\",\"registered\":false}}")
+            (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+            (func (export "filter") (param i32 i32) (result i64) {body}))"#
+    );
+
+    let path = std::env::temp_dir().join(format!("ucware-filter-{name}-{}.wat", std::process::id()));
+    std::fs::write(&path, wat).unwrap();
+    EventFilterConfig {
+        module: path,
+        fuel: None,
+    }
+}
+
+fn registered(event: Option<Event>) -> Option<bool> {
+    match event? {
+        Event::Registered { registered } => Some(registered),
+        event => panic!("unexpected event {event:?}"),
+    }
+}
+
+#[test]
+fn filters_keep_drop_and_replace_events() {
+    let keep = module("keep", "(i64.const 0)");
+    let drop = module("drop", "(i64.const -1)");
+    // Points to the 41 bytes of JSON at the start of the memory
+    let replace = module("replace", "(i64.const 41)");
+
+    let event = || Event::Registered { registered: true };
+
+    let filters = Filters::load(std::slice::from_ref(&keep));
+    assert_eq!(registered(filters.apply(event())), Some(true));
+
+    let filters = Filters::load(&[keep.clone(), replace.clone()]);
+    assert_eq!(registered(filters.apply(event())), Some(false));
+
+    let filters = Filters::load(&[replace, drop]);
+    assert_eq!(registered(filters.apply(event())), None);
+}
+
+#[test]
+fn failing_filters_pass_events_on() {
+    let endless = module("endless", "(loop (br 0)) (i64.const -1)");
+    let missing = EventFilterConfig {
+        module: PathBuf::from("/nonexistent/filter.wasm"),
+        fuel: None,
+    };
+
+    let filters = Filters::load(&[endless, missing]);
+    assert_eq!(registered(filters.apply(Event::Registered { registered: true })), Some(true));
+}
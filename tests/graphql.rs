@@ -0,0 +1,20 @@
+#![cfg(feature = "graphql")]
+
+use async_graphql::{EmptyMutation, Schema};
+use ucware_cli::graphql::{Query, Subscription};
+
+#[test]
+fn schema_exposes_state_and_events() {
+    let sdl = Schema::build(Query, EmptyMutation, Subscription).finish().sdl();
+
+    for field in [
+        "registered: Boolean!",
+        "calls: [Call!]!",
+        "history(since: Int): [HistoryEntry!]!",
+        "queues: [QueueStats!]!",
+        "events: CallEvent!",
+        "serviceLevel: Float!",
+    ] {
+        assert!(sdl.contains(field), "missing {field} in\n{sdl}");
+    }
+}
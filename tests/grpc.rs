@@ -0,0 +1,39 @@
+#![cfg(feature = "grpc")]
+
+use rsip::{Request, SipMessage};
+use ucware_cli::callstate::{CallState, Event, Incoming, Origin};
+use ucware_cli::config::Config;
+use ucware_cli::grpc::proto;
+
+#[test]
+fn events_carry_call_and_json() {
+    let message = "INVITE sip:alice@example.com SIP/2.0\r\n\
+         Via: SIP/2.0/WSS proxy.example.com;branch=z9hG4bK1\r\n\
+         From: Bob <sip:+4930123@example.com>;tag=a\r\n\
+         To: <sip:alice@example.com>\r\n\
+         Call-ID: call-1\r\n\
+         CSeq: 1 INVITE\r\n\
+         Content-Length: 0\r\n\r\n";
+    let request: Request = match SipMessage::try_from(message).expect("valid message") {
+        SipMessage::Request(request) => request,
+        SipMessage::Response(_) => unreachable!(),
+    };
+    let Incoming::New(call) = CallState::new().incoming(&request, &Config::default(), &Origin::default()).unwrap() else {
+        panic!("expected new call");
+    };
+
+    let event = proto::Event::try_from(&Event::Incoming { call, waiting: false }).unwrap();
+    assert_eq!(event.kind, "incoming");
+
+    let call = event.call.unwrap();
+    assert_eq!(call.call_id, "call-1");
+    assert_eq!(call.caller_name.as_deref(), Some("Bob"));
+    assert_eq!(call.caller_number.as_deref(), Some("+4930123"));
+
+    let json: serde_json::Value = serde_json::from_str(&event.json).unwrap();
+    assert_eq!(json["waiting"], false);
+
+    let event = proto::Event::try_from(&Event::Registered { registered: true }).unwrap();
+    assert_eq!(event.kind, "registered");
+    assert!(event.call.is_none());
+}
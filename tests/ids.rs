@@ -0,0 +1,59 @@
+use rsip::{Host, HostWithPort};
+use std::collections::HashSet;
+use ucware_cli::sipsocket::ids::{RandomIds, SipIdGenerator};
+
+fn host() -> HostWithPort {
+    HostWithPort {
+        host: Host::from("abc123.invalid"),
+        port: Some(5060.into()),
+    }
+}
+
+#[test]
+fn call_id_has_host_part() {
+    let call_id = RandomIds.call_id(&host());
+
+    let (random, host) = call_id.split_once('@').expect("host part");
+    assert_eq!(host, "abc123.invalid");
+
+    // 22 alphanumeric characters carry just over 128 bits
+    assert!(random.len() >= 22, "{random} too short");
+    assert!(random.chars().all(|c| c.is_ascii_alphanumeric()));
+}
+
+#[test]
+fn call_ids_are_unique() {
+    let call_ids = (0..1000)
+        .map(|_| RandomIds.call_id(&host()))
+        .collect::<HashSet<_>>();
+
+    assert_eq!(call_ids.len(), 1000);
+}
+
+#[test]
+fn branch_has_magic_cookie() {
+    assert!(RandomIds.branch().starts_with("z9hG4bK"));
+}
+
+#[cfg(feature = "deterministic-ids")]
+#[test]
+fn seeded_ids_repeat() {
+    use ucware_cli::sipsocket::ids::SeededIds;
+
+    let sequence = |ids: SeededIds| {
+        vec![
+            ids.contact_user(),
+            ids.call_id(&host()),
+            ids.tag(),
+            ids.branch(),
+            ids.branch(),
+        ]
+    };
+
+    let first = sequence(SeededIds::new(42));
+    assert_eq!(first, sequence(SeededIds::new(42)));
+    assert_ne!(first, sequence(SeededIds::new(7)));
+
+    assert_eq!(first[1], "call2a000001@abc123.invalid");
+    assert_ne!(first[3], first[4]);
+}
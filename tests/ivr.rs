@@ -0,0 +1,71 @@
+use std::time::Duration;
+use ucware_cli::ivr::{Action, Event, Ivr, Session};
+
+const FLOW: &str = r#"
+start: welcome
+nodes:
+  welcome:
+    type: say
+    text: Welcome to ACME
+    next: menu
+  menu:
+    type: menu
+    prompt: Press 1 for support or 2 to leave a message
+    options:
+      "1": support
+      "2": voicemail
+    timeout: 3s
+    retries: 1
+    fallback: support
+  support:
+    type: transfer
+    target: "100"
+  voicemail:
+    type: record
+    max: 1m
+    next: bye
+  bye:
+    type: hangup
+"#;
+
+fn flow() -> Ivr {
+    let ivr: Ivr = serde_yaml::from_str(FLOW).unwrap();
+    ivr.validate().unwrap();
+    ivr
+}
+
+#[test]
+fn menu_branches_by_digit() {
+    let ivr = flow();
+    let (mut session, action) = Session::start(&ivr);
+    assert_eq!(action, Action::Say("Welcome to ACME".to_string()));
+
+    assert_eq!(session.next(Event::Done), Action::Say("Press 1 for support or 2 to leave a message".to_string()));
+    assert_eq!(session.next(Event::Done), Action::Collect { timeout: Duration::from_secs(3) });
+    assert_eq!(session.next(Event::Digit('2')), Action::Record { max: Duration::from_secs(60) });
+    assert_eq!(session.next(Event::Digit('1')), Action::Record { max: Duration::from_secs(60) });
+    assert_eq!(session.next(Event::Done), Action::Hangup);
+    assert_eq!(session.node(), "bye");
+}
+
+#[test]
+fn menu_falls_back_after_retries() {
+    let ivr = flow();
+    let (mut session, _) = Session::start(&ivr);
+    session.next(Event::Done);
+
+    // Pressed during the prompt, invalid
+    let prompt = session.next(Event::Digit('9'));
+    assert_eq!(prompt, Action::Say("Press 1 for support or 2 to leave a message".to_string()));
+    session.next(Event::Done);
+
+    assert_eq!(session.next(Event::Timeout), Action::Transfer("100".to_string()));
+    assert_eq!(session.next(Event::Done), Action::Hangup);
+}
+
+#[test]
+fn unknown_nodes_are_rejected() {
+    let ivr: Ivr = serde_yaml::from_str("start: a\nnodes:\n  a:\n    type: say\n    text: Hi\n    next: b\n").unwrap();
+    let err = ivr.validate().unwrap_err();
+    assert_eq!(err.to_string(), "Node a refers to unknown node b");
+}
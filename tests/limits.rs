@@ -0,0 +1,111 @@
+use futures::{SinkExt, StreamExt};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use ucware_cli::sipsocket::{Connection, Limits, Metrics};
+use url::Url;
+
+/// Accepts a single SIP socket, sends the given frames and passes on the frames received
+async fn peer(frames: Vec<String>) -> (Url, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/sipsockets/", listener.local_addr().unwrap()).parse().unwrap();
+
+    let (received_tx, received_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_hdr_async(stream, sip_protocol).await.unwrap();
+
+        for frame in frames {
+            socket.send(Message::text(frame)).await.unwrap();
+        }
+
+        while let Some(Ok(Message::Text(frame))) = socket.next().await {
+            let _ = received_tx.send(frame.to_string());
+        }
+    });
+
+    (url, received_rx)
+}
+
+/// Agrees on the SIP subprotocol the client asks for
+#[allow(clippy::result_large_err)]
+fn sip_protocol(_: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    response.headers_mut().insert("Sec-WebSocket-Protocol", "sip".parse().unwrap());
+    Ok(response)
+}
+
+fn invite(body: &str) -> String {
+    format!(
+        "INVITE sip:1001@127.0.0.1 SIP/2.0\r\n\
+         Via: SIP/2.0/WSS pbx.invalid;branch=z9hG4bKoversized\r\n\
+         From: <sip:1002@127.0.0.1>;tag=caller\r\n\
+         To: <sip:1001@127.0.0.1>\r\n\
+         Call-ID: oversized@pbx.invalid\r\n\
+         CSeq: 1 INVITE\r\n\
+         Content-Type: application/sdp\r\n\
+         Content-Length: {length}\r\n\r\n{body}",
+        length = body.len(),
+    )
+}
+
+#[tokio::test]
+async fn oversized_requests_are_rejected_from_their_head() {
+    let limits = Limits {
+        max_message_size: 1024,
+        ..Limits::default()
+    };
+    let metrics = Arc::new(Metrics::default());
+
+    // The body exceeds the limit and would not even parse as SDP
+    let (url, mut received) = peer(vec![invite(&"x".repeat(3 * 1024))]).await;
+    let (_connection, mut requests) =
+        Connection::builder(url, "1001").limits(limits).metrics(metrics.clone()).connect().await.unwrap();
+
+    let response = received.recv().await.unwrap();
+    assert!(response.starts_with("SIP/2.0 513 "), "{response}");
+    assert!(response.contains("branch=z9hG4bKoversized"), "{response}");
+    assert!(requests.try_recv().is_err());
+
+    let metrics = metrics.snapshot();
+    assert_eq!(metrics.received, 1);
+    assert_eq!(metrics.oversized, 1);
+}
+
+#[tokio::test]
+async fn oversized_heads_are_dropped_unparsed() {
+    let limits = Limits {
+        max_message_size: 256,
+        ..Limits::default()
+    };
+    let metrics = Arc::new(Metrics::default());
+
+    let head = invite("").replace("Content-Type: application/sdp", &format!("Subject: {}", "x".repeat(512)));
+    let (url, mut received) = peer(vec![head, invite("")]).await;
+    let (_connection, mut requests) =
+        Connection::builder(url, "1001").limits(limits).metrics(metrics.clone()).connect().await.unwrap();
+
+    // Only the second INVITE within the limit arrives
+    requests.recv().await.unwrap();
+    assert!(received.try_recv().is_err());
+
+    let metrics = metrics.snapshot();
+    assert_eq!(metrics.received, 2);
+    assert_eq!(metrics.oversized, 1);
+}
+
+#[tokio::test]
+async fn unparsable_frames_are_counted() {
+    let metrics = Arc::new(Metrics::default());
+
+    let (url, _received) = peer(vec!["garbage\r\n\r\n".to_string(), invite("")]).await;
+    let (_connection, mut requests) =
+        Connection::builder(url, "1001").metrics(metrics.clone()).connect().await.unwrap();
+
+    requests.recv().await.unwrap();
+
+    let metrics = metrics.snapshot();
+    assert_eq!(metrics.received, 2);
+    assert_eq!(metrics.unparsable, 1);
+}
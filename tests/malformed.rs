@@ -0,0 +1,53 @@
+//! Regression tests for malformed requests as sent by some trunks
+
+use rsip::{Request, SipMessage};
+use std::path::Path;
+use ucware_cli::callstate::{CallState, Origin};
+use ucware_cli::config::Config;
+
+fn load(name: &str) -> Request {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/malformed")
+        .join(name);
+    let message = std::fs::read_to_string(path).expect("readable message");
+
+    match SipMessage::try_from(message.as_str()).expect("parsable message") {
+        SipMessage::Request(request) => request,
+        SipMessage::Response(_) => panic!("expected request"),
+    }
+}
+
+#[test]
+fn invite_with_malformed_from_is_rejected() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    for name in ["invite-empty-from.sip", "invite-missing-from.sip"] {
+        let request = load(name);
+        assert!(calls.incoming(&request, &config, &Origin::default()).is_err(), "{name} accepted");
+    }
+
+    assert!(calls.calls().is_empty());
+}
+
+#[test]
+fn invite_with_broken_cseq_is_tolerated() {
+    let calls = CallState::new();
+    let config = Config::default();
+
+    // Calls are tracked by dialog, the sequence number does not matter
+    let request = load("invite-broken-cseq.sip");
+    let incoming = calls.incoming(&request, &config, &Origin::default());
+    assert!(incoming.is_ok(), "{incoming:?}");
+    assert_eq!(calls.calls().len(), 1);
+}
+
+#[test]
+fn cancel_without_call_id_is_rejected() {
+    let calls = CallState::new();
+
+    let request = load("cancel-missing-call-id.sip");
+    assert!(calls.cancelled(&request).is_err());
+    assert!(calls.ended(&request).is_err());
+    assert_eq!(calls.missed(), 0);
+}
@@ -0,0 +1,434 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ucware_cli::config::{
+    Age, HoldConfig, HttpTranscriptionConfig, HttpTtsConfig, SilenceSuppressionConfig, TranscriptionConfig, TtsConfig,
+};
+use std::time::{Duration, Instant, SystemTime};
+use ucware_cli::media::codec::{self, Codec, Codecs, G711};
+use ucware_cli::media::dtmf::{DtmfDetector, DtmfEvent};
+use ucware_cli::media::hold::HoldMusic;
+use ucware_cli::media::jitter::{JitterBuffer, Playout};
+use ucware_cli::media::rtcp::{self, ReportBlock, RtcpPacket};
+use ucware_cli::media::rtp::RtpPacket;
+use ucware_cli::media::transcribe::Transcriber;
+use ucware_cli::media::tts::Tts;
+use ucware_cli::store::Store;
+use ucware_cli::media::vad::{Decision, SilenceSuppression};
+use ucware_cli::media::level::{Level, Volume};
+use ucware_cli::media::sdp::{self, AudioOffer};
+
+const OFFER: &str = "v=0\r\n\
+    o=- 1 1 IN IP4 192.0.2.1\r\n\
+    s=-\r\n\
+    c=IN IP4 192.0.2.1\r\n\
+    t=0 0\r\n\
+    m=audio 40000 RTP/AVP 111 8 0 101\r\n\
+    c=IN IP4 192.0.2.2\r\n\
+    a=rtpmap:111 opus/48000/2\r\n\
+    a=fmtp:111 minptime=10;useinbandfec=1\r\n\
+    a=rtpmap:101 telephone-event/8000\r\n\
+    m=video 40002 RTP/AVP 96\r\n\
+    a=rtpmap:96 VP8/90000\r\n";
+
+#[test]
+fn g711_round_trip() {
+    let pcm = [0, 100, -100, 1000, -1000, 12345, -12345, i16::MAX, i16::MIN];
+
+    for mut codec in [G711::ulaw(), G711::alaw()] {
+        let mut payload = Vec::new();
+        codec.encode(&pcm, &mut payload).unwrap();
+        assert_eq!(payload.len(), pcm.len());
+
+        let mut decoded = Vec::new();
+        codec.decode(&payload, &mut decoded).unwrap();
+        for (sample, decoded) in pcm.iter().zip(&decoded) {
+            // Quantization error grows with the magnitude
+            let error = (*sample as i32 - *decoded as i32).abs();
+            assert!(error <= (sample.unsigned_abs() as i32 / 16).max(16), "{sample} became {decoded}");
+        }
+    }
+}
+
+#[test]
+fn parse_audio_offer() {
+    let offer = AudioOffer::parse(OFFER).unwrap();
+
+    assert_eq!(offer.address, Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2))));
+    assert_eq!(offer.port, 40000);
+    assert_eq!(offer.protocol, "RTP/AVP");
+    assert_eq!(
+        offer.formats.iter().map(|format| format.name.as_str()).collect::<Vec<_>>(),
+        ["opus", "PCMA", "PCMU", "telephone-event"]
+    );
+    assert_eq!(offer.formats[0].fmtp.as_deref(), Some("minptime=10;useinbandfec=1"));
+}
+
+#[test]
+fn parse_webrtc_offer() {
+    let offer = AudioOffer::parse(
+        "v=0\r\n\
+        o=- 1 1 IN IP4 192.0.2.1\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        a=fingerprint:sha-256 AB:CD:EF\r\n\
+        m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+        c=IN IP4 0.0.0.0\r\n\
+        a=ice-ufrag:abcd\r\n\
+        a=ice-pwd:secret\r\n\
+        a=candidate:1 1 udp 2122260223 192.0.2.1 50000 typ host\r\n\
+        a=setup:actpass\r\n\
+        a=rtpmap:111 opus/48000/2\r\n",
+    )
+    .unwrap();
+
+    assert!(offer.is_webrtc());
+    assert_eq!(offer.transport.ice_ufrag.as_deref(), Some("abcd"));
+    assert_eq!(offer.transport.ice_pwd.as_deref(), Some("secret"));
+    assert_eq!(offer.transport.candidates, ["1 1 udp 2122260223 192.0.2.1 50000 typ host"]);
+    assert_eq!(
+        offer.transport.candidate_addresses().collect::<Vec<_>>(),
+        ["192.0.2.1:50000".parse::<std::net::SocketAddr>().unwrap()]
+    );
+    assert_eq!(offer.transport.fingerprint.as_deref(), Some("sha-256 AB:CD:EF"));
+    assert_eq!(offer.transport.setup.as_deref(), Some("actpass"));
+    assert_eq!(offer.formats[0].name, "opus");
+
+    assert!(!AudioOffer::parse(OFFER).unwrap().is_webrtc());
+}
+
+#[test]
+fn negotiate_by_local_preference() {
+    let offer = AudioOffer::parse(OFFER).unwrap();
+
+    let g711 = Codecs::empty()
+        .with(codec::PCMU, || Ok(Box::new(G711::ulaw())))
+        .with(codec::PCMA, || Ok(Box::new(G711::alaw())));
+    let negotiated = sdp::negotiate(&offer, &g711).unwrap();
+    assert_eq!(negotiated.payload_type, 0);
+    assert_eq!(negotiated.format, codec::PCMU);
+    assert_eq!(g711.create(&negotiated.format).unwrap().format(), &codec::PCMU);
+
+    let answer = sdp::answer(&negotiated, IpAddr::V4(Ipv4Addr::LOCALHOST), 50000, &offer.protocol);
+    assert!(answer.contains("m=audio 50000 RTP/AVP 0 101\r\n"), "{answer}");
+    assert!(answer.contains("a=rtpmap:0 PCMU/8000\r\n"), "{answer}");
+
+    let none = Codecs::empty().with(codec::OPUS, || unreachable!());
+    let offer = AudioOffer::parse("v=0\r\nc=IN IP4 192.0.2.1\r\nm=audio 4000 RTP/AVP 0\r\n").unwrap();
+    assert_eq!(sdp::negotiate(&offer, &none), None);
+}
+
+#[test]
+fn level_and_volume() {
+    assert_eq!(Level::measure(&[0; 160]).meter(10), "[          ]");
+
+    let mut pcm = [i16::MAX / 2, i16::MIN / 2].repeat(80);
+    let level = Level::measure(&pcm);
+    assert!((level.dbfs() + 6.0).abs() < 0.1, "{}", level.dbfs());
+    assert_eq!(level.meter(10), "[######### ]");
+
+    Volume(50).apply(&mut pcm);
+    assert!((Level::measure(&pcm).dbfs() + 12.0).abs() < 0.1);
+
+    Volume(500).apply(&mut pcm);
+    assert_eq!(pcm[0], i16::MAX);
+}
+
+fn packet(sequence: u16) -> RtpPacket {
+    RtpPacket {
+        payload_type: 0,
+        marker: false,
+        sequence,
+        // Starting at zero for the first packet of the tests
+        timestamp: sequence.wrapping_sub(u16::MAX) as u32 * 160,
+        ssrc: 0x1234,
+        payload: vec![sequence as u8; 160].into(),
+    }
+}
+
+#[test]
+fn rtp_round_trip() {
+    let packet = packet(42);
+    assert_eq!(RtpPacket::parse(&packet.encode()).unwrap(), packet);
+
+    // CSRC, header extension and padding are skipped
+    let mut data = vec![0xb1, 0x80, 0, 1, 0, 0, 0, 160, 0, 0, 0x12, 0x34];
+    data.extend([0, 0, 0, 1]);
+    data.extend([0xbe, 0xde, 0, 1, 1, 2, 3, 4]);
+    data.extend([0xff, 0xff, 0, 0, 3]);
+    let parsed = RtpPacket::parse(&data).unwrap();
+    assert!(parsed.marker);
+    assert_eq!(parsed.sequence, 1);
+    assert_eq!(&parsed.payload[..], [0xff, 0xff]);
+
+    assert!(RtpPacket::parse(&data[..8]).is_err());
+}
+
+#[test]
+fn jitter_buffer_reorders_and_conceals() {
+    let start = Instant::now();
+    let mut buffer = JitterBuffer::new(8000, Duration::from_millis(80), Duration::from_millis(200));
+
+    // Sequence numbers wrap around, 1 is lost and 2 arrives before 0
+    for (index, sequence) in [u16::MAX, 2, 0, 3, 4].into_iter().enumerate() {
+        buffer.push(packet(sequence), start + Duration::from_millis(20 * index as u64));
+        if sequence == 2 {
+            assert_eq!(buffer.pop(), None, "Still buffering");
+        }
+    }
+
+    let played = std::iter::from_fn(|| buffer.pop())
+        .map(|playout| match playout {
+            Playout::Packet(packet) => Some(packet.sequence),
+            Playout::Lost => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(played, [Some(u16::MAX), Some(0), None, Some(2), Some(3), Some(4)]);
+
+    buffer.push(packet(1), start + Duration::from_millis(200));
+    let stats = buffer.stats();
+    assert_eq!((stats.received, stats.expected, stats.late, stats.lost()), (6, 6, 1, 0));
+    assert!(stats.jitter_ms > 0.0);
+}
+
+#[test]
+fn rtcp_compound_round_trip() {
+    let packets = vec![
+        RtcpPacket::SenderReport {
+            ssrc: 1,
+            ntp_timestamp: rtcp::ntp_timestamp(SystemTime::now()),
+            rtp_timestamp: 160,
+            packet_count: 10,
+            octet_count: 1600,
+            reports: vec![ReportBlock {
+                ssrc: 2,
+                fraction_lost: 64,
+                cumulative_lost: -3,
+                highest_sequence: 0x1_0005,
+                jitter: 80,
+                last_sr: 0x1234_5678,
+                delay_since_last_sr: 0x8000,
+            }],
+        },
+        RtcpPacket::SourceDescription {
+            ssrc: 1,
+            cname: "ucware@example.com".to_string(),
+        },
+        RtcpPacket::Bye {
+            sources: vec![1],
+            reason: Some("Hangup".to_string()),
+        },
+    ];
+
+    let data = rtcp::encode_compound(&packets);
+    assert_eq!(data.len() % 4, 0);
+    assert_eq!(RtcpPacket::parse_compound(&data).unwrap(), packets);
+}
+
+#[test]
+fn rtcp_reports_and_round_trip_time() {
+    let start = Instant::now();
+    let mut buffer = JitterBuffer::new(8000, Duration::ZERO, Duration::from_millis(200));
+    for sequence in [u16::MAX, 0, 2, 3] {
+        buffer.push(packet(sequence), start);
+    }
+
+    let sent = SystemTime::now();
+    buffer.sender_report(rtcp::ntp_timestamp(sent), start);
+    let block = buffer.report_block(start + Duration::from_millis(500)).unwrap();
+    assert_eq!(block.ssrc, 0x1234);
+    // One of five packets lost, in 1/256
+    assert_eq!(block.fraction_lost, 51);
+    assert_eq!(block.cumulative_lost, 1);
+    assert_eq!(block.highest_sequence, 0x1_0003);
+    assert_eq!(block.delay_since_last_sr, 65536 / 2);
+
+    // Answered 500ms after the report, arriving 100ms later
+    let rtt = rtcp::round_trip_time(&block, sent + Duration::from_millis(600)).unwrap();
+    assert!(rtt.abs_diff(Duration::from_millis(100)) < Duration::from_millis(1), "{rtt:?}");
+
+    let block = buffer.report_block(start + Duration::from_secs(1)).unwrap();
+    assert_eq!(block.fraction_lost, 0);
+}
+
+#[test]
+fn comfort_noise_in_answer() {
+    let offer = AudioOffer::parse("v=0\r\nc=IN IP4 192.0.2.1\r\nm=audio 4000 RTP/AVP 8 13\r\n").unwrap();
+    let negotiated = sdp::negotiate(&offer, &Codecs::default()).unwrap();
+    assert_eq!(negotiated.comfort_noise, Some(13));
+
+    let answer = sdp::answer(&negotiated, IpAddr::V4(Ipv4Addr::LOCALHOST), 50000, &offer.protocol);
+    assert!(answer.contains("m=audio 50000 RTP/AVP 8 13\r\n"), "{answer}");
+    assert!(answer.contains("a=rtpmap:13 CN/8000\r\n"), "{answer}");
+}
+
+#[test]
+fn telephone_events_in_answer() {
+    let offer = AudioOffer::parse(OFFER).unwrap();
+    let negotiated = sdp::negotiate(&offer, &Codecs::default()).unwrap();
+    assert_eq!(negotiated.telephone_event, Some(101));
+
+    let answer = sdp::answer(&negotiated, IpAddr::V4(Ipv4Addr::LOCALHOST), 50000, &offer.protocol);
+    assert!(answer.contains("a=rtpmap:101 telephone-event/8000\r\n"), "{answer}");
+    assert!(answer.contains("a=fmtp:101 0-15\r\n"), "{answer}");
+}
+
+#[test]
+fn dtmf_digits_reported_once() {
+    assert_eq!(
+        DtmfEvent::parse(&[11, 0x8a, 0x03, 0x20]).unwrap(),
+        DtmfEvent {
+            digit: '#',
+            end: true,
+            duration: 800,
+        }
+    );
+    assert!(DtmfEvent::parse(&[16, 0, 0, 0]).is_err());
+    assert!(DtmfEvent::parse(&[1, 0]).is_err());
+
+    let event = |digit: u8, timestamp: u32, duration: u16| RtpPacket {
+        payload_type: 101,
+        marker: duration == 160,
+        sequence: 0,
+        timestamp,
+        ssrc: 0x1234,
+        payload: [&[digit, 0x0a][..], &duration.to_be_bytes()].concat().into(),
+    };
+
+    let mut detector = DtmfDetector::default();
+    assert_eq!(detector.push(&event(5, 1000, 160)), Some('5'));
+    assert_eq!(detector.push(&event(5, 1000, 320)), None);
+    assert_eq!(detector.push(&event(5, 1000, 480)), None);
+    assert_eq!(detector.push(&event(5, 2000, 160)), Some('5'));
+    assert_eq!(detector.push(&event(10, 3000, 160)), Some('*'));
+}
+
+#[test]
+fn silence_suppression() {
+    assert!(SilenceSuppression::new(&SilenceSuppressionConfig::default(), Duration::from_millis(20)).is_none());
+
+    let config = SilenceSuppressionConfig {
+        enabled: true,
+        hangover: Some(Age(Duration::from_millis(40))),
+        refresh: Some(Age(Duration::from_millis(60))),
+        ..Default::default()
+    };
+    let mut suppression = SilenceSuppression::new(&config, Duration::from_millis(20)).unwrap();
+
+    let voice = [8000, -8000].repeat(80);
+    let silence = [1, -1].repeat(80);
+    let decisions = [&voice, &voice, &silence, &silence, &silence, &silence, &silence, &silence, &voice]
+        .map(|pcm| suppression.process(pcm));
+    assert_eq!(
+        decisions,
+        [
+            Decision::Voice { talkspurt: true },
+            Decision::Voice { talkspurt: false },
+            Decision::Voice { talkspurt: false },
+            Decision::Voice { talkspurt: false },
+            Decision::ComfortNoise(90),
+            Decision::Skip,
+            Decision::Skip,
+            Decision::ComfortNoise(90),
+            Decision::Voice { talkspurt: true },
+        ]
+    );
+}
+
+#[test]
+fn hold_music_loops_resampled_file() {
+    let path = std::env::temp_dir().join(format!("ucware-hold-{}.wav", std::process::id()));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 16000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for sample in [1000, 3000, 2000, 2000, -1000, -3000, -2000, -2000] {
+        writer.write_sample(sample as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let config = HoldConfig { music: Some(path.clone()) };
+    let mut music = HoldMusic::load(&config, 8000).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut pcm = [0; 5];
+    music.fill(&mut pcm);
+    assert_eq!(pcm, [2000, -2000, 2000, -2000, 2000]);
+
+    let mut silence = HoldMusic::load(&HoldConfig::default(), 8000).unwrap();
+    silence.fill(&mut pcm);
+    assert_eq!(pcm, [0; 5]);
+}
+
+#[tokio::test]
+async fn tts_synthesizes_once() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::post({
+            let requests = requests.clone();
+            move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+                assert_eq!(body["text"], "Your ticket number is 42");
+                requests.fetch_add(1, Ordering::SeqCst);
+
+                let spec = hound::WavSpec {
+                    channels: 1,
+                    sample_rate: 8000,
+                    bits_per_sample: 16,
+                    sample_format: hound::SampleFormat::Int,
+                };
+                let mut wav = std::io::Cursor::new(Vec::new());
+                let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+                (0..160).for_each(|sample| writer.write_sample(sample as i16).unwrap());
+                writer.finalize().unwrap();
+                wav.into_inner()
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let cache = std::env::temp_dir().join(format!("ucware-tts-{}", std::process::id()));
+    let tts = Tts::new(TtsConfig::Http(HttpTtsConfig { url, token: None }), cache.clone());
+
+    for _ in 0..2 {
+        let samples = tts.speak("Your ticket number is 42", 8000).await.unwrap();
+        assert_eq!(samples.len(), 160);
+        assert_eq!(samples[42], 42);
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    std::fs::remove_dir_all(cache).unwrap();
+}
+
+#[tokio::test]
+async fn transcript_attached_to_call() {
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::post(|body: axum::body::Bytes| async move {
+            let wav = hound::WavReader::new(std::io::Cursor::new(body)).unwrap();
+            assert_eq!(wav.spec().sample_rate, 8000);
+            axum::Json(serde_json::json!({ "text": format!(" {} samples\n", wav.len()) }))
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let transcriber = Transcriber::new(TranscriptionConfig::Http(HttpTranscriptionConfig { url, token: None }));
+    let store = Store::memory();
+
+    // Chunks of 30 seconds are transcribed as they are complete
+    let mut transcript = transcriber.stream("call@host", 8000);
+    for _ in 0..4 {
+        transcript.push(&[0; 80000]).await.unwrap();
+    }
+    let text = transcript.finish(&store).await.unwrap();
+
+    assert_eq!(text, "240000 samples 80000 samples");
+    assert_eq!(store.transcript("call@host").unwrap().as_deref(), Some(text.as_str()));
+}
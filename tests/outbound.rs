@@ -0,0 +1,106 @@
+//! Ordering of outgoing messages contending for the socket
+
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use rsip::{Method, StatusCode};
+use tungstenite::Message;
+use ucware_cli::sipsocket::outbound::{Outbound, Priority};
+use ucware_cli::sipsocket::Connection;
+
+fn text(text: &str) -> Message {
+    Message::text(text)
+}
+
+#[test]
+fn responses_overtake_request_burst() {
+    let mut outbound = Outbound::default();
+
+    for i in 0..10 {
+        outbound.push(Priority::Request, text(&format!("INVITE {i}")));
+    }
+    outbound.push(Priority::Response, text("200 OPTIONS"));
+    outbound.push(Priority::Keepalive, Message::Pong(Default::default()));
+    outbound.push(Priority::Response, text("200 NOTIFY"));
+
+    assert_eq!(outbound.len(), 13);
+    assert_eq!(outbound.pop(), Some(Message::Pong(Default::default())));
+    assert_eq!(outbound.pop(), Some(text("200 OPTIONS")));
+    assert_eq!(outbound.pop(), Some(text("200 NOTIFY")));
+    assert_eq!(outbound.pop(), Some(text("INVITE 0")));
+}
+
+#[test]
+fn same_priority_keeps_order() {
+    let mut outbound = Outbound::default();
+
+    // Interleave producers as if requests and responses arrived concurrently
+    for i in 0..5 {
+        outbound.push(Priority::Request, text(&format!("request {i}")));
+        outbound.push(Priority::Response, text(&format!("response {i}")));
+    }
+
+    let order = std::iter::from_fn(|| outbound.pop()).collect::<Vec<_>>();
+    let expected = (0..5)
+        .map(|i| text(&format!("response {i}")))
+        .chain((0..5).map(|i| text(&format!("request {i}"))))
+        .collect::<Vec<_>>();
+
+    assert_eq!(order, expected);
+    assert!(outbound.is_empty());
+}
+
+/// The first line of a text message, or the kind of a control frame
+fn start_line(message: Message) -> String {
+    match message {
+        Message::Text(text) => text.lines().next().unwrap_or_default().to_string(),
+        other => format!("{other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn connection_answers_ahead_of_waiting_requests() {
+    // Holds a single message, so writing the next one blocks the connection until it is drained
+    let (sink_tx, mut sink_rx) = mpsc::channel::<Message>(1);
+    let (mut stream_tx, stream_rx) = mpsc::unbounded::<anyhow::Result<Message>>();
+
+    let (connection, mut requests) = Connection::builder("wss://pbx.invalid/".parse().unwrap(), "1001")
+        .attach(sink_tx.sink_map_err(anyhow::Error::from), stream_rx)
+        .unwrap();
+
+    stream_tx
+        .send(Ok(Message::text(
+            "OPTIONS sip:1001@pbx.invalid SIP/2.0\r\n\
+             Via: SIP/2.0/WSS pbx.invalid;branch=z9hG4bKoptions\r\n\
+             From: <sip:pbx.invalid>;tag=server\r\n\
+             To: <sip:1001@pbx.invalid>\r\n\
+             Call-ID: options@pbx.invalid\r\n\
+             CSeq: 1 OPTIONS\r\n\
+             Content-Length: 0\r\n\r\n",
+        )))
+        .await
+        .unwrap();
+    let mut options = requests.recv().await.unwrap();
+
+    // The first request is written, the second blocks the socket and the third has to wait
+    let dialog = connection.dialog();
+    let mut sent = Vec::new();
+    for method in [Method::Message, Method::Info, Method::Subscribe] {
+        sent.push(dialog.request(method).send([]).await.unwrap());
+    }
+
+    options.respond(StatusCode::OK).send([]).await;
+    stream_tx.send(Ok(Message::Ping(Default::default()))).await.unwrap();
+
+    let mut written = Vec::new();
+    for _ in 0..5 {
+        written.push(start_line(sink_rx.next().await.unwrap()));
+    }
+
+    assert_eq!(written, [
+        "MESSAGE sip:pbx.invalid SIP/2.0",
+        "INFO sip:pbx.invalid SIP/2.0",
+        "SIP/2.0 200 OK",
+        "Pong(b\"\")",
+        "SUBSCRIBE sip:pbx.invalid SIP/2.0",
+    ]);
+}
@@ -0,0 +1,80 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use ucware_cli::plugin::{self, Environment, Plugin};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ucware-plugins-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[test]
+fn discovers_plugins_on_path() {
+    let first = dir("first");
+    let second = dir("second");
+
+    let hello = script(&first, "ucware-hello", "exit 0");
+    script(&second, "ucware-hello", "exit 1");
+    script(&second, "ucware-report", "exit 0");
+    script(&second, "ucware-call-notify", "exit 0");
+    script(&second, "other-tool", "exit 0");
+    std::fs::write(second.join("ucware-data"), "not executable").unwrap();
+
+    let path = std::env::join_paths([&first, &second]).unwrap();
+    let names: Vec<_> = plugin::discover(&path).into_iter().map(|plugin| plugin.name).collect();
+    assert_eq!(names, ["hello", "report"]);
+
+    // The first directory on the path takes precedence
+    assert_eq!(plugin::discover(&path)[0].path, hello);
+}
+
+#[tokio::test]
+async fn handshake_and_environment() {
+    let dir = dir("run");
+    let out = dir.join("env");
+
+    let body = format!(
+        r#"if [ "$1" = "--ucware-handshake" ]; then
+    echo '{{"protocol": 1, "description": "Says hello", "connect": true}}'
+    exit 0
+fi
+echo "$UCWARE_PLUGIN_PROTOCOL $UCWARE_OUTPUT $UCWARE_URL $UCWARE_TOKEN $*" > {out}
+exit 3"#,
+        out = out.display()
+    );
+    let plugin = Plugin {
+        name: "hello".to_string(),
+        path: script(&dir, "ucware-hello", &body),
+    };
+
+    let handshake = plugin.handshake().await.unwrap();
+    assert_eq!(handshake.description.as_deref(), Some("Says hello"));
+    assert!(handshake.connect);
+
+    let env = Environment {
+        output: "json".to_string(),
+        connection: Some(("https://ucware.example.com/api/2/".to_string(), "secret".to_string())),
+        ..Default::default()
+    };
+    let status = plugin.run(&["a".to_string(), "b".to_string()], &env).await.unwrap();
+    assert_eq!(status.code(), Some(3));
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap().trim(),
+        "1 json https://ucware.example.com/api/2/ secret a b"
+    );
+
+    let old = Plugin {
+        name: "old".to_string(),
+        path: script(&dir, "ucware-old", r#"echo '{"protocol": 0}'"#),
+    };
+    assert!(old.handshake().await.is_err());
+}
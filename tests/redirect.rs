@@ -0,0 +1,41 @@
+use rsip::{Method, Response, SipMessage, Uri};
+use ucware_cli::sipsocket::redirect::{self, Redirects};
+
+fn response(contact: &str) -> Response {
+    let message = format!(
+        "SIP/2.0 302 Moved Temporarily\r\n\
+        Via: SIP/2.0/WSS abc.invalid;branch=z9hG4bK1\r\n\
+        From: <sip:1001@pbx.example.com>;tag=a\r\n\
+        To: <sip:1001@pbx.example.com>;tag=b\r\n\
+        Call-ID: call-1@abc.invalid\r\n\
+        CSeq: 2 REGISTER\r\n\
+        {contact}\
+        Content-Length: 0\r\n\r\n"
+    );
+
+    match SipMessage::try_from(message.as_str()).expect("parsable message") {
+        SipMessage::Response(response) => response,
+        SipMessage::Request(_) => panic!("expected response"),
+    }
+}
+
+#[test]
+fn redirect_target_from_contact() {
+    let target = redirect::target(&response(
+        "Contact: <sip:pbx2.example.com;transport=ws>;q=0.5;+sip.instance=\"<urn:uuid:1>\"\r\n",
+    ));
+    assert_eq!(target, Some(Uri::try_from("sip:pbx2.example.com;transport=ws").unwrap()));
+
+    assert_eq!(redirect::target(&response("")), None);
+}
+
+#[test]
+fn redirects_can_be_vetoed() {
+    let target = Uri::try_from("sip:pbx2.example.com").unwrap();
+
+    assert!(Redirects::default().allows(&Method::Register, &target));
+
+    let redirects = Redirects::default().filter(|method, _| *method != Method::Register);
+    assert!(!redirects.allows(&Method::Register, &target));
+    assert!(redirects.allows(&Method::Subscribe, &target));
+}
@@ -0,0 +1,130 @@
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use ucware_cli::sipsocket::ids::{ContactId, RandomIds};
+use ucware_cli::sipsocket::Connection;
+use url::Url;
+
+/// Accepts SIP sockets answering every request with 200 and passes on the requests received
+async fn registrar() -> (Url, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/sipsockets/", listener.local_addr().unwrap()).parse().unwrap();
+
+    let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let requests_tx = requests_tx.clone();
+            tokio::spawn(async move {
+                let mut socket = tokio_tungstenite::accept_hdr_async(stream, sip_protocol).await.unwrap();
+
+                while let Some(Ok(Message::Text(request))) = socket.next().await {
+                    socket.send(Message::text(ok(&request))).await.unwrap();
+                    let _ = requests_tx.send(request.to_string());
+                }
+            });
+        }
+    });
+
+    (url, requests_rx)
+}
+
+/// Agrees on the SIP subprotocol the client asks for
+#[allow(clippy::result_large_err)]
+fn sip_protocol(_: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    response.headers_mut().insert("Sec-WebSocket-Protocol", "sip".parse().unwrap());
+    Ok(response)
+}
+
+/// A 200 response to the given request, keeping the headers a client matches it by
+fn ok(request: &str) -> String {
+    let mut response = "SIP/2.0 200 OK\r\n".to_string();
+    for line in request.lines() {
+        let name = line.split(':').next().unwrap_or_default().to_ascii_lowercase();
+        match name.as_str() {
+            "via" | "from" | "call-id" | "cseq" | "contact" => response.push_str(&format!("{line}\r\n")),
+            "to" => response.push_str(&format!("{line};tag=registrar\r\n")),
+            _ => {}
+        }
+    }
+    response.push_str("Content-Length: 0\r\n\r\n");
+    response
+}
+
+/// The value of the first header with the given name
+fn header<'r>(request: &'r str, name: &str) -> &'r str {
+    request
+        .lines()
+        .find_map(|line| {
+            let (header, value) = line.split_once(':')?;
+            header.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+        .unwrap_or_else(|| panic!("no {name} header in {request}"))
+}
+
+#[tokio::test]
+async fn registers_at_ip_literals() {
+    let (url, mut requests) = registrar().await;
+
+    let (mut connection, _requests) = Connection::builder(url, "1001").connect().await.unwrap();
+    connection.register("1001", "secret").await.unwrap();
+
+    let register = requests.recv().await.unwrap();
+    assert!(register.starts_with("REGISTER sip:127.0.0.1 SIP/2.0\r\n"), "{register}");
+    assert!(header(&register, "To").contains("sip:1001@127.0.0.1"));
+}
+
+#[tokio::test]
+async fn registers_in_domain_independent_of_socket_host() {
+    let (url, mut requests) = registrar().await;
+
+    let (mut connection, _requests) =
+        Connection::builder(url, "1001").domain("pbx.example.com").connect().await.unwrap();
+    connection.register("1001", "secret").await.unwrap();
+
+    let register = requests.recv().await.unwrap();
+    assert!(register.starts_with("REGISTER sip:pbx.example.com SIP/2.0\r\n"), "{register}");
+    assert!(header(&register, "From").contains("sip:1001@pbx.example.com"));
+}
+
+#[tokio::test]
+async fn stored_contacts_are_registered_identically() {
+    let (url, mut requests) = registrar().await;
+    let contact = ContactId::generate(&RandomIds);
+
+    let mut contacts = Vec::new();
+    for _ in 0..2 {
+        let (mut connection, _requests) =
+            Connection::builder(url.clone(), "1001").contact(Some(contact.clone())).connect().await.unwrap();
+        connection.register("1001", "secret").await.unwrap();
+
+        let register = requests.recv().await.unwrap();
+        contacts.push(header(&register, "Contact").to_string());
+    }
+
+    assert_eq!(contacts[0], contacts[1]);
+    assert!(contacts[0].contains(&format!("sip:{user}@{host}", user = contact.user, host = contact.host)));
+}
+
+#[tokio::test]
+async fn unregisters_the_stored_contact() {
+    let (url, mut requests) = registrar().await;
+    let contact = ContactId::generate(&RandomIds);
+
+    let (mut connection, _requests) =
+        Connection::builder(url.clone(), "1001").contact(Some(contact.clone())).connect().await.unwrap();
+    connection.register("1001", "secret").await.unwrap();
+    let registered = requests.recv().await.unwrap();
+
+    // A later run connecting anew, like `ucware register --expires 0`
+    let (mut connection, _requests) =
+        Connection::builder(url, "1001").contact(Some(contact)).connect().await.unwrap();
+    connection.unregister("1001", "secret", false).await.unwrap();
+    let unregistered = requests.recv().await.unwrap();
+
+    let uri = |register: &str| header(register, "Contact").split(';').next().unwrap().to_string();
+    assert_eq!(uri(&registered), uri(&unregistered));
+    assert!(header(&unregistered, "Contact").contains("expires=0"));
+}
@@ -0,0 +1,47 @@
+use rsip::{Response, SipMessage};
+use std::time::Duration;
+use ucware_cli::sipsocket::RetryAfter;
+
+fn response(status: &str, retry_after: &str) -> Response {
+    let message = format!(
+        "SIP/2.0 {status}\r\n\
+        Via: SIP/2.0/WSS abc.invalid;branch=z9hG4bK1\r\n\
+        From: <sip:1001@pbx.example.com>;tag=a\r\n\
+        To: <sip:1001@pbx.example.com>;tag=b\r\n\
+        Call-ID: call-1@abc.invalid\r\n\
+        CSeq: 2 REGISTER\r\n\
+        {retry_after}\
+        Content-Length: 0\r\n\r\n"
+    );
+
+    match SipMessage::try_from(message.as_str()).expect("parsable message") {
+        SipMessage::Response(response) => response,
+        SipMessage::Request(_) => panic!("expected response"),
+    }
+}
+
+#[test]
+fn retry_after_of_server_errors() {
+    let retry_after = |status, header| RetryAfter::from_response(&response(status, header));
+
+    assert_eq!(
+        retry_after("503 Service Unavailable", "Retry-After: 120 (maintenance);duration=3600\r\n"),
+        Some(RetryAfter(Duration::from_secs(120)))
+    );
+    assert_eq!(
+        retry_after("500 Server Internal Error", "Retry-After: 5\r\n"),
+        Some(RetryAfter(Duration::from_secs(5)))
+    );
+
+    assert_eq!(retry_after("503 Service Unavailable", ""), None);
+    assert_eq!(retry_after("486 Busy Here", "Retry-After: 5\r\n"), None);
+}
+
+#[test]
+fn retry_after_found_behind_context() {
+    let err = anyhow::Error::new(RetryAfter(Duration::from_secs(30))).context("Failed to register");
+    assert_eq!(RetryAfter::of(&err), Some(RetryAfter(Duration::from_secs(30))));
+
+    let jittered = RetryAfter(Duration::from_secs(30)).jittered();
+    assert!((Duration::from_secs(30)..=Duration::from_secs(33)).contains(&jittered));
+}
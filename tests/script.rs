@@ -0,0 +1,80 @@
+use rsip::{Request, SipMessage, StatusCode};
+use ucware_cli::callstate::script::{Script, ScriptAction};
+use ucware_cli::callstate::{Call, CallState, Incoming, Origin};
+use ucware_cli::config::Config;
+
+const HOOKS: &str = r#"
+fn on_incoming(incoming) {
+    if incoming.caller.anonymous {
+        return reject(603);
+    }
+    if incoming.caller.number.starts_with("+4930") {
+        return forward("1234");
+    }
+    if incoming.caller.name == "Support" {
+        return ring();
+    }
+}
+"#;
+
+fn call(from: &str) -> Call {
+    let message = format!(
+        "INVITE sip:alice@example.com SIP/2.0\r\n\
+         Via: SIP/2.0/WSS proxy.example.com;branch=z9hG4bK1\r\n\
+         From: {from};tag=a\r\n\
+         To: <sip:alice@example.com>\r\n\
+         Call-ID: call-1\r\n\
+         CSeq: 1 INVITE\r\n\
+         Content-Length: 0\r\n\r\n"
+    );
+
+    let request: Request = match SipMessage::try_from(message.as_str()).expect("valid message") {
+        SipMessage::Request(request) => request,
+        SipMessage::Response(_) => unreachable!(),
+    };
+
+    let Incoming::New(call) = CallState::new().incoming(&request, &Config::default(), &Origin::default()).unwrap() else {
+        panic!("expected new call");
+    };
+    call
+}
+
+#[test]
+fn hook_decides_on_calls() {
+    let script = Script::compile(HOOKS).unwrap();
+
+    let action = script.on_incoming(&call("\"Anonymous\" <sip:anonymous@anonymous.invalid>")).unwrap();
+    assert_eq!(action, Some(ScriptAction::Reject(StatusCode::Decline)));
+
+    let action = script.on_incoming(&call("<sip:+4930123@example.com>")).unwrap();
+    assert_eq!(action, Some(ScriptAction::Forward("1234".to_string())));
+
+    let action = script.on_incoming(&call("Support <sip:+4989123@example.com>")).unwrap();
+    assert_eq!(action, Some(ScriptAction::Ring));
+
+    // Calls the script leaves alone go by the static policy
+    let action = script.on_incoming(&call("Bob <sip:+4989123@example.com>")).unwrap();
+    assert_eq!(action, None);
+}
+
+#[test]
+fn scripts_without_hook_leave_calls_alone() {
+    let script = Script::compile("let unused = 1;").unwrap();
+    assert_eq!(script.on_incoming(&call("<sip:+4930123@example.com>")).unwrap(), None);
+
+    assert!(Script::compile("fn on_incoming(incoming) {").is_err());
+}
+
+#[test]
+fn runaway_hooks_fail() {
+    let script = Script::compile("fn on_incoming(incoming) { loop {} }").unwrap();
+    assert!(script.on_incoming(&call("<sip:+4930123@example.com>")).is_err());
+}
+
+#[test]
+fn rejecting_without_error_status_fails() {
+    for code in [200, 302, 700, -1] {
+        let script = Script::compile(&format!("fn on_incoming(incoming) {{ reject({code}) }}")).unwrap();
+        assert!(script.on_incoming(&call("<sip:+4930123@example.com>")).is_err(), "{code}");
+    }
+}
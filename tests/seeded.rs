@@ -0,0 +1,96 @@
+#![cfg(feature = "deterministic-ids")]
+
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use ucware_cli::sipsocket::ids::{SeededIds, SipIdGenerator};
+use ucware_cli::sipsocket::Connection;
+use url::Url;
+
+/// Accepts one SIP socket answering every request with 200 and passes on the requests received
+async fn registrar() -> (Url, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/sipsockets/", listener.local_addr().unwrap()).parse().unwrap();
+
+    let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_hdr_async(stream, sip_protocol).await.unwrap();
+
+        while let Some(Ok(Message::Text(request))) = socket.next().await {
+            socket.send(Message::text(ok(&request))).await.unwrap();
+            let _ = requests_tx.send(request.to_string());
+        }
+    });
+
+    (url, requests_rx)
+}
+
+/// Agrees on the SIP subprotocol the client asks for
+#[allow(clippy::result_large_err)]
+fn sip_protocol(_: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    response.headers_mut().insert("Sec-WebSocket-Protocol", "sip".parse().unwrap());
+    Ok(response)
+}
+
+/// A 200 response to the given request, keeping the headers a client matches it by
+fn ok(request: &str) -> String {
+    let mut response = "SIP/2.0 200 OK\r\n".to_string();
+    for line in request.lines() {
+        let name = line.split(':').next().unwrap_or_default().to_ascii_lowercase();
+        match name.as_str() {
+            "via" | "from" | "call-id" | "cseq" | "contact" => response.push_str(&format!("{line}\r\n")),
+            "to" => response.push_str(&format!("{line};tag=registrar\r\n")),
+            _ => {}
+        }
+    }
+    response.push_str("Content-Length: 0\r\n\r\n");
+    response
+}
+
+#[tokio::test]
+async fn register_is_reproducible() {
+    let (url, mut requests) = registrar().await;
+
+    let (mut connection, _requests) =
+        Connection::builder(url, "1001").domain("pbx.example.com").ids(SeededIds::new(42)).connect().await.unwrap();
+    connection.register("1001", "secret").await.unwrap();
+
+    let register = requests.recv().await.unwrap();
+    let expected = format!(
+        "REGISTER sip:pbx.example.com SIP/2.0\r\n\
+         Via: SIP/2.0/WSS host2a000001.invalid;branch=z9hG4bK2a000006\r\n\
+         To: <sip:1001@pbx.example.com>\r\n\
+         From: <sip:1001@pbx.example.com>;tag=tag2a000005\r\n\
+         CSeq: 5 REGISTER\r\n\
+         Call-ID: call2a000003@host2a000001.invalid\r\n\
+         User-Agent: ucware-cli/{version}\r\n\
+         Contact: <sip:contact2a000000@host2a000001.invalid;transport=WS>\
+         ;+sip.instance=\"<urn:uuid:00000000-0000-4002-800a-000000000002>\";expires=6000\r\n\r\n",
+        version = env!("CARGO_PKG_VERSION"),
+    );
+    assert_eq!(register, expected);
+}
+
+#[test]
+fn instances_are_uuids_for_any_seed() {
+    for seed in [0, 42, 1 << 40 | 42, u64::MAX] {
+        let instance = SeededIds::new(seed).instance();
+        let uuid = instance.strip_prefix("urn:uuid:").unwrap();
+
+        let groups = uuid.split('-').map(str::len).collect::<Vec<_>>();
+        assert_eq!(groups, [8, 4, 4, 4, 12], "{instance}");
+    }
+}
+
+#[test]
+fn instances_differ_by_seed() {
+    let instance = |seed: u64| SeededIds::new(seed).instance();
+
+    assert_ne!(instance(42), instance(1 << 24 | 42));
+    assert_ne!(instance(42), instance(1 << 40 | 42));
+    assert_ne!(instance(42), instance(1 << 63 | 42));
+    assert_eq!(instance(1 << 40 | 42), "urn:uuid:00000100-0000-4002-800a-000000000000");
+}
@@ -0,0 +1,346 @@
+use std::time::{Duration, UNIX_EPOCH};
+use ucware_cli::cache::Cache;
+use ucware_cli::callstate::blocklist::BlockRule;
+use ucware_cli::callstate::{Call, Caller, DialogKey, Origin};
+use ucware_cli::config::{Age, RetentionConfig};
+use ucware_cli::sipsocket::ids::{ContactId, RandomIds};
+use ucware_cli::store::{CallLogKey, Favorite, LastNumber, Memory, Sqlite, Storage, Store};
+
+fn stores() -> [Store; 2] {
+    [
+        Store::new(Memory::default()),
+        Store::new(Sqlite::open(":memory:").expect("in-memory database")),
+    ]
+}
+
+#[test]
+fn favorites_by_name() {
+    for store in stores() {
+        let favorite = |name: &str, number: &str| Favorite {
+            name: name.to_string(),
+            number: number.to_string(),
+        };
+
+        store.add_favorite(&favorite("bob", "200")).unwrap();
+        store.add_favorite(&favorite("alice", "100")).unwrap();
+        store.add_favorite(&favorite("bob", "201")).unwrap();
+
+        assert_eq!(store.favorites().unwrap(), [favorite("alice", "100"), favorite("bob", "201")]);
+        assert_eq!(store.favorite("bob").unwrap(), Some(favorite("bob", "201")));
+
+        assert!(store.remove_favorite("bob").unwrap());
+        assert!(!store.remove_favorite("bob").unwrap());
+        assert_eq!(store.favorite("bob").unwrap(), None);
+    }
+}
+
+#[test]
+fn token_and_checkpoints() {
+    for store in stores() {
+        assert_eq!(store.token().unwrap(), None);
+        store.set_token("first").unwrap();
+        store.set_token("second").unwrap();
+        assert_eq!(store.token().unwrap().as_deref(), Some("second"));
+
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(store.checkpoint("sync").unwrap(), None);
+        store.set_checkpoint("sync", time).unwrap();
+        assert_eq!(store.checkpoint("sync").unwrap(), Some(time));
+    }
+}
+
+#[test]
+fn account_tokens_apart_from_main_token() {
+    for store in stores() {
+        store.set_token("main").unwrap();
+        assert_eq!(store.account_token("customer").unwrap(), None);
+
+        store.set_account_token("customer", "first").unwrap();
+        store.set_account_token("other", "other").unwrap();
+        store.set_account_token("customer", "second").unwrap();
+
+        assert_eq!(store.account_token("customer").unwrap().as_deref(), Some("second"));
+        assert_eq!(store.account_token("other").unwrap().as_deref(), Some("other"));
+        assert_eq!(store.token().unwrap().as_deref(), Some("main"));
+    }
+}
+
+#[test]
+fn last_numbers_by_kind() {
+    for store in stores() {
+        assert_eq!(store.last_number(LastNumber::Dialed).unwrap(), None);
+
+        store.set_last_number(LastNumber::Dialed, "100").unwrap();
+        store.set_last_number(LastNumber::Missed, "200").unwrap();
+        store.set_last_number(LastNumber::Dialed, "101").unwrap();
+
+        assert_eq!(store.last_number(LastNumber::Dialed).unwrap().as_deref(), Some("101"));
+        assert_eq!(store.last_number(LastNumber::Missed).unwrap().as_deref(), Some("200"));
+    }
+}
+
+#[test]
+fn contacts_by_username() {
+    for store in stores() {
+        assert_eq!(store.contact("1001").unwrap(), None);
+
+        let first = ContactId::generate(&RandomIds);
+        let second = ContactId::generate(&RandomIds);
+        assert_ne!(first, second);
+
+        store.set_contact("1001", &first).unwrap();
+        store.set_contact("1002", &first).unwrap();
+        store.set_contact("1001", &second).unwrap();
+
+        assert_eq!(store.contact("1001").unwrap(), Some(second));
+        assert_eq!(store.contact("1002").unwrap(), Some(first));
+    }
+}
+
+#[test]
+fn blocklist_rules_are_unique() {
+    for store in stores() {
+        let anonymous: BlockRule = "anonymous".parse().unwrap();
+        let network: BlockRule = "192.0.2.0/24".parse().unwrap();
+
+        assert!(store.add_block(&anonymous).unwrap());
+        assert!(store.add_block(&network).unwrap());
+        assert!(!store.add_block(&anonymous).unwrap());
+        assert_eq!(store.blocklist().unwrap(), [anonymous.clone(), network.clone()]);
+
+        assert!(store.remove_block(&anonymous).unwrap());
+        assert!(!store.remove_block(&anonymous).unwrap());
+        assert_eq!(store.blocklist().unwrap(), [network]);
+    }
+}
+
+#[test]
+fn cache_invalidation() {
+    for store in stores() {
+        let cache = Cache::new(store);
+
+        cache.put("user/slot/getAll", &["a", "b"]).unwrap();
+        cache.put("user/queue/getAll", &["c"]).unwrap();
+
+        let hour = Duration::from_secs(3600);
+        assert_eq!(cache.get::<Vec<String>>("user/slot/getAll", hour).unwrap(), ["a", "b"]);
+        assert_eq!(cache.find::<Vec<String>>("queue/getAll"), [["c"]]);
+
+        cache.invalidate("user/slot/").unwrap();
+        assert_eq!(cache.get::<Vec<String>>("user/slot/getAll", hour), None);
+        assert!(cache.get::<Vec<String>>("user/queue/getAll", hour).is_some());
+
+        cache.clear().unwrap();
+        assert!(cache.find::<Vec<String>>("").is_empty());
+    }
+}
+
+fn call(number: &str, since: u64) -> Call {
+    Call {
+        key: DialogKey {
+            call_id: number.to_string(),
+            from_tag: None,
+        },
+        caller: Caller {
+            name: Some("Spam".to_string()),
+            number: Some(number.to_string()),
+            uri: format!("sip:{number}@example.com"),
+            anonymous: false,
+        },
+        since: UNIX_EPOCH + Duration::from_secs(since),
+        origin: Origin {
+            account: "customer".to_string(),
+            slot: Some("Desk".to_string()),
+        },
+        forwarded: None,
+        reason: None,
+        ucware: Default::default(),
+        sources: Vec::new(),
+        media: None,
+    }
+}
+
+#[test]
+fn call_log_encryption_and_purge() {
+    let rule: BlockRule = "/^Spam$/".parse().unwrap();
+
+    let mut sqlite = Sqlite::open(":memory:").unwrap();
+    sqlite.log_blocked(&call("100", 1000), &rule).unwrap();
+
+    sqlite.encrypt_call_log(&CallLogKey::Passphrase("secret".to_string())).unwrap();
+    sqlite.log_blocked(&call("200", 2000), &rule).unwrap();
+
+    let numbers = |sqlite: &Sqlite| {
+        sqlite
+            .blocked_calls()
+            .unwrap()
+            .into_iter()
+            .map(|call| call.number.unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(numbers(&sqlite), ["200", "100"]);
+
+    let before = UNIX_EPOCH + Duration::from_secs(1500);
+    assert_eq!(sqlite.purge_blocked_calls(before).unwrap(), 1);
+    assert_eq!(numbers(&sqlite), ["200"]);
+}
+
+#[test]
+fn retention_purges_old_entries() {
+    let store = Store::memory();
+    let rule: BlockRule = "anonymous".parse().unwrap();
+    store.log_blocked(&call("100", 1000), &rule).unwrap();
+    store.log_blocked(&call("200", 5000), &rule).unwrap();
+
+    let config = RetentionConfig {
+        call_log: Some(Age(Duration::from_secs(2000))),
+        ..RetentionConfig::default()
+    };
+    let now = UNIX_EPOCH + Duration::from_secs(6000);
+
+    let purged = store.enforce_retention(&config, now).unwrap();
+    assert_eq!(purged.call_log, 1);
+    assert_eq!(store.blocked_calls().unwrap().len(), 1);
+}
+
+#[test]
+fn blocked_calls_keep_origin() {
+    for store in stores() {
+        let rule: BlockRule = "anonymous".parse().unwrap();
+        store.log_blocked(&call("100", 1000), &rule).unwrap();
+
+        let blocked = store.blocked_calls().unwrap();
+        assert_eq!(blocked[0].account.as_deref(), Some("customer"));
+        assert_eq!(blocked[0].slot.as_deref(), Some("Desk"));
+    }
+}
+
+#[test]
+fn transcripts_by_call_id() {
+    for store in stores() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        store.set_transcript("a@host", at(1000), "Hello").unwrap();
+        store.set_transcript("b@host", at(5000), "Call me back").unwrap();
+        store.set_transcript("a@host", at(1000), "Hello there").unwrap();
+
+        assert_eq!(store.transcript("a@host").unwrap().as_deref(), Some("Hello there"));
+        assert_eq!(store.transcript("c@host").unwrap(), None);
+
+        assert_eq!(store.purge_transcripts(at(2000)).unwrap(), 1);
+        assert_eq!(store.transcript("a@host").unwrap(), None);
+        assert_eq!(store.transcript("b@host").unwrap().as_deref(), Some("Call me back"));
+    }
+}
+
+#[test]
+fn transcripts_encrypted_once_enabled() {
+    let dir = std::env::temp_dir().join(format!("ucware-transcripts-{}", std::process::id()));
+    let path = dir.join("state.db");
+    let at = UNIX_EPOCH + Duration::from_secs(1000);
+
+    let plain = Sqlite::open(&path).unwrap();
+    plain.set_transcript("a@host", at, "Call me back").unwrap();
+    drop(plain);
+
+    let mut sqlite = Sqlite::open(&path).unwrap();
+    sqlite.encrypt_call_log(&CallLogKey::Passphrase("secret".to_string())).unwrap();
+    assert_eq!(sqlite.transcript("a@host").unwrap().as_deref(), Some("Call me back"));
+    drop(sqlite);
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let plain: i64 = conn
+        .query_row("SELECT count(*) FROM transcripts WHERE typeof(transcript) != 'blob'", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(plain, 0);
+    drop(conn);
+
+    let file = std::fs::read(&path).unwrap();
+    assert!(!file.windows(12).any(|window| window == b"Call me back"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn recordings_sealed_and_purged() {
+    let dir = std::env::temp_dir().join(format!("ucware-recordings-{}", std::process::id()));
+    let recordings = dir.join("recordings");
+    let wav = b"RIFF recording of the caller";
+
+    let mut sqlite = Sqlite::open(dir.join("state.db")).unwrap();
+    sqlite.encrypt_call_log(&CallLogKey::Passphrase("secret".to_string())).unwrap();
+    let store = Store::new(sqlite);
+
+    let path = store.save_recording(&recordings, "call-1-record", "a@host", wav).unwrap();
+    assert_eq!(path, recordings.join("call-1-record.wav.sealed"));
+    assert!(!std::fs::read(&path).unwrap().windows(wav.len()).any(|window| window == wav));
+    assert_eq!(store.load_recording(&path).unwrap(), wav);
+    assert_eq!(store.recordings("a@host").unwrap(), std::slice::from_ref(&path));
+
+    let config = RetentionConfig {
+        recordings: Some(Age(Duration::from_secs(60))),
+        ..RetentionConfig::default()
+    };
+    let later = std::time::SystemTime::now() + Duration::from_secs(120);
+    assert_eq!(store.enforce_retention(&config, later).unwrap().recordings, 1);
+    assert!(!path.exists());
+    assert_eq!(store.recordings("a@host").unwrap(), Vec::<std::path::PathBuf>::new());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn recordings_sealed_once_enabled() {
+    let dir = std::env::temp_dir().join(format!("ucware-plain-then-sealed-{}", std::process::id()));
+    let path = dir.join("state.db");
+    let wav = b"RIFF recording of the caller";
+
+    let plain = Store::new(Sqlite::open(&path).unwrap());
+    let recording = plain.save_recording(&dir, "call-1-record", "a@host", wav).unwrap();
+    assert!(std::fs::read(&recording).unwrap().starts_with(wav));
+    drop(plain);
+
+    let mut sqlite = Sqlite::open(&path).unwrap();
+    sqlite.encrypt_call_log(&CallLogKey::Passphrase("secret".to_string())).unwrap();
+    let store = Store::new(sqlite);
+
+    let sealed = store.recordings("a@host").unwrap();
+    assert_eq!(sealed, [dir.join("call-1-record.wav.sealed")]);
+    assert!(!recording.exists());
+    assert!(!std::fs::read(&sealed[0]).unwrap().windows(wav.len()).any(|window| window == wav));
+    assert_eq!(store.load_recording(&sealed[0]).unwrap(), wav);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn recordings_plain_without_encryption() {
+    let dir = std::env::temp_dir().join(format!("ucware-plain-recordings-{}", std::process::id()));
+    let store = Store::memory();
+
+    let path = store.save_recording(&dir, "call-1-record", "a@host", b"RIFF").unwrap();
+    assert_eq!(path, dir.join("call-1-record.wav"));
+    assert_eq!(store.load_recording(&path).unwrap(), b"RIFF");
+
+    let now = std::time::SystemTime::now() + Duration::from_secs(1);
+    assert_eq!(store.purge_recordings(now).unwrap(), 1);
+    assert!(!path.exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn files_are_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("ucware-store-{}", std::process::id())).join("ucware");
+    let path = dir.join("state.db");
+
+    Sqlite::open(&path).expect("opened store");
+
+    let mode = |path: &std::path::Path| std::fs::metadata(path).expect("metadata").permissions().mode() & 0o777;
+    assert_eq!(mode(&dir), 0o700);
+    assert_eq!(mode(&path), 0o600);
+
+    std::fs::remove_dir_all(dir.parent().expect("parent")).expect("removed store");
+}
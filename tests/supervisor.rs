@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+use ucware_cli::config::{Age, SupervisorConfig};
+use ucware_cli::daemon::Supervisor;
+
+fn supervisor(max_restarts: u32) -> Supervisor {
+    Supervisor::new(&SupervisorConfig {
+        max_restarts: Some(max_restarts),
+        window: Some(Age(Duration::from_secs(60))),
+        backoff: Some(Age(Duration::from_secs(1))),
+    })
+}
+
+/// Whether the delay is the given one with at most a tenth of jitter added
+fn around(delay: Option<Duration>, expected: u64) -> bool {
+    let expected = Duration::from_secs(expected);
+    delay.is_some_and(|delay| delay >= expected && delay <= expected.mul_f64(1.1))
+}
+
+#[test]
+fn backs_off_until_the_budget_is_used_up() {
+    let mut supervisor = supervisor(3);
+    let start = Instant::now();
+
+    assert!(around(supervisor.restart_at(start), 1));
+    assert!(around(supervisor.restart_at(start + Duration::from_secs(5)), 2));
+    assert!(around(supervisor.restart_at(start + Duration::from_secs(10)), 4));
+    assert_eq!(supervisor.restart_at(start + Duration::from_secs(15)), None);
+}
+
+#[test]
+fn restarts_outside_the_window_are_forgotten() {
+    let mut supervisor = supervisor(2);
+    let start = Instant::now();
+
+    assert!(around(supervisor.restart_at(start), 1));
+    assert!(around(supervisor.restart_at(start + Duration::from_secs(30)), 2));
+
+    // The first restart left the window, the second is still counted
+    assert!(around(supervisor.restart_at(start + Duration::from_secs(60)), 2));
+    assert_eq!(supervisor.restart_at(start + Duration::from_secs(70)), None);
+}
+
+#[test]
+fn no_budget_exits_on_the_first_failure() {
+    assert_eq!(supervisor(0).restart_at(Instant::now()), None);
+}
@@ -0,0 +1,32 @@
+use ucware_cli::config::Template;
+
+fn template(value: &str) -> Template {
+    Template::try_from(value.to_string()).expect("valid template")
+}
+
+#[test]
+fn renders_placeholders() {
+    let rendered = template("Call from {name} ({number})").render(&[("name", "Alice"), ("number", "1001")]);
+    assert_eq!(rendered, "Call from Alice (1001)");
+}
+
+#[test]
+fn keeps_placeholders_within_values() {
+    let rendered = template("{name} {number}").render(&[("name", "{number}"), ("number", "1001")]);
+    assert_eq!(rendered, "{number} 1001");
+}
+
+#[test]
+fn trims_empty_values() {
+    assert_eq!(template("{name} {forwarded}").render(&[("name", "Alice"), ("forwarded", "")]), "Alice");
+}
+
+#[test]
+fn renders_call_origin() {
+    let rendered = template("{name} via {account} on {slot}").render(&[
+        ("name", "Alice"),
+        ("account", "support"),
+        ("slot", "Desk"),
+    ]);
+    assert_eq!(rendered, "Alice via support on Desk");
+}
@@ -0,0 +1,23 @@
+use std::time::Duration;
+use ucware_cli::sipsocket::SipTimers;
+
+#[test]
+fn timeouts_derive_from_t1() {
+    let timers = SipTimers::with_t1(Duration::from_secs(2));
+
+    assert_eq!(timers.timer_b, Duration::from_secs(128));
+    assert_eq!(timers.timer_f, Duration::from_secs(128));
+    assert_eq!(timers.transaction_timeout(&rsip::Method::Invite), timers.timer_b);
+    assert_eq!(timers.transaction_timeout(&rsip::Method::Register), timers.timer_f);
+}
+
+#[test]
+fn refresh_before_expiry() {
+    let timers = SipTimers {
+        refresh_margin: Duration::from_secs(120),
+        ..SipTimers::default()
+    };
+
+    assert_eq!(timers.refresh_after(Duration::from_secs(3600)), Duration::from_secs(3480));
+    assert_eq!(timers.refresh_after(Duration::from_secs(180)), Duration::from_secs(90));
+}
@@ -0,0 +1,39 @@
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ucware_cli::ucware::token::TokenClaims;
+
+fn jwt(claims: &str) -> String {
+    format!(
+        "{header}.{claims}.signature",
+        header = BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+        claims = BASE64_URL_SAFE_NO_PAD.encode(claims),
+    )
+}
+
+fn unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[test]
+fn decode_expiry() {
+    let claims = TokenClaims::decode(&jwt(r#"{"sub":"1001","exp":1700003600}"#)).expect("claims");
+    assert_eq!(claims.exp, Some(1700003600));
+    assert_eq!(claims.expires_at(true), Some(UNIX_EPOCH + Duration::from_secs(1700003600)));
+
+    assert!(TokenClaims::decode("opaque-token").is_none());
+    assert_eq!(TokenClaims::decode(&jwt(r#"{"sub":"1001"}"#)).unwrap().expires_at(true), None);
+}
+
+#[test]
+fn expiry_corrected_by_server_skew() {
+    // Server clock runs an hour behind, the token is valid for ten minutes
+    let iat = unix(SystemTime::now()) - 3600;
+    let claims = TokenClaims::decode(&jwt(&format!(r#"{{"iat":{iat},"exp":{exp}}}"#, exp = iat + 600))).unwrap();
+
+    let expires_in = unix(claims.expires_at(true).unwrap()) - unix(SystemTime::now());
+    assert!((598..=600).contains(&expires_in), "{expires_in}");
+
+    // Tokens not issued just now tell nothing about the skew
+    assert_eq!(claims.expires_at(false), Some(UNIX_EPOCH + Duration::from_secs(iat + 600)));
+}
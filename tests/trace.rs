@@ -0,0 +1,62 @@
+use ucware_cli::sipsocket::{Trace, TraceDirection};
+
+fn register(cseq: u32, authorization: &str) -> String {
+    format!(
+        "REGISTER sip:pbx.example.com SIP/2.0\r\n\
+        Via: SIP/2.0/WSS abc.invalid;branch=z9hG4bK{cseq}\r\n\
+        From: <sip:1001@pbx.example.com>;tag=a\r\n\
+        To: <sip:1001@pbx.example.com>\r\n\
+        Call-ID: call-1@abc.invalid\r\n\
+        CSeq: {cseq} REGISTER\r\n\
+        {authorization}\
+        Content-Length: 0\r\n\r\n"
+    )
+}
+
+#[test]
+fn keeps_the_last_messages() {
+    let trace = Trace::new(2);
+
+    for cseq in 1..=3 {
+        trace.record(TraceDirection::Sent, register(cseq, "").as_bytes());
+    }
+
+    let messages = trace.recent();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].message.contains("CSeq: 2 REGISTER"));
+    assert!(messages[1].message.contains("CSeq: 3 REGISTER"));
+}
+
+#[test]
+fn redacts_credentials() {
+    let trace = Trace::default();
+
+    trace.record(
+        TraceDirection::Sent,
+        register(1, "Authorization: Digest username=\"1001\", response=\"secret\"\r\n").as_bytes(),
+    );
+    trace.record(
+        TraceDirection::Received,
+        register(2, "proxy-authorization: Digest username=\"1001\", response=\"secret\"\r\n").as_bytes(),
+    );
+
+    let messages = trace.recent();
+    assert_eq!(messages[0].direction, TraceDirection::Sent);
+    assert!(messages[0].message.contains("Authorization: <redacted>\r\n"));
+    assert!(messages[1].message.contains("proxy-authorization: <redacted>\r\n"));
+    assert!(messages.iter().all(|message| !message.message.contains("secret")));
+    assert!(messages[0].message.contains("CSeq: 1 REGISTER\r\n"));
+}
+
+#[tokio::test]
+async fn followers_receive_new_messages() {
+    let trace = Trace::default();
+    trace.record(TraceDirection::Sent, register(1, "").as_bytes());
+
+    let mut live = trace.follow();
+    trace.record(TraceDirection::Received, register(2, "").as_bytes());
+
+    let message = live.recv().await.expect("traced message");
+    assert_eq!(message.direction, TraceDirection::Received);
+    assert!(message.message.contains("CSeq: 2 REGISTER"));
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "webrtc")]
+
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslVerifyMode};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use ucware_cli::media::rtp::RtpPacket;
+use ucware_cli::media::sdp::SecureTransport;
+use ucware_cli::media::srtp::{self, SrtpContext};
+use ucware_cli::media::stun::{self, StunMessage};
+use ucware_cli::media::webrtc::{Certificate, WebRtcTransport};
+
+fn packet(sequence: u16) -> Vec<u8> {
+    RtpPacket {
+        payload_type: 0,
+        marker: false,
+        sequence,
+        timestamp: 160 * sequence as u32,
+        ssrc: 0x1234_5678,
+        payload: vec![0xd5; 160].into(),
+    }
+    .encode()
+    .to_vec()
+}
+
+#[test]
+fn srtp_round_trip() {
+    let material = (0..srtp::KEYING_MATERIAL_LEN as u8).collect::<Vec<_>>();
+    let (mut client_out, mut client_in) = SrtpContext::from_keying_material(&material, false).unwrap();
+    let (mut server_out, mut server_in) = SrtpContext::from_keying_material(&material, true).unwrap();
+
+    // Sequence numbers wrap around, which the rollover counter follows
+    for sequence in [65534, 65535, 0, 1] {
+        let protected = client_out.protect_rtp(&packet(sequence)).unwrap();
+        assert_ne!(protected[12..172], packet(sequence)[12..]);
+        assert_eq!(server_in.unprotect_rtp(&protected).unwrap(), packet(sequence));
+    }
+
+    let protected = server_out.protect_rtp(&packet(7)).unwrap();
+    let mut tampered = protected.clone();
+    tampered[20] ^= 1;
+    assert!(client_in.unprotect_rtp(&tampered).is_err());
+    assert_eq!(client_in.unprotect_rtp(&protected).unwrap(), packet(7));
+
+    let report = [0x80, 201, 0, 1, 0x12, 0x34, 0x56, 0x78];
+    let protected = server_out.protect_rtcp(&report).unwrap();
+    assert_eq!(client_in.unprotect_rtcp(&protected).unwrap(), report);
+}
+
+#[test]
+fn stun_round_trip() {
+    let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 50000);
+    let message = StunMessage::new(stun::BINDING_SUCCESS, [7; 12])
+        .with(stun::USERNAME, "local:remote")
+        .with_mapped_address(address);
+    let data = message.encode("secret").unwrap();
+
+    assert!(StunMessage::is_stun(&data));
+    let parsed = StunMessage::parse(&data, "secret").unwrap();
+    assert_eq!(parsed, message);
+    assert_eq!(parsed.username(), Some("local:remote"));
+    assert_eq!(parsed.mapped_address(), Some(address));
+
+    assert!(StunMessage::parse(&data, "wrong").is_err());
+}
+
+/// The socket of the remote party as DTLS reads and writes it
+#[derive(Debug)]
+struct Datagrams(UdpSocket);
+
+impl Read for Datagrams {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.recv(buf)
+    }
+}
+
+impl Write for Datagrams {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn webrtc_media_after_ice_and_dtls() {
+    let certificate = Certificate::generate().unwrap();
+    let offer = SecureTransport {
+        ice_ufrag: Some("remote".to_string()),
+        ice_pwd: Some("remote-password-of-24-chr".to_string()),
+        candidates: Vec::new(),
+        fingerprint: Some(certificate.fingerprint().unwrap()),
+        setup: Some("actpass".to_string()),
+        mid: Some("0".to_string()),
+    };
+
+    let mut transport = WebRtcTransport::bind(IpAddr::V4(Ipv4Addr::LOCALHOST), &offer).await.unwrap();
+    let local = transport.local().clone();
+    assert_eq!(local.mid.as_deref(), Some("0"));
+    let server = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), transport.port().unwrap());
+
+    let remote = tokio::task::spawn_blocking(move || {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.connect(server).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let check = StunMessage::new(stun::BINDING_REQUEST, [1; 12])
+            .with(stun::USERNAME, format!("{ufrag}:remote", ufrag = local.ice_ufrag))
+            .with(stun::USE_CANDIDATE, [])
+            .encode(&local.ice_pwd)
+            .unwrap();
+        socket.send(&check).unwrap();
+
+        let mut buf = [0; 1500];
+        let len = socket.recv(&mut buf).unwrap();
+        let response = StunMessage::parse(&buf[..len], &local.ice_pwd).unwrap();
+        assert_eq!(response.kind, stun::BINDING_SUCCESS);
+        assert_eq!(response.mapped_address(), Some(socket.local_addr().unwrap()));
+
+        let mut context = SslContext::builder(SslMethod::dtls()).unwrap();
+        context.set_certificate(certificate.x509()).unwrap();
+        context.set_private_key(certificate.key()).unwrap();
+        context.set_tlsext_use_srtp(srtp::PROFILE).unwrap();
+        context.set_verify_callback(SslVerifyMode::PEER, |_, _| true);
+        let ssl = Ssl::new(&context.build()).unwrap();
+        let stream = ssl.connect(Datagrams(socket.try_clone().unwrap())).unwrap();
+
+        let presented = stream.ssl().peer_certificate().unwrap();
+        assert_eq!(ucware_cli::media::webrtc::fingerprint(&presented, "sha-256").unwrap(), local.fingerprint);
+
+        let mut material = [0; srtp::KEYING_MATERIAL_LEN];
+        stream
+            .ssl()
+            .export_keying_material(&mut material, srtp::EXPORTER_LABEL, None)
+            .unwrap();
+        let (mut outbound, mut inbound) = SrtpContext::from_keying_material(&material, false).unwrap();
+
+        socket.send(&outbound.protect_rtp(&packet(1)).unwrap()).unwrap();
+
+        let len = socket.recv(&mut buf).unwrap();
+        inbound.unprotect_rtp(&buf[..len]).unwrap()
+    });
+
+    assert!(!transport.send_rtp(&packet(1)).await.unwrap());
+
+    let (received, _) = tokio::time::timeout(Duration::from_secs(5), transport.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received, packet(1));
+    assert!(transport.is_connected());
+
+    assert!(transport.send_rtp(&packet(2)).await.unwrap());
+    assert_eq!(remote.await.unwrap(), packet(2));
+}